@@ -1,7 +1,23 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
 use anchor_lang::system_program;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, TransferChecked};
+use anchor_spl::token::TokenAccount as LegacyTokenAccount;
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use anchor_spl::token_2022::spl_token_2022::extension::{
+    BaseStateWithExtensions, StateWithExtensions,
+};
+use anchor_spl::token_2022::spl_token_2022::state::Mint as MintState;
+use anchor_spl::token_interface::{
+    self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+use solana_sdk_ids::ed25519_program;
+use solana_sha256_hasher::hashv;
+use staking::{DepositCounter, StakingPool};
+use vesting::program::Vesting;
+use vesting::{RoundingMode, VestingSchedule};
 
 declare_id!("4FKK3U22YDwotz1yHk8Ye6TkQ32whRdnHCv34eRBuLJ9");
 
@@ -10,6 +26,10 @@ pub mod ico {
     use super::*;
 
     /// Create a new presale pool and deposit `tokens_for_sale` into the pool vault.
+    /// The sale token may be an SPL Token or a Token-2022 mint; with a transfer fee only the
+    /// tokens reaching the vault are put up for sale. The launchpad's protocol fee at creation
+    /// applies to the raise for the pool's lifetime.
+    #[allow(clippy::too_many_arguments)]
     pub fn create_presale_pool(
         ctx: Context<CreatePresalePool>,
         pool_id: u64,
@@ -38,6 +58,7 @@ pub mod ico {
 
         presale.authority = authority.key();
         presale.token_mint = ctx.accounts.token_mint.key();
+        presale.token_decimals = ctx.accounts.token_mint.decimals;
         presale.funds_receiver = ctx.accounts.funds_receiver.key();
         presale.soft_cap = soft_cap;
         presale.hard_cap = hard_cap;
@@ -49,6 +70,40 @@ pub mod ico {
         presale.token_price_lamports = token_price_lamports;
         presale.pool_id = pool_id;
         presale.bump = ctx.bumps.presale;
+        presale.tokenomics_balances_checked = false;
+        presale.whitelist_root = None;
+        presale.rounds = Vec::new();
+        presale.payment_mint = None;
+        presale.claim_vesting = None;
+        presale.outcome = SaleOutcome::Pending;
+        presale.final_rate = 0;
+        presale.funds_withdrawn = false;
+        presale.claimed_contributions = 0;
+        presale.dutch_auction = None;
+        presale.auction_tokens_sold = 0;
+        presale.tokens_for_sale =
+            tokens_for_sale - transfer_fee(&ctx.accounts.token_mint, tokens_for_sale)?;
+        presale.fair_launch = false;
+        presale.overflow_refunded = 0;
+        presale.tiers = Vec::new();
+        presale.referral_bonus_bps = 0;
+        presale.referral_allocation = 0;
+        presale.referral_bonus_paid = 0;
+        presale.referred_volume = 0;
+        presale.tokens_claimed = 0;
+        presale.refunded_contributions = 0;
+        presale.paused = false;
+        presale.kyc_authority = None;
+        presale.usd_pricing = None;
+        presale.accepted_mints = Vec::new();
+        presale.staking_allocations = Vec::new();
+        presale.staking_pool = Pubkey::default();
+        presale.claim_deadline = None;
+        presale.unclaimed_swept = false;
+        presale.protocol_fee_bps = ctx.accounts.launchpad_config.fee_bps;
+        presale.pending_authority = None;
+        presale.tokenomics_verified = false;
+        presale.version = PresalePool::VERSION;
 
         // Transfer the tokens that will be sold into the presale vault.
         if tokens_for_sale > 0 {
@@ -65,144 +120,230 @@ pub mod ico {
                 ctx.accounts.token_program.to_account_info(),
                 cpi_accounts,
             );
-            token::transfer_checked(
+            token_interface::transfer_checked(
                 cpi_ctx,
                 tokens_for_sale,
                 ctx.accounts.token_mint.decimals,
             )?;
         }
 
+        let presale = &ctx.accounts.presale;
+        emit!(PresaleCreated {
+            presale: presale.key(),
+            pool_id,
+            authority: presale.authority,
+            token_mint: presale.token_mint,
+            funds_receiver: presale.funds_receiver,
+            token_price_lamports,
+            soft_cap,
+            hard_cap,
+            min_contribution,
+            max_contribution,
+            start_timestamp,
+            end_timestamp,
+            tokens_for_sale: presale.tokens_for_sale,
+        });
+
         Ok(())
     }
 
-    /// Contribute SOL into a presale pool according to its parameters.
-    pub fn contribute(ctx: Context<Contribute>, amount: u64) -> Result<()> {
+    /// Admin-only: top up the sale inventory while the sale is pending or active.
+    /// Optionally raises the hard cap, which is only allowed before the start.
+    /// The vault may never hold more tokens than the hard cap can buy at the pool price.
+    pub fn deposit_additional_tokens(
+        ctx: Context<DepositAdditionalTokens>,
+        amount: u64,
+        new_hard_cap: Option<u64>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
         let presale = &mut ctx.accounts.presale;
-        let profile = &mut ctx.accounts.profile;
-        let contributor = &ctx.accounts.contributor;
 
-        let clock = Clock::get()?;
-        let now = clock.unix_timestamp;
-
-        // Time window checks.
-        require!(
-            now >= presale.start_timestamp,
-            IcoError::SaleNotStartedYet
-        );
         require!(now <= presale.end_timestamp, IcoError::SaleEnded);
+        require!(amount > 0, IcoError::InvalidTokenAmount);
 
-        // Min / max contribution checks.
-        require!(
-            amount >= presale.min_contribution,
-            IcoError::ContributionBelowMinimum
-        );
+        if let Some(new_hard_cap) = new_hard_cap {
+            require!(
+                now < presale.start_timestamp,
+                IcoError::HardcapChangeOnlyBeforeStart
+            );
+            require!(
+                presale.soft_cap < new_hard_cap,
+                IcoError::SoftcapHigherThanHardcap
+            );
+            presale.hard_cap = new_hard_cap;
+        }
 
-        let new_contribution = profile
-            .contributed
-            .checked_add(amount)
-            .ok_or(IcoError::MathOverflow)?;
-        require!(
-            new_contribution <= presale.max_contribution,
-            IcoError::ContributionAboveMaximum
-        );
+        // Only what reaches the vault after a Token-2022 transfer fee is for sale.
+        let received = amount - transfer_fee(&ctx.accounts.token_mint, amount)?;
 
-        let new_total = presale
-            .total_contributions
-            .checked_add(amount)
+        // The sale share changes, so checked tokenomics balances no longer hold
+        presale.tokenomics_balances_checked = false;
+        presale.tokens_for_sale = presale
+            .tokens_for_sale
+            .checked_add(received)
             .ok_or(IcoError::MathOverflow)?;
-        require!(new_total <= presale.hard_cap, IcoError::HardcapExceeded);
-
-        // Initialize profile on first contribution.
-        if profile.contributed == 0 {
-            profile.presale = presale.key();
-            profile.contributor = contributor.key();
-            profile.bump = ctx.bumps.profile;
-            profile.claimed = false;
-        }
 
-        profile.contributed = new_contribution;
-        presale.total_contributions = new_total;
+        let decimals = ctx.accounts.token_mint.decimals;
+        let tokens_for_hard_cap = presale.max_tokens_for_sale(decimals)?;
+        let total_tokens = ctx
+            .accounts
+            .presale_vault
+            .amount
+            .checked_add(received)
+            .ok_or(IcoError::MathOverflow)?;
+        // Referral bonus tokens share the vault but are not for sale.
+        require!(
+            total_tokens.saturating_sub(presale.referral_bonus_left()) <= tokens_for_hard_cap,
+            IcoError::TokensExceedHardcap
+        );
 
-        // Transfer SOL from contributor to the presale pool PDA.
+        let cpi_accounts = TransferChecked {
+            from: ctx
+                .accounts
+                .authority_token_account
+                .to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.presale_vault.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+        };
         let cpi_ctx = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: contributor.to_account_info(),
-                to: presale.to_account_info(),
-            },
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
         );
-        system_program::transfer(cpi_ctx, amount)?;
+        token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
 
-        emit!(Contributed {
+        emit!(TokensDeposited {
             presale: presale.key(),
-            contributor: contributor.key(),
             amount,
+            total_tokens,
+            hard_cap: presale.hard_cap,
+            tokens_for_hard_cap,
         });
 
         Ok(())
     }
 
-    /// Claim: if soft cap not reached – refund SOL; otherwise receive tokens.
-    pub fn claim(ctx: Context<Claim>) -> Result<()> {
+    /// Contribute SOL into a presale pool according to its parameters.
+    /// Whitelisted sales require `whitelist`, proving the contributor is in the allowlist.
+    /// KYC-gated sales require the KYC attestation, see `set_kyc_authority`.
+    /// Passing a referrer's `ReferralProfile` credits the contribution to that referrer.
+    pub fn contribute(
+        ctx: Context<Contribute>,
+        amount: u64,
+        whitelist: Option<WhitelistProof>,
+    ) -> Result<()> {
+        process_contribution(ctx, amount, whitelist, false)
+    }
+
+    /// Like `contribute`, but a contribution crossing the hard cap is clipped to the room left
+    /// instead of rejected. The remainder never leaves the contributor's wallet.
+    /// The minimum contribution is checked against the requested amount.
+    pub fn contribute_up_to_cap(
+        ctx: Context<Contribute>,
+        amount: u64,
+        whitelist: Option<WhitelistProof>,
+    ) -> Result<()> {
+        process_contribution(ctx, amount, whitelist, true)
+    }
+
+    /// Admin-only: price and collect the sale in `payment_mint` (e.g. USDC) instead of SOL,
+    /// held in a payment vault PDA. Prices, caps and contribution limits are then read in
+    /// the payment token's base units. Only before the sale starts and only once.
+    pub fn set_payment_mint(ctx: Context<SetPaymentMint>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
         let presale = &mut ctx.accounts.presale;
-        let profile = &mut ctx.accounts.profile;
-        let contributor = &ctx.accounts.contributor;
 
-        require!(!profile.claimed, IcoError::AlreadyClaimed);
-        let contributed = profile.contributed;
-        require!(contributed > 0, IcoError::NothingToClaim);
+        require!(
+            now < presale.start_timestamp,
+            IcoError::PaymentMintOnlyBeforeStart
+        );
+        require!(
+            !presale.fair_launch
+                && presale.usd_pricing.is_none()
+                && presale.accepted_mints.is_empty(),
+            IcoError::PricingModeConflict
+        );
+        // Contributions and refunds are booked gross, so the payment token must not take a fee.
+        require!(
+            transfer_fee_config(&ctx.accounts.payment_mint)?.is_none(),
+            IcoError::InvalidPaymentMint
+        );
 
-        // If soft cap not reached, refund SOL.
-        if presale.total_contributions < presale.soft_cap {
-            // Move lamports directly from the presale PDA to the contributor.
-            // This avoids needing the presale PDA to sign a system_program::transfer CPI.
-            **presale.to_account_info().try_borrow_mut_lamports()? -= contributed;
-            **contributor
-                .to_account_info()
-                .try_borrow_mut_lamports()? += contributed;
+        presale.payment_mint = Some(ctx.accounts.payment_mint.key());
 
-            profile.claimed = true;
+        emit!(PaymentMintSet {
+            presale: presale.key(),
+            payment_mint: ctx.accounts.payment_mint.key(),
+            payment_vault: ctx.accounts.payment_vault.key(),
+        });
 
-            emit!(Refunded {
-                presale: presale.key(),
-                contributor: contributor.key(),
-                amount: contributed,
-            });
+        Ok(())
+    }
 
-            return Ok(());
-        }
+    /// `contribute` for presales paid in an SPL token: moves `amount` of the payment token
+    /// into the payment vault. With `clip_to_hard_cap` only the room left under the caps is
+    /// taken, like `contribute_up_to_cap`.
+    pub fn contribute_spl(
+        ctx: Context<ContributeSpl>,
+        amount: u64,
+        whitelist: Option<WhitelistProof>,
+        clip_to_hard_cap: bool,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let accounts = ctx.accounts;
 
-        // Successful sale: send tokens.
-        let price = presale.token_price_lamports;
-        require!(price > 0, IcoError::InvalidPrice);
+        verify_kyc(
+            &accounts.presale,
+            &accounts.contributor.key(),
+            accounts.instructions_sysvar.as_ref(),
+        )?;
+        let amount = record_contribution(
+            &mut accounts.presale,
+            &mut accounts.profile,
+            accounts.contributor.key(),
+            ctx.bumps.profile,
+            now,
+            amount,
+            whitelist,
+            accounts.contributor_tier.as_ref().map(|tier| tier.tier),
+            accounts.deposit_counter.as_ref().map(|counter| counter.staked),
+            accounts.referral.as_mut(),
+            None,
+            clip_to_hard_cap,
+        )?;
 
-        let decimals = ctx.accounts.token_mint.decimals;
-        let ten_pow_decimals = 10u64
-            .checked_pow(decimals as u32)
-            .ok_or(IcoError::MathOverflow)?;
+        let cpi_accounts = TransferChecked {
+            from: accounts.contributor_payment_account.to_account_info(),
+            mint: accounts.payment_mint.to_account_info(),
+            to: accounts.payment_vault.to_account_info(),
+            authority: accounts.contributor.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, accounts.payment_mint.decimals)?;
 
-        // tokens_to_send = contributed * 10^decimals / price_lamports_per_token
-        let numerator = contributed
-            .checked_mul(ten_pow_decimals)
-            .ok_or(IcoError::MathOverflow)?;
-        let tokens_to_send = numerator
-            .checked_div(price)
-            .ok_or(IcoError::MathOverflow)?;
+        Ok(())
+    }
 
-        require!(tokens_to_send > 0, IcoError::NothingToClaim);
-        require!(
-            ctx.accounts.presale_vault.amount >= tokens_to_send,
-            IcoError::NotEnoughTokensInVault
-        );
+    /// Refund of a presale paid in an SPL token that missed its soft cap, sent to the
+    /// contributor's payment token account. `claim` handles SOL refunds.
+    pub fn refund_spl(ctx: Context<RefundSpl>) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let profile = &mut ctx.accounts.profile;
+
+        require!(!profile.claimed, IcoError::AlreadyClaimed);
+        let contributed = profile.contributed;
+        require!(contributed > 0, IcoError::NothingToClaim);
+        presale.require_refunding()?;
+        presale.require_claim_open()?;
 
         let signer_seeds: &[&[u8]] =
             &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
-        let signers = &[&signer_seeds[..]];
+        let signers = &[signer_seeds];
 
         let cpi_accounts = TransferChecked {
-            from: ctx.accounts.presale_vault.to_account_info(),
-            mint: ctx.accounts.token_mint.to_account_info(),
-            to: ctx.accounts.contributor_ata.to_account_info(),
+            from: ctx.accounts.payment_vault.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.contributor_payment_account.to_account_info(),
             authority: presale.to_account_info(),
         };
         let cpi_ctx = CpiContext::new_with_signer(
@@ -210,223 +351,4772 @@ pub mod ico {
             cpi_accounts,
             signers,
         );
-        token::transfer_checked(
+        token_interface::transfer_checked(
             cpi_ctx,
-            tokens_to_send,
-            ctx.accounts.token_mint.decimals,
+            contributed,
+            ctx.accounts.payment_mint.decimals,
         )?;
 
         profile.claimed = true;
+        let presale = &mut ctx.accounts.presale;
+        presale.refunded_contributions = presale
+            .refunded_contributions
+            .checked_add(contributed)
+            .ok_or(IcoError::MathOverflow)?;
 
-        emit!(TokensClaimed {
+        emit!(Refunded {
             presale: presale.key(),
-            contributor: contributor.key(),
-            contribution: contributed,
-            amount: tokens_to_send,
+            contributor: ctx.accounts.contributor.key(),
+            amount: contributed,
         });
 
         Ok(())
     }
 
-    /// Admin-only: withdraw SOL from the pool to the receiver address if soft cap reached.
-    pub fn admin_withdraw(ctx: Context<AdminWithdraw>, amount: u64) -> Result<()> {
+    /// Admin-only: make successful claims vest through the vesting program instead of paying
+    /// out at once, see `claim_vested`. The cliff runs from the end of the sale. Only before
+    /// the sale starts; `None` goes back to immediate claims.
+    pub fn set_claim_vesting(
+        ctx: Context<SetClaimVesting>,
+        claim_vesting: Option<ClaimVesting>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
         let presale = &mut ctx.accounts.presale;
 
         require!(
-            presale.total_contributions >= presale.soft_cap,
-            IcoError::SoftcapNotReached
+            now < presale.start_timestamp,
+            IcoError::ClaimVestingOnlyBeforeStart
         );
+        if let Some(config) = &claim_vesting {
+            require!(
+                config.cliff_duration >= 0
+                    && config.interval_duration > 0
+                    && config.unlock_percentage > 0
+                    && config.unlock_percentage <= 100,
+                IcoError::InvalidClaimVesting
+            );
+            // The vesting program only handles SPL Token mints.
+            require_keys_eq!(
+                *ctx.accounts.token_mint.to_account_info().owner,
+                anchor_spl::token::ID,
+                IcoError::ClaimVestingRequiresSplToken
+            );
+        }
 
-        let available = amount.min(presale.get_lamports());
+        presale.claim_vesting = claim_vesting;
+        Ok(())
+    }
+
+    /// `claim` for sales with claim vesting: moves the contributor's tokens into a schedule
+    /// created by CPI into the vesting program, with the claim destination as beneficiary.
+    /// Each contributor gets their own schedule creator, so contributors sharing a claim
+    /// destination do not collide. The contributor pays the rent of the schedule and its
+    /// vault. Refunds still go through `claim`.
+    pub fn claim_vested(ctx: Context<ClaimVested>) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let profile = &mut ctx.accounts.profile;
+
+        let config = presale
+            .claim_vesting
+            .ok_or(IcoError::ClaimVestingDisabled)?;
+        require!(!profile.claimed, IcoError::AlreadyClaimed);
+        require!(profile.contributed > 0, IcoError::NothingToClaim);
+        presale.require_succeeded()?;
+        presale.require_claim_open()?;
+
+        let decimals = ctx.accounts.token_mint.decimals;
+        let tokens_to_vest = presale.tokens_bought(profile, decimals)?;
+        require!(tokens_to_vest > 0, IcoError::NothingToClaim);
+        require!(
+            ctx.accounts.presale_vault.amount >= tokens_to_vest,
+            IcoError::NotEnoughTokensInVault
+        );
+
+        // Stage the tokens with the vesting authority, which creates the schedule.
+        let presale_seeds: &[&[u8]] =
+            &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
+        let presale_signers = &[presale_seeds];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.presale_vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.vesting_escrow.to_account_info(),
+                authority: presale.to_account_info(),
+            },
+            presale_signers,
+        );
+        token_interface::transfer_checked(cpi_ctx, tokens_to_vest, decimals)?;
+
+        // The presale PDA holds data and cannot pay for accounts, so the contributor funds
+        // the vesting authority with the rent of the schedule and its vault.
         let rent = Rent::get()?;
-        let min_balance = rent.minimum_balance(8 + PresalePool::INIT_SPACE);
-        require!(available > min_balance, IcoError::NothingToWithdraw);
+        let rent_needed = rent
+            .minimum_balance(8 + VestingSchedule::INIT_SPACE)
+            .checked_add(rent.minimum_balance(LegacyTokenAccount::LEN))
+            .ok_or(IcoError::MathOverflow)?;
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.contributor.to_account_info(),
+                to: ctx.accounts.vesting_authority.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_ctx, rent_needed)?;
 
-        let withdraw_amount = available - min_balance;
+        // Vesting starts when the sale ends, whenever the contributor claims: intervals
+        // elapsed before a late claim are unlockable right away.
+        let cliff_end = presale
+            .end_timestamp
+            .checked_add(config.cliff_duration)
+            .ok_or(IcoError::MathOverflow)?;
 
-        presale.sub_lamports(withdraw_amount)?;
-        ctx.accounts
-            .funds_receiver
-            .add_lamports(withdraw_amount)?;
+        let presale_key = presale.key();
+        let contributor_key = ctx.accounts.contributor.key();
+        let authority_seeds: &[&[u8]] = &[
+            b"vesting-authority",
+            presale_key.as_ref(),
+            contributor_key.as_ref(),
+            &[ctx.bumps.vesting_authority],
+        ];
+        let authority_signers = &[authority_seeds];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.vesting_program.to_account_info(),
+            vesting::cpi::accounts::CreateVesting {
+                vesting_schedule: ctx.accounts.vesting_schedule.to_account_info(),
+                creator: ctx.accounts.vesting_authority.to_account_info(),
+                beneficiary: ctx.accounts.destination.to_account_info(),
+                token_mint: ctx.accounts.token_mint.to_account_info(),
+                creator_token_account: ctx.accounts.vesting_escrow.to_account_info(),
+                vault: ctx.accounts.vesting_vault.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            authority_signers,
+        );
+        vesting::cpi::create_vesting_at(
+            cpi_ctx,
+            cliff_end,
+            config.interval_duration,
+            config.unlock_percentage,
+            tokens_to_vest,
+            false,
+            RoundingMode::Floor,
+        )?;
+
+        // The escrow is empty again, return its rent to the contributor.
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.vesting_escrow.to_account_info(),
+                destination: ctx.accounts.contributor.to_account_info(),
+                authority: ctx.accounts.vesting_authority.to_account_info(),
+            },
+            authority_signers,
+        ))?;
+
+        profile.claimed = true;
+        let presale = &mut ctx.accounts.presale;
+        presale.claimed_contributions = presale
+            .claimed_contributions
+            .checked_add(profile.contributed)
+            .ok_or(IcoError::MathOverflow)?;
+        presale.tokens_claimed = presale
+            .tokens_claimed
+            .checked_add(tokens_to_vest)
+            .ok_or(IcoError::MathOverflow)?;
+
+        let excess_refund = refund_excess(
+            presale,
+            &ctx.accounts.contributor.to_account_info(),
+            profile.contributed,
+        )?;
+
+        emit!(TokensVested {
+            presale: presale_key,
+            contributor: ctx.accounts.contributor.key(),
+            vesting_schedule: ctx.accounts.vesting_schedule.key(),
+            contribution: profile.contributed,
+            amount: tokens_to_vest,
+            excess_refund,
+        });
 
         Ok(())
     }
 
-    /// Admin-only: emergency withdraw of tokens from the vault before the sale starts.
-    pub fn emergency_withdraw_token(
-        ctx: Context<EmergencyWithdrawToken>,
-        amount: u64,
-    ) -> Result<()> {
+    /// Admin-only: `admin_withdraw` for presales paid in an SPL token, sending the payment
+    /// token to the funds receiver's token account once the soft cap is reached, or once
+    /// the refunds of a failed sale left unclaimed were swept. The protocol fee goes to the
+    /// treasury's token account.
+    pub fn admin_withdraw_spl(ctx: Context<AdminWithdrawSpl>, amount: u64) -> Result<()> {
         let presale = &ctx.accounts.presale;
-        let clock = Clock::get()?;
+
+        presale.require_withdrawable()?;
+
+        let withdraw_amount = amount.min(ctx.accounts.payment_vault.amount);
+        require!(withdraw_amount > 0, IcoError::NothingToWithdraw);
+        let protocol_fee = presale.protocol_fee(withdraw_amount)?;
+
+        let accounts = &ctx.accounts;
+        transfer_from_vault(
+            &accounts.presale,
+            &accounts.payment_vault,
+            &accounts.payment_mint,
+            &accounts.receiver_payment_account,
+            &accounts.token_program,
+            withdraw_amount - protocol_fee,
+        )?;
+        if protocol_fee > 0 {
+            transfer_from_vault(
+                &accounts.presale,
+                &accounts.payment_vault,
+                &accounts.payment_mint,
+                &accounts.treasury_payment_account,
+                &accounts.token_program,
+                protocol_fee,
+            )?;
+        }
+
+        ctx.accounts.presale.funds_withdrawn = true;
+
+        emit!(AdminWithdrawn {
+            presale: ctx.accounts.presale.key(),
+            funds_receiver: ctx.accounts.funds_receiver.key(),
+            payment_mint: Some(ctx.accounts.payment_mint.key()),
+            amount: withdraw_amount,
+            protocol_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: accept `payment_mint` next to SOL, each full token counting for
+    /// `rate_lamports` towards the caps, limits and price. Opens the mint's vault. Only
+    /// before the sale starts and not for fair launches, USD-priced or single-currency SPL
+    /// sales.
+    pub fn add_accepted_mint(ctx: Context<AddAcceptedMint>, rate_lamports: u64) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
 
         require!(
-            clock.unix_timestamp < presale.start_timestamp,
-            IcoError::EmergencyWithdrawOnlyBeforeStart
+            now < presale.start_timestamp,
+            IcoError::PaymentMintOnlyBeforeStart
+        );
+        require!(
+            !presale.fair_launch
+                && presale.usd_pricing.is_none()
+                && presale.payment_mint.is_none(),
+            IcoError::PricingModeConflict
+        );
+        require!(
+            presale.accepted_mints.len() < MAX_ACCEPTED_MINTS,
+            IcoError::TooManyAcceptedMints
+        );
+        require!(rate_lamports > 0, IcoError::InvalidPrice);
+        // Contributions and refunds are booked gross, so the mint must not take a fee.
+        require!(
+            transfer_fee_config(&ctx.accounts.payment_mint)?.is_none(),
+            IcoError::InvalidPaymentMint
         );
 
-        let actual_amount = amount.min(ctx.accounts.presale_vault.amount);
-        require!(actual_amount > 0, IcoError::NothingToWithdraw);
+        presale.accepted_mints.push(AcceptedMint {
+            mint: ctx.accounts.payment_mint.key(),
+            decimals: ctx.accounts.payment_mint.decimals,
+            rate_lamports,
+            raised: 0,
+        });
+
+        emit!(AcceptedMintAdded {
+            presale: presale.key(),
+            mint: ctx.accounts.payment_mint.key(),
+            mint_vault: ctx.accounts.mint_vault.key(),
+            rate_lamports,
+        });
+
+        Ok(())
+    }
+
+    /// `contribute` paying `amount` base units of an accepted mint, counted at its lamport
+    /// value. With `clip_to_hard_cap` only the value left under the caps is taken.
+    pub fn contribute_with_mint(
+        ctx: Context<ContributeWithMint>,
+        amount: u64,
+        whitelist: Option<WhitelistProof>,
+        clip_to_hard_cap: bool,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let accounts = ctx.accounts;
+
+        let index = accounts
+            .presale
+            .accepted_mint_index(&accounts.payment_mint.key())?;
+        let accepted = accounts.presale.accepted_mints[index];
+
+        verify_kyc(
+            &accounts.presale,
+            &accounts.contributor.key(),
+            accounts.instructions_sysvar.as_ref(),
+        )?;
+        let value = accepted.lamports_value(amount)?;
+        let value = record_contribution(
+            &mut accounts.presale,
+            &mut accounts.profile,
+            accounts.contributor.key(),
+            ctx.bumps.profile,
+            now,
+            value,
+            whitelist,
+            accounts.contributor_tier.as_ref().map(|tier| tier.tier),
+            accounts.deposit_counter.as_ref().map(|counter| counter.staked),
+            accounts.referral.as_mut(),
+            None,
+            clip_to_hard_cap,
+        )?;
+        // A clipped contribution pays the matching part of `amount`.
+        let amount = accepted.amount_for_lamports(value)?.min(amount);
+
+        let profile = &mut accounts.profile;
+        profile.mint_contributions[index] = profile.mint_contributions[index]
+            .checked_add(amount)
+            .ok_or(IcoError::MathOverflow)?;
+        profile.mint_values[index] = profile.mint_values[index]
+            .checked_add(value)
+            .ok_or(IcoError::MathOverflow)?;
+        let accepted = &mut accounts.presale.accepted_mints[index];
+        accepted.raised = accepted
+            .raised
+            .checked_add(amount)
+            .ok_or(IcoError::MathOverflow)?;
+
+        let cpi_accounts = TransferChecked {
+            from: accounts.contributor_payment_account.to_account_info(),
+            mint: accounts.payment_mint.to_account_info(),
+            to: accounts.mint_vault.to_account_info(),
+            authority: accounts.contributor.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, accounts.payment_mint.decimals)?;
+
+        Ok(())
+    }
+
+    /// Refund of the payments in one accepted mint after the sale failed or was cancelled.
+    /// The SOL part is refunded by `claim`.
+    pub fn refund_with_mint(ctx: Context<RefundWithMint>) -> Result<()> {
+        ctx.accounts.presale.require_refunding()?;
+        ctx.accounts.presale.require_claim_open()?;
+        let index = ctx
+            .accounts
+            .presale
+            .accepted_mint_index(&ctx.accounts.payment_mint.key())?;
+
+        let profile = &mut ctx.accounts.profile;
+        let amount = profile.mint_contributions[index];
+        require!(amount > 0, IcoError::NothingToClaim);
+        let value = profile.mint_values[index];
+        profile.mint_contributions[index] = 0;
+
+        let presale = &mut ctx.accounts.presale;
+        presale.accepted_mints[index].raised = presale.accepted_mints[index]
+            .raised
+            .checked_sub(amount)
+            .ok_or(IcoError::MathOverflow)?;
+        presale.refunded_contributions = presale
+            .refunded_contributions
+            .checked_add(value)
+            .ok_or(IcoError::MathOverflow)?;
 
         let signer_seeds: &[&[u8]] =
             &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
-        let signers = &[&signer_seeds[..]];
+        let signers = &[signer_seeds];
 
         let cpi_accounts = TransferChecked {
-            from: ctx.accounts.presale_vault.to_account_info(),
-            mint: ctx.accounts.token_mint.to_account_info(),
-            to: ctx.accounts.receiver_ata.to_account_info(),
-            authority: ctx.accounts.presale.to_account_info(),
+            from: ctx.accounts.mint_vault.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.contributor_payment_account.to_account_info(),
+            authority: presale.to_account_info(),
         };
         let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
             cpi_accounts,
             signers,
         );
-        token::transfer_checked(
-            cpi_ctx,
-            actual_amount,
-            ctx.accounts.token_mint.decimals,
-        )?;
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        emit!(Refunded {
+            presale: presale.key(),
+            contributor: ctx.accounts.contributor.key(),
+            amount: value,
+        });
 
         Ok(())
     }
-}
 
-#[account]
-#[derive(InitSpace)]
-pub struct PresalePool {
-    /// Admin / creator of the pool.
-    pub authority: Pubkey,
-    /// SPL token mint sold in this presale.
-    pub token_mint: Pubkey,
-    /// Where SOL goes if the soft cap is reached.
-    pub funds_receiver: Pubkey,
+    /// Admin-only: `admin_withdraw` for an accepted mint, sending its vault to the funds
+    /// receiver's token account once the sale succeeded, or once the refunds of a failed
+    /// sale left unclaimed were swept.
+    pub fn admin_withdraw_mint(ctx: Context<AdminWithdrawMint>, amount: u64) -> Result<()> {
+        ctx.accounts.presale.require_withdrawable()?;
+        let index = ctx
+            .accounts
+            .presale
+            .accepted_mint_index(&ctx.accounts.payment_mint.key())?;
 
-    /// Minimum total raised for the sale to be valid (lamports).
-    pub soft_cap: u64,
-    /// Maximum total raised (lamports).
-    pub hard_cap: u64,
+        let withdraw_amount = amount.min(ctx.accounts.mint_vault.amount);
+        require!(withdraw_amount > 0, IcoError::NothingToWithdraw);
 
-    /// Min / max contribution per user (lamports).
-    pub min_contribution: u64,
-    pub max_contribution: u64,
+        let presale = &mut ctx.accounts.presale;
+        let protocol_fee = presale.protocol_fee(withdraw_amount)?;
+        let accepted = &mut presale.accepted_mints[index];
+        accepted.raised = accepted.raised.saturating_sub(withdraw_amount);
+        presale.funds_withdrawn = true;
 
-    /// Sale window.
-    pub start_timestamp: i64,
-    pub end_timestamp: i64,
+        let accounts = &ctx.accounts;
+        transfer_from_vault(
+            &accounts.presale,
+            &accounts.mint_vault,
+            &accounts.payment_mint,
+            &accounts.receiver_payment_account,
+            &accounts.token_program,
+            withdraw_amount - protocol_fee,
+        )?;
+        if protocol_fee > 0 {
+            transfer_from_vault(
+                &accounts.presale,
+                &accounts.mint_vault,
+                &accounts.payment_mint,
+                &accounts.treasury_payment_account,
+                &accounts.token_program,
+                protocol_fee,
+            )?;
+        }
 
-    /// Total SOL contributed so far (lamports).
-    pub total_contributions: u64,
+        emit!(AdminWithdrawn {
+            presale: accounts.presale.key(),
+            funds_receiver: accounts.funds_receiver.key(),
+            payment_mint: Some(accounts.payment_mint.key()),
+            amount: withdraw_amount,
+            protocol_fee,
+        });
 
-    /// Price in lamports per full token (10^decimals units).
-    pub token_price_lamports: u64,
+        Ok(())
+    }
 
-    /// Pool id used in PDA derivation.
-    pub pool_id: u64,
-    /// PDA bump.
+    /// Admin-only: pause or resume contributions, e.g. during an incident. The sale window
+    /// is left untouched; claims and refunds are not affected.
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        presale.paused = paused;
+
+        emit!(PausedSet {
+            presale: presale.key(),
+            paused,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: first step of an authority handover, e.g. from a deployer wallet to a
+    /// multisig. Nominates `new_authority`, or withdraws the nomination with `None`.
+    pub fn nominate_authority(
+        ctx: Context<NominateAuthority>,
+        new_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        presale.pending_authority = new_authority;
+
+        emit!(AuthorityNominated {
+            presale: presale.key(),
+            pending_authority: new_authority,
+        });
+
+        Ok(())
+    }
+
+    /// Second step of an authority handover: the nominated authority accepts.
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        require!(
+            presale.pending_authority == Some(ctx.accounts.new_authority.key()),
+            IcoError::NotPendingAuthority
+        );
+
+        let previous_authority = presale.authority;
+        presale.authority = ctx.accounts.new_authority.key();
+        presale.pending_authority = None;
+
+        emit!(AuthorityTransferred {
+            presale: presale.key(),
+            previous_authority,
+            authority: presale.authority,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: send withdrawn funds to `new_receiver` from now on, e.g. when the treasury
+    /// moves to a multisig. Only before anything was withdrawn. For SPL payments the new
+    /// receiver needs a token account of the payment mint.
+    pub fn set_funds_receiver(ctx: Context<SetFundsReceiver>, new_receiver: Pubkey) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        require!(!presale.funds_withdrawn, IcoError::FundsAlreadyWithdrawn);
+
+        presale.funds_receiver = new_receiver;
+
+        emit!(FundsReceiverSet {
+            presale: presale.key(),
+            funds_receiver: new_receiver,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: require contributions to be attested by `kyc_authority`, or drop the KYC
+    /// gate with `None`. The attestation is an ed25519 signature by that key over
+    /// `kyc_message(contributor, presale)`, verified by an Ed25519 program instruction placed
+    /// right before the contribution. Only before the sale starts.
+    pub fn set_kyc_authority(
+        ctx: Context<SetKycAuthority>,
+        kyc_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::KycOnlyBeforeStart
+        );
+
+        presale.kyc_authority = kyc_authority;
+
+        emit!(KycAuthoritySet {
+            presale: presale.key(),
+            kyc_authority,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: close claims and refunds at `claim_deadline`, after which
+    /// `sweep_unclaimed` returns what was left unclaimed; `None` keeps them open forever.
+    /// Only before the sale starts, and the deadline must fall after the sale ends.
+    pub fn set_claim_deadline(
+        ctx: Context<SetClaimDeadline>,
+        claim_deadline: Option<i64>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::ClaimDeadlineOnlyBeforeStart
+        );
+        require!(
+            claim_deadline.is_none_or(|deadline| deadline > presale.end_timestamp),
+            IcoError::InvalidClaimDeadline
+        );
+
+        presale.claim_deadline = claim_deadline;
+
+        emit!(ClaimDeadlineSet {
+            presale: presale.key(),
+            claim_deadline,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: restrict contributions to the allowlist committed to by `root`, or open
+    /// the sale to everyone with `None`. Only before the sale starts.
+    /// Leaves are `whitelist_leaf(contributor, allocation, tier)`, see `WhitelistProof`.
+    pub fn set_whitelist_root(
+        ctx: Context<SetWhitelistRoot>,
+        root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::WhitelistOnlyBeforeStart
+        );
+
+        presale.whitelist_root = root;
+
+        emit!(WhitelistRootSet {
+            presale: presale.key(),
+            root,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: move the sale window, e.g. after a launch slip. Only before the sale
+    /// starts, with the same checks as `create_presale_pool`; sale rounds must still fit.
+    pub fn update_schedule(
+        ctx: Context<UpdateSchedule>,
+        new_start: i64,
+        new_end: i64,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::ScheduleOnlyBeforeStart
+        );
+        require!(new_start < new_end, IcoError::EndTimestampBeforeStart);
+        require!(
+            presale.claim_deadline.is_none_or(|deadline| deadline > new_end),
+            IcoError::InvalidClaimDeadline
+        );
+        if let (Some(first), Some(last)) = (presale.rounds.first(), presale.rounds.last()) {
+            require!(
+                first.start_timestamp >= new_start && last.end_timestamp <= new_end,
+                IcoError::InvalidSaleRound
+            );
+        }
+
+        presale.start_timestamp = new_start;
+        presale.end_timestamp = new_end;
+
+        emit!(ScheduleUpdated {
+            presale: presale.key(),
+            start_timestamp: new_start,
+            end_timestamp: new_end,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: correct the token price. Strictly before the sale starts.
+    pub fn update_price(ctx: Context<UpdatePrice>, new_price_lamports: u64) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::PriceOnlyBeforeStart
+        );
+        require!(new_price_lamports > 0, IcoError::InvalidPrice);
+
+        let old_price_lamports = presale.token_price_lamports;
+        presale.token_price_lamports = new_price_lamports;
+
+        emit!(PriceUpdated {
+            presale: presale.key(),
+            old_price_lamports,
+            new_price_lamports,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: sell in a dutch auction whose price falls from `start_price_lamports` to
+    /// `floor_price_lamports` over the sale window, linearly or in `step_duration` steps.
+    /// Each contribution buys at the price of its time. Only before the sale starts and not
+    /// together with sale rounds; `None` goes back to the fixed pool price.
+    pub fn set_dutch_auction(
+        ctx: Context<SetDutchAuction>,
+        dutch_auction: Option<DutchAuction>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::PriceOnlyBeforeStart
+        );
+        if let Some(auction) = &dutch_auction {
+            require!(
+                presale.rounds.is_empty()
+                    && !presale.fair_launch
+                    && presale.usd_pricing.is_none(),
+                IcoError::PricingModeConflict
+            );
+            require!(
+                auction.floor_price_lamports > 0
+                    && auction.floor_price_lamports < auction.start_price_lamports
+                    && auction.step_duration >= 0,
+                IcoError::InvalidDutchAuction
+            );
+        }
+
+        presale.dutch_auction = dutch_auction;
+        Ok(())
+    }
+
+    /// Admin-only: fix the token price in USD. Each contribution is then priced in lamports
+    /// from the Pyth SOL/USD price passed to `contribute`, and books its tokens at that
+    /// price. Only before the sale starts and only for single-price SOL sales.
+    pub fn set_usd_pricing(
+        ctx: Context<SetUsdPricing>,
+        usd_pricing: Option<UsdPricing>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::PriceOnlyBeforeStart
+        );
+        if let Some(pricing) = &usd_pricing {
+            require!(
+                presale.rounds.is_empty()
+                    && presale.dutch_auction.is_none()
+                    && !presale.fair_launch
+                    && presale.payment_mint.is_none()
+                    && presale.accepted_mints.is_empty(),
+                IcoError::PricingModeConflict
+            );
+            require!(
+                pricing.token_price_usd > 0 && pricing.max_price_age > 0,
+                IcoError::InvalidPrice
+            );
+        }
+
+        presale.usd_pricing = usd_pricing;
+        Ok(())
+    }
+
+    /// Admin-only: run the sale as a fair launch. There is no hard cap; if more than the
+    /// price of `tokens_for_sale` is raised, every contributor gets a pro-rata share of
+    /// `tokens_for_sale` and the excess back at claim. Only before the sale starts and only
+    /// for SOL sales at the fixed pool price.
+    pub fn set_fair_launch(ctx: Context<SetFairLaunch>, enabled: bool) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::PriceOnlyBeforeStart
+        );
+        require!(
+            !enabled
+                || (presale.rounds.is_empty()
+                    && presale.dutch_auction.is_none()
+                    && presale.payment_mint.is_none()
+                    && presale.usd_pricing.is_none()
+                    && presale.accepted_mints.is_empty()),
+            IcoError::PricingModeConflict
+        );
+
+        presale.fair_launch = enabled;
+        Ok(())
+    }
+
+    /// Admin-only: define per-tier contribution limits. Tiers are proven in the allowlist
+    /// leaf or assigned with `set_contributor_tier`; wallets without a tier keep the pool
+    /// limits. Only before the sale starts.
+    pub fn set_tiers(ctx: Context<SetTiers>, tiers: Vec<TierLimits>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::TiersOnlyBeforeStart
+        );
+        require!(tiers.len() <= MAX_TIERS, IcoError::TooManyTiers);
+        for tier in &tiers {
+            require!(
+                tier.min_contribution <= tier.max_contribution,
+                IcoError::MaxContributionLessThanMinContribution
+            );
+        }
+
+        presale.tiers = tiers;
+        Ok(())
+    }
+
+    /// Admin-only: raise the maximum contribution of wallets staking in `staking_pool` of the
+    /// workspace staking program. Thresholds are read against the contributor's stake in that
+    /// pool only, and the highest one reached applies when they pass their deposit counter.
+    /// The pool must have a claim cooldown, so the stake can't be withdrawn within the same
+    /// transaction. Only before the sale starts.
+    pub fn set_staking_allocations(
+        ctx: Context<SetStakingAllocations>,
+        allocations: Vec<StakingAllocation>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::StakingAllocationsOnlyBeforeStart
+        );
+        require!(
+            allocations.len() <= MAX_STAKING_ALLOCATIONS,
+            IcoError::InvalidStakingAllocations
+        );
+        let mut previous: Option<&StakingAllocation> = None;
+        for allocation in &allocations {
+            require!(
+                allocation.min_staked > 0
+                    && allocation.max_contribution >= presale.min_contribution,
+                IcoError::InvalidStakingAllocations
+            );
+            if let Some(previous) = previous {
+                require!(
+                    allocation.min_staked > previous.min_staked
+                        && allocation.max_contribution > previous.max_contribution,
+                    IcoError::InvalidStakingAllocations
+                );
+            }
+            previous = Some(allocation);
+        }
+        require!(
+            ctx.accounts.staking_pool.claim_cooldown > 0,
+            IcoError::StakingPoolNotLocked
+        );
+
+        presale.staking_allocations = allocations;
+        presale.staking_pool = ctx.accounts.staking_pool.key();
+        Ok(())
+    }
+
+    /// Admin-only: assign `contributor` to `tier`, read by `contribute` when the tier
+    /// account is passed.
+    pub fn set_contributor_tier(
+        ctx: Context<SetContributorTier>,
+        contributor: Pubkey,
+        tier: u8,
+    ) -> Result<()> {
+        require!(
+            (tier as usize) < ctx.accounts.presale.tiers.len(),
+            IcoError::InvalidTier
+        );
+
+        let contributor_tier = &mut ctx.accounts.contributor_tier;
+        contributor_tier.presale = ctx.accounts.presale.key();
+        contributor_tier.contributor = contributor;
+        contributor_tier.tier = tier;
+        contributor_tier.bump = ctx.bumps.contributor_tier;
+
+        Ok(())
+    }
+
+    /// Admin-only: set the referral bonus, in basis points of the tokens bought by referred
+    /// contributors, and top up the bonus allocation with `deposit` tokens. Bonuses are paid
+    /// from that allocation only, first come first served. Only before the sale starts.
+    pub fn set_referral_bonus(
+        ctx: Context<SetReferralBonus>,
+        bonus_bps: u16,
+        deposit: u64,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::ReferralOnlyBeforeStart
+        );
+        require!(bonus_bps <= 10_000, IcoError::InvalidReferralBonus);
+
+        presale.referral_bonus_bps = bonus_bps;
+        presale.referral_allocation = presale
+            .referral_allocation
+            .checked_add(deposit - transfer_fee(&ctx.accounts.token_mint, deposit)?)
+            .ok_or(IcoError::MathOverflow)?;
+
+        if deposit > 0 {
+            let cpi_accounts = TransferChecked {
+                from: ctx
+                    .accounts
+                    .authority_token_account
+                    .to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.presale_vault.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+            );
+            token_interface::transfer_checked(cpi_ctx, deposit, ctx.accounts.token_mint.decimals)?;
+        }
+
+        emit!(ReferralBonusSet {
+            presale: presale.key(),
+            bonus_bps,
+            referral_allocation: presale.referral_allocation,
+        });
+
+        Ok(())
+    }
+
+    /// Open a referral profile, which contributors pass to `contribute` to credit the
+    /// signer as their referrer.
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        let referral = &mut ctx.accounts.referral;
+        referral.presale = ctx.accounts.presale.key();
+        referral.referrer = ctx.accounts.referrer.key();
+        referral.referred_volume = 0;
+        referral.bonus_claimed = false;
+        referral.bump = ctx.bumps.referral;
+
+        Ok(())
+    }
+
+    /// Referrer claims the bonus on the volume they brought in, once the sale succeeded.
+    /// Paid out while the bonus allocation lasts.
+    pub fn claim_referral_bonus(ctx: Context<ClaimReferralBonus>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let referral = &mut ctx.accounts.referral;
+
+        presale.require_succeeded()?;
+        presale.require_claim_open()?;
+        require!(!referral.bonus_claimed, IcoError::AlreadyClaimed);
+
+        let bonus = presale
+            .referral_bonus(referral.referred_volume)?
+            .min(presale.referral_bonus_left());
+        require!(bonus > 0, IcoError::NothingToClaim);
+
+        referral.bonus_claimed = true;
+        presale.referral_bonus_paid = presale
+            .referral_bonus_paid
+            .checked_add(bonus)
+            .ok_or(IcoError::MathOverflow)?;
+
+        let signer_seeds: &[&[u8]] =
+            &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
+        let signers = &[signer_seeds];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.presale_vault.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.referrer_ata.to_account_info(),
+            authority: presale.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signers,
+        );
+        token_interface::transfer_checked(cpi_ctx, bonus, ctx.accounts.token_mint.decimals)?;
+
+        emit!(ReferralBonusClaimed {
+            presale: presale.key(),
+            referrer: referral.referrer,
+            referred_volume: referral.referred_volume,
+            bonus,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: split the sale into rounds (e.g. seed / private / public), each with its own
+    /// price, raise cap, per-wallet cap and window inside the sale window. Rounds must be in
+    /// order and must not overlap; `contribute` uses the round active at the time. Only before
+    /// the sale starts; an empty list goes back to a single-price sale.
+    pub fn set_sale_rounds(ctx: Context<SetSaleRounds>, rounds: Vec<SaleRound>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::SaleRoundsOnlyBeforeStart
+        );
+        require!(
+            rounds.len() <= MAX_SALE_ROUNDS,
+            IcoError::TooManySaleRounds
+        );
+        require!(
+            rounds.is_empty()
+                || (presale.dutch_auction.is_none()
+                    && !presale.fair_launch
+                    && presale.usd_pricing.is_none()),
+            IcoError::PricingModeConflict
+        );
+
+        let mut previous_end = presale.start_timestamp - 1;
+        for round in &rounds {
+            require!(round.token_price_lamports > 0, IcoError::InvalidPrice);
+            require!(
+                round.start_timestamp < round.end_timestamp,
+                IcoError::EndTimestampBeforeStart
+            );
+            require!(
+                round.start_timestamp > previous_end
+                    && round.end_timestamp <= presale.end_timestamp,
+                IcoError::InvalidSaleRound
+            );
+            require!(
+                round.cap > 0 && round.cap <= presale.hard_cap,
+                IcoError::InvalidSaleRound
+            );
+            require!(
+                round.max_contribution >= presale.min_contribution,
+                IcoError::InvalidSaleRound
+            );
+            previous_end = round.end_timestamp;
+        }
+
+        presale.rounds = rounds
+            .into_iter()
+            .map(|round| SaleRound { raised: 0, ..round })
+            .collect();
+
+        emit!(SaleRoundsSet {
+            presale: presale.key(),
+            rounds: presale.rounds.len() as u8,
+        });
+
+        Ok(())
+    }
+
+    /// Contributor: deliver claimed tokens to `destination`'s ATA instead of their own,
+    /// e.g. a cold wallet. Pass `None` to go back to the contributor's ATA. Refunds are
+    /// always paid to the contributor.
+    pub fn set_claim_destination(
+        ctx: Context<SetClaimDestination>,
+        destination: Option<Pubkey>,
+    ) -> Result<()> {
+        let profile = &mut ctx.accounts.profile;
+        require!(!profile.claimed, IcoError::AlreadyClaimed);
+
+        profile.claim_destination = destination;
+
+        emit!(ClaimDestinationSet {
+            presale: ctx.accounts.presale.key(),
+            contributor: ctx.accounts.contributor.key(),
+            destination,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless: settle the sale once it ended or filled its hard cap. Freezes the
+    /// raise and records whether the soft cap was met and the average token rate; claims,
+    /// refunds and withdrawals only run on a finalized sale. Also records whether the sale
+    /// ran with tokenomics balances checked by `check_tokenomics_balances`.
+    pub fn finalize(ctx: Context<Finalize>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            presale.outcome == SaleOutcome::Pending,
+            IcoError::SaleFinalized
+        );
+        require!(
+            now > presale.end_timestamp || presale.hard_cap_filled(),
+            IcoError::SaleNotEnded
+        );
+
+        let (outcome, tokens_sold) = if presale.total_contributions >= presale.soft_cap {
+            let tokens_sold = presale.tokens_sold(ctx.accounts.token_mint.decimals)?;
+            (SaleOutcome::Succeeded, tokens_sold)
+        } else {
+            (SaleOutcome::Failed, 0)
+        };
+
+        let final_rate = if presale.total_contributions == 0 {
+            0
+        } else {
+            (tokens_sold as u128)
+                .checked_mul(RATE_PRECISION)
+                .ok_or(IcoError::MathOverflow)?
+                / presale.total_contributions as u128
+        };
+
+        presale.outcome = outcome;
+        presale.final_rate = u64::try_from(final_rate).map_err(|_| IcoError::MathOverflow)?;
+        // Any change to the sale inventory clears the check, so a check still standing
+        // covers the tokens the sale ran with
+        presale.tokenomics_verified = presale.tokenomics_balances_checked;
+
+        emit!(PresaleFinalized {
+            presale: presale.key(),
+            outcome,
+            total_contributions: presale.total_contributions,
+            tokens_sold,
+            final_rate: presale.final_rate,
+            tokenomics_verified: presale.tokenomics_verified,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: cancel the sale while every contribution is still in the pool, i.e. before
+    /// any admin withdrawal or token claim. All claims become refunds and the sale tokens can
+    /// be taken back with `emergency_withdraw_token`.
+    pub fn cancel_presale(ctx: Context<CancelPresale>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            matches!(
+                presale.outcome,
+                SaleOutcome::Pending | SaleOutcome::Succeeded
+            ),
+            IcoError::SaleFinalized
+        );
+        require!(
+            !presale.funds_withdrawn
+                && presale.claimed_contributions == 0
+                && presale.referral_bonus_paid == 0,
+            IcoError::CancelAfterPayout
+        );
+
+        presale.outcome = SaleOutcome::Cancelled;
+        presale.final_rate = 0;
+
+        emit!(PresaleCancelled {
+            presale: presale.key(),
+            total_contributions: presale.total_contributions,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: push SOL refunds of a failed or cancelled sale to contributors who did not
+    /// claim them. Payments in accepted mints still go through `refund_with_mint`.
+    /// `remaining_accounts` holds (profile, contributor wallet) pairs, both writable; profiles
+    /// already settled are skipped.
+    pub fn refund_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RefundBatch<'info>>,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.require_refunding()?;
+        presale.require_claim_open()?;
+        require!(
+            presale.payment_mint.is_none(),
+            IcoError::SplPaymentRequired
+        );
+        require!(
+            !ctx.remaining_accounts.is_empty() && ctx.remaining_accounts.len() % 2 == 0,
+            IcoError::InvalidRefundBatch
+        );
+
+        for pair in ctx.remaining_accounts.chunks_exact(2) {
+            let (profile_info, contributor_info) = (&pair[0], &pair[1]);
+            let mut profile = Account::<ContributorProfile>::try_from(profile_info)?;
+            require_keys_eq!(
+                profile.presale,
+                presale.key(),
+                IcoError::InvalidRefundBatch
+            );
+            require_keys_eq!(
+                profile.contributor,
+                contributor_info.key(),
+                IcoError::InvalidRefundBatch
+            );
+
+            if profile.claimed || profile.contributed == 0 {
+                continue;
+            }
+
+            let refund = profile.sol_contributed();
+            presale.sub_lamports(refund)?;
+            contributor_info.add_lamports(refund)?;
+
+            profile.claimed = true;
+            profile.exit(&crate::ID)?;
+            presale.refunded_contributions = presale
+                .refunded_contributions
+                .checked_add(refund)
+                .ok_or(IcoError::MathOverflow)?;
+
+            emit!(Refunded {
+                presale: presale.key(),
+                contributor: contributor_info.key(),
+                amount: refund,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Claim on a finalized sale: refund SOL if it failed or was cancelled, otherwise
+    /// receive tokens.
+    pub fn claim(ctx: Context<Claim>) -> Result<()> {
+        let accounts = ctx.accounts;
+        settle_claim(
+            &mut accounts.presale,
+            &mut accounts.profile,
+            &accounts.contributor,
+            &accounts.token_mint,
+            &accounts.presale_vault,
+            &accounts.contributor_ata,
+            &accounts.token_program,
+        )
+    }
+
+    /// Same as `claim`, but sends the tokens to a freshly created token account owned by
+    /// the destination. For contributors whose ATA is frozen or has a delegate.
+    pub fn claim_to_new_ata(ctx: Context<ClaimToNewAta>) -> Result<()> {
+        let accounts = ctx.accounts;
+        settle_claim(
+            &mut accounts.presale,
+            &mut accounts.profile,
+            &accounts.contributor,
+            &accounts.token_mint,
+            &accounts.presale_vault,
+            &accounts.recipient,
+            &accounts.token_program,
+        )
+    }
+
+    /// Close a settled contributor profile and return its rent to the contributor.
+    pub fn close_profile(_ctx: Context<CloseProfile>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Admin-only: withdraw SOL from the pool to the receiver address once the sale was
+    /// finalized as successful. The protocol fee goes to the launchpad treasury.
+    pub fn admin_withdraw(ctx: Context<AdminWithdraw>, amount: u64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.require_succeeded()?;
+
+        // Oversubscribed fair launches keep the excess not yet refunded.
+        let reserved = presale
+            .oversubscription()?
+            .saturating_sub(presale.overflow_refunded);
+
+        let available = amount.min(presale.get_lamports());
+        let rent = Rent::get()?;
+        let min_balance = rent
+            .minimum_balance(8 + PresalePool::INIT_SPACE)
+            .checked_add(reserved)
+            .ok_or(IcoError::MathOverflow)?;
+        require!(available > min_balance, IcoError::NothingToWithdraw);
+
+        let withdraw_amount = available - min_balance;
+        let protocol_fee = presale.protocol_fee(withdraw_amount)?;
+
+        presale.sub_lamports(withdraw_amount)?;
+        ctx.accounts
+            .funds_receiver
+            .add_lamports(withdraw_amount - protocol_fee)?;
+        if protocol_fee > 0 {
+            ctx.accounts.treasury.add_lamports(protocol_fee)?;
+        }
+        presale.funds_withdrawn = true;
+
+        emit!(AdminWithdrawn {
+            presale: presale.key(),
+            funds_receiver: ctx.accounts.funds_receiver.key(),
+            payment_mint: None,
+            amount: withdraw_amount,
+            protocol_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: emergency withdraw of tokens from the vault before the sale starts, or
+    /// of all sale tokens once the sale was cancelled.
+    pub fn emergency_withdraw_token(
+        ctx: Context<EmergencyWithdrawToken>,
+        amount: u64,
+    ) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        require!(
+            now < presale.start_timestamp || presale.outcome == SaleOutcome::Cancelled,
+            IcoError::EmergencyWithdrawOnlyBeforeStart
+        );
+
+        let actual_amount = amount.min(ctx.accounts.presale_vault.amount);
+        require!(actual_amount > 0, IcoError::NothingToWithdraw);
+
+        let signer_seeds: &[&[u8]] =
+            &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
+        let signers = &[signer_seeds];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.presale_vault.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.receiver_ata.to_account_info(),
+            authority: ctx.accounts.presale.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signers,
+        );
+        token_interface::transfer_checked(
+            cpi_ctx,
+            actual_amount,
+            ctx.accounts.token_mint.decimals,
+        )?;
+
+        // The sale share changes, so checked tokenomics balances no longer hold
+        let presale = &mut ctx.accounts.presale;
+        presale.tokenomics_balances_checked = false;
+        let from_sale = actual_amount.min(presale.tokens_for_sale);
+        presale.tokens_for_sale -= from_sale;
+        presale.referral_allocation = presale
+            .referral_allocation
+            .saturating_sub(actual_amount - from_sale);
+
+        emit!(EmergencyTokensWithdrawn {
+            presale: presale.key(),
+            receiver: ctx.accounts.receiver.key(),
+            amount: actual_amount,
+            tokens_for_sale: presale.tokens_for_sale,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: take back the tokens the vault holds beyond what contributors and
+    /// referrers can still claim, once the sale ended and was finalized. Unsold tokens of a
+    /// successful sale, all of them for a failed one.
+    pub fn withdraw_unsold_tokens(ctx: Context<WithdrawUnsoldTokens>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &ctx.accounts.presale;
+
+        require!(now > presale.end_timestamp, IcoError::SaleNotEnded);
+        require!(
+            presale.outcome != SaleOutcome::Pending,
+            IcoError::SaleNotFinalized
+        );
+
+        let decimals = ctx.accounts.token_mint.decimals;
+        let tokens_owed = presale.tokens_owed(decimals)?;
+        let amount = ctx.accounts.presale_vault.amount.saturating_sub(tokens_owed);
+        require!(amount > 0, IcoError::NothingToWithdraw);
+
+        let signer_seeds: &[&[u8]] =
+            &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
+        let signers = &[signer_seeds];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.presale_vault.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.authority_ata.to_account_info(),
+            authority: presale.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signers,
+        );
+        token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
+
+        emit!(UnsoldTokensWithdrawn {
+            presale: presale.key(),
+            amount,
+            tokens_owed,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: after the claim deadline, reclaim what contributors left unclaimed. Tokens
+    /// still owed by a successful sale go to the authority's ATA; SOL refunds of a failed or
+    /// cancelled sale go to the authority, as does the fair-launch excess nobody claimed, and
+    /// refunds in payment tokens become withdrawable with `admin_withdraw_spl` /
+    /// `admin_withdraw_mint`. Claims and refunds end here.
+    pub fn sweep_unclaimed(ctx: Context<SweepUnclaimed>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            presale.outcome != SaleOutcome::Pending,
+            IcoError::SaleNotFinalized
+        );
+        presale.require_claim_open()?;
+        let deadline = presale
+            .claim_deadline
+            .ok_or(IcoError::ClaimDeadlineNotReached)?;
+        require!(now > deadline, IcoError::ClaimDeadlineNotReached);
+
+        let decimals = ctx.accounts.token_mint.decimals;
+        let tokens = presale
+            .tokens_owed(decimals)?
+            .min(ctx.accounts.presale_vault.amount);
+        if tokens > 0 {
+            let signer_seeds: &[&[u8]] =
+                &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
+            let signers = &[signer_seeds];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.presale_vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.authority_ata.to_account_info(),
+                authority: presale.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                signers,
+            );
+            token_interface::transfer_checked(cpi_ctx, tokens, decimals)?;
+        }
+
+        // Only unrefunded SOL contributions sit above the rent of a refunding pool. A
+        // succeeded fair launch still holds the excess owed to contributors who never
+        // claimed; releasing it lifts the reserve `admin_withdraw` keeps back.
+        let rent = Rent::get()?.minimum_balance(8 + PresalePool::INIT_SPACE);
+        let above_rent = presale.get_lamports().saturating_sub(rent);
+        let lamports = if presale.is_refunding() {
+            above_rent
+        } else {
+            let oversubscription = presale.oversubscription()?;
+            let unrefunded = oversubscription.saturating_sub(presale.overflow_refunded);
+            presale.overflow_refunded = oversubscription;
+            unrefunded.min(above_rent)
+        };
+        if lamports > 0 {
+            presale.sub_lamports(lamports)?;
+            ctx.accounts.authority.add_lamports(lamports)?;
+        }
+
+        presale.unclaimed_swept = true;
+
+        emit!(UnclaimedSwept {
+            presale: presale.key(),
+            tokens,
+            lamports,
+        });
+
+        Ok(())
+    }
+
+    /// Admin-only: close the vault, the payment vault if any, and the presale PDA once every
+    /// contribution was claimed or refunded, or swept after the claim deadline, and the raised
+    /// funds were withdrawn. Leftover sale tokens go to the authority's ATA, rent to the
+    /// authority. Vaults of accepted mints are passed in remaining accounts as
+    /// (vault, token program) pairs.
+    pub fn close_presale<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClosePresale<'info>>,
+    ) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        require!(
+            presale.outcome != SaleOutcome::Pending,
+            IcoError::SaleNotFinalized
+        );
+        let settled = presale
+            .claimed_contributions
+            .checked_add(presale.refunded_contributions)
+            .ok_or(IcoError::MathOverflow)?;
+        require!(
+            (settled == presale.total_contributions || presale.unclaimed_swept)
+                && presale.tokens_owed(ctx.accounts.token_mint.decimals)? == 0,
+            IcoError::ClaimsOutstanding
+        );
+
+        // Raised SOL must have gone to the receiver; SPL raises must have left the vault.
+        match (presale.payment_mint, &ctx.accounts.payment_vault) {
+            (None, None) => {
+                let rent = Rent::get()?.minimum_balance(8 + PresalePool::INIT_SPACE);
+                require!(
+                    presale.outcome != SaleOutcome::Succeeded || presale.get_lamports() <= rent,
+                    IcoError::FundsNotWithdrawn
+                );
+            }
+            (Some(_), Some(payment_vault)) => {
+                require!(payment_vault.amount == 0, IcoError::FundsNotWithdrawn);
+            }
+            _ => return err!(IcoError::InvalidPaymentMint),
+        }
+
+        let signer_seeds: &[&[u8]] =
+            &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
+        let signers = &[signer_seeds];
+        let token_program = ctx.accounts.token_program.to_account_info();
+
+        let leftover = ctx.accounts.presale_vault.amount;
+        if leftover > 0 {
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.presale_vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.authority_ata.to_account_info(),
+                authority: presale.to_account_info(),
+            };
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signers),
+                leftover,
+                ctx.accounts.token_mint.decimals,
+            )?;
+        }
+
+        let mut vaults = vec![(ctx.accounts.presale_vault.to_account_info(), token_program)];
+        if let Some(payment_vault) = &ctx.accounts.payment_vault {
+            let payment_token_program = ctx
+                .accounts
+                .payment_token_program
+                .as_ref()
+                .ok_or(IcoError::InvalidPaymentMint)?;
+            vaults.push((
+                payment_vault.to_account_info(),
+                payment_token_program.to_account_info(),
+            ));
+        }
+        require!(
+            ctx.remaining_accounts.len() == presale.accepted_mints.len() * 2,
+            IcoError::InvalidAcceptedMintVaults
+        );
+        for (accepted, pair) in presale
+            .accepted_mints
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(2))
+        {
+            let (vault_key, _) = Pubkey::find_program_address(
+                &[b"mint-vault", presale.key().as_ref(), accepted.mint.as_ref()],
+                &crate::ID,
+            );
+            require!(
+                pair[0].key() == vault_key && pair[1].key() == *pair[0].owner,
+                IcoError::InvalidAcceptedMintVaults
+            );
+            let vault = InterfaceAccount::<TokenAccount>::try_from(&pair[0])?;
+            require!(vault.amount == 0, IcoError::FundsNotWithdrawn);
+            vaults.push((pair[0].clone(), pair[1].clone()));
+        }
+        for (vault, program) in vaults {
+            let cpi_accounts = CloseAccount {
+                account: vault,
+                destination: ctx.accounts.authority.to_account_info(),
+                authority: presale.to_account_info(),
+            };
+            token_interface::close_account(CpiContext::new_with_signer(
+                program,
+                cpi_accounts,
+                signers,
+            ))?;
+        }
+
+        emit!(PresaleClosed {
+            presale: presale.key(),
+            leftover_tokens: leftover,
+        });
+
+        Ok(())
+    }
+
+    /// Read-only snapshot of the sale for front-ends, returned via return data.
+    /// Pass the contributor profile to also get the user's remaining allowance.
+    pub fn get_sale_state(ctx: Context<GetSaleState>) -> Result<SaleState> {
+        let presale = &ctx.accounts.presale;
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        let status = if presale.outcome == SaleOutcome::Succeeded {
+            SaleStatus::Succeeded
+        } else if presale.outcome == SaleOutcome::Failed {
+            SaleStatus::Failed
+        } else if presale.outcome == SaleOutcome::Cancelled {
+            SaleStatus::Cancelled
+        } else if now < presale.start_timestamp {
+            SaleStatus::Upcoming
+        } else if now <= presale.end_timestamp && !presale.hard_cap_filled() {
+            SaleStatus::Active
+        } else if presale.total_contributions >= presale.soft_cap {
+            SaleStatus::Succeeded
+        } else {
+            SaleStatus::Failed
+        };
+
+        let seconds_until_start = presale.start_timestamp.saturating_sub(now).max(0);
+        let seconds_remaining = presale.end_timestamp.saturating_sub(now).max(0);
+
+        // Percentage of the hard cap filled, in basis points.
+        let hard_cap_filled_bps = (presale.total_contributions as u128)
+            .checked_mul(10_000)
+            .ok_or(IcoError::MathOverflow)?
+            .checked_div(presale.hard_cap as u128)
+            .ok_or(IcoError::MathOverflow)?
+            .min(10_000) as u16;
+
+        let active_round = presale.active_round(now);
+        let mut cap_room = presale.cap_room();
+        if let Some(index) = active_round {
+            let round = &presale.rounds[index];
+            cap_room = cap_room.min(round.cap.saturating_sub(round.raised));
+        }
+        // Tiers and staking allocations can raise the pool maximum, a whitelisted allocation
+        // only lowers it, so the highest of them bounds what any profile may still add.
+        let max_contribution = presale
+            .tiers
+            .iter()
+            .map(|tier| tier.max_contribution)
+            .chain(
+                presale
+                    .staking_allocations
+                    .iter()
+                    .map(|allocation| allocation.max_contribution),
+            )
+            .fold(presale.max_contribution, u64::max);
+        let remaining_allowance = ctx.accounts.profile.as_ref().map(|profile| {
+            let mut allowance = max_contribution.saturating_sub(profile.contributed);
+            if let Some(index) = active_round {
+                allowance = allowance.min(
+                    presale.rounds[index]
+                        .max_contribution
+                        .saturating_sub(profile.round_contributions[index]),
+                );
+            }
+            allowance.min(cap_room)
+        });
+
+        // Tokens in the vault not yet owed to contributors; nothing is claimed before
+        // finalization, afterwards claims and referral bonuses are accounted for.
+        let decimals = ctx.accounts.token_mint.decimals;
+        let tokens_owed = if presale.outcome == SaleOutcome::Pending {
+            presale.tokens_sold(decimals)?
+        } else {
+            presale.tokens_owed(decimals)?
+        };
+        let tokens_remaining = ctx
+            .accounts
+            .presale_vault
+            .amount
+            .saturating_sub(tokens_owed);
+
+        Ok(SaleState {
+            status,
+            seconds_until_start,
+            seconds_remaining,
+            total_contributions: presale.total_contributions,
+            hard_cap: presale.hard_cap,
+            hard_cap_filled_bps,
+            remaining_allowance,
+            tokens_remaining,
+            tokenomics_balances_checked: presale.tokenomics_balances_checked,
+            tokenomics_verified: presale.tokenomics_verified,
+            active_round: active_round.map(|index| index as u8),
+            finalized: presale.outcome != SaleOutcome::Pending,
+            paused: presale.paused,
+        })
+    }
+
+    /// Admin-only: declare how the token supply is split, in basis points, and which token
+    /// accounts (lockers, vesting vaults, ...) hold the non-sale shares. Only before the sale
+    /// starts; declaring again clears an earlier balance check.
+    pub fn declare_tokenomics(
+        ctx: Context<DeclareTokenomics>,
+        allocation: TokenomicsAllocation,
+        liquidity_account: Pubkey,
+        team_account: Pubkey,
+        treasury_account: Pubkey,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::TokenomicsOnlyBeforeStart
+        );
+        require!(
+            allocation.total_bps() == 10_000,
+            IcoError::InvalidTokenomics
+        );
+
+        let tokenomics = &mut ctx.accounts.tokenomics;
+        tokenomics.presale = presale.key();
+        tokenomics.allocation = allocation;
+        tokenomics.liquidity_account = liquidity_account;
+        tokenomics.team_account = team_account;
+        tokenomics.treasury_account = treasury_account;
+        tokenomics.bump = ctx.bumps.tokenomics;
+
+        presale.tokenomics_balances_checked = false;
+
+        Ok(())
+    }
+
+    /// Permissionless: check the balances of the sale vault and the declared accounts against
+    /// the declared split of the mint supply, within `TOKENOMICS_TOLERANCE_BPS`, and mark the
+    /// presale as checked. Runs before the sale starts, while the vault still holds the whole
+    /// sale share. Only balances are checked: whether the declared accounts are locked, and
+    /// for how long, is left to the buyer.
+    pub fn check_tokenomics_balances(ctx: Context<CheckTokenomicsBalances>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            now < presale.start_timestamp,
+            IcoError::TokenomicsOnlyBeforeStart
+        );
+
+        let allocation = &ctx.accounts.tokenomics.allocation;
+        let supply = ctx.accounts.token_mint.supply;
+        let sale_amount = ctx.accounts.presale_vault.amount;
+        let liquidity_amount = ctx.accounts.liquidity_account.amount;
+        let team_amount = ctx.accounts.team_account.amount;
+        let treasury_amount = ctx.accounts.treasury_account.amount;
+
+        check_allocation(sale_amount, supply, allocation.sale_bps)?;
+        check_allocation(liquidity_amount, supply, allocation.liquidity_bps)?;
+        check_allocation(team_amount, supply, allocation.team_bps)?;
+        check_allocation(treasury_amount, supply, allocation.treasury_bps)?;
+
+        presale.tokenomics_balances_checked = true;
+
+        emit!(TokenomicsBalancesChecked {
+            presale: presale.key(),
+            supply,
+            sale_amount,
+            liquidity_amount,
+            team_amount,
+            treasury_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Grow a presale created before the current layout to it. Settings added since start
+    /// unset and counters at zero; the token decimals are read from the mint. The payer
+    /// covers the extra rent.
+    pub fn migrate_presale(ctx: Context<MigratePresale>) -> Result<()> {
+        let presale_info = ctx.accounts.presale.to_account_info();
+        grow_account(
+            &presale_info,
+            8 + PresalePool::INIT_SPACE,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+        )?;
+
+        // Deserializing checks the discriminator
+        let mut data = presale_info.try_borrow_mut_data()?;
+        let mut presale = PresalePool::try_deserialize(&mut &data[..])?;
+        require!(
+            presale.version < PresalePool::VERSION,
+            IcoError::AccountUpToDate
+        );
+        require_keys_eq!(
+            ctx.accounts.token_mint.key(),
+            presale.token_mint,
+            ErrorCode::ConstraintAddress
+        );
+
+        presale.token_decimals = ctx.accounts.token_mint.decimals;
+        presale.version = PresalePool::VERSION;
+        presale.try_serialize(&mut &mut data[..])?;
+
+        Ok(())
+    }
+
+    /// Grow a contributor profile created before the current layout to it. Fields added
+    /// since start unset and at zero. The payer covers the extra rent.
+    pub fn migrate_contributor_profile(ctx: Context<MigrateContributorProfile>) -> Result<()> {
+        let profile_info = ctx.accounts.profile.to_account_info();
+        grow_account(
+            &profile_info,
+            8 + ContributorProfile::INIT_SPACE,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+        )?;
+
+        // Deserializing checks the discriminator
+        let mut data = profile_info.try_borrow_mut_data()?;
+        let mut profile = ContributorProfile::try_deserialize(&mut &data[..])?;
+        require!(
+            profile.version < ContributorProfile::VERSION,
+            IcoError::AccountUpToDate
+        );
+
+        profile.version = ContributorProfile::VERSION;
+        profile.try_serialize(&mut &mut data[..])?;
+
+        Ok(())
+    }
+
+    /// Create the launchpad config. Can only happen once, by the program's upgrade
+    /// authority, which becomes admin.
+    pub fn initialize_launchpad_config(
+        ctx: Context<InitializeLaunchpadConfig>,
+        fee_bps: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        require!(
+            fee_bps <= MAX_PROTOCOL_FEE_BPS,
+            IcoError::ProtocolFeeTooHigh
+        );
+        require!(
+            fee_bps == 0 || treasury != Pubkey::default(),
+            IcoError::MissingFeeTreasury
+        );
+
+        let config = &mut ctx.accounts.launchpad_config;
+        config.admin = ctx.accounts.admin.key();
+        config.pending_admin = None;
+        config.treasury = treasury;
+        config.fee_bps = fee_bps;
+        config.bump = ctx.bumps.launchpad_config;
+
+        emit!(LaunchpadConfigUpdated {
+            admin: config.admin,
+            treasury,
+            fee_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Launchpad admin only: update the fee and treasury. Existing presales keep the fee
+    /// they were created with, but pay it to the new treasury.
+    pub fn update_launchpad_config(
+        ctx: Context<UpdateLaunchpadConfig>,
+        fee_bps: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        require!(
+            fee_bps <= MAX_PROTOCOL_FEE_BPS,
+            IcoError::ProtocolFeeTooHigh
+        );
+        require!(
+            fee_bps == 0 || treasury != Pubkey::default(),
+            IcoError::MissingFeeTreasury
+        );
+
+        let config = &mut ctx.accounts.launchpad_config;
+        config.treasury = treasury;
+        config.fee_bps = fee_bps;
+
+        emit!(LaunchpadConfigUpdated {
+            admin: config.admin,
+            treasury,
+            fee_bps,
+        });
+
+        Ok(())
+    }
+
+    /// First step of a launchpad admin handover: nominate the new admin.
+    pub fn transfer_launchpad_admin(
+        ctx: Context<UpdateLaunchpadConfig>,
+        new_admin: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.launchpad_config.pending_admin = Some(new_admin);
+
+        Ok(())
+    }
+
+    /// Second step of a launchpad admin handover: the nominated admin accepts.
+    pub fn accept_launchpad_admin(ctx: Context<AcceptLaunchpadAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.launchpad_config;
+        require!(
+            config.pending_admin == Some(ctx.accounts.new_admin.key()),
+            IcoError::NotPendingAdmin
+        );
+
+        config.admin = ctx.accounts.new_admin.key();
+        config.pending_admin = None;
+
+        emit!(LaunchpadConfigUpdated {
+            admin: config.admin,
+            treasury: config.treasury,
+            fee_bps: config.fee_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Dev builds only: shift the clock seen by the program by `offset` seconds so QA can
+    /// move through sale windows. Only the program's upgrade authority.
+    pub fn set_dev_clock(ctx: Context<SetDevClock>, offset: i64) -> Result<()> {
+        require!(cfg!(feature = "dev-clock"), IcoError::DevClockDisabled);
+
+        let dev_clock = &mut ctx.accounts.dev_clock;
+        dev_clock.authority = ctx.accounts.authority.key();
+        dev_clock.offset = offset;
+        dev_clock.bump = ctx.bumps.dev_clock;
+        Ok(())
+    }
+}
+
+/// Seed of the dev clock PDA read by `current_timestamp` in `dev-clock` builds.
+pub const DEV_CLOCK_SEED: &[u8] = b"dev-clock";
+
+/// `vesting::current_timestamp`, reading this program's dev clock.
+pub fn current_timestamp(accounts: &[AccountInfo]) -> Result<i64> {
+    vesting::program_timestamp(&crate::ID, accounts)
+}
+
+/// Transfer fee configuration of a Token-2022 mint, if it has one.
+/// Grow `account` to `space` bytes, the payer topping up its rent. The new bytes read as
+/// zero, which decodes as unset options, empty lists and zero counters.
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    space: usize,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    if account.data_len() >= space {
+        return Ok(());
+    }
+
+    let rent = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if rent > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: account.clone(),
+                },
+            ),
+            rent,
+        )?;
+    }
+    account.resize(space)?;
+
+    Ok(())
+}
+
+fn transfer_fee_config(mint: &InterfaceAccount<Mint>) -> Result<Option<TransferFeeConfig>> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != anchor_spl::token_2022::ID {
+        return Ok(None);
+    }
+    let data = mint_info.try_borrow_data()?;
+    let state = StateWithExtensions::<MintState>::unpack(&data)?;
+    Ok(state.get_extension::<TransferFeeConfig>().ok().copied())
+}
+
+/// Fee withheld by the mint when moving `amount` in the current epoch; zero for mints
+/// without a transfer fee.
+fn transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    match transfer_fee_config(mint)? {
+        Some(config) => Ok(config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(IcoError::MathOverflow)?),
+        None => Ok(0),
+    }
+}
+
+/// Pyth Solana receiver program, owner of `PriceUpdateV2` accounts.
+pub const PYTH_RECEIVER_ID: Pubkey =
+    pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+/// Anchor discriminator of Pyth's `PriceUpdateV2` account.
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+/// Lamports per full token for a USD-priced sale, read from a Pyth `PriceUpdateV2` account.
+/// Only fully verified updates of the configured feed, fresh and precise enough, are used.
+fn usd_price_lamports(pricing: &UsdPricing, price_update: &AccountInfo, now: i64) -> Result<u64> {
+    require_keys_eq!(
+        *price_update.owner,
+        PYTH_RECEIVER_ID,
+        IcoError::InvalidOraclePrice
+    );
+    let data = price_update.try_borrow_data()?;
+
+    // discriminator, write authority, verification level (1 = full), then the message:
+    // feed id, price, conf, exponent, publish time.
+    require!(
+        data.len() >= 101
+            && data[..8] == PRICE_UPDATE_V2_DISCRIMINATOR
+            && data[40] == 1
+            && data[41..73] == pricing.price_feed_id,
+        IcoError::InvalidOraclePrice
+    );
+    let read_8 = |at: usize| -> [u8; 8] { data[at..at + 8].try_into().unwrap() };
+    let price = i64::from_le_bytes(read_8(73));
+    let conf = u64::from_le_bytes(read_8(81));
+    let exponent = i32::from_le_bytes(data[89..93].try_into().unwrap());
+    let publish_time = i64::from_le_bytes(read_8(93));
+
+    require!(price > 0, IcoError::InvalidOraclePrice);
+    require!(
+        now.saturating_sub(publish_time) <= pricing.max_price_age as i64,
+        IcoError::StaleOraclePrice
+    );
+    require!(
+        (conf as u128) * 10_000 <= (price as u128) * pricing.max_confidence_bps as u128,
+        IcoError::OraclePriceTooUncertain
+    );
+
+    // SOL price is price * 10^exponent USD, the token price token_price_usd * 10^-6 USD:
+    // lamports = token_price_usd * 10^3 / (price * 10^exponent).
+    let scale = 10u128
+        .checked_pow(exponent.unsigned_abs())
+        .ok_or(IcoError::MathOverflow)?;
+    let mut numerator = (pricing.token_price_usd as u128)
+        .checked_mul(1_000)
+        .ok_or(IcoError::MathOverflow)?;
+    let mut denominator = price as u128;
+    if exponent < 0 {
+        numerator = numerator.checked_mul(scale).ok_or(IcoError::MathOverflow)?;
+    } else {
+        denominator = denominator.checked_mul(scale).ok_or(IcoError::MathOverflow)?;
+    }
+
+    let price_lamports =
+        u64::try_from(numerator / denominator).map_err(|_| IcoError::MathOverflow)?;
+    require!(price_lamports > 0, IcoError::InvalidPrice);
+    Ok(price_lamports)
+}
+
+/// Tolerance of the tokenomics check, in basis points of the mint supply.
+pub const TOKENOMICS_TOLERANCE_BPS: u64 = 50;
+
+/// Fails unless `amount` is within `TOKENOMICS_TOLERANCE_BPS` of `bps` of `supply`.
+fn check_allocation(amount: u64, supply: u64, bps: u16) -> Result<()> {
+    let expected = (supply as u128)
+        .checked_mul(bps as u128)
+        .ok_or(IcoError::MathOverflow)?
+        / 10_000;
+    let tolerance = (supply as u128)
+        .checked_mul(TOKENOMICS_TOLERANCE_BPS as u128)
+        .ok_or(IcoError::MathOverflow)?
+        / 10_000;
+
+    require!(
+        (amount as u128).abs_diff(expected) <= tolerance,
+        IcoError::TokenomicsMismatch
+    );
+    Ok(())
+}
+
+/// Leaf of the whitelist merkle tree: sha256(contributor || allocation as u64 LE), with the
+/// tier byte appended for tiered wallets.
+pub fn whitelist_leaf(contributor: &Pubkey, allocation: u64, tier: Option<u8>) -> [u8; 32] {
+    let allocation = allocation.to_le_bytes();
+    match tier {
+        Some(tier) => hashv(&[contributor.as_ref(), allocation.as_ref(), &[tier]]).to_bytes(),
+        None => hashv(&[contributor.as_ref(), allocation.as_ref()]).to_bytes(),
+    }
+}
+
+/// Message the KYC authority signs to attest a contributor: contributor || presale.
+pub fn kyc_message(contributor: &Pubkey, presale: &Pubkey) -> [u8; 64] {
+    let mut message = [0u8; 64];
+    message[..32].copy_from_slice(contributor.as_ref());
+    message[32..].copy_from_slice(presale.as_ref());
+    message
+}
+
+/// Size of the Ed25519 program's signature offsets record.
+const ED25519_OFFSETS_LEN: usize = 14;
+
+/// For KYC-gated sales, check that the instruction before the current one is an Ed25519
+/// program verification of the KYC authority's signature over `kyc_message`. The signature
+/// itself is checked by the Ed25519 program, which fails the transaction if it is invalid.
+fn verify_kyc(
+    presale: &Account<PresalePool>,
+    contributor: &Pubkey,
+    instructions_sysvar: Option<&UncheckedAccount>,
+) -> Result<()> {
+    let Some(kyc_authority) = presale.kyc_authority else {
+        return Ok(());
+    };
+    let instructions_sysvar = instructions_sysvar.ok_or(IcoError::KycRequired)?;
+
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    require!(current_index > 0, IcoError::KycRequired);
+    let ix = load_instruction_at_checked(current_index as usize - 1, instructions_sysvar)?;
+    require_keys_eq!(ix.program_id, ed25519_program::ID, IcoError::KycRequired);
+
+    // One signature whose public key and message are stored in the instruction itself.
+    let data = &ix.data;
+    require!(
+        data.len() >= 2 + ED25519_OFFSETS_LEN && data[0] == 1,
+        IcoError::InvalidKycAttestation
+    );
+    let read_u16 = |at: usize| u16::from_le_bytes([data[2 + at], data[2 + at + 1]]);
+    let (public_key_offset, message_offset, message_size) =
+        (read_u16(4) as usize, read_u16(8) as usize, read_u16(10) as usize);
+    require!(
+        [read_u16(2), read_u16(6), read_u16(12)]
+            .iter()
+            .all(|index| *index == u16::MAX),
+        IcoError::InvalidKycAttestation
+    );
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(IcoError::InvalidKycAttestation)?;
+    let message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(IcoError::InvalidKycAttestation)?;
+    require!(
+        public_key == kyc_authority.as_ref()
+            && message == kyc_message(contributor, &presale.key()).as_slice(),
+        IcoError::InvalidKycAttestation
+    );
+
+    Ok(())
+}
+
+/// Whether `proof` links `leaf` to `root`. Pairs are hashed in sorted order, so the proof
+/// needs no left/right flags.
+fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            hashv(&[node.as_ref(), sibling.as_ref()]).to_bytes()
+        } else {
+            hashv(&[sibling.as_ref(), node.as_ref()]).to_bytes()
+        }
+    });
+    computed == *root
+}
+
+/// Shared contribution logic. With `clip_to_hard_cap` the accepted amount is limited to the
+/// room left under the hard cap and the clipped part is reported in the event.
+fn process_contribution(
+    ctx: Context<Contribute>,
+    amount: u64,
+    whitelist: Option<WhitelistProof>,
+    clip_to_hard_cap: bool,
+) -> Result<()> {
+    let now = current_timestamp(ctx.remaining_accounts)?;
+    let presale = &mut ctx.accounts.presale;
+    let contributor = &ctx.accounts.contributor;
+
+    require!(
+        presale.payment_mint.is_none(),
+        IcoError::SplPaymentRequired
+    );
+    verify_kyc(
+        presale,
+        &contributor.key(),
+        ctx.accounts.instructions_sysvar.as_ref(),
+    )?;
+    let oracle_price_lamports = match &presale.usd_pricing {
+        Some(pricing) => {
+            let price_update = ctx
+                .accounts
+                .price_update
+                .as_ref()
+                .ok_or(IcoError::OraclePriceRequired)?;
+            Some(usd_price_lamports(pricing, price_update, now)?)
+        }
+        None => None,
+    };
+
+    let amount = record_contribution(
+        presale,
+        &mut ctx.accounts.profile,
+        contributor.key(),
+        ctx.bumps.profile,
+        now,
+        amount,
+        whitelist,
+        ctx.accounts.contributor_tier.as_ref().map(|tier| tier.tier),
+        ctx.accounts.deposit_counter.as_ref().map(|counter| counter.staked),
+        ctx.accounts.referral.as_mut(),
+        oracle_price_lamports,
+        clip_to_hard_cap,
+    )?;
+
+    // Transfer SOL from contributor to the presale pool PDA.
+    let cpi_ctx = CpiContext::new(
+        ctx.accounts.system_program.to_account_info(),
+        system_program::Transfer {
+            from: contributor.to_account_info(),
+            to: presale.to_account_info(),
+        },
+    );
+    system_program::transfer(cpi_ctx, amount)?;
+
+    Ok(())
+}
+
+/// Checks a contribution against the sale rules, books it on the pool and the profile and
+/// emits `Contributed`. Returns the accepted amount, which the caller must collect.
+#[allow(clippy::too_many_arguments)]
+fn record_contribution(
+    presale: &mut Account<PresalePool>,
+    profile: &mut Account<ContributorProfile>,
+    contributor: Pubkey,
+    profile_bump: u8,
+    now: i64,
+    amount: u64,
+    whitelist: Option<WhitelistProof>,
+    assigned_tier: Option<u8>,
+    staked: Option<u64>,
+    referral: Option<&mut Account<ReferralProfile>>,
+    oracle_price_lamports: Option<u64>,
+    clip_to_hard_cap: bool,
+) -> Result<u64> {
+    require!(
+        presale.outcome == SaleOutcome::Pending,
+        IcoError::SaleFinalized
+    );
+    require!(!presale.paused, IcoError::SalePaused);
+
+    // Time window checks.
+    require!(
+        now >= presale.start_timestamp,
+        IcoError::SaleNotStartedYet
+    );
+    require!(now <= presale.end_timestamp, IcoError::SaleEnded);
+
+    // Allowlist check; a whitelisted allocation lowers the per-user maximum.
+    let mut allocation = 0;
+    let mut tier = assigned_tier;
+    if let Some(root) = presale.whitelist_root {
+        let whitelist = whitelist.ok_or(IcoError::NotWhitelisted)?;
+        let leaf = whitelist_leaf(&contributor, whitelist.allocation, whitelist.tier);
+        require!(
+            verify_merkle_proof(&whitelist.proof, &root, leaf),
+            IcoError::NotWhitelisted
+        );
+        allocation = whitelist.allocation;
+        // A tier proven in the allowlist wins over an assigned one.
+        tier = whitelist.tier.or(tier);
+    }
+
+    // Tiered wallets use their tier's limits instead of the pool's.
+    let (min_contribution, mut max_contribution) = match tier {
+        Some(tier) => {
+            let limits = presale
+                .tiers
+                .get(tier as usize)
+                .ok_or(IcoError::InvalidTier)?;
+            (limits.min_contribution, limits.max_contribution)
+        }
+        None => (presale.min_contribution, presale.max_contribution),
+    };
+    // Stakers may contribute more; a whitelisted allocation still caps them.
+    if let Some(staking_max) = staked.and_then(|staked| presale.staking_allocation(staked)) {
+        max_contribution = max_contribution.max(staking_max);
+    }
+    if allocation > 0 {
+        max_contribution = max_contribution.min(allocation);
+    }
+
+    // Multi-round sales only accept contributions inside a round.
+    let round_index = presale.active_round(now);
+    require!(
+        presale.rounds.is_empty() || round_index.is_some(),
+        IcoError::NoActiveSaleRound
+    );
+
+    // Min / max contribution checks.
+    require!(
+        amount >= min_contribution,
+        IcoError::ContributionBelowMinimum
+    );
+
+    // Clip to the room left under the hard cap (and the round cap) when requested.
+    let requested_amount = amount;
+    let amount = if clip_to_hard_cap {
+        let mut cap_room = presale.cap_room();
+        if let Some(index) = round_index {
+            let round = &presale.rounds[index];
+            cap_room = cap_room.min(round.cap.saturating_sub(round.raised));
+        }
+        require!(cap_room > 0, IcoError::HardcapExceeded);
+        amount.min(cap_room)
+    } else {
+        amount
+    };
+    let clipped_amount = requested_amount - amount;
+
+    let new_contribution = profile
+        .contributed
+        .checked_add(amount)
+        .ok_or(IcoError::MathOverflow)?;
+    require!(
+        new_contribution <= max_contribution,
+        IcoError::ContributionAboveMaximum
+    );
+
+    let new_total = presale
+        .total_contributions
+        .checked_add(amount)
+        .ok_or(IcoError::MathOverflow)?;
+    require!(
+        presale.fair_launch || new_total <= presale.hard_cap,
+        IcoError::HardcapExceeded
+    );
+
+    if let Some(index) = round_index {
+        let round = &mut presale.rounds[index];
+        let new_round_contribution = profile.round_contributions[index]
+            .checked_add(amount)
+            .ok_or(IcoError::MathOverflow)?;
+        require!(
+            new_round_contribution <= round.max_contribution,
+            IcoError::ContributionAboveMaximum
+        );
+        let new_raised = round.raised.checked_add(amount).ok_or(IcoError::MathOverflow)?;
+        require!(new_raised <= round.cap, IcoError::SaleRoundCapExceeded);
+
+        round.raised = new_raised;
+        profile.round_contributions[index] = new_round_contribution;
+    }
+
+    // Dutch auctions and USD-priced sales book the tokens at the price of the moment.
+    let price_lamports =
+        oracle_price_lamports.unwrap_or_else(|| presale.price_at(now, round_index));
+    if presale.prices_each_contribution() {
+        let tokens = tokens_at_price(amount, price_lamports, presale.token_decimals)?;
+        if profile.auction_tokens == 0 {
+            profile.entry_price_lamports = price_lamports;
+        }
+        profile.auction_tokens = profile
+            .auction_tokens
+            .checked_add(tokens)
+            .ok_or(IcoError::MathOverflow)?;
+        presale.auction_tokens_sold = presale
+            .auction_tokens_sold
+            .checked_add(tokens)
+            .ok_or(IcoError::MathOverflow)?;
+        // Prices falling below hard cap / sale tokens, or an oracle price without a floor,
+        // would sell more than the vault holds, so the sale tokens bound every booking.
+        require!(
+            presale.auction_tokens_sold <= presale.tokens_for_sale,
+            IcoError::NotEnoughTokensInVault
+        );
+    }
+
+    // Initialize profile on first contribution.
+    if profile.contributed == 0 {
+        profile.presale = presale.key();
+        profile.contributor = contributor;
+        profile.bump = profile_bump;
+        profile.claimed = false;
+        profile.referrer = None;
+        profile.version = ContributorProfile::VERSION;
+    }
+
+    // A contributor is credited to one referrer, fixed by the first referred contribution.
+    if let Some(referral) = referral {
+        require!(
+            referral.referrer != contributor,
+            IcoError::SelfReferral
+        );
+        require!(
+            profile.referrer.unwrap_or(referral.referrer) == referral.referrer,
+            IcoError::ReferrerMismatch
+        );
+        profile.referrer = Some(referral.referrer);
+        referral.referred_volume = referral
+            .referred_volume
+            .checked_add(amount)
+            .ok_or(IcoError::MathOverflow)?;
+        presale.referred_volume = presale
+            .referred_volume
+            .checked_add(amount)
+            .ok_or(IcoError::MathOverflow)?;
+    }
+
+    profile.contributed = new_contribution;
+    presale.total_contributions = new_total;
+
+    emit!(Contributed {
+        presale: presale.key(),
+        contributor,
+        amount,
+        clipped_amount,
+        round: round_index.map(|index| index as u8),
+        price_lamports,
+    });
+
+    Ok(amount)
+}
+
+/// Refund the contribution if the soft cap was missed, otherwise send the bought tokens to
+/// `recipient`.
+fn settle_claim<'info>(
+    presale: &mut Account<'info, PresalePool>,
+    profile: &mut Account<'info, ContributorProfile>,
+    contributor: &Signer<'info>,
+    token_mint: &InterfaceAccount<'info, Mint>,
+    presale_vault: &InterfaceAccount<'info, TokenAccount>,
+    recipient: &InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    require!(!profile.claimed, IcoError::AlreadyClaimed);
+    let contributed = profile.contributed;
+    require!(contributed > 0, IcoError::NothingToClaim);
+    require!(
+        presale.outcome != SaleOutcome::Pending,
+        IcoError::SaleNotFinalized
+    );
+    presale.require_claim_open()?;
+
+    // If the sale failed or was cancelled, refund SOL.
+    if presale.is_refunding() {
+        require!(
+            presale.payment_mint.is_none(),
+            IcoError::SplPaymentRequired
+        );
+
+        // Payments in accepted mints are refunded by `refund_with_mint`.
+        let refund = profile.sol_contributed();
+
+        // Move lamports directly from the presale PDA to the contributor.
+        // This avoids needing the presale PDA to sign a system_program::transfer CPI.
+        **presale.to_account_info().try_borrow_mut_lamports()? -= refund;
+        **contributor
+            .to_account_info()
+            .try_borrow_mut_lamports()? += refund;
+
+        profile.claimed = true;
+        presale.refunded_contributions = presale
+            .refunded_contributions
+            .checked_add(refund)
+            .ok_or(IcoError::MathOverflow)?;
+
+        emit!(Refunded {
+            presale: presale.key(),
+            contributor: contributor.key(),
+            amount: refund,
+        });
+
+        return Ok(());
+    }
+
+    // Successful sale: send tokens.
+    require!(
+        presale.claim_vesting.is_none(),
+        IcoError::VestedClaimRequired
+    );
+    let tokens_to_send = presale.tokens_bought(profile, token_mint.decimals)?;
+
+    require!(tokens_to_send > 0, IcoError::NothingToClaim);
+
+    // Surface recipient problems before the token program fails with a generic error
+    require!(!recipient.is_frozen(), IcoError::RecipientAccountFrozen);
+    require!(
+        recipient.delegate.is_none(),
+        IcoError::RecipientAccountDelegated
+    );
+    require!(
+        presale_vault.amount >= tokens_to_send,
+        IcoError::NotEnoughTokensInVault
+    );
+
+    let signer_seeds: &[&[u8]] =
+        &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
+    let signers = &[signer_seeds];
+
+    let cpi_accounts = TransferChecked {
+        from: presale_vault.to_account_info(),
+        mint: token_mint.to_account_info(),
+        to: recipient.to_account_info(),
+        authority: presale.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        cpi_accounts,
+        signers,
+    );
+    token_interface::transfer_checked(cpi_ctx, tokens_to_send, token_mint.decimals)?;
+    let received = tokens_to_send - transfer_fee(token_mint, tokens_to_send)?;
+
+    profile.claimed = true;
+    presale.claimed_contributions = presale
+        .claimed_contributions
+        .checked_add(contributed)
+        .ok_or(IcoError::MathOverflow)?;
+    presale.tokens_claimed = presale
+        .tokens_claimed
+        .checked_add(tokens_to_send)
+        .ok_or(IcoError::MathOverflow)?;
+
+    let excess_refund = refund_excess(presale, &contributor.to_account_info(), contributed)?;
+
+    emit!(TokensClaimed {
+        presale: presale.key(),
+        contributor: contributor.key(),
+        contribution: contributed,
+        amount: tokens_to_send,
+        received,
+        excess_refund,
+    });
+
+    Ok(())
+}
+
+/// Sends `amount` out of a vault owned by the presale PDA.
+fn transfer_from_vault<'info>(
+    presale: &Account<'info, PresalePool>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[u8]] = &[b"state", &presale.pool_id.to_le_bytes(), &[presale.bump]];
+    let cpi_accounts = TransferChecked {
+        from: vault.to_account_info(),
+        mint: mint.to_account_info(),
+        to: to.to_account_info(),
+        authority: presale.to_account_info(),
+    };
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, &[signer_seeds]),
+        amount,
+        mint.decimals,
+    )
+}
+
+/// Pays back the part of `contributed` not used by an oversubscribed fair launch.
+/// Returns the refunded lamports.
+fn refund_excess(
+    presale: &mut Account<PresalePool>,
+    contributor: &AccountInfo,
+    contributed: u64,
+) -> Result<u64> {
+    let excess = presale.excess_refund(contributed)?;
+    if excess > 0 {
+        presale.sub_lamports(excess)?;
+        contributor.add_lamports(excess)?;
+        presale.overflow_refunded = presale
+            .overflow_refunded
+            .checked_add(excess)
+            .ok_or(IcoError::MathOverflow)?;
+    }
+    Ok(excess)
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PresalePool {
+    /// Admin / creator of the pool.
+    pub authority: Pubkey,
+    /// SPL token mint sold in this presale.
+    pub token_mint: Pubkey,
+    /// Where SOL goes if the soft cap is reached.
+    pub funds_receiver: Pubkey,
+
+    /// Minimum total raised for the sale to be valid (lamports).
+    pub soft_cap: u64,
+    /// Maximum total raised (lamports).
+    pub hard_cap: u64,
+
+    /// Min / max contribution per user (lamports).
+    pub min_contribution: u64,
+    pub max_contribution: u64,
+
+    /// Sale window.
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+
+    /// Total SOL contributed so far (lamports).
+    pub total_contributions: u64,
+
+    /// Price in lamports per full token (10^decimals units).
+    pub token_price_lamports: u64,
+
+    /// Pool id used in PDA derivation.
+    pub pool_id: u64,
+    /// PDA bump.
+    pub bump: u8,
+
+    /// Set by `check_tokenomics_balances`, cleared whenever the sale inventory changes.
+    pub tokenomics_balances_checked: bool,
+
+    /// Merkle root of the contribution allowlist; anyone may contribute when unset.
+    pub whitelist_root: Option<[u8; 32]>,
+
+    /// Sale rounds with their own prices; empty for a single-price sale.
+    #[max_len(MAX_SALE_ROUNDS)]
+    pub rounds: Vec<SaleRound>,
+
+    /// SPL token the sale is paid in; SOL when unset. When set, all prices and
+    /// contribution amounts are in its base units instead of lamports.
+    pub payment_mint: Option<Pubkey>,
+
+    /// Vesting applied to claimed tokens; paid out at once when unset.
+    pub claim_vesting: Option<ClaimVesting>,
+
+    /// Result of the sale, set by `finalize`.
+    pub outcome: SaleOutcome,
+    /// Tokens (base units) sold per lamport at finalization, scaled by `RATE_PRECISION`.
+    /// An average over the rounds in multi-round sales.
+    pub final_rate: u64,
+
+    /// Set by the first admin withdrawal; the sale can no longer be cancelled.
+    pub funds_withdrawn: bool,
+    /// Contributions already settled in tokens (claimed or vested).
+    pub claimed_contributions: u64,
+
+    /// Decimals of `token_mint`.
+    pub token_decimals: u8,
+    /// Falling price replacing `token_price_lamports` when set.
+    pub dutch_auction: Option<DutchAuction>,
+    /// Tokens booked by dutch auction or USD-priced contributions.
+    pub auction_tokens_sold: u64,
+
+    /// Tokens deposited for sale, shared pro-rata in an oversubscribed fair launch.
+    pub tokens_for_sale: u64,
+    /// No hard cap, allocations scaled down when oversubscribed, see `set_fair_launch`.
+    pub fair_launch: bool,
+    /// Excess lamports already refunded by fair launch claims.
+    pub overflow_refunded: u64,
+
+    /// Contribution limits per tier, indexed by tier number.
+    #[max_len(MAX_TIERS)]
+    pub tiers: Vec<TierLimits>,
+
+    /// Referral bonus in basis points of the tokens bought by referred contributors.
+    pub referral_bonus_bps: u16,
+    /// Tokens deposited in the vault for referral bonuses, on top of the sale tokens.
+    pub referral_allocation: u64,
+    /// Referral bonus tokens already paid out.
+    pub referral_bonus_paid: u64,
+    /// Total contributed by referred contributors (lamports).
+    pub referred_volume: u64,
+
+    /// Sale tokens already sent to contributors or their vesting schedules.
+    pub tokens_claimed: u64,
+    /// Contributions already refunded.
+    pub refunded_contributions: u64,
+
+    /// Contributions are rejected while set, see `set_paused`.
+    pub paused: bool,
+
+    /// Signer of the KYC attestations `contribute` requires when set, see `set_kyc_authority`.
+    pub kyc_authority: Option<Pubkey>,
+
+    /// USD price replacing `token_price_lamports` when set, see `set_usd_pricing`.
+    pub usd_pricing: Option<UsdPricing>,
+
+    /// SPL mints accepted next to SOL, see `add_accepted_mint`.
+    #[max_len(MAX_ACCEPTED_MINTS)]
+    pub accepted_mints: Vec<AcceptedMint>,
+
+    /// Maximum contributions unlocked by staking, see `set_staking_allocations`.
+    #[max_len(MAX_STAKING_ALLOCATIONS)]
+    pub staking_allocations: Vec<StakingAllocation>,
+
+    /// End of the claim window, see `set_claim_deadline`.
+    pub claim_deadline: Option<i64>,
+    /// Set by `sweep_unclaimed`; no claims or refunds afterwards.
+    pub unclaimed_swept: bool,
+
+    /// Launchpad fee on the raise, in basis points, taken from `LaunchpadConfig` at creation.
+    pub protocol_fee_bps: u16,
+
+    /// Authority nominated by `nominate_authority`, until they accept.
+    pub pending_authority: Option<Pubkey>,
+
+    /// Whether the tokenomics balances were still checked when the sale was finalized.
+    pub tokenomics_verified: bool,
+
+    /// Staking pool whose stake unlocks `staking_allocations`.
+    pub staking_pool: Pubkey,
+
+    /// Layout version, see `migrate_presale`.
+    pub version: u8,
+}
+
+/// Most SPL mints a presale can accept next to SOL.
+pub const MAX_ACCEPTED_MINTS: usize = 3;
+
+/// SPL mint accepted next to SOL, valued in lamports at a fixed rate, see `add_accepted_mint`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct AcceptedMint {
+    pub mint: Pubkey,
+    /// Decimals of `mint`.
+    pub decimals: u8,
+    /// Lamports one full token of `mint` counts for.
+    pub rate_lamports: u64,
+    /// Base units of `mint` contributed and not refunded.
+    pub raised: u64,
+}
+
+impl AcceptedMint {
+    /// Lamports `amount` base units count for: amount * rate_lamports / 10^decimals.
+    pub fn lamports_value(&self, amount: u64) -> Result<u64> {
+        let value = (amount as u128)
+            .checked_mul(self.rate_lamports as u128)
+            .ok_or(IcoError::MathOverflow)?
+            / 10u128.pow(self.decimals as u32);
+        Ok(u64::try_from(value).map_err(|_| IcoError::MathOverflow)?)
+    }
+
+    /// Base units of `mint` worth `lamports`, rounded up.
+    pub fn amount_for_lamports(&self, lamports: u64) -> Result<u64> {
+        let amount = (lamports as u128)
+            .checked_mul(10u128.pow(self.decimals as u32))
+            .ok_or(IcoError::MathOverflow)?
+            .div_ceil(self.rate_lamports as u128);
+        Ok(u64::try_from(amount).map_err(|_| IcoError::MathOverflow)?)
+    }
+}
+
+/// USD pricing, see `set_usd_pricing`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct UsdPricing {
+    /// Price of one full token in micro-USD (10^-6 USD).
+    pub token_price_usd: u64,
+    /// Pyth feed id of SOL/USD.
+    pub price_feed_id: [u8; 32],
+    /// Oldest accepted oracle price, in seconds.
+    pub max_price_age: u64,
+    /// Widest accepted confidence interval, in basis points of the price.
+    pub max_confidence_bps: u16,
+}
+
+/// Dutch auction pricing, see `set_dutch_auction`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct DutchAuction {
+    /// Price per full token at the start of the sale.
+    pub start_price_lamports: u64,
+    /// Price reached at the end of the sale.
+    pub floor_price_lamports: u64,
+    /// Seconds between price drops; 0 decays continuously.
+    pub step_duration: i64,
+}
+
+impl DutchAuction {
+    /// Price at `now` for a sale running from `start` to `end`.
+    pub fn price_at(&self, start: i64, end: i64, now: i64) -> u64 {
+        let duration = end.saturating_sub(start).max(1);
+        let mut elapsed = now.saturating_sub(start).clamp(0, duration);
+        if self.step_duration > 0 {
+            elapsed -= elapsed % self.step_duration;
+        }
+
+        let drop = (self.start_price_lamports - self.floor_price_lamports) as u128
+            * elapsed as u128
+            / duration as u128;
+        self.start_price_lamports - drop as u64
+    }
+}
+
+/// Scale of `PresalePool::final_rate`.
+pub const RATE_PRECISION: u128 = 1_000_000_000;
+
+/// Result of a presale recorded by `finalize`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum SaleOutcome {
+    /// Not finalized yet.
+    Pending,
+    /// Soft cap reached: tokens are claimable and funds withdrawable.
+    Succeeded,
+    /// Soft cap missed: contributions are refunded.
+    Failed,
+    /// Cancelled by the authority: contributions are refunded.
+    Cancelled,
+}
+
+/// Vesting schedule created for each contributor by `claim_vested`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct ClaimVesting {
+    /// Seconds after the sale end before the first unlock.
+    pub cliff_duration: i64,
+    /// Seconds between unlocks.
+    pub interval_duration: i64,
+    /// Share of the tokens unlocked per interval (1-100).
+    pub unlock_percentage: u8,
+}
+
+/// Most rounds a presale can be split into.
+pub const MAX_SALE_ROUNDS: usize = 4;
+
+/// One round of a multi-round sale, see `set_sale_rounds`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct SaleRound {
+    /// Price in lamports per full token during the round.
+    pub token_price_lamports: u64,
+    /// Most lamports the round may raise.
+    pub cap: u64,
+    /// Most lamports one wallet may contribute in the round.
+    pub max_contribution: u64,
+    /// Round window, inside the sale window.
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+    /// Lamports raised in the round so far, ignored when the round is set.
+    pub raised: u64,
+}
+
+impl PresalePool {
+    /// Current layout version.
+    pub const VERSION: u8 = 1;
+
+    /// Tokens (base units) bought with `lamports` at the pool price.
+    pub fn tokens_for_lamports(&self, lamports: u64, decimals: u8) -> Result<u64> {
+        tokens_at_price(lamports, self.token_price_lamports, decimals)
+    }
+
+    /// Index of the round open at `now`, if any.
+    pub fn active_round(&self, now: i64) -> Option<usize> {
+        self.rounds
+            .iter()
+            .position(|round| round.start_timestamp <= now && now <= round.end_timestamp)
+    }
+
+    /// Whether the hard cap stops further contributions; never in a fair launch.
+    pub fn hard_cap_filled(&self) -> bool {
+        !self.fair_launch && self.total_contributions >= self.hard_cap
+    }
+
+    /// Contributions still accepted under the hard cap.
+    pub fn cap_room(&self) -> u64 {
+        if self.fair_launch {
+            u64::MAX
+        } else {
+            self.hard_cap.saturating_sub(self.total_contributions)
+        }
+    }
+
+    /// Lamports buying all of `tokens_for_sale` at the pool price.
+    pub fn sale_target(&self) -> Result<u64> {
+        let ten_pow_decimals = 10u128
+            .checked_pow(self.token_decimals as u32)
+            .ok_or(IcoError::MathOverflow)?;
+        let target = (self.tokens_for_sale as u128)
+            .checked_mul(self.token_price_lamports as u128)
+            .ok_or(IcoError::MathOverflow)?
+            / ten_pow_decimals;
+        Ok(u64::try_from(target).unwrap_or(u64::MAX))
+    }
+
+    /// Lamports raised above the sale target in a fair launch, refunded pro-rata.
+    pub fn oversubscription(&self) -> Result<u64> {
+        if !self.fair_launch {
+            return Ok(0);
+        }
+        Ok(self.total_contributions.saturating_sub(self.sale_target()?))
+    }
+
+    /// Part of `contributed` refunded by an oversubscribed fair launch. Rounded down, so
+    /// the refunds never exceed the oversubscription.
+    pub fn excess_refund(&self, contributed: u64) -> Result<u64> {
+        if self.oversubscription()? == 0 {
+            return Ok(0);
+        }
+        let used = (contributed as u128)
+            .checked_mul(self.sale_target()? as u128)
+            .ok_or(IcoError::MathOverflow)?
+            .div_ceil(self.total_contributions as u128);
+        Ok(contributed.saturating_sub(used as u64))
+    }
+
+    /// Tokens owed to `profile`, at the round prices in a multi-round sale.
+    pub fn tokens_bought(&self, profile: &ContributorProfile, decimals: u8) -> Result<u64> {
+        if self.prices_each_contribution() {
+            return Ok(profile.auction_tokens);
+        }
+        if self.oversubscription()? > 0 {
+            let share = (profile.contributed as u128)
+                .checked_mul(self.tokens_for_sale as u128)
+                .ok_or(IcoError::MathOverflow)?
+                / self.total_contributions as u128;
+            return Ok(share as u64);
+        }
+        if self.rounds.is_empty() {
+            return self.tokens_for_lamports(profile.contributed, decimals);
+        }
+        self.rounds
+            .iter()
+            .zip(profile.round_contributions.iter())
+            .try_fold(0u64, |total, (round, contributed)| {
+                let tokens = tokens_at_price(*contributed, round.token_price_lamports, decimals)?;
+                Ok(total.checked_add(tokens).ok_or(IcoError::MathOverflow)?)
+            })
+    }
+
+    /// Tokens owed to all contributors so far.
+    pub fn tokens_sold(&self, decimals: u8) -> Result<u64> {
+        if self.prices_each_contribution() {
+            return Ok(self.auction_tokens_sold);
+        }
+        if self.oversubscription()? > 0 {
+            return Ok(self.tokens_for_sale);
+        }
+        if self.rounds.is_empty() {
+            return self.tokens_for_lamports(self.total_contributions, decimals);
+        }
+        self.rounds.iter().try_fold(0u64, |total, round| {
+            let tokens = tokens_at_price(round.raised, round.token_price_lamports, decimals)?;
+            Ok(total.checked_add(tokens).ok_or(IcoError::MathOverflow)?)
+        })
+    }
+
+    /// Index of `mint` in `accepted_mints`.
+    pub fn accepted_mint_index(&self, mint: &Pubkey) -> Result<usize> {
+        self.accepted_mints
+            .iter()
+            .position(|accepted| accepted.mint == *mint)
+            .ok_or_else(|| error!(IcoError::InvalidPaymentMint))
+    }
+
+    /// Whether every contribution books its tokens at its own price: dutch auctions and
+    /// USD-priced sales.
+    pub fn prices_each_contribution(&self) -> bool {
+        self.dutch_auction.is_some() || self.usd_pricing.is_some()
+    }
+
+    /// Price per full token paid at `now`: the active round's, the dutch auction's or the
+    /// pool price.
+    pub fn price_at(&self, now: i64, round_index: Option<usize>) -> u64 {
+        if let Some(index) = round_index {
+            return self.rounds[index].token_price_lamports;
+        }
+        match &self.dutch_auction {
+            Some(auction) => auction.price_at(self.start_timestamp, self.end_timestamp, now),
+            None => self.token_price_lamports,
+        }
+    }
+
+    /// Maximum contribution unlocked by `staked` tokens, if any threshold is reached.
+    pub fn staking_allocation(&self, staked: u64) -> Option<u64> {
+        self.staking_allocations
+            .iter()
+            .rev()
+            .find(|allocation| staked >= allocation.min_staked)
+            .map(|allocation| allocation.max_contribution)
+    }
+
+    /// Referral bonus earned on `referred_volume` at the average sale rate, so every pricing
+    /// mode is covered.
+    pub fn referral_bonus(&self, referred_volume: u64) -> Result<u64> {
+        let referred_tokens = (referred_volume as u128)
+            .checked_mul(self.final_rate as u128)
+            .ok_or(IcoError::MathOverflow)?
+            / RATE_PRECISION;
+        let bonus = referred_tokens
+            .checked_mul(self.referral_bonus_bps as u128)
+            .ok_or(IcoError::MathOverflow)?
+            / 10_000;
+        Ok(u64::try_from(bonus).map_err(|_| IcoError::MathOverflow)?)
+    }
+
+    /// Tokens the vault still owes to contributors and referrers; nothing unless the sale
+    /// succeeded and the unclaimed tokens were not swept.
+    pub fn tokens_owed(&self, decimals: u8) -> Result<u64> {
+        if self.outcome != SaleOutcome::Succeeded || self.unclaimed_swept {
+            return Ok(0);
+        }
+        let unclaimed = self.tokens_sold(decimals)?.saturating_sub(self.tokens_claimed);
+        let bonus_owed = self
+            .referral_bonus(self.referred_volume)?
+            .saturating_sub(self.referral_bonus_paid)
+            .min(self.referral_bonus_left());
+        Ok(unclaimed
+            .checked_add(bonus_owed)
+            .ok_or(IcoError::MathOverflow)?)
+    }
+
+    /// Referral bonus tokens still in the vault.
+    pub fn referral_bonus_left(&self) -> u64 {
+        self.referral_allocation
+            .saturating_sub(self.referral_bonus_paid)
+    }
+
+    /// Whether claims are refunds: the sale failed or was cancelled.
+    pub fn is_refunding(&self) -> bool {
+        matches!(self.outcome, SaleOutcome::Failed | SaleOutcome::Cancelled)
+    }
+
+    /// Fails unless the sale was finalized as successful.
+    pub fn require_succeeded(&self) -> Result<()> {
+        match self.outcome {
+            SaleOutcome::Succeeded => Ok(()),
+            SaleOutcome::Pending => err!(IcoError::SaleNotFinalized),
+            SaleOutcome::Failed => err!(IcoError::SoftcapNotReached),
+            SaleOutcome::Cancelled => err!(IcoError::SaleCancelled),
+        }
+    }
+
+    /// Fails once unclaimed tokens and refunds were swept after the claim deadline.
+    pub fn require_claim_open(&self) -> Result<()> {
+        require!(!self.unclaimed_swept, IcoError::ClaimWindowClosed);
+        Ok(())
+    }
+
+    /// Fails unless raised funds can leave the vaults: the sale succeeded, or it was
+    /// refunding and the refunds left unclaimed were swept.
+    pub fn require_withdrawable(&self) -> Result<()> {
+        if self.is_refunding() && self.unclaimed_swept {
+            return Ok(());
+        }
+        self.require_succeeded()
+    }
+
+    /// Launchpad fee on withdrawing `amount` of the raise. Swept refunds are not a raise and
+    /// pay no fee.
+    pub fn protocol_fee(&self, amount: u64) -> Result<u64> {
+        if self.outcome != SaleOutcome::Succeeded {
+            return Ok(0);
+        }
+        let fee = (amount as u128)
+            .checked_mul(self.protocol_fee_bps as u128)
+            .ok_or(IcoError::MathOverflow)?
+            / 10_000;
+        Ok(fee as u64)
+    }
+
+    /// Fails unless contributions are being refunded.
+    pub fn require_refunding(&self) -> Result<()> {
+        match self.outcome {
+            SaleOutcome::Failed | SaleOutcome::Cancelled => Ok(()),
+            SaleOutcome::Pending => err!(IcoError::SaleNotFinalized),
+            SaleOutcome::Succeeded => err!(IcoError::SoftcapReached),
+        }
+    }
+
+    /// Most tokens the sale can sell: the hard cap at the pool price, or every round filled
+    /// to its cap at the round price.
+    pub fn max_tokens_for_sale(&self, decimals: u8) -> Result<u64> {
+        if self.fair_launch || self.usd_pricing.is_some() {
+            return Ok(self.tokens_for_sale);
+        }
+        if let Some(auction) = &self.dutch_auction {
+            return tokens_at_price(self.hard_cap, auction.floor_price_lamports, decimals);
+        }
+        if self.rounds.is_empty() {
+            return self.tokens_for_lamports(self.hard_cap, decimals);
+        }
+        self.rounds.iter().try_fold(0u64, |total, round| {
+            let tokens = tokens_at_price(
+                round.cap.min(self.hard_cap),
+                round.token_price_lamports,
+                decimals,
+            )?;
+            Ok(total.checked_add(tokens).ok_or(IcoError::MathOverflow)?)
+        })
+    }
+}
+
+/// Tokens (base units) bought with `lamports` at `price_lamports` per full token:
+/// lamports * 10^decimals / price_lamports.
+fn tokens_at_price(lamports: u64, price_lamports: u64, decimals: u8) -> Result<u64> {
+    require!(price_lamports > 0, IcoError::InvalidPrice);
+
+    let ten_pow_decimals = 10u64
+        .checked_pow(decimals as u32)
+        .ok_or(IcoError::MathOverflow)?;
+    let numerator = lamports
+        .checked_mul(ten_pow_decimals)
+        .ok_or(IcoError::MathOverflow)?;
+    let tokens = numerator
+        .checked_div(price_lamports)
+        .ok_or(IcoError::MathOverflow)?;
+
+    Ok(tokens)
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ContributorProfile {
+    /// Presale this profile belongs to.
+    pub presale: Pubkey,
+    /// Contributor address.
+    pub contributor: Pubkey,
+    /// Total contributed SOL (lamports).
+    pub contributed: u64,
+    /// Whether claim/refund has already been made.
+    pub claimed: bool,
+    /// PDA bump.
+    pub bump: u8,
+    /// Wallet whose ATA receives claimed tokens, the contributor's when unset.
+    pub claim_destination: Option<Pubkey>,
+    /// Lamports contributed in each sale round.
+    pub round_contributions: [u64; MAX_SALE_ROUNDS],
+    /// Dutch auction price of the first contribution.
+    pub entry_price_lamports: u64,
+    /// Tokens bought in a dutch auction or USD-priced sale, each contribution at its own
+    /// price.
+    pub auction_tokens: u64,
+    /// Referrer credited with this contributor's volume.
+    pub referrer: Option<Pubkey>,
+    /// Base units paid in each accepted mint, cleared by its refund.
+    pub mint_contributions: [u64; MAX_ACCEPTED_MINTS],
+    /// Lamport value of the payments in each accepted mint, part of `contributed`.
+    pub mint_values: [u64; MAX_ACCEPTED_MINTS],
+    /// Layout version, see `migrate_contributor_profile`.
+    pub version: u8,
+}
+
+impl ContributorProfile {
+    /// Current layout version.
+    pub const VERSION: u8 = 1;
+
+    /// Part of `contributed` paid in SOL.
+    pub fn sol_contributed(&self) -> u64 {
+        self.contributed - self.mint_values.iter().sum::<u64>()
+    }
+}
+
+/// Proof that a contributor is in the presale allowlist.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct WhitelistProof {
+    /// Per-wallet cap committed in the leaf, in lamports; 0 keeps the pool maximum.
+    pub allocation: u64,
+    /// Tier committed in the leaf, see `set_tiers`.
+    pub tier: Option<u8>,
+    /// Sibling hashes from the leaf up to the root.
+    pub proof: Vec<[u8; 32]>,
+}
+
+/// Most contribution tiers a presale can define.
+pub const MAX_TIERS: usize = 4;
+
+/// Most staking thresholds a presale can define.
+pub const MAX_STAKING_ALLOCATIONS: usize = 4;
+
+/// Maximum contribution unlocked by staking at least `min_staked` tokens.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct StakingAllocation {
+    pub min_staked: u64,
+    pub max_contribution: u64,
+}
+
+/// Contribution limits of one tier (e.g. bronze / silver / gold), in lamports.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct TierLimits {
+    pub min_contribution: u64,
+    pub max_contribution: u64,
+}
+
+/// Tier assigned to a contributor by the authority, an alternative to proving it in the
+/// allowlist.
+#[account]
+#[derive(InitSpace)]
+pub struct ContributorTier {
+    /// Presale the tier applies to.
+    pub presale: Pubkey,
+    /// Contributor address.
+    pub contributor: Pubkey,
+    /// Index into `PresalePool::tiers`.
+    pub tier: u8,
+    /// PDA bump.
+    pub bump: u8,
+}
+
+/// Volume a referrer brought into a presale, see `register_referrer`.
+#[account]
+#[derive(InitSpace)]
+pub struct ReferralProfile {
+    /// Presale the referrals apply to.
+    pub presale: Pubkey,
+    /// Referrer address.
+    pub referrer: Pubkey,
+    /// Total contributed by referred contributors (lamports).
+    pub referred_volume: u64,
+    /// Whether the bonus has already been claimed.
+    pub bonus_claimed: bool,
+    /// PDA bump.
+    pub bump: u8,
+}
+
+/// Split of the token supply, in basis points summing to 10_000.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct TokenomicsAllocation {
+    pub sale_bps: u16,
+    pub liquidity_bps: u16,
+    pub team_bps: u16,
+    pub treasury_bps: u16,
+}
+
+impl TokenomicsAllocation {
+    pub fn total_bps(&self) -> u32 {
+        self.sale_bps as u32
+            + self.liquidity_bps as u32
+            + self.team_bps as u32
+            + self.treasury_bps as u32
+    }
+}
+
+/// Tokenomics declared for a presale, checked by `check_tokenomics_balances`.
+#[account]
+#[derive(InitSpace)]
+pub struct Tokenomics {
+    /// Presale this declaration belongs to.
+    pub presale: Pubkey,
+    /// Declared split of the mint supply.
+    pub allocation: TokenomicsAllocation,
+    /// Token accounts holding the locked / vested non-sale shares.
+    pub liquidity_account: Pubkey,
+    pub team_account: Pubkey,
+    pub treasury_account: Pubkey,
+    /// PDA bump.
+    pub bump: u8,
+}
+
+/// Highest launchpad fee on raises, 10%.
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000;
+
+/// Launchpad-wide settings, a single PDA.
+#[account]
+#[derive(InitSpace)]
+pub struct LaunchpadConfig {
+    /// Manages the config.
+    pub admin: Pubkey,
+    /// Admin nominated by `transfer_launchpad_admin`, until they accept.
+    pub pending_admin: Option<Pubkey>,
+    /// Receives the protocol fee.
+    pub treasury: Pubkey,
+    /// Fee on raises for presales created from now on, in basis points.
+    pub fee_bps: u16,
+    /// PDA bump.
+    pub bump: u8,
+}
+
+/// Clock offset used by `dev-clock` builds, see `current_timestamp`.
+#[account]
+#[derive(InitSpace)]
+pub struct DevClock {
+    /// Upgrade authority that last set the offset.
+    pub authority: Pubkey,
+    /// Seconds added to the sysvar clock.
+    pub offset: i64,
+    /// PDA bump.
     pub bump: u8,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct ContributorProfile {
-    /// Presale this profile belongs to.
-    pub presale: Pubkey,
-    /// Contributor address.
-    pub contributor: Pubkey,
-    /// Total contributed SOL (lamports).
-    pub contributed: u64,
-    /// Whether claim/refund has already been made.
-    pub claimed: bool,
-    /// PDA bump.
-    pub bump: u8,
+#[derive(Accounts)]
+#[instruction(pool_id: u64)]
+pub struct CreatePresalePool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PresalePool::INIT_SPACE,
+        seeds = [b"state".as_ref(), &pool_id.to_le_bytes()],
+        bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Pool admin.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// Where raised SOL will be sent on `admin_withdraw`.
+    /// CHECK: stored as a Pubkey in `PresalePool`.
+    #[account(mut)]
+    pub funds_receiver: AccountInfo<'info>,
+
+    /// SPL token mint being sold.
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// PDA token account that will hold sale tokens.
+    #[account(
+        init,
+        payer = authority,
+        token::mint = token_mint,
+        token::authority = presale,
+        token::token_program = token_program,
+        seeds = [b"vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Admin's token account from which tokens are deposited into the vault.
+    #[account(
+        mut,
+        constraint = authority_token_account.mint == token_mint.key(),
+        constraint = authority_token_account.owner == authority.key()
+    )]
+    pub authority_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Launchpad config, supplying the protocol fee.
+    #[account(seeds = [b"launchpad-config".as_ref()], bump = launchpad_config.bump)]
+    pub launchpad_config: Account<'info, LaunchpadConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositAdditionalTokens<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = token_mint,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale,
+        seeds = [b"vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Admin's token account the extra tokens are taken from.
+    #[account(
+        mut,
+        constraint = authority_token_account.mint == token_mint.key(),
+        constraint = authority_token_account.owner == authority.key()
+    )]
+    pub authority_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Contribute<'info> {
+    /// Contributor paying SOL.
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    /// Presale pool PDA.
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Contributor profile PDA, one per (contributor, presale).
+    #[account(
+        init_if_needed,
+        payer = contributor,
+        space = 8 + ContributorProfile::INIT_SPACE,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+
+    /// Tier assigned by the authority, if any.
+    #[account(
+        seeds = [
+            b"contributor-tier".as_ref(),
+            presale.key().as_ref(),
+            contributor.key().as_ref()
+        ],
+        bump = contributor_tier.bump
+    )]
+    pub contributor_tier: Option<Account<'info, ContributorTier>>,
+
+    /// Referral profile of the referrer, if any.
+    #[account(
+        mut,
+        seeds = [
+            b"referral".as_ref(),
+            presale.key().as_ref(),
+            referral.referrer.as_ref()
+        ],
+        bump = referral.bump
+    )]
+    pub referral: Option<Account<'info, ReferralProfile>>,
+
+    /// Contributor's deposit counter in `presale.staking_pool`, unlocking `staking_allocations`.
+    #[account(
+        seeds = [
+            b"deposit-counter".as_ref(),
+            contributor.key().as_ref(),
+            presale.staking_pool.as_ref()
+        ],
+        seeds::program = staking::ID,
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Option<Account<'info, DepositCounter>>,
+
+    /// CHECK: instructions sysvar, required by KYC-gated sales to read the attestation.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Pyth SOL/USD `PriceUpdateV2`, required by USD-priced sales; owner, layout
+    /// and feed are checked when the price is read.
+    pub price_update: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetKycAuthority<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetClaimDeadline<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct NominateAuthority<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Nominated authority.
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFundsReceiver<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetWhitelistRoot<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaymentMint<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// Token contributions are paid in.
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    /// PDA token account collecting the contributions.
+    #[account(
+        init,
+        payer = authority,
+        token::mint = payment_mint,
+        token::authority = presale,
+        token::token_program = token_program,
+        seeds = [b"payment-vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub payment_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddAcceptedMint<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// Token accepted next to SOL.
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    /// PDA token account collecting the contributions in `payment_mint`.
+    #[account(
+        init,
+        payer = authority,
+        token::mint = payment_mint,
+        token::authority = presale,
+        token::token_program = token_program,
+        seeds = [b"mint-vault".as_ref(), presale.key().as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub mint_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ContributeWithMint<'info> {
+    /// Contributor paying in an accepted mint.
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    /// Presale pool PDA.
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Contributor profile PDA, one per (contributor, presale).
+    #[account(
+        init_if_needed,
+        payer = contributor,
+        space = 8 + ContributorProfile::INIT_SPACE,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+
+    /// Tier assigned by the authority, if any.
+    #[account(
+        seeds = [
+            b"contributor-tier".as_ref(),
+            presale.key().as_ref(),
+            contributor.key().as_ref()
+        ],
+        bump = contributor_tier.bump
+    )]
+    pub contributor_tier: Option<Account<'info, ContributorTier>>,
+
+    /// Referral profile of the referrer, if any.
+    #[account(
+        mut,
+        seeds = [
+            b"referral".as_ref(),
+            presale.key().as_ref(),
+            referral.referrer.as_ref()
+        ],
+        bump = referral.bump
+    )]
+    pub referral: Option<Account<'info, ReferralProfile>>,
+
+    /// Contributor's deposit counter in `presale.staking_pool`, unlocking `staking_allocations`.
+    #[account(
+        seeds = [
+            b"deposit-counter".as_ref(),
+            contributor.key().as_ref(),
+            presale.staking_pool.as_ref()
+        ],
+        seeds::program = staking::ID,
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Option<Account<'info, DepositCounter>>,
+
+    /// CHECK: instructions sysvar, required by KYC-gated sales to read the attestation.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    /// Accepted mint paid in, checked against `presale.accepted_mints`.
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = presale,
+        seeds = [b"mint-vault".as_ref(), presale.key().as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub mint_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Contributor's token account the payment is taken from.
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = contributor
+    )]
+    pub contributor_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundWithMint<'info> {
+    pub contributor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump = profile.bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+
+    /// Accepted mint refunded, checked against `presale.accepted_mints`.
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = presale,
+        seeds = [b"mint-vault".as_ref(), presale.key().as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub mint_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Contributor's token account receiving the refund.
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = contributor
+    )]
+    pub contributor_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ContributeSpl<'info> {
+    /// Contributor paying the payment token.
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    /// Presale pool PDA.
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Contributor profile PDA, one per (contributor, presale).
+    #[account(
+        init_if_needed,
+        payer = contributor,
+        space = 8 + ContributorProfile::INIT_SPACE,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+
+    /// Tier assigned by the authority, if any.
+    #[account(
+        seeds = [
+            b"contributor-tier".as_ref(),
+            presale.key().as_ref(),
+            contributor.key().as_ref()
+        ],
+        bump = contributor_tier.bump
+    )]
+    pub contributor_tier: Option<Account<'info, ContributorTier>>,
+
+    /// Referral profile of the referrer, if any.
+    #[account(
+        mut,
+        seeds = [
+            b"referral".as_ref(),
+            presale.key().as_ref(),
+            referral.referrer.as_ref()
+        ],
+        bump = referral.bump
+    )]
+    pub referral: Option<Account<'info, ReferralProfile>>,
+
+    /// Contributor's deposit counter in `presale.staking_pool`, unlocking `staking_allocations`.
+    #[account(
+        seeds = [
+            b"deposit-counter".as_ref(),
+            contributor.key().as_ref(),
+            presale.staking_pool.as_ref()
+        ],
+        seeds::program = staking::ID,
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Option<Account<'info, DepositCounter>>,
+
+    /// CHECK: instructions sysvar, required by KYC-gated sales to read the attestation.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        constraint = presale.payment_mint == Some(payment_mint.key())
+            @ IcoError::InvalidPaymentMint
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = presale,
+        seeds = [b"payment-vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub payment_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Contributor's token account the payment is taken from.
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = contributor
+    )]
+    pub contributor_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundSpl<'info> {
+    pub contributor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump = profile.bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+
+    #[account(
+        constraint = presale.payment_mint == Some(payment_mint.key())
+            @ IcoError::InvalidPaymentMint
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = presale,
+        seeds = [b"payment-vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub payment_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Contributor's token account receiving the refund.
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = contributor
+    )]
+    pub contributor_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetClaimVesting<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = token_mint,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimVested<'info> {
+    /// Contributor claiming, pays the rent of the new schedule.
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump = profile.bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: beneficiary of the schedule, pinned to the profile's claim destination.
+    #[account(
+        address = profile.claim_destination.unwrap_or(contributor.key())
+            @ IcoError::InvalidClaimDestination
+    )]
+    pub destination: UncheckedAccount<'info>,
+
+    /// Creator of the contributor's schedule, one per contributor and presale.
+    #[account(
+        mut,
+        seeds = [
+            b"vesting-authority".as_ref(),
+            presale.key().as_ref(),
+            contributor.key().as_ref()
+        ],
+        bump
+    )]
+    pub vesting_authority: SystemAccount<'info>,
+
+    /// Stages the claimed tokens for the vesting program, closed once the schedule is funded.
+    #[account(
+        init,
+        payer = contributor,
+        token::mint = token_mint,
+        token::authority = vesting_authority,
+        token::token_program = token_program,
+        seeds = [
+            b"vesting-escrow".as_ref(),
+            presale.key().as_ref(),
+            contributor.key().as_ref()
+        ],
+        bump
+    )]
+    pub vesting_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: created and checked by the vesting program.
+    #[account(mut)]
+    pub vesting_schedule: UncheckedAccount<'info>,
+
+    /// CHECK: created and checked by the vesting program.
+    #[account(mut)]
+    pub vesting_vault: UncheckedAccount<'info>,
+
+    pub vesting_program: Program<'info, Vesting>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminWithdrawSpl<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = funds_receiver,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority of the pool.
+    pub authority: Signer<'info>,
+
+    /// CHECK: checked by `has_one = funds_receiver`, owner of the receiving token account.
+    pub funds_receiver: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = presale.payment_mint == Some(payment_mint.key())
+            @ IcoError::InvalidPaymentMint
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = presale,
+        seeds = [b"payment-vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub payment_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Funds receiver's token account for the payment token.
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = funds_receiver
+    )]
+    pub receiver_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"launchpad-config".as_ref()], bump = launchpad_config.bump)]
+    pub launchpad_config: Account<'info, LaunchpadConfig>,
+
+    /// Treasury's token account for the payment token, receiving the protocol fee.
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        constraint = treasury_payment_account.owner == launchpad_config.treasury
+            @ IcoError::InvalidTreasury
+    )]
+    pub treasury_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AdminWithdrawMint<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = funds_receiver,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority of the pool.
+    pub authority: Signer<'info>,
+
+    /// CHECK: checked by `has_one = funds_receiver`, owner of the receiving token account.
+    pub funds_receiver: UncheckedAccount<'info>,
+
+    /// Accepted mint withdrawn, checked against `presale.accepted_mints`.
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = presale,
+        seeds = [b"mint-vault".as_ref(), presale.key().as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub mint_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Funds receiver's token account for `payment_mint`.
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = funds_receiver
+    )]
+    pub receiver_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"launchpad-config".as_ref()], bump = launchpad_config.bump)]
+    pub launchpad_config: Account<'info, LaunchpadConfig>,
+
+    /// Treasury's token account for `payment_mint`, receiving the protocol fee.
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        constraint = treasury_payment_account.owner == launchpad_config.treasury
+            @ IcoError::InvalidTreasury
+    )]
+    pub treasury_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSchedule<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePrice<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDutchAuction<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetUsdPricing<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFairLaunch<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTiers<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetStakingAllocations<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Staking pool whose stake unlocks the allocations.
+    pub staking_pool: Account<'info, StakingPool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(contributor: Pubkey)]
+pub struct SetContributorTier<'info> {
+    #[account(
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + ContributorTier::INIT_SPACE,
+        seeds = [
+            b"contributor-tier".as_ref(),
+            presale.key().as_ref(),
+            contributor.as_ref()
+        ],
+        bump
+    )]
+    pub contributor_tier: Account<'info, ContributorTier>,
+
+    /// Admin / authority.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetReferralBonus<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = token_mint,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale,
+        seeds = [b"vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Admin's token account the bonus tokens are taken from.
+    #[account(
+        mut,
+        constraint = authority_token_account.mint == token_mint.key(),
+        constraint = authority_token_account.owner == authority.key()
+    )]
+    pub authority_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        init,
+        payer = referrer,
+        space = 8 + ReferralProfile::INIT_SPACE,
+        seeds = [
+            b"referral".as_ref(),
+            presale.key().as_ref(),
+            referrer.key().as_ref()
+        ],
+        bump
+    )]
+    pub referral: Account<'info, ReferralProfile>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralBonus<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        mut,
+        has_one = referrer,
+        seeds = [
+            b"referral".as_ref(),
+            presale.key().as_ref(),
+            referrer.key().as_ref()
+        ],
+        bump = referral.bump
+    )]
+    pub referral: Account<'info, ReferralProfile>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale,
+        seeds = [b"vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Referrer's ATA for the sale token.
+    #[account(
+        init_if_needed,
+        payer = referrer,
+        associated_token::mint = token_mint,
+        associated_token::authority = referrer,
+        associated_token::token_program = token_program
+    )]
+    pub referrer_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetSaleRounds<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPresale<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RefundBatch<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Finalize<'info> {
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct SetClaimDestination<'info> {
+    pub contributor: Signer<'info>,
+
+    #[account(
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump = profile.bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+}
+
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    /// Contributor receiving refund or tokens.
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump = profile.bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: wallet receiving the tokens, pinned to the profile's claim destination.
+    #[account(
+        address = profile.claim_destination.unwrap_or(contributor.key())
+            @ IcoError::InvalidClaimDestination
+    )]
+    pub destination: UncheckedAccount<'info>,
+
+    /// Destination's ATA for the sale token.
+    #[account(
+        init_if_needed,
+        payer = contributor,
+        associated_token::mint = token_mint,
+        associated_token::authority = destination,
+        associated_token::token_program = token_program
+    )]
+    pub contributor_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseProfile<'info> {
+    /// Contributor receiving the profile rent.
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    #[account(
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        mut,
+        close = contributor,
+        constraint = profile.claimed @ IcoError::ProfileNotSettled,
+        constraint = presale.outcome == SaleOutcome::Succeeded
+            || profile.mint_contributions.iter().all(|amount| *amount == 0)
+            @ IcoError::ProfileNotSettled,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump = profile.bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
 }
 
 #[derive(Accounts)]
-#[instruction(pool_id: u64)]
-pub struct CreatePresalePool<'info> {
+pub struct ClaimToNewAta<'info> {
+    /// Contributor receiving refund or tokens.
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"contributor-profile".as_ref(),
+            contributor.key().as_ref(),
+            presale.key().as_ref()
+        ],
+        bump = profile.bump
+    )]
+    pub profile: Account<'info, ContributorProfile>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: wallet receiving the tokens, pinned to the profile's claim destination.
+    #[account(
+        address = profile.claim_destination.unwrap_or(contributor.key())
+            @ IcoError::InvalidClaimDestination
+    )]
+    pub destination: UncheckedAccount<'info>,
+
+    /// New token account of the destination, replacing its unusable ATA.
     #[account(
         init,
-        payer = authority,
-        space = 8 + PresalePool::INIT_SPACE,
-        seeds = [b"state".as_ref(), &pool_id.to_le_bytes()],
-        bump
+        payer = contributor,
+        token::mint = token_mint,
+        token::authority = destination,
+        token::token_program = token_program
+    )]
+    pub recipient: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminWithdraw<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = funds_receiver,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
     )]
     pub presale: Account<'info, PresalePool>,
 
-    /// Pool admin.
-    #[account(mut)]
+    /// Admin / authority of the pool.
     pub authority: Signer<'info>,
 
-    /// Where raised SOL will be sent on `admin_withdraw`.
-    /// CHECK: stored as a Pubkey in `PresalePool`.
+    /// Destination for withdrawn SOL.
+    /// CHECK: checked by `has_one = funds_receiver`.
     #[account(mut)]
     pub funds_receiver: AccountInfo<'info>,
 
-    /// SPL token mint being sold.
-    pub token_mint: Account<'info, Mint>,
+    #[account(seeds = [b"launchpad-config".as_ref()], bump = launchpad_config.bump)]
+    pub launchpad_config: Account<'info, LaunchpadConfig>,
 
-    /// PDA token account that will hold sale tokens.
+    /// CHECK: launchpad treasury receiving the protocol fee, checked against the config.
+    #[account(mut, address = launchpad_config.treasury @ IcoError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawUnsoldTokens<'info> {
     #[account(
-        init,
+        has_one = authority,
+        has_one = token_mint,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale,
+        seeds = [b"vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Authority's ATA receiving the unsold tokens.
+    #[account(
+        init_if_needed,
         payer = authority,
+        associated_token::mint = token_mint,
+        associated_token::authority = authority,
+        associated_token::token_program = token_program
+    )]
+    pub authority_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepUnclaimed<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = token_mint,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority, receives the unclaimed SOL refunds.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
         token::mint = token_mint,
         token::authority = presale,
         seeds = [b"vault".as_ref(), presale.key().as_ref()],
         bump
     )]
-    pub presale_vault: Account<'info, TokenAccount>,
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// Admin's token account from which tokens are deposited into the vault.
+    /// Authority's ATA receiving the unclaimed tokens.
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = token_mint,
+        associated_token::authority = authority,
+        associated_token::token_program = token_program
+    )]
+    pub authority_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePresale<'info> {
     #[account(
         mut,
-        constraint = authority_token_account.mint == token_mint.key(),
-        constraint = authority_token_account.owner == authority.key()
+        close = authority,
+        has_one = authority,
+        has_one = token_mint,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority, receives the rent.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale,
+        seeds = [b"vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Payment vault, required for presales paid in an SPL token.
+    #[account(
+        mut,
+        token::authority = presale,
+        seeds = [b"payment-vault".as_ref(), presale.key().as_ref()],
+        bump
+    )]
+    pub payment_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program of the payment mint, passed with the payment vault.
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Authority's ATA receiving the leftover sale tokens.
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = token_mint,
+        associated_token::authority = authority,
+        associated_token::token_program = token_program
+    )]
+    pub authority_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyWithdrawToken<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = token_mint,
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    /// Admin / authority.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = presale
+    )]
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receiver of emergency-withdrawn tokens.
+    /// CHECK: arbitrary receiver, only its pubkey is used for ATA derivation.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = token_mint,
+        associated_token::authority = receiver,
+        associated_token::token_program = token_program
+    )]
+    pub receiver_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetSaleState<'info> {
+    #[account(
+        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, PresalePool>,
+
+    #[account(address = presale.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        token::mint = token_mint,
+        token::authority = presale
     )]
-    pub authority_token_account: Account<'info, TokenAccount>,
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    /// Optional contributor profile used to compute the remaining allowance.
+    #[account(constraint = profile.presale == presale.key())]
+    pub profile: Option<Account<'info, ContributorProfile>>,
 }
 
 #[derive(Accounts)]
-pub struct Contribute<'info> {
-    /// Contributor paying SOL.
-    #[account(mut)]
-    pub contributor: Signer<'info>,
-
-    /// Presale pool PDA.
+pub struct DeclareTokenomics<'info> {
     #[account(
         mut,
+        has_one = authority,
         seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
         bump = presale.bump
     )]
     pub presale: Account<'info, PresalePool>,
 
-    /// Contributor profile PDA, one per (contributor, presale).
     #[account(
         init_if_needed,
-        payer = contributor,
-        space = 8 + ContributorProfile::INIT_SPACE,
-        seeds = [
-            b"contributor-profile".as_ref(),
-            contributor.key().as_ref(),
-            presale.key().as_ref()
-        ],
+        payer = authority,
+        space = 8 + Tokenomics::INIT_SPACE,
+        seeds = [b"tokenomics".as_ref(), presale.key().as_ref()],
         bump
     )]
-    pub profile: Account<'info, ContributorProfile>,
+    pub tokenomics: Account<'info, Tokenomics>,
+
+    /// Admin / authority.
+    #[account(mut)]
+    pub authority: Signer<'info>,
 
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Claim<'info> {
-    /// Contributor receiving refund or tokens.
+pub struct MigratePresale<'info> {
+    /// CHECK: presale that may no longer deserialize as `PresalePool`; ownership is checked
+    /// here, the discriminator and layout in `migrate_presale`
+    #[account(mut, owner = crate::ID)]
+    pub presale: UncheckedAccount<'info>,
+
+    /// Mint sold by the presale, its decimals are recorded.
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
     #[account(mut)]
-    pub contributor: Signer<'info>,
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateContributorProfile<'info> {
+    /// CHECK: profile that may no longer deserialize as `ContributorProfile`; ownership is
+    /// checked here, the discriminator and layout in `migrate_contributor_profile`
+    #[account(mut, owner = crate::ID)]
+    pub profile: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct CheckTokenomicsBalances<'info> {
     #[account(
         mut,
         seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
@@ -435,104 +5125,149 @@ pub struct Claim<'info> {
     pub presale: Account<'info, PresalePool>,
 
     #[account(
-        mut,
-        seeds = [
-            b"contributor-profile".as_ref(),
-            contributor.key().as_ref(),
-            presale.key().as_ref()
-        ],
-        bump = profile.bump
+        has_one = presale,
+        seeds = [b"tokenomics".as_ref(), presale.key().as_ref()],
+        bump = tokenomics.bump
     )]
-    pub profile: Account<'info, ContributorProfile>,
+    pub tokenomics: Account<'info, Tokenomics>,
 
     #[account(address = presale.token_mint)]
-    pub token_mint: Account<'info, Mint>,
+    pub token_mint: InterfaceAccount<'info, Mint>,
 
     #[account(
-        mut,
         token::mint = token_mint,
-        token::authority = presale
+        token::authority = presale,
+        seeds = [b"vault".as_ref(), presale.key().as_ref()],
+        bump
     )]
-    pub presale_vault: Account<'info, TokenAccount>,
+    pub presale_vault: InterfaceAccount<'info, TokenAccount>,
 
-    #[account(
-        init_if_needed,
-        payer = contributor,
-        associated_token::mint = token_mint,
-        associated_token::authority = contributor
-    )]
-    pub contributor_ata: Account<'info, TokenAccount>,
+    #[account(address = tokenomics.liquidity_account, token::mint = token_mint)]
+    pub liquidity_account: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
+    #[account(address = tokenomics.team_account, token::mint = token_mint)]
+    pub team_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = tokenomics.treasury_account, token::mint = token_mint)]
+    pub treasury_account: InterfaceAccount<'info, TokenAccount>,
 }
 
 #[derive(Accounts)]
-pub struct AdminWithdraw<'info> {
+pub struct InitializeLaunchpadConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Ico>,
+    /// Only the upgrade authority may claim the admin role
     #[account(
-        mut,
-        has_one = authority,
-        has_one = funds_receiver,
-        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
-        bump = presale.bump
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ IcoError::UnauthorizedAdmin
     )]
-    pub presale: Account<'info, PresalePool>,
-
-    /// Admin / authority of the pool.
-    pub authority: Signer<'info>,
+    pub program_data: Account<'info, ProgramData>,
 
-    /// Destination for withdrawn SOL.
-    /// CHECK: checked by `has_one = funds_receiver`.
-    #[account(mut)]
-    pub funds_receiver: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + LaunchpadConfig::INIT_SPACE,
+        seeds = [b"launchpad-config".as_ref()],
+        bump
+    )]
+    pub launchpad_config: Account<'info, LaunchpadConfig>,
 
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct EmergencyWithdrawToken<'info> {
+pub struct UpdateLaunchpadConfig<'info> {
+    pub admin: Signer<'info>,
+
     #[account(
         mut,
-        has_one = authority,
-        has_one = token_mint,
-        seeds = [b"state".as_ref(), &presale.pool_id.to_le_bytes()],
-        bump = presale.bump
+        has_one = admin @ IcoError::UnauthorizedAdmin,
+        seeds = [b"launchpad-config".as_ref()],
+        bump = launchpad_config.bump
     )]
-    pub presale: Account<'info, PresalePool>,
-
-    /// Admin / authority.
-    #[account(mut)]
-    pub authority: Signer<'info>,
+    pub launchpad_config: Account<'info, LaunchpadConfig>,
+}
 
-    #[account(address = presale.token_mint)]
-    pub token_mint: Account<'info, Mint>,
+#[derive(Accounts)]
+pub struct AcceptLaunchpadAdmin<'info> {
+    pub new_admin: Signer<'info>,
 
     #[account(
         mut,
-        token::mint = token_mint,
-        token::authority = presale
+        seeds = [b"launchpad-config".as_ref()],
+        bump = launchpad_config.bump
     )]
-    pub presale_vault: Account<'info, TokenAccount>,
-
-    /// Receiver of emergency-withdrawn tokens.
-    /// CHECK: arbitrary receiver, only its pubkey is used for ATA derivation.
-    #[account(mut)]
-    pub receiver: AccountInfo<'info>,
+    pub launchpad_config: Account<'info, LaunchpadConfig>,
+}
 
+#[derive(Accounts)]
+pub struct SetDevClock<'info> {
     #[account(
         init_if_needed,
         payer = authority,
-        associated_token::mint = token_mint,
-        associated_token::authority = receiver
+        space = 8 + DevClock::INIT_SPACE,
+        seeds = [DEV_CLOCK_SEED],
+        bump
     )]
-    pub receiver_ata: Account<'info, TokenAccount>,
+    pub dev_clock: Account<'info, DevClock>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Ico>,
+
+    /// Only the upgrade authority may shift the clock
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ IcoError::InvalidDevClockAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
+/// Lifecycle stage of a presale as seen at the current timestamp.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SaleStatus {
+    Upcoming,
+    Active,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// Return data of `get_sale_state`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SaleState {
+    pub status: SaleStatus,
+    pub seconds_until_start: i64,
+    pub seconds_remaining: i64,
+    pub total_contributions: u64,
+    pub hard_cap: u64,
+    /// Share of the hard cap already raised, in basis points.
+    pub hard_cap_filled_bps: u16,
+    /// Upper bound on how much more the profile owner may contribute: the highest of the
+    /// pool, tier and staking maximums, so a wallet's own tier, whitelisted allocation or
+    /// stake may leave it less. `None` without a profile.
+    pub remaining_allowance: Option<u64>,
+    /// Vault tokens not yet owed to contributors.
+    pub tokens_remaining: u64,
+    /// Whether the balances matched the declared tokenomics, see `check_tokenomics_balances`.
+    pub tokenomics_balances_checked: bool,
+    /// Whether the sale was finalized with the tokenomics balances checked.
+    pub tokenomics_verified: bool,
+    /// Index of the open sale round, `None` outside rounds or in single-price sales.
+    pub active_round: Option<u8>,
+    /// Whether `finalize` ran; claims and withdrawals wait for it.
+    pub finalized: bool,
+    /// Whether contributions are paused, see `set_paused`.
+    pub paused: bool,
+}
+
 #[error_code]
 pub enum IcoError {
     #[msg("Soft cap must be less than hard cap")]
@@ -565,8 +5300,152 @@ pub enum IcoError {
     NotEnoughTokensInVault,
     #[msg("Nothing to withdraw")]
     NothingToWithdraw,
-    #[msg("Emergency withdraw allowed only before sale starts")]
+    #[msg("Emergency withdraw allowed only before sale starts or after cancellation")]
     EmergencyWithdrawOnlyBeforeStart,
+    #[msg("Token amount must be greater than zero")]
+    InvalidTokenAmount,
+    #[msg("Hard cap can only be changed before sale starts")]
+    HardcapChangeOnlyBeforeStart,
+    #[msg("Vault would hold more tokens than the hard cap can buy")]
+    TokensExceedHardcap,
+    #[msg("Destination does not match the profile's claim destination")]
+    InvalidClaimDestination,
+    #[msg("Recipient token account is frozen, claim to a new account instead")]
+    RecipientAccountFrozen,
+    #[msg("Recipient token account has a delegate, claim to a new account instead")]
+    RecipientAccountDelegated,
+    #[msg("Dev clock is only available in dev-clock builds")]
+    DevClockDisabled,
+    #[msg("Signer is not the upgrade authority")]
+    InvalidDevClockAuthority,
+    #[msg("Tokenomics can only be declared and checked before sale starts")]
+    TokenomicsOnlyBeforeStart,
+    #[msg("Tokenomics allocation must sum to 10000 bps")]
+    InvalidTokenomics,
+    #[msg("Token balances do not match the declared tokenomics")]
+    TokenomicsMismatch,
+    #[msg("Whitelist can only be changed before sale starts")]
+    WhitelistOnlyBeforeStart,
+    #[msg("Contributor is not in the whitelist")]
+    NotWhitelisted,
+    #[msg("Sale rounds can only be changed before sale starts")]
+    SaleRoundsOnlyBeforeStart,
+    #[msg("Too many sale rounds")]
+    TooManySaleRounds,
+    #[msg("Sale rounds must be ordered, non-overlapping and inside the sale window and caps")]
+    InvalidSaleRound,
+    #[msg("No sale round is open")]
+    NoActiveSaleRound,
+    #[msg("Sale round cap exceeded")]
+    SaleRoundCapExceeded,
+    #[msg("Payment mint can only be set before sale starts")]
+    PaymentMintOnlyBeforeStart,
+    #[msg("Presale is paid in an SPL token, use the SPL instructions")]
+    SplPaymentRequired,
+    #[msg("Mint is not the presale payment mint")]
+    InvalidPaymentMint,
+    #[msg("Soft cap reached, claim tokens instead")]
+    SoftcapReached,
+    #[msg("Claim vesting can only be changed before sale starts")]
+    ClaimVestingOnlyBeforeStart,
+    #[msg("Claim vesting needs a non-negative cliff, a positive interval and 1-100%")]
+    InvalidClaimVesting,
+    #[msg("Presale has no claim vesting")]
+    ClaimVestingDisabled,
+    #[msg("Presale vests claimed tokens, use claim_vested")]
+    VestedClaimRequired,
+    #[msg("Sale is already finalized")]
+    SaleFinalized,
+    #[msg("Sale has not ended and the hard cap is not filled")]
+    SaleNotEnded,
+    #[msg("Sale is not finalized yet")]
+    SaleNotFinalized,
+    #[msg("Sale was cancelled, claim a refund instead")]
+    SaleCancelled,
+    #[msg("Sale cannot be cancelled after funds were withdrawn or tokens claimed")]
+    CancelAfterPayout,
+    #[msg("Sale window can only be changed before sale starts")]
+    ScheduleOnlyBeforeStart,
+    #[msg("Token price can only be changed before sale starts")]
+    PriceOnlyBeforeStart,
+    #[msg("Sale rounds and dutch auctions cannot be combined")]
+    PricingModeConflict,
+    #[msg("Dutch auction needs a floor price between zero and the start price")]
+    InvalidDutchAuction,
+    #[msg("Tiers can only be changed before sale starts")]
+    TiersOnlyBeforeStart,
+    #[msg("Too many tiers")]
+    TooManyTiers,
+    #[msg("Tier is not defined for this presale")]
+    InvalidTier,
+    #[msg("Referral bonus can only be changed before sale starts")]
+    ReferralOnlyBeforeStart,
+    #[msg("Referral bonus cannot exceed 10000 basis points")]
+    InvalidReferralBonus,
+    #[msg("Contributors cannot refer themselves")]
+    SelfReferral,
+    #[msg("Contributor is already credited to another referrer")]
+    ReferrerMismatch,
+    #[msg("Refund batch expects (profile, contributor) pairs of this presale")]
+    InvalidRefundBatch,
+    #[msg("Profile can only be closed after its claim or refund")]
+    ProfileNotSettled,
+    #[msg("Some contributions are not claimed or refunded yet")]
+    ClaimsOutstanding,
+    #[msg("Raised funds must be withdrawn before closing the presale")]
+    FundsNotWithdrawn,
+    #[msg("Claim vesting needs an SPL Token sale mint")]
+    ClaimVestingRequiresSplToken,
+    #[msg("Contributions are paused")]
+    SalePaused,
+    #[msg("KYC authority can only be changed before sale starts")]
+    KycOnlyBeforeStart,
+    #[msg("Sale requires a KYC attestation before the contribution")]
+    KycRequired,
+    #[msg("KYC attestation is not signed by the KYC authority for this contributor")]
+    InvalidKycAttestation,
+    #[msg("USD-priced sale requires the Pyth price update account")]
+    OraclePriceRequired,
+    #[msg("Oracle account is not a verified Pyth price update of the configured feed")]
+    InvalidOraclePrice,
+    #[msg("Oracle price is too old")]
+    StaleOraclePrice,
+    #[msg("Oracle price confidence interval is too wide")]
+    OraclePriceTooUncertain,
+    #[msg("Too many accepted payment mints")]
+    TooManyAcceptedMints,
+    #[msg("Expected the (vault, token program) pair of every accepted mint")]
+    InvalidAcceptedMintVaults,
+    #[msg("Claim deadline can only be changed before sale starts")]
+    ClaimDeadlineOnlyBeforeStart,
+    #[msg("Claim deadline must fall after the sale ends")]
+    InvalidClaimDeadline,
+    #[msg("Claim deadline has not passed yet")]
+    ClaimDeadlineNotReached,
+    #[msg("Claim deadline passed and unclaimed funds were swept")]
+    ClaimWindowClosed,
+    #[msg("Staking allocations can only be changed before sale starts")]
+    StakingAllocationsOnlyBeforeStart,
+    #[msg("Staking allocations need at most 4 thresholds, rising in stake and maximum")]
+    InvalidStakingAllocations,
+    #[msg("Protocol fee cannot exceed 1000 basis points")]
+    ProtocolFeeTooHigh,
+    #[msg("A protocol fee needs a treasury")]
+    MissingFeeTreasury,
+    #[msg("Account is not the launchpad treasury")]
+    InvalidTreasury,
+    #[msg("Only the launchpad admin can do this")]
+    UnauthorizedAdmin,
+    #[msg("Signer is not the nominated launchpad admin")]
+    NotPendingAdmin,
+    #[msg("Funds receiver can only be changed before any withdrawal")]
+    FundsAlreadyWithdrawn,
+    #[msg("Signer is not the nominated presale authority")]
+    NotPendingAuthority,
+    #[msg("Account already has the current layout")]
+    AccountUpToDate,
+    #[msg("Staking pool has no claim cooldown")]
+    StakingPoolNotLocked,
 }
 
 #[event]
@@ -574,6 +5453,68 @@ pub struct Contributed {
     pub presale: Pubkey,
     pub contributor: Pubkey,
     pub amount: u64,
+    /// Part of the requested amount left with the contributor because of the hard cap.
+    pub clipped_amount: u64,
+    /// Sale round the contribution went to, `None` in single-price sales.
+    pub round: Option<u8>,
+    /// Price per full token paid by the contribution.
+    pub price_lamports: u64,
+}
+
+#[event]
+pub struct PresaleCreated {
+    pub presale: Pubkey,
+    pub pool_id: u64,
+    pub authority: Pubkey,
+    pub token_mint: Pubkey,
+    pub funds_receiver: Pubkey,
+    pub token_price_lamports: u64,
+    pub soft_cap: u64,
+    pub hard_cap: u64,
+    pub min_contribution: u64,
+    pub max_contribution: u64,
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+    /// Tokens deposited in the vault for sale.
+    pub tokens_for_sale: u64,
+}
+
+#[event]
+pub struct PresaleFinalized {
+    pub presale: Pubkey,
+    pub outcome: SaleOutcome,
+    pub total_contributions: u64,
+    /// Tokens owed to contributors, 0 for a failed sale.
+    pub tokens_sold: u64,
+    pub final_rate: u64,
+    pub tokenomics_verified: bool,
+}
+
+#[event]
+pub struct AdminWithdrawn {
+    pub presale: Pubkey,
+    pub funds_receiver: Pubkey,
+    /// Payment token withdrawn, SOL when unset.
+    pub payment_mint: Option<Pubkey>,
+    pub amount: u64,
+    /// Part of `amount` paid to the launchpad treasury.
+    pub protocol_fee: u64,
+}
+
+#[event]
+pub struct EmergencyTokensWithdrawn {
+    pub presale: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    /// Sale inventory left after the withdrawal.
+    pub tokens_for_sale: u64,
+}
+
+#[event]
+pub struct PresaleCancelled {
+    pub presale: Pubkey,
+    /// Contributions now open for refunds.
+    pub total_contributions: u64,
 }
 
 #[event]
@@ -582,6 +5523,122 @@ pub struct TokensClaimed {
     pub contributor: Pubkey,
     pub contribution: u64,
     pub amount: u64,
+    /// Tokens reaching the recipient after a Token-2022 transfer fee.
+    pub received: u64,
+    /// Lamports refunded by an oversubscribed fair launch.
+    pub excess_refund: u64,
+}
+
+#[event]
+pub struct TokensVested {
+    pub presale: Pubkey,
+    pub contributor: Pubkey,
+    pub vesting_schedule: Pubkey,
+    pub contribution: u64,
+    pub amount: u64,
+    /// Lamports refunded by an oversubscribed fair launch.
+    pub excess_refund: u64,
+}
+
+#[event]
+pub struct ClaimDestinationSet {
+    pub presale: Pubkey,
+    pub contributor: Pubkey,
+    pub destination: Option<Pubkey>,
+}
+
+#[event]
+pub struct KycAuthoritySet {
+    pub presale: Pubkey,
+    pub kyc_authority: Option<Pubkey>,
+}
+
+#[event]
+pub struct ClaimDeadlineSet {
+    pub presale: Pubkey,
+    pub claim_deadline: Option<i64>,
+}
+
+#[event]
+pub struct UnclaimedSwept {
+    pub presale: Pubkey,
+    /// Tokens owed to contributors and referrers returned to the authority.
+    pub tokens: u64,
+    /// Unrefunded SOL contributions, or fair-launch excess, returned to the authority.
+    pub lamports: u64,
+}
+
+#[event]
+pub struct LaunchpadConfigUpdated {
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub fee_bps: u16,
+}
+
+#[event]
+pub struct AuthorityNominated {
+    pub presale: Pubkey,
+    pub pending_authority: Option<Pubkey>,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub presale: Pubkey,
+    pub previous_authority: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct FundsReceiverSet {
+    pub presale: Pubkey,
+    pub funds_receiver: Pubkey,
+}
+
+#[event]
+pub struct PausedSet {
+    pub presale: Pubkey,
+    pub paused: bool,
+}
+
+#[event]
+pub struct WhitelistRootSet {
+    pub presale: Pubkey,
+    pub root: Option<[u8; 32]>,
+}
+
+#[event]
+pub struct AcceptedMintAdded {
+    pub presale: Pubkey,
+    pub mint: Pubkey,
+    pub mint_vault: Pubkey,
+    pub rate_lamports: u64,
+}
+
+#[event]
+pub struct PaymentMintSet {
+    pub presale: Pubkey,
+    pub payment_mint: Pubkey,
+    pub payment_vault: Pubkey,
+}
+
+#[event]
+pub struct ScheduleUpdated {
+    pub presale: Pubkey,
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+}
+
+#[event]
+pub struct PriceUpdated {
+    pub presale: Pubkey,
+    pub old_price_lamports: u64,
+    pub new_price_lamports: u64,
+}
+
+#[event]
+pub struct SaleRoundsSet {
+    pub presale: Pubkey,
+    pub rounds: u8,
 }
 
 #[event]
@@ -589,4 +5646,56 @@ pub struct Refunded {
     pub presale: Pubkey,
     pub contributor: Pubkey,
     pub amount: u64,
-}
\ No newline at end of file
+}
+
+#[event]
+pub struct ReferralBonusSet {
+    pub presale: Pubkey,
+    pub bonus_bps: u16,
+    /// Total tokens deposited for referral bonuses.
+    pub referral_allocation: u64,
+}
+
+#[event]
+pub struct ReferralBonusClaimed {
+    pub presale: Pubkey,
+    pub referrer: Pubkey,
+    pub referred_volume: u64,
+    pub bonus: u64,
+}
+
+#[event]
+pub struct PresaleClosed {
+    pub presale: Pubkey,
+    /// Sale tokens returned to the authority before closing the vault.
+    pub leftover_tokens: u64,
+}
+
+#[event]
+pub struct UnsoldTokensWithdrawn {
+    pub presale: Pubkey,
+    pub amount: u64,
+    /// Tokens left in the vault for pending claims.
+    pub tokens_owed: u64,
+}
+
+#[event]
+pub struct TokensDeposited {
+    pub presale: Pubkey,
+    pub amount: u64,
+    /// Vault balance after the deposit.
+    pub total_tokens: u64,
+    pub hard_cap: u64,
+    /// Tokens needed to fill the hard cap at the pool price.
+    pub tokens_for_hard_cap: u64,
+}
+
+#[event]
+pub struct TokenomicsBalancesChecked {
+    pub presale: Pubkey,
+    pub supply: u64,
+    pub sale_amount: u64,
+    pub liquidity_amount: u64,
+    pub team_amount: u64,
+    pub treasury_amount: u64,
+}
@@ -1,20 +1,36 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{bpf_loader_upgradeable, program::invoke_signed};
 use anchor_lang::system_program;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, TransferChecked};
+use anchor_spl::token::{self, ApproveChecked, Mint, Revoke, Token, TokenAccount, TransferChecked};
+use staking::program::Staking;
 
 declare_id!("7SmvmUGRK9sx9eVXspVWyQeaTPqjTPa5xQui3kgg6AMk");
 
+/// Maximum number of amount bands a vault can configure
+pub const MAX_THRESHOLD_BANDS: usize = 5;
+
+/// Decimals of native SOL (lamports)
+pub const SOL_DECIMALS: u8 = 9;
+
+/// Maximum number of recipients on a vault's allowlist
+pub const MAX_ALLOWED_RECIPIENTS: usize = 10;
+
 #[program]
 pub mod multisig {
     use super::*;
 
-    /// Create a new multisig vault with specified signers and threshold
+    /// Create a new multisig vault with specified signers and threshold.
+    /// Optional amount bands override the threshold for proposals of a given asset and size.
+    /// Proposals can only be executed once they are `review_period` seconds old, however
+    /// fast they collect approvals.
     pub fn create_vault(
         ctx: Context<CreateVault>,
         vault_id: u64,
         signers: Vec<Pubkey>,
         threshold: u8,
+        threshold_bands: Vec<ThresholdBand>,
+        review_period: i64,
     ) -> Result<()> {
         let vault = &mut ctx.accounts.vault;
         let creator = &ctx.accounts.creator;
@@ -37,11 +53,115 @@ pub mod multisig {
             MultisigError::DuplicateSigners
         );
 
+        // Validate amount bands
+        require!(
+            threshold_bands.len() <= MAX_THRESHOLD_BANDS,
+            MultisigError::TooManyThresholdBands
+        );
+        for band in threshold_bands.iter() {
+            require!(band.threshold > 0, MultisigError::InvalidThreshold);
+            require!(
+                band.threshold as usize <= signers.len(),
+                MultisigError::ThresholdTooHigh
+            );
+            // Bands can only tighten the vault threshold, never bypass it
+            require!(
+                band.threshold >= threshold,
+                MultisigError::BandThresholdBelowVault
+            );
+        }
+
+        require!(review_period >= 0, MultisigError::InvalidReviewPeriod);
+
         vault.vault_id = vault_id;
         vault.signers = signers;
         vault.threshold = threshold;
+        vault.threshold_bands = threshold_bands;
         vault.vault_bump = ctx.bumps.vault;
         vault.creator = creator.key();
+        vault.review_period = review_period;
+        vault.allowlist_enabled = false;
+        vault.allowed_recipients = Vec::new();
+
+        Ok(())
+    }
+
+    /// Grow a vault created before amount bands, review periods and allowlists to the
+    /// current layout. The new settings start empty: no bands, no review period and no
+    /// allowlist. The payer covers the extra rent.
+    pub fn migrate_vault(ctx: Context<MigrateVault>) -> Result<()> {
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let space = 8 + MultisigVault::INIT_SPACE;
+
+        let legacy = {
+            let data = vault_info.try_borrow_data()?;
+            require!(data.len() < space, MultisigError::VaultAlreadyMigrated);
+            require!(
+                data.len() >= 8 && data[..8] == *MultisigVault::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+            LegacyMultisigVault::deserialize(&mut &data[8..])?
+        };
+
+        grow_account(
+            &vault_info,
+            space,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+        )?;
+
+        let vault = MultisigVault {
+            vault_id: legacy.vault_id,
+            signers: legacy.signers,
+            threshold: legacy.threshold,
+            vault_bump: legacy.vault_bump,
+            creator: legacy.creator,
+            threshold_bands: Vec::new(),
+            review_period: 0,
+            allowlist_enabled: false,
+            allowed_recipients: Vec::new(),
+        };
+        vault.try_serialize(&mut &mut vault_info.try_borrow_mut_data()?[..])?;
+
+        Ok(())
+    }
+
+    /// Grow a transfer proposal created before review periods to the current layout. Legacy
+    /// proposals carry no creation time, so the vault's review period runs from the
+    /// migration. The payer covers the extra rent.
+    pub fn migrate_proposal(ctx: Context<MigrateProposal>) -> Result<()> {
+        let proposal_info = ctx.accounts.proposal.to_account_info();
+        let space = 8 + TransferProposal::INIT_SPACE;
+
+        let legacy = {
+            let data = proposal_info.try_borrow_data()?;
+            require!(data.len() < space, MultisigError::ProposalAlreadyMigrated);
+            require!(
+                data.len() >= 8 && data[..8] == *TransferProposal::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+            LegacyTransferProposal::deserialize(&mut &data[8..])?
+        };
+
+        grow_account(
+            &proposal_info,
+            space,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+        )?;
+
+        let proposal = TransferProposal {
+            vault: legacy.vault,
+            proposer: legacy.proposer,
+            recipient: legacy.recipient,
+            amount: legacy.amount,
+            token_mint: legacy.token_mint,
+            approvals: legacy.approvals,
+            executed: legacy.executed,
+            proposal_id: legacy.proposal_id,
+            created_at: Clock::get()?.unix_timestamp,
+        };
+        proposal.try_serialize(&mut &mut proposal_info.try_borrow_mut_data()?[..])?;
 
         Ok(())
     }
@@ -71,6 +191,11 @@ pub mod multisig {
             .position(|&s| s == proposer.key())
             .ok_or(MultisigError::InvalidSigner)?;
 
+        require!(
+            vault.is_recipient_allowed(&recipient),
+            MultisigError::RecipientNotAllowed
+        );
+
         // Initialize proposal
         proposal.vault = vault.key();
         proposal.proposer = proposer.key();
@@ -79,6 +204,7 @@ pub mod multisig {
         proposal.token_mint = token_mint;
         proposal.proposal_id = proposal_id;
         proposal.executed = false;
+        proposal.created_at = Clock::get()?.unix_timestamp;
 
         // Initialize approvals vector
         proposal.approvals = vec![false; vault.signers.len()];
@@ -89,39 +215,13 @@ pub mod multisig {
 
     /// Approve a transfer proposal
     pub fn approve_transfer(ctx: Context<ApproveTransfer>) -> Result<()> {
-        let vault = &ctx.accounts.vault;
-        let proposal = &mut ctx.accounts.proposal;
-        let approver = &ctx.accounts.approver;
-
-        // Verify proposal hasn't been executed
-        require!(!proposal.executed, MultisigError::AlreadyExecuted);
-
-        // Verify approver is a signer
-        require!(
-            vault.signers.contains(approver.key),
-            MultisigError::InvalidSigner
-        );
-
-        // Find approver index
-        let approver_index = vault
-            .signers
-            .iter()
-            .position(|&s| s == approver.key())
-            .ok_or(MultisigError::InvalidSigner)?;
-
-        // Verify approver hasn't already approved
-        require!(
-            !proposal.approvals[approver_index],
-            MultisigError::AlreadyApproved
-        );
-
-        // Mark approval
-        proposal.approvals[approver_index] = true;
-
-        Ok(())
+        ctx.accounts
+            .proposal
+            .approve(&ctx.accounts.vault, &ctx.accounts.approver.key())
     }
 
-    /// Execute a SOL transfer proposal if threshold is met
+    /// Execute a SOL transfer proposal if threshold is met.
+    /// The only writable accounts are the vault's SOL PDA and the approved recipient.
     pub fn execute_sol_transfer(ctx: Context<ExecuteSolTransfer>) -> Result<()> {
         let vault = &ctx.accounts.vault;
         let proposal = &mut ctx.accounts.proposal;
@@ -142,12 +242,25 @@ pub mod multisig {
             .filter(|&&approved| approved)
             .count();
 
-        // Verify threshold is met
+        // Verify threshold for this asset and amount is met
+        let required_threshold = vault.required_threshold(proposal.token_mint, proposal.amount);
         require!(
-            approval_count >= vault.threshold as usize,
+            approval_count >= required_threshold as usize,
             MultisigError::InsufficientApprovals
         );
 
+        // Give monitoring time to react, however fast approvals came in
+        require!(
+            Clock::get()?.unix_timestamp >= proposal.executable_at(vault),
+            MultisigError::ReviewPeriodNotElapsed
+        );
+
+        // The allowlist may have changed since the proposal was made
+        require!(
+            vault.is_recipient_allowed(&proposal.recipient),
+            MultisigError::RecipientNotAllowed
+        );
+
         // Mark as executed before transfer to prevent reentrancy
         proposal.executed = true;
 
@@ -176,7 +289,9 @@ pub mod multisig {
         Ok(())
     }
 
-    /// Execute an SPL token transfer proposal if threshold is met
+    /// Execute an SPL token transfer proposal if threshold is met.
+    /// The only writable accounts are the vault's token account and a token account owned by
+    /// the approved recipient.
     pub fn execute_spl_transfer(ctx: Context<ExecuteSplTransfer>) -> Result<()> {
         let vault = &ctx.accounts.vault;
         let proposal = &mut ctx.accounts.proposal;
@@ -205,18 +320,31 @@ pub mod multisig {
             .filter(|&&approved| approved)
             .count();
 
-        // Verify threshold is met
+        // Verify threshold for this asset and amount is met
+        let required_threshold = vault.required_threshold(proposal.token_mint, proposal.amount);
         require!(
-            approval_count >= vault.threshold as usize,
+            approval_count >= required_threshold as usize,
             MultisigError::InsufficientApprovals
         );
 
+        // Give monitoring time to react, however fast approvals came in
+        require!(
+            Clock::get()?.unix_timestamp >= proposal.executable_at(vault),
+            MultisigError::ReviewPeriodNotElapsed
+        );
+
         // Verify vault token account owner
         require!(
             vault_token_account.owner == vault.key(),
             MultisigError::InvalidTokenAccount
         );
 
+        // The allowlist may have changed since the proposal was made
+        require!(
+            vault.is_recipient_allowed(&proposal.recipient),
+            MultisigError::RecipientNotAllowed
+        );
+
         // Mark as executed before transfer to prevent reentrancy
         proposal.executed = true;
 
@@ -246,161 +374,1451 @@ pub mod multisig {
 
         Ok(())
     }
-}
 
-#[account]
-#[derive(InitSpace)]
-pub struct MultisigVault {
-    pub vault_id: u64,
-    #[max_len(5)]
-    pub signers: Vec<Pubkey>,
-    pub threshold: u8,
-    pub vault_bump: u8,
-    pub creator: Pubkey,
-}
+    /// Propose a change to the vault's configuration. Applied by `execute_config_change`
+    /// once it has the vault threshold of approvals and is past the review period.
+    pub fn propose_config_change(
+        ctx: Context<ProposeConfigChange>,
+        proposal_id: u64,
+        change: ConfigChange,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let proposal = &mut ctx.accounts.proposal;
+        let proposer = &ctx.accounts.proposer;
 
-#[account]
-#[derive(InitSpace)]
-pub struct TransferProposal {
-    pub vault: Pubkey,
-    pub proposer: Pubkey,
-    pub recipient: Pubkey,
-    pub amount: u64,
-    pub token_mint: Option<Pubkey>,
-    #[max_len(5)]
-    pub approvals: Vec<bool>,
-    pub executed: bool,
-    pub proposal_id: u64,
-}
+        // Find proposer index
+        let proposer_index = vault
+            .signers
+            .iter()
+            .position(|&s| s == proposer.key())
+            .ok_or(MultisigError::InvalidSigner)?;
 
-#[derive(Accounts)]
-#[instruction(vault_id: u64, signers: Vec<Pubkey>, threshold: u8)]
-pub struct CreateVault<'info> {
-    #[account(mut)]
-    pub creator: Signer<'info>,
+        proposal.vault = vault.key();
+        proposal.proposer = proposer.key();
+        proposal.proposal_id = proposal_id;
+        proposal.change = change;
+        proposal.executed = false;
+        proposal.created_at = Clock::get()?.unix_timestamp;
 
-    #[account(
-        init,
-        payer = creator,
-        space = 8 + MultisigVault::INIT_SPACE,
-        seeds = [b"multisig_vault", creator.key().as_ref(), vault_id.to_le_bytes().as_ref()],
-        bump
-    )]
-    pub vault: Account<'info, MultisigVault>,
+        // Initialize approvals vector
+        proposal.approvals = vec![false; vault.signers.len()];
+        proposal.approvals[proposer_index] = true; // Auto-approve proposer
 
-    pub system_program: Program<'info, System>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-#[instruction(proposal_id: u64)]
-pub struct ProposeTransfer<'info> {
-    #[account(
-        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
-        bump = vault.vault_bump
-    )]
-    pub vault: Account<'info, MultisigVault>,
+    /// Approve a config change proposal
+    pub fn approve_config_change(ctx: Context<ApproveConfigChange>) -> Result<()> {
+        ctx.accounts
+            .proposal
+            .approve(&ctx.accounts.vault, &ctx.accounts.approver.key())
+    }
 
-    #[account(
-        init,
-        payer = proposer,
-        space = 8 + TransferProposal::INIT_SPACE,
-        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal_id.to_le_bytes().as_ref()],
-        bump
-    )]
-    pub proposal: Account<'info, TransferProposal>,
+    /// Apply a config change proposal if the vault threshold is met.
+    pub fn execute_config_change(ctx: Context<ExecuteConfigChange>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let proposal = &mut ctx.accounts.proposal;
 
-    #[account(mut)]
-    pub proposer: Signer<'info>,
+        // Verify proposal hasn't been executed
+        require!(!proposal.executed, MultisigError::AlreadyExecuted);
 
-    pub system_program: Program<'info, System>,
-}
+        // Count approvals
+        let approval_count = proposal
+            .approvals
+            .iter()
+            .filter(|&&approved| approved)
+            .count();
 
-#[derive(Accounts)]
-pub struct ApproveTransfer<'info> {
-    #[account(
-        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
-        bump = vault.vault_bump
-    )]
-    pub vault: Account<'info, MultisigVault>,
+        // Config changes always need the vault threshold, bands only apply to transfers
+        require!(
+            approval_count >= vault.threshold as usize,
+            MultisigError::InsufficientApprovals
+        );
 
-    #[account(
-        mut,
-        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
-        bump,
-        has_one = vault
-    )]
-    pub proposal: Account<'info, TransferProposal>,
+        // Give monitoring time to react, however fast approvals came in
+        require!(
+            Clock::get()?.unix_timestamp >= proposal.executable_at(vault),
+            MultisigError::ReviewPeriodNotElapsed
+        );
 
-    pub approver: Signer<'info>,
-}
+        proposal.executed = true;
 
-#[derive(Accounts)]
-pub struct ExecuteSolTransfer<'info> {
-    #[account(
-        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
-        bump = vault.vault_bump
-    )]
-    pub vault: Account<'info, MultisigVault>,
+        match proposal.change.clone() {
+            ConfigChange::SetAllowlistEnabled { enabled } => {
+                vault.allowlist_enabled = enabled;
+            }
+            ConfigChange::AddAllowedRecipient { recipient } => {
+                require!(
+                    !vault.allowed_recipients.contains(&recipient),
+                    MultisigError::RecipientAlreadyAllowed
+                );
+                require!(
+                    vault.allowed_recipients.len() < MAX_ALLOWED_RECIPIENTS,
+                    MultisigError::TooManyAllowedRecipients
+                );
+                vault.allowed_recipients.push(recipient);
+            }
+            ConfigChange::RemoveAllowedRecipient { recipient } => {
+                let index = vault
+                    .allowed_recipients
+                    .iter()
+                    .position(|&allowed| allowed == recipient)
+                    .ok_or(MultisigError::RecipientNotAllowed)?;
+                vault.allowed_recipients.remove(index);
+            }
+        }
+
+        emit!(ConfigChangeExecuted {
+            vault: vault.key(),
+            proposal_id: proposal.proposal_id,
+            change: proposal.change.clone(),
+        });
 
-    #[account(
-        mut,
-        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
-        bump,
-        has_one = vault
-    )]
-    pub proposal: Account<'info, TransferProposal>,
+        Ok(())
+    }
 
-    /// CHECK: SOL account for vault (separate PDA without data)
-    #[account(
-        mut,
-        seeds = [b"vault_sol", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
-        bump
-    )]
-    pub vault_sol_account: AccountInfo<'info>,
+    /// Propose setting or revoking the delegate of a vault token account, e.g. to grant a
+    /// bounded allowance to a DCA program. Applied by `execute_delegate_change`.
+    pub fn propose_delegate_change(
+        ctx: Context<ProposeDelegateChange>,
+        proposal_id: u64,
+        action: DelegateAction,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let proposal = &mut ctx.accounts.proposal;
+        let proposer = &ctx.accounts.proposer;
 
-    /// CHECK: Recipient account
-    #[account(mut)]
-    pub recipient: AccountInfo<'info>,
+        // Find proposer index
+        let proposer_index = vault
+            .signers
+            .iter()
+            .position(|&s| s == proposer.key())
+            .ok_or(MultisigError::InvalidSigner)?;
 
-    pub system_program: Program<'info, System>,
-}
+        // A delegate can move funds anywhere, so it must pass the allowlist itself
+        if let DelegateAction::Approve { delegate, .. } = &action {
+            require!(
+                vault.is_recipient_allowed(delegate),
+                MultisigError::RecipientNotAllowed
+            );
+        }
 
-#[derive(Accounts)]
-pub struct ExecuteSplTransfer<'info> {
-    #[account(
-        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
-        bump = vault.vault_bump
-    )]
-    pub vault: Account<'info, MultisigVault>,
+        proposal.vault = vault.key();
+        proposal.proposer = proposer.key();
+        proposal.proposal_id = proposal_id;
+        proposal.token_account = ctx.accounts.token_account.key();
+        proposal.mint = ctx.accounts.token_account.mint;
+        proposal.action = action;
+        proposal.executed = false;
+        proposal.created_at = Clock::get()?.unix_timestamp;
 
-    /// CHECK: PDA signer for vault
-    #[account(
-        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
-        bump = vault.vault_bump
-    )]
-    pub vault_pda: AccountInfo<'info>,
+        // Initialize approvals vector
+        proposal.approvals = vec![false; vault.signers.len()];
+        proposal.approvals[proposer_index] = true; // Auto-approve proposer
 
-    #[account(
-        mut,
-        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
-        bump,
-        has_one = vault
-    )]
-    pub proposal: Account<'info, TransferProposal>,
+        Ok(())
+    }
 
-    pub mint: Account<'info, Mint>,
+    /// Approve a delegate change proposal
+    pub fn approve_delegate_change(ctx: Context<ApproveDelegateChange>) -> Result<()> {
+        ctx.accounts
+            .proposal
+            .approve(&ctx.accounts.vault, &ctx.accounts.approver.key())
+    }
 
-    #[account(mut)]
-    pub vault_token_account: Account<'info, TokenAccount>,
+    /// Apply a delegate change proposal with `approve_checked` or `revoke` once approved.
+    /// Allowances need the threshold of a transfer of the same amount.
+    pub fn execute_delegate_change(ctx: Context<ExecuteDelegateChange>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let proposal = &mut ctx.accounts.proposal;
 
-    #[account(mut)]
-    pub recipient_token_account: Account<'info, TokenAccount>,
+        // Verify proposal hasn't been executed
+        require!(!proposal.executed, MultisigError::AlreadyExecuted);
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-}
+        // Count approvals
+        let approval_count = proposal
+            .approvals
+            .iter()
+            .filter(|&&approved| approved)
+            .count();
 
-#[error_code]
+        let required_threshold = match proposal.action {
+            DelegateAction::Approve { amount, .. } => {
+                vault.required_threshold(Some(proposal.mint), amount)
+            }
+            DelegateAction::Revoke => vault.threshold,
+        };
+        require!(
+            approval_count >= required_threshold as usize,
+            MultisigError::InsufficientApprovals
+        );
+
+        // Give monitoring time to react, however fast approvals came in
+        require!(
+            Clock::get()?.unix_timestamp >= proposal.executable_at(vault),
+            MultisigError::ReviewPeriodNotElapsed
+        );
+
+        proposal.executed = true;
+
+        let vault_id_bytes = vault.vault_id.to_le_bytes();
+        let seeds = &[
+            b"multisig_vault",
+            vault.creator.as_ref(),
+            vault_id_bytes.as_ref(),
+            &[vault.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        match proposal.action.clone() {
+            DelegateAction::Approve { delegate, amount } => {
+                // The allowlist may have changed since the proposal was made
+                require!(
+                    vault.is_recipient_allowed(&delegate),
+                    MultisigError::RecipientNotAllowed
+                );
+                let delegate_account = ctx
+                    .accounts
+                    .delegate
+                    .as_ref()
+                    .ok_or(MultisigError::DelegateMismatch)?;
+                require_keys_eq!(
+                    delegate_account.key(),
+                    delegate,
+                    MultisigError::DelegateMismatch
+                );
+
+                let cpi_accounts = ApproveChecked {
+                    to: ctx.accounts.token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    delegate: delegate_account.to_account_info(),
+                    authority: ctx.accounts.vault_pda.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    cpi_accounts,
+                    signer,
+                );
+                token::approve_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+            }
+            DelegateAction::Revoke => {
+                let cpi_accounts = Revoke {
+                    source: ctx.accounts.token_account.to_account_info(),
+                    authority: ctx.accounts.vault_pda.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    cpi_accounts,
+                    signer,
+                );
+                token::revoke(cpi_ctx)?;
+            }
+        }
+
+        emit!(DelegateChangeExecuted {
+            vault: vault.key(),
+            proposal_id: proposal.proposal_id,
+            token_account: proposal.token_account,
+            action: proposal.action.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Propose upgrading `program`, whose upgrade authority is the vault, to the code in
+    /// `buffer`. The buffer's authority must also be the vault. Applied by
+    /// `execute_program_upgrade`.
+    pub fn propose_program_upgrade(
+        ctx: Context<ProposeProgramUpgrade>,
+        proposal_id: u64,
+        program: Pubkey,
+        buffer: Pubkey,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let proposal = &mut ctx.accounts.proposal;
+        let proposer = &ctx.accounts.proposer;
+
+        // Find proposer index
+        let proposer_index = vault
+            .signers
+            .iter()
+            .position(|&s| s == proposer.key())
+            .ok_or(MultisigError::InvalidSigner)?;
+
+        proposal.vault = vault.key();
+        proposal.proposer = proposer.key();
+        proposal.proposal_id = proposal_id;
+        proposal.program = program;
+        proposal.buffer = buffer;
+        proposal.executed = false;
+        proposal.created_at = Clock::get()?.unix_timestamp;
+
+        // Initialize approvals vector
+        proposal.approvals = vec![false; vault.signers.len()];
+        proposal.approvals[proposer_index] = true; // Auto-approve proposer
+
+        Ok(())
+    }
+
+    /// Approve a program upgrade proposal
+    pub fn approve_program_upgrade(ctx: Context<ApproveProgramUpgrade>) -> Result<()> {
+        ctx.accounts
+            .proposal
+            .approve(&ctx.accounts.vault, &ctx.accounts.approver.key())
+    }
+
+    /// Upgrade the program with the approved buffer through the upgradeable loader, signing
+    /// as the vault. The buffer's rent goes to `spill`.
+    pub fn execute_program_upgrade(ctx: Context<ExecuteProgramUpgrade>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let proposal = &mut ctx.accounts.proposal;
+
+        // Verify proposal hasn't been executed
+        require!(!proposal.executed, MultisigError::AlreadyExecuted);
+
+        // Count approvals
+        let approval_count = proposal
+            .approvals
+            .iter()
+            .filter(|&&approved| approved)
+            .count();
+        require!(
+            approval_count >= vault.threshold as usize,
+            MultisigError::InsufficientApprovals
+        );
+
+        // Give monitoring time to react, however fast approvals came in
+        require!(
+            Clock::get()?.unix_timestamp >= proposal.executable_at(vault),
+            MultisigError::ReviewPeriodNotElapsed
+        );
+
+        proposal.executed = true;
+
+        let vault_id_bytes = vault.vault_id.to_le_bytes();
+        let seeds = &[
+            b"multisig_vault",
+            vault.creator.as_ref(),
+            vault_id_bytes.as_ref(),
+            &[vault.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let upgrade_ix = bpf_loader_upgradeable::upgrade(
+            &proposal.program,
+            &proposal.buffer,
+            &ctx.accounts.vault_pda.key(),
+            &ctx.accounts.spill.key(),
+        );
+        invoke_signed(
+            &upgrade_ix,
+            &[
+                ctx.accounts.program_data.to_account_info(),
+                ctx.accounts.program.to_account_info(),
+                ctx.accounts.buffer.to_account_info(),
+                ctx.accounts.spill.to_account_info(),
+                ctx.accounts.rent.to_account_info(),
+                ctx.accounts.clock.to_account_info(),
+                ctx.accounts.vault_pda.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        emit!(ProgramUpgraded {
+            vault: vault.key(),
+            proposal_id: proposal.proposal_id,
+            program: proposal.program,
+            buffer: proposal.buffer,
+        });
+
+        Ok(())
+    }
+
+    /// Propose an administrative change to a staking pool whose authority is the vault, see
+    /// the staking program's `set_pool_authority`. Applied by `execute_pool_admin`.
+    pub fn propose_pool_admin(
+        ctx: Context<ProposePoolAdmin>,
+        proposal_id: u64,
+        pool: Pubkey,
+        action: PoolAdminAction,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let proposal = &mut ctx.accounts.proposal;
+        let proposer = &ctx.accounts.proposer;
+
+        // Find proposer index
+        let proposer_index = vault
+            .signers
+            .iter()
+            .position(|&s| s == proposer.key())
+            .ok_or(MultisigError::InvalidSigner)?;
+
+        proposal.vault = vault.key();
+        proposal.proposer = proposer.key();
+        proposal.proposal_id = proposal_id;
+        proposal.pool = pool;
+        proposal.action = action;
+        proposal.executed = false;
+        proposal.created_at = Clock::get()?.unix_timestamp;
+
+        // Initialize approvals vector
+        proposal.approvals = vec![false; vault.signers.len()];
+        proposal.approvals[proposer_index] = true; // Auto-approve proposer
+
+        Ok(())
+    }
+
+    /// Approve a pool admin proposal
+    pub fn approve_pool_admin(ctx: Context<ApprovePoolAdmin>) -> Result<()> {
+        ctx.accounts
+            .proposal
+            .approve(&ctx.accounts.vault, &ctx.accounts.approver.key())
+    }
+
+    /// Call the approved staking admin instruction through CPI, signing as the vault.
+    /// Remaining accounts are passed on, e.g. the staking program's dev clock.
+    pub fn execute_pool_admin<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecutePoolAdmin<'info>>,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let proposal = &mut ctx.accounts.proposal;
+
+        // Verify proposal hasn't been executed
+        require!(!proposal.executed, MultisigError::AlreadyExecuted);
+
+        // Count approvals
+        let approval_count = proposal
+            .approvals
+            .iter()
+            .filter(|&&approved| approved)
+            .count();
+
+        // Pool administration always needs the vault threshold, bands only apply to transfers
+        require!(
+            approval_count >= vault.threshold as usize,
+            MultisigError::InsufficientApprovals
+        );
+
+        // Give monitoring time to react, however fast approvals came in
+        require!(
+            Clock::get()?.unix_timestamp >= proposal.executable_at(vault),
+            MultisigError::ReviewPeriodNotElapsed
+        );
+
+        proposal.executed = true;
+
+        let vault_id_bytes = vault.vault_id.to_le_bytes();
+        let seeds = &[
+            b"multisig_vault",
+            vault.creator.as_ref(),
+            vault_id_bytes.as_ref(),
+            &[vault.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = staking::cpi::accounts::UpdatePool {
+            mint: ctx.accounts.mint.to_account_info(),
+            creator: ctx.accounts.vault_pda.to_account_info(),
+            pool: ctx.accounts.pool.to_account_info(),
+            pool_vault: ctx.accounts.pool_vault.to_account_info(),
+            creator_ata: ctx.accounts.creator_ata.to_account_info(),
+            associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.staking_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        )
+        .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+
+        match proposal.action.clone() {
+            PoolAdminAction::EnableEmergencyMode => staking::cpi::enable_emergency_mode(cpi_ctx)?,
+            PoolAdminAction::DisableEmergencyMode => staking::cpi::disable_emergency_mode(cpi_ctx)?,
+            PoolAdminAction::ChangePoolCooldown { cooldown } => {
+                staking::cpi::change_pool_cooldown(cpi_ctx, cooldown)?
+            }
+            PoolAdminAction::ChangeMinStakeAmount { min_stake_amount } => {
+                staking::cpi::change_min_stake_amount(cpi_ctx, min_stake_amount)?
+            }
+            PoolAdminAction::ChangeMaxOpenDeposits { max_open_deposits } => {
+                staking::cpi::change_max_open_deposits(cpi_ctx, max_open_deposits)?
+            }
+            PoolAdminAction::ChangeEndTimestamp { end_timestamp } => {
+                staking::cpi::change_end_timestamp(cpi_ctx, end_timestamp)?
+            }
+            PoolAdminAction::ChangeEmergencyWithdrawDelay { delay } => {
+                staking::cpi::change_emergency_withdraw_delay(cpi_ctx, delay)?
+            }
+            PoolAdminAction::ChangeReferralShare { referral_bps } => {
+                staking::cpi::change_referral_share(cpi_ctx, referral_bps)?
+            }
+            PoolAdminAction::ChangeInstantUnstakeFee { fee_bps } => {
+                staking::cpi::change_instant_unstake_fee(cpi_ctx, fee_bps)?
+            }
+            PoolAdminAction::ChangeMaxSlash { max_slash_bps } => {
+                staking::cpi::change_max_slash(cpi_ctx, max_slash_bps)?
+            }
+            PoolAdminAction::ConfigureClaimVesting { duration } => {
+                staking::cpi::configure_claim_vesting(cpi_ctx, duration)?
+            }
+            PoolAdminAction::ConfigureLockBoost { boost_bps } => {
+                staking::cpi::configure_lock_boost(cpi_ctx, boost_bps)?
+            }
+            PoolAdminAction::SweepDust => staking::cpi::sweep_dust(cpi_ctx)?,
+            PoolAdminAction::SetPoolAuthority { new_authority } => {
+                staking::cpi::set_pool_authority(cpi_ctx, new_authority)?
+            }
+        }
+
+        emit!(PoolAdminExecuted {
+            vault: vault.key(),
+            proposal_id: proposal.proposal_id,
+            pool: proposal.pool,
+            action: proposal.action.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Summarize a proposal for signers, as one log line and as return data.
+    /// Pass the mint for SPL proposals so the amount can be rendered in UI units.
+    pub fn describe_proposal(ctx: Context<DescribeProposal>) -> Result<ProposalDescription> {
+        let vault = &ctx.accounts.vault;
+        let proposal = &ctx.accounts.proposal;
+
+        let decimals = match proposal.token_mint {
+            None => SOL_DECIMALS,
+            Some(token_mint) => {
+                let mint = ctx
+                    .accounts
+                    .mint
+                    .as_ref()
+                    .ok_or(MultisigError::TokenMintMismatch)?;
+                require!(mint.key() == token_mint, MultisigError::TokenMintMismatch);
+                mint.decimals
+            }
+        };
+
+        let approved_by: Vec<Pubkey> = vault
+            .signers
+            .iter()
+            .zip(proposal.approvals.iter())
+            .filter(|(_, &approved)| approved)
+            .map(|(signer, _)| *signer)
+            .collect();
+
+        let description = ProposalDescription {
+            proposal_id: proposal.proposal_id,
+            token_mint: proposal.token_mint,
+            amount: proposal.amount,
+            decimals,
+            ui_amount: format_ui_amount(proposal.amount, decimals),
+            recipient: proposal.recipient,
+            approvals: approved_by.len() as u8,
+            required_approvals: vault.required_threshold(proposal.token_mint, proposal.amount),
+            approved_by,
+            executed: proposal.executed,
+            executable_at: proposal.executable_at(vault),
+        };
+
+        let asset = match description.token_mint {
+            None => "SOL".to_string(),
+            Some(token_mint) => token_mint.to_string(),
+        };
+        msg!(
+            "proposal={} asset={} amount={} recipient={} approvals={}/{} executed={}",
+            description.proposal_id,
+            asset,
+            description.ui_amount,
+            description.recipient,
+            description.approvals,
+            description.required_approvals,
+            description.executed
+        );
+
+        Ok(description)
+    }
+}
+
+/// Render `amount` base units with `decimals` places, trimming trailing zeros ("1.5", "20").
+/// Grow `account` to `space` bytes, the payer topping up its rent.
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    space: usize,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let rent = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if rent > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: account.clone(),
+                },
+            ),
+            rent,
+        )?;
+    }
+    account.resize(space)?;
+
+    Ok(())
+}
+
+pub fn format_ui_amount(amount: u64, decimals: u8) -> String {
+    let divisor = 10u128.pow(decimals as u32);
+    let whole = amount as u128 / divisor;
+    let fraction = amount as u128 % divisor;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MultisigVault {
+    pub vault_id: u64,
+    #[max_len(5)]
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub vault_bump: u8,
+    pub creator: Pubkey,
+    #[max_len(MAX_THRESHOLD_BANDS)]
+    pub threshold_bands: Vec<ThresholdBand>,
+    /// Minimum age in seconds of a proposal before it can be executed
+    pub review_period: i64,
+    /// Whether transfers may only go to `allowed_recipients`
+    pub allowlist_enabled: bool,
+    #[max_len(MAX_ALLOWED_RECIPIENTS)]
+    pub allowed_recipients: Vec<Pubkey>,
+}
+
+/// Vault layout before amount bands, review periods and allowlists, see `migrate_vault`
+#[derive(AnchorDeserialize)]
+pub struct LegacyMultisigVault {
+    pub vault_id: u64,
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub vault_bump: u8,
+    pub creator: Pubkey,
+}
+
+impl MultisigVault {
+    /// Approvals needed to execute a proposal moving `amount` of `token_mint` (None for SOL).
+    /// The matching band with the highest `min_amount` wins, otherwise the vault threshold applies.
+    pub fn required_threshold(&self, token_mint: Option<Pubkey>, amount: u64) -> u8 {
+        self.threshold_bands
+            .iter()
+            .filter(|band| band.token_mint == token_mint && amount >= band.min_amount)
+            .max_by_key(|band| band.min_amount)
+            .map(|band| band.threshold)
+            .unwrap_or(self.threshold)
+    }
+
+    /// Whether transfers to `recipient` pass the vault's allowlist.
+    pub fn is_recipient_allowed(&self, recipient: &Pubkey) -> bool {
+        !self.allowlist_enabled || self.allowed_recipients.contains(recipient)
+    }
+}
+
+/// Threshold override for proposals of one asset with `amount >= min_amount`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct ThresholdBand {
+    /// Asset the band applies to, `None` for SOL
+    pub token_mint: Option<Pubkey>,
+    pub min_amount: u64,
+    pub threshold: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TransferProposal {
+    pub vault: Pubkey,
+    pub proposer: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub token_mint: Option<Pubkey>,
+    #[max_len(5)]
+    pub approvals: Vec<bool>,
+    pub executed: bool,
+    pub proposal_id: u64,
+    pub created_at: i64,
+}
+
+/// Transfer proposal layout before review periods, see `migrate_proposal`
+#[derive(AnchorDeserialize)]
+pub struct LegacyTransferProposal {
+    pub vault: Pubkey,
+    pub proposer: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub token_mint: Option<Pubkey>,
+    pub approvals: Vec<bool>,
+    pub executed: bool,
+    pub proposal_id: u64,
+}
+
+/// State every proposal kind shares: one approval slot per vault signer, the execution
+/// flag and the creation time.
+pub trait Proposal {
+    fn approvals_mut(&mut self) -> &mut Vec<bool>;
+    fn executed(&self) -> bool;
+    fn created_at(&self) -> i64;
+
+    /// Earliest time the proposal can be executed, given the vault's review period.
+    fn executable_at(&self, vault: &MultisigVault) -> i64 {
+        self.created_at().saturating_add(vault.review_period)
+    }
+
+    /// Record the approval of `approver`, a vault signer who has not approved yet, while the
+    /// proposal is pending.
+    fn approve(&mut self, vault: &MultisigVault, approver: &Pubkey) -> Result<()> {
+        require!(!self.executed(), MultisigError::AlreadyExecuted);
+
+        let approver_index = vault
+            .signers
+            .iter()
+            .position(|s| s == approver)
+            .ok_or(MultisigError::InvalidSigner)?;
+
+        let approval = self
+            .approvals_mut()
+            .get_mut(approver_index)
+            .ok_or(MultisigError::InvalidSigner)?;
+        require!(!*approval, MultisigError::AlreadyApproved);
+        *approval = true;
+
+        Ok(())
+    }
+}
+
+impl Proposal for TransferProposal {
+    fn approvals_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.approvals
+    }
+    fn executed(&self) -> bool {
+        self.executed
+    }
+    fn created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+/// Vault configuration change, proposed and approved like a transfer
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub enum ConfigChange {
+    SetAllowlistEnabled { enabled: bool },
+    AddAllowedRecipient { recipient: Pubkey },
+    RemoveAllowedRecipient { recipient: Pubkey },
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ConfigProposal {
+    pub vault: Pubkey,
+    pub proposer: Pubkey,
+    pub proposal_id: u64,
+    pub change: ConfigChange,
+    #[max_len(5)]
+    pub approvals: Vec<bool>,
+    pub executed: bool,
+    pub created_at: i64,
+}
+
+impl Proposal for ConfigProposal {
+    fn approvals_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.approvals
+    }
+    fn executed(&self) -> bool {
+        self.executed
+    }
+    fn created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+/// Delegate change on a vault token account, proposed and approved like a transfer
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub enum DelegateAction {
+    /// Let `delegate` move up to `amount` base units out of the token account
+    Approve { delegate: Pubkey, amount: u64 },
+    /// Remove the current delegate
+    Revoke,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct DelegateProposal {
+    pub vault: Pubkey,
+    pub proposer: Pubkey,
+    pub proposal_id: u64,
+    /// Vault token account whose delegate changes
+    pub token_account: Pubkey,
+    pub mint: Pubkey,
+    pub action: DelegateAction,
+    #[max_len(5)]
+    pub approvals: Vec<bool>,
+    pub executed: bool,
+    pub created_at: i64,
+}
+
+impl Proposal for DelegateProposal {
+    fn approvals_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.approvals
+    }
+    fn executed(&self) -> bool {
+        self.executed
+    }
+    fn created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+/// Upgrade of a program whose upgrade authority is the vault
+#[account]
+#[derive(InitSpace)]
+pub struct UpgradeProposal {
+    pub vault: Pubkey,
+    pub proposer: Pubkey,
+    pub proposal_id: u64,
+    /// Program to upgrade
+    pub program: Pubkey,
+    /// Buffer holding the new program data
+    pub buffer: Pubkey,
+    #[max_len(5)]
+    pub approvals: Vec<bool>,
+    pub executed: bool,
+    pub created_at: i64,
+}
+
+impl Proposal for UpgradeProposal {
+    fn approvals_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.approvals
+    }
+    fn executed(&self) -> bool {
+        self.executed
+    }
+    fn created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+/// Staking admin instruction called on a pool whose authority is the vault
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub enum PoolAdminAction {
+    EnableEmergencyMode,
+    DisableEmergencyMode,
+    ChangePoolCooldown {
+        cooldown: i64,
+    },
+    ChangeMinStakeAmount {
+        min_stake_amount: u64,
+    },
+    ChangeMaxOpenDeposits {
+        max_open_deposits: u16,
+    },
+    ChangeEndTimestamp {
+        end_timestamp: i64,
+    },
+    ChangeEmergencyWithdrawDelay {
+        delay: i64,
+    },
+    ChangeReferralShare {
+        referral_bps: u16,
+    },
+    ChangeInstantUnstakeFee {
+        fee_bps: u16,
+    },
+    ChangeMaxSlash {
+        max_slash_bps: u16,
+    },
+    ConfigureClaimVesting {
+        duration: i64,
+    },
+    ConfigureLockBoost {
+        boost_bps: u16,
+    },
+    SweepDust,
+    /// Hand the pool to another authority, which ends the vault's control over it
+    SetPoolAuthority {
+        new_authority: Pubkey,
+    },
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PoolAdminProposal {
+    pub vault: Pubkey,
+    pub proposer: Pubkey,
+    pub proposal_id: u64,
+    /// Staking pool to administer
+    pub pool: Pubkey,
+    pub action: PoolAdminAction,
+    #[max_len(5)]
+    pub approvals: Vec<bool>,
+    pub executed: bool,
+    pub created_at: i64,
+}
+
+impl Proposal for PoolAdminProposal {
+    fn approvals_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.approvals
+    }
+    fn executed(&self) -> bool {
+        self.executed
+    }
+    fn created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(vault_id: u64)]
+pub struct CreateVault<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + MultisigVault::INIT_SPACE,
+        seeds = [b"multisig_vault", creator.key().as_ref(), vault_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct ProposeTransfer<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + TransferProposal::INIT_SPACE,
+        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, TransferProposal>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveTransfer<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        mut,
+        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, TransferProposal>,
+
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSolTransfer<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        mut,
+        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault,
+        // Legacy proposals read a zero creation time, which would skip the review period
+        constraint = proposal.to_account_info().data_len() >= 8 + TransferProposal::INIT_SPACE
+            @ MultisigError::ProposalNotMigrated
+    )]
+    pub proposal: Account<'info, TransferProposal>,
+
+    /// CHECK: SOL account for vault (separate PDA without data)
+    #[account(
+        mut,
+        seeds = [b"vault_sol", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub vault_sol_account: AccountInfo<'info>,
+
+    /// CHECK: Recipient account, must be the one the signers approved
+    #[account(
+        mut,
+        address = proposal.recipient @ MultisigError::RecipientMismatch
+    )]
+    pub recipient: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSplTransfer<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    /// CHECK: PDA signer for vault
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault_pda: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault,
+        // Legacy proposals read a zero creation time, which would skip the review period
+        constraint = proposal.to_account_info().data_len() >= 8 + TransferProposal::INIT_SPACE
+            @ MultisigError::ProposalNotMigrated
+    )]
+    pub proposal: Account<'info, TransferProposal>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.mint == mint.key() @ MultisigError::InvalidTokenAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    /// Must belong to the recipient the signers approved
+    #[account(
+        mut,
+        constraint = recipient_token_account.mint == mint.key() @ MultisigError::InvalidTokenAccount,
+        constraint = recipient_token_account.owner == proposal.recipient @ MultisigError::RecipientMismatch
+    )]
+    pub recipient_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateProposal<'info> {
+    /// CHECK: legacy proposal that may no longer deserialize as `TransferProposal`;
+    /// ownership is checked here, the discriminator and layout in `migrate_proposal`
+    #[account(mut, owner = crate::ID)]
+    pub proposal: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateVault<'info> {
+    /// CHECK: legacy vault that no longer deserializes as `MultisigVault`; ownership is
+    /// checked here, the discriminator and layout in `migrate_vault`
+    #[account(mut, owner = crate::ID)]
+    pub vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct ProposeConfigChange<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + ConfigProposal::INIT_SPACE,
+        seeds = [b"config_proposal", vault.key().as_ref(), proposal_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, ConfigProposal>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveConfigChange<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        mut,
+        seeds = [b"config_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, ConfigProposal>,
+
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        mut,
+        seeds = [b"config_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, ConfigProposal>,
+}
+
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct ProposeDelegateChange<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + DelegateProposal::INIT_SPACE,
+        seeds = [b"delegate_proposal", vault.key().as_ref(), proposal_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, DelegateProposal>,
+
+    #[account(
+        constraint = token_account.owner == vault.key() @ MultisigError::InvalidTokenAccount
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveDelegateChange<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        mut,
+        seeds = [b"delegate_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, DelegateProposal>,
+
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteDelegateChange<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    /// CHECK: PDA signer for vault
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault_pda: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegate_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault,
+        has_one = mint @ MultisigError::TokenMintMismatch,
+        has_one = token_account @ MultisigError::InvalidTokenAccount
+    )]
+    pub proposal: Account<'info, DelegateProposal>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: delegate the signers approved, required for `Approve`
+    pub delegate: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct ProposeProgramUpgrade<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + UpgradeProposal::INIT_SPACE,
+        seeds = [b"upgrade_proposal", vault.key().as_ref(), proposal_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, UpgradeProposal>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveProgramUpgrade<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        mut,
+        seeds = [b"upgrade_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, UpgradeProposal>,
+
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteProgramUpgrade<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    /// CHECK: PDA signer for vault, the program's upgrade authority
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault_pda: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"upgrade_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, UpgradeProposal>,
+
+    /// CHECK: program being upgraded, pinned to the proposal
+    #[account(mut, address = proposal.program @ MultisigError::ProgramMismatch)]
+    pub program: UncheckedAccount<'info>,
+
+    /// CHECK: program data account of `program`, validated by the loader
+    #[account(
+        mut,
+        seeds = [program.key().as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID
+    )]
+    pub program_data: UncheckedAccount<'info>,
+
+    /// CHECK: buffer with the new code, pinned to the proposal
+    #[account(mut, address = proposal.buffer @ MultisigError::BufferMismatch)]
+    pub buffer: UncheckedAccount<'info>,
+
+    /// CHECK: receives the buffer's lamports
+    #[account(mut)]
+    pub spill: UncheckedAccount<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
+
+    /// CHECK: the upgradeable BPF loader
+    #[account(address = bpf_loader_upgradeable::ID)]
+    pub bpf_loader_upgradeable_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct ProposePoolAdmin<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + PoolAdminProposal::INIT_SPACE,
+        seeds = [b"pool_admin_proposal", vault.key().as_ref(), proposal_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, PoolAdminProposal>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApprovePoolAdmin<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_admin_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, PoolAdminProposal>,
+
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecutePoolAdmin<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    /// CHECK: PDA signer for vault, the pool authority
+    #[account(
+        mut,
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault_pda: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_admin_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, PoolAdminProposal>,
+
+    /// CHECK: staking pool, pinned to the proposal and validated by the staking program
+    #[account(mut, address = proposal.pool @ MultisigError::PoolMismatch)]
+    pub pool: UncheckedAccount<'info>,
+
+    /// CHECK: stake mint of the pool, validated by the staking program
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: stake vault of the pool, validated by the staking program
+    #[account(mut)]
+    pub pool_vault: UncheckedAccount<'info>,
+
+    /// CHECK: token account required by the staking program's `UpdatePool`, unused by the
+    /// actions above
+    #[account(mut)]
+    pub creator_ata: UncheckedAccount<'info>,
+
+    pub staking_program: Program<'info, Staking>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DescribeProposal<'info> {
+    #[account(
+        seeds = [b"multisig_vault", vault.creator.as_ref(), vault.vault_id.to_le_bytes().as_ref()],
+        bump = vault.vault_bump
+    )]
+    pub vault: Account<'info, MultisigVault>,
+
+    #[account(
+        seeds = [b"transfer_proposal", vault.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump,
+        has_one = vault
+    )]
+    pub proposal: Account<'info, TransferProposal>,
+
+    /// Mint of SPL proposals, used for its decimals
+    pub mint: Option<Account<'info, Mint>>,
+}
+
+/// Return data of `describe_proposal`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProposalDescription {
+    pub proposal_id: u64,
+    /// `None` for SOL
+    pub token_mint: Option<Pubkey>,
+    /// Amount in base units
+    pub amount: u64,
+    pub decimals: u8,
+    /// Amount in UI units, e.g. "1.5"
+    pub ui_amount: String,
+    pub recipient: Pubkey,
+    pub approvals: u8,
+    pub required_approvals: u8,
+    pub approved_by: Vec<Pubkey>,
+    pub executed: bool,
+    /// End of the vault's review period for this proposal
+    pub executable_at: i64,
+}
+
+#[error_code]
 pub enum MultisigError {
     #[msg("Signers list cannot be empty")]
     EmptySigners,
@@ -424,4 +1842,65 @@ pub enum MultisigError {
     TokenMintMismatch,
     #[msg("Invalid token account")]
     InvalidTokenAccount,
+    #[msg("Too many threshold bands")]
+    TooManyThresholdBands,
+    #[msg("Recipient does not match the approved proposal")]
+    RecipientMismatch,
+    #[msg("Review period cannot be negative")]
+    InvalidReviewPeriod,
+    #[msg("Proposal is still in its review period")]
+    ReviewPeriodNotElapsed,
+    #[msg("Recipient is not on the vault's allowlist")]
+    RecipientNotAllowed,
+    #[msg("Recipient is already on the vault's allowlist")]
+    RecipientAlreadyAllowed,
+    #[msg("Too many allowed recipients")]
+    TooManyAllowedRecipients,
+    #[msg("Delegate does not match the approved proposal")]
+    DelegateMismatch,
+    #[msg("Program does not match the approved proposal")]
+    ProgramMismatch,
+    #[msg("Buffer does not match the approved proposal")]
+    BufferMismatch,
+    #[msg("Threshold bands cannot require fewer approvals than the vault threshold")]
+    BandThresholdBelowVault,
+    #[msg("Vault already uses the current layout")]
+    VaultAlreadyMigrated,
+    #[msg("Proposal already uses the current layout")]
+    ProposalAlreadyMigrated,
+    #[msg("Proposal uses a legacy layout, migrate it first")]
+    ProposalNotMigrated,
+    #[msg("Pool does not match the approved proposal")]
+    PoolMismatch,
+}
+
+#[event]
+pub struct ConfigChangeExecuted {
+    pub vault: Pubkey,
+    pub proposal_id: u64,
+    pub change: ConfigChange,
+}
+
+#[event]
+pub struct DelegateChangeExecuted {
+    pub vault: Pubkey,
+    pub proposal_id: u64,
+    pub token_account: Pubkey,
+    pub action: DelegateAction,
+}
+
+#[event]
+pub struct ProgramUpgraded {
+    pub vault: Pubkey,
+    pub proposal_id: u64,
+    pub program: Pubkey,
+    pub buffer: Pubkey,
+}
+
+#[event]
+pub struct PoolAdminExecuted {
+    pub vault: Pubkey,
+    pub proposal_id: u64,
+    pub pool: Pubkey,
+    pub action: PoolAdminAction,
 }
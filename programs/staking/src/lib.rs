@@ -1,25 +1,109 @@
 use anchor_lang::prelude::*;
-use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, TransferChecked};
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::system_program;
+use anchor_spl::associated_token::{self, get_associated_token_address, AssociatedToken};
+use anchor_spl::metadata::{
+    create_metadata_accounts_v3, mpl_token_metadata::types::DataV2, CreateMetadataAccountsV3,
+    Metadata, MetadataAccount,
+};
+use anchor_spl::token::spl_token::instruction::AuthorityType;
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token::{
+    self, Burn, CloseAccount, Mint, MintTo, SetAuthority, SyncNative, Token, TokenAccount,
+    TransferChecked,
+};
+use vesting::program::Vesting;
 
 declare_id!("ZnxPrdCiNFeCA79TVCrx5v57CkftWL3yS3LxmToK4UK");
 
+/// Maximum number of extra reward tokens a pool can distribute next to its reward mint.
+pub const MAX_REWARD_TOKENS: usize = 4;
+
+/// Scale of the per-share accumulators used for extra reward tokens.
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+/// Maximum number of pools listed in a creator's registry; later pools are not listed.
+pub const MAX_REGISTERED_POOLS: usize = 64;
+
+/// Upper bound for the fee a pool may take from reward payouts, in basis points.
+pub const MAX_REWARD_FEE_BPS: u16 = 1_000;
+
+/// Upper bound for the referrer's share of a staker's rewards, in basis points.
+pub const MAX_REFERRAL_BPS: u16 = 1_000;
+
+/// Lock duration at which a deposit's voting weight equals its amount (4 years).
+pub const MAX_VOTE_LOCK_DURATION: i64 = 4 * 365 * 24 * 60 * 60;
+
+/// Slots a deposit must age before its cooldown can start, and liquid shares before they
+/// can be redeemed, so capital cannot be staked and pulled out within one flash loan.
+pub const MIN_STAKE_SLOTS: u64 = 2;
+
+/// Maximum length in bytes of a pool's display name.
+pub const MAX_POOL_NAME_LEN: usize = 32;
+
+/// Maximum length in bytes of a pool's metadata URI.
+pub const MAX_METADATA_URI_LEN: usize = 200;
+
+/// Upper bound for the NFT reward boost multiplier, in basis points (3x).
+pub const MAX_NFT_BOOST_BPS: u16 = 30_000;
+
+/// Upper bound for the extra rewards of a lock extended by `MAX_VOTE_LOCK_DURATION`, in
+/// basis points (2x on top of the base rewards).
+pub const MAX_LOCK_BOOST_BPS: u16 = 20_000;
+
+/// Longest claim cooldown a pool may set, in seconds (one year).
+pub const MAX_CLAIM_COOLDOWN: i64 = 365 * 24 * 60 * 60;
+
+/// Seed of the dev clock PDA read by `current_timestamp` in `dev-clock` builds.
+pub const DEV_CLOCK_SEED: &[u8] = b"dev-clock";
+
+/// Direction `mul_div` rounds in. Payouts round down so the pool never pays out more
+/// than it holds, obligations round up so the pool never sets aside too little.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// `value * numerator / denominator` computed in u128 and rounded as asked.
+pub fn mul_div(value: u64, numerator: u64, denominator: u64, rounding: Rounding) -> Result<u64> {
+    require!(denominator != 0, StakingError::MathOverflow);
+
+    let product = value as u128 * numerator as u128;
+    let denominator = denominator as u128;
+    let mut result = product / denominator;
+    if rounding == Rounding::Up && product % denominator != 0 {
+        result += 1;
+    }
+
+    u64::try_from(result).map_err(|_| error!(StakingError::MathOverflow))
+}
+
+/// Share of `total_rewards` earned by `user_staked_tokens` out of `total_staked_tokens`,
+/// rounded down as a payout. Zero when nothing is staked.
 pub fn economy_estimate_rewards(
     total_staked_tokens: u64,
     user_staked_tokens: u64,
     total_rewards: u64,
 ) -> u64 {
-    // parse those into u128 to avoid overflow
-    let user_staked_tokens_u128 = user_staked_tokens as u128;
-    let total_rewards_u128 = total_rewards as u128;
-    let total_staked_tokens_u128 = total_staked_tokens as u128;
+    // The user's stake is part of the total, so the share always fits
+    mul_div(
+        user_staked_tokens,
+        total_rewards,
+        total_staked_tokens,
+        Rounding::Down,
+    )
+    .unwrap_or(0)
+}
 
-    let final_result_u128 =
-        (user_staked_tokens_u128 * total_rewards_u128) / total_staked_tokens_u128;
+/// Vote-escrow weight of `amount` locked for `lock_duration` seconds, scaled so that
+/// a lock of `MAX_VOTE_LOCK_DURATION` or longer counts the full amount.
+pub fn economy_voting_weight(amount: u64, lock_duration: i64) -> u64 {
+    let lock_duration = lock_duration.clamp(0, MAX_VOTE_LOCK_DURATION) as u128;
 
-    let final_result_u64 = final_result_u128 as u64;
+    let weight = (amount as u128 * lock_duration) / MAX_VOTE_LOCK_DURATION as u128;
 
-    final_result_u64
+    weight as u64
 }
 
 #[program]
@@ -28,34 +112,116 @@ pub mod staking {
 
     // ********* START POOL CREATOR FUNCTIONS **************
     /// Create a new staking pool. Any user can create a pool and becomes its authority.
+    /// Stakers deposit `mint` and earn `reward_mint`, which may be the same token.
+    /// Staked principal lives in `pool_vault` and rewards in `reward_vault`, so reward
+    /// payouts can never draw from stakers' principal.
     pub fn create_pool(
         ctx: Context<CreatePool>,
         pool_id: u64,
         initial_funding_amount: u64,
         claim_cooldown: i64,
+        charge_reward_fee: bool,
     ) -> Result<()> {
+        let config = &ctx.accounts.global_config;
         let pool = &mut ctx.accounts.pool;
 
+        require!(!config.paused, StakingError::ProtocolPaused);
+        require!(
+            (0..=MAX_CLAIM_COOLDOWN).contains(&claim_cooldown),
+            StakingError::InvalidCooldown
+        );
+
+        pool.referral_bps = 0;
+        pool.total_voting_weight = 0;
+        pool.max_slash_bps = 0;
+        pool.reward_lockup = RewardLockupConfig::default();
+        pool.reward_drip = RewardDrip::default();
+        pool.min_stake_amount = 0;
+        pool.cooldown_stake = 0;
+        pool.emergency_reserve = EmergencyReserve::default();
+        pool.min_reward_age = 0;
+        pool.end_timestamp = 0;
+        pool.name = String::new();
+        pool.metadata_uri = String::new();
+        pool.nft_boost = NftBoostConfig::default();
+        pool.emergency_withdraw_delay = config.min_emergency_withdraw_delay;
+        pool.share_mint = None;
+        pool.total_shares = 0;
+        pool.liquid_staked = 0;
+        pool.claim_vesting_duration = 0;
+        pool.lifetime_staked = 0;
+        pool.lifetime_rewards_paid = 0;
+        pool.unique_staker_count = 0;
+        pool.last_liquid_stake_slot = 0;
+        pool.lock_boost_bps = 0;
+        pool.max_open_deposits = 0;
+        pool.instant_unstake_fee_bps = 0;
+        pool.reward_dust = 0;
+        // Opt-in fee on reward payouts, locked in at the protocol default
+        pool.reward_fee_bps = if charge_reward_fee {
+            config.default_reward_fee_bps
+        } else {
+            0
+        };
+
         // Configure bumps
         let bump = ctx.bumps.pool;
         pool.bump = bump;
+        pool.version = StakingPool::VERSION;
+        pool.reward_vault_bump = ctx.bumps.reward_vault;
 
         // Configure authority and identity
         pool.pool_id = pool_id;
         pool.creator = *ctx.accounts.creator.key;
+        pool.authority = *ctx.accounts.creator.key;
+        pool.mint = ctx.accounts.mint.key();
+        pool.reward_mint = ctx.accounts.reward_mint.key();
 
         // Set default pool values
         pool.current_tokens_staked = 0;
         pool.current_rewards = initial_funding_amount;
         pool.claim_cooldown = claim_cooldown;
         pool.emergency_mode_enabled = false;
+        pool.last_emergency_withdraw_slot = 0;
+
+        // Protocol fee in lamports for creating a pool, paid to the treasury
+        if config.pool_creation_fee > 0 {
+            let treasury = ctx
+                .accounts
+                .treasury
+                .as_ref()
+                .ok_or(StakingError::MissingFeeTreasury)?;
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.creator.to_account_info(),
+                        to: treasury.to_account_info(),
+                    },
+                ),
+                config.pool_creation_fee,
+            )?;
+        }
+
+        // Record the pool in the creator's registry for discovery. A full registry only
+        // lists the creator's first pools, it never blocks creating more.
+        let registry = &mut ctx.accounts.pool_registry;
+        registry.creator = *ctx.accounts.creator.key;
+        registry.bump = ctx.bumps.pool_registry;
+        if registry.pools.len() < MAX_REGISTERED_POOLS {
+            registry.pools.push(RegisteredPool {
+                pool: pool.key(),
+                mint: pool.mint,
+                reward_mint: pool.reward_mint,
+            });
+        }
 
-        // Send the tokens from the creator to the pool if initial funding is provided
+        // Send the reward tokens from the creator to the pool if initial funding is provided
         if initial_funding_amount > 0 {
             token::transfer_checked(
                 ctx.accounts.into_transfer_to_pda_context(),
                 initial_funding_amount,
-                ctx.accounts.mint.decimals,
+                ctx.accounts.reward_mint.decimals,
             )?;
         }
 
@@ -63,412 +229,5387 @@ pub mod staking {
     }
 
     /// Fund rewards pool. Only the pool creator can fund their pool.
-    pub fn fund_pool(ctx: Context<UpdatePool>, amount: u64) -> Result<()> {
+    pub fn fund_pool(ctx: Context<FundPool>, amount: u64) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
         let pool = &mut ctx.accounts.pool;
 
-        // Verify the signer is the pool creator
+        // Verify the signer is the pool authority
         require!(
-            pool.creator == *ctx.accounts.creator.key,
+            pool.authority == *ctx.accounts.creator.key,
             StakingError::UnauthorizedPoolAccess
         );
+        require!(!pool.has_ended(now), StakingError::PoolEnded);
 
         pool.current_rewards += amount;
 
-        // Send the tokens from the creator to the pool
+        // Send the reward tokens from the creator to the pool
         token::transfer_checked(
             ctx.accounts.into_transfer_to_pda_context(),
             amount,
-            ctx.accounts.mint.decimals,
+            ctx.accounts.reward_mint.decimals,
         )?;
 
         Ok(())
     }
 
-    /// Enable emergency mode where people can withdraw their tokens and the pool creator can withdraw the rewards.
-    /// Only the pool creator can enable emergency mode.
-    pub fn enable_emergency_mode(ctx: Context<UpdatePool>) -> Result<()> {
+    /// Fund rewards that unlock linearly between `start` and `end` instead of all at once.
+    /// Only the pool creator can fund their pool, and only once the previous drip is over.
+    pub fn fund_pool_drip(
+        ctx: Context<FundPool>,
+        amount: u64,
+        start: i64,
+        end: i64,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool_key = ctx.accounts.pool.key();
         let pool = &mut ctx.accounts.pool;
 
-        // Verify the signer is the pool creator
+        // Verify the signer is the pool authority
         require!(
-            pool.creator == *ctx.accounts.creator.key,
+            pool.authority == *ctx.accounts.creator.key,
             StakingError::UnauthorizedPoolAccess
         );
+        require!(amount > 0 && end > start, StakingError::InvalidRewardDrip);
+        require!(!pool.has_ended(now), StakingError::PoolEnded);
 
-        // Require the mode to have been disabled
+        pool.release_drip(now)?;
         require!(
-            !pool.emergency_mode_enabled,
-            StakingError::EmergencyModeAlreadyEnabled
+            pool.reward_drip.remaining() == 0,
+            StakingError::RewardDripInProgress
         );
-        pool.emergency_mode_enabled = true;
-
-        Ok(())
-    }
 
-    /// Change pool cooldown period. Only affects new cooldowns.
-    /// Only the pool creator can change the cooldown.
-    pub fn change_pool_cooldown(ctx: Context<UpdatePool>, new_cooldown: i64) -> Result<()> {
-        let pool = &mut ctx.accounts.pool;
+        pool.reward_drip = RewardDrip {
+            amount,
+            released: 0,
+            start,
+            end,
+        };
 
-        // Verify the signer is the pool creator
-        require!(
-            pool.creator == *ctx.accounts.creator.key,
-            StakingError::UnauthorizedPoolAccess
-        );
+        // Send the reward tokens from the creator to the pool
+        token::transfer_checked(
+            ctx.accounts.into_transfer_to_pda_context(),
+            amount,
+            ctx.accounts.reward_mint.decimals,
+        )?;
 
-        pool.claim_cooldown = new_cooldown;
+        emit!(RewardDripFunded {
+            pool: pool_key,
+            amount,
+            start,
+            end,
+        });
 
         Ok(())
     }
 
-    // ********* END POOL CREATOR FUNCTIONS **************
-
-    /// Create a staker deposit in a pool.
-    pub fn stake(
-        ctx: Context<CreateDeposit>,
-        deposit_id: u64,
-        deposit_amount: u64,
+    /// Extend a running reward campaign: add `additional_funding` to the rewards still
+    /// dripping and spread both from now until `new_end`. A pool with an end timestamp
+    /// ends at `new_end` as well. Only the pool creator can extend their pool.
+    pub fn extend_rewards(
+        ctx: Context<FundPool>,
+        new_end: i64,
+        additional_funding: u64,
     ) -> Result<()> {
-        let deposit = &mut ctx.accounts.deposit;
-        let staker_stats = &mut ctx.accounts.staker_stats;
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool_key = ctx.accounts.pool.key();
         let pool = &mut ctx.accounts.pool;
 
-        let now = Clock::get()?.unix_timestamp;
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(!pool.has_ended(now), StakingError::PoolEnded);
 
-        // Depositing tokens is only allowed if the pool is not in emergency mode
+        pool.release_drip(now)?;
+        let start = now.max(pool.reward_drip.start);
         require!(
-            pool.emergency_mode_enabled == false,
-            StakingError::EmergencyModeEnabled
+            new_end > start && new_end >= pool.reward_drip.end,
+            StakingError::InvalidRewardDrip
+        );
+        require!(
+            pool.end_timestamp == 0 || new_end >= pool.end_timestamp,
+            StakingError::InvalidEndTimestamp
         );
 
-        deposit.deposit_id = deposit_id;
-        deposit.tokens_deposited = deposit_amount;
-        deposit.tokens_claimed = 0;
-        deposit.unlock_timestamp = now + pool.claim_cooldown;
-        deposit.is_withdrawn = false;
-        deposit.is_cooldown_active = false;
-        deposit.bump = ctx.bumps.deposit;
+        let amount = pool
+            .reward_drip
+            .remaining()
+            .checked_add(additional_funding)
+            .ok_or(StakingError::MathOverflow)?;
+        require!(amount > 0, StakingError::InvalidRewardDrip);
 
-        // Update stats
-        staker_stats.staker = *ctx.accounts.staker.key;
-        staker_stats.total_staked += deposit_amount;
-        staker_stats.bump = ctx.bumps.staker_stats;
+        // Restart the drip so the new rate applies from now on
+        pool.reward_drip = RewardDrip {
+            amount,
+            released: 0,
+            start,
+            end: new_end,
+        };
+        if pool.end_timestamp != 0 {
+            pool.end_timestamp = new_end;
+        }
 
-        // Update the pool
-        pool.current_tokens_staked += deposit_amount;
+        // Send the reward tokens from the creator to the pool
+        if additional_funding > 0 {
+            token::transfer_checked(
+                ctx.accounts.into_transfer_to_pda_context(),
+                additional_funding,
+                ctx.accounts.reward_mint.decimals,
+            )?;
+        }
 
-        // Send the tokens from the staker to the pool
-        token::transfer_checked(
-            ctx.accounts.into_transfer_to_pda_context(),
-            deposit_amount,
-            ctx.accounts.mint.decimals,
-        )?;
+        emit!(RewardsExtended {
+            pool: pool_key,
+            additional_funding,
+            drip_amount: amount,
+            start,
+            end: new_end,
+        });
 
         Ok(())
     }
 
-    /// Activate cooldown for a deposit to enable unstaking.
-    pub fn activate_cooldown(
-        ctx: Context<ActivateDepositCooldown>,
-        _deposit_id: u64,
-    ) -> Result<()> {
-        let deposit = &mut ctx.accounts.deposit;
+    /// Enable emergency mode where people can withdraw their tokens and the pool creator can withdraw the rewards.
+    /// Only the pool creator can enable emergency mode.
+    pub fn enable_emergency_mode(ctx: Context<UpdatePool>) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        let now = Clock::get()?.unix_timestamp;
 
+        // Verify the signer is the pool authority
         require!(
-            deposit.is_withdrawn == false,
-            StakingError::DepositAlreadyWithdrawn
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
         );
 
+        // Require the mode to have been disabled
         require!(
-            deposit.is_cooldown_active == false,
-            StakingError::CooldownAlreadyActivated
+            !pool.emergency_mode_enabled,
+            StakingError::EmergencyModeAlreadyEnabled
         );
+        pool.emergency_mode_enabled = true;
+
+        // Set aside the rewards of deposits already in cooldown
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        pool.release_drip(now)?;
+        // Rounded up, the reserve covers every payout; what is left once the last of these
+        // deposits exits becomes dust
+        let reserved = if pool.current_tokens_staked == 0 {
+            0
+        } else {
+            mul_div(
+                pool.cooldown_stake,
+                pool.current_rewards,
+                pool.current_tokens_staked,
+                Rounding::Up,
+            )?
+        };
+        pool.emergency_reserve = EmergencyReserve {
+            enabled_at: now,
+            total_staked: pool.current_tokens_staked,
+            total_rewards: pool.current_rewards,
+            reserved,
+        };
 
-        deposit.is_cooldown_active = true;
-        deposit.unlock_timestamp = now + pool.claim_cooldown;
         Ok(())
     }
 
-    /// Unstake tokens from a pool after cooldown has elapsed.
-    pub fn unstake(ctx: Context<UnstakeDeposit>, _deposit_id: u64) -> Result<()> {
-        // Extract values from pool and deposit before mutable borrow
-        let pool_creator = ctx.accounts.pool.creator;
-        let pool_id = ctx.accounts.pool.pool_id;
-        let pool_bump = ctx.accounts.pool.bump;
-        let emergency_mode_enabled = ctx.accounts.pool.emergency_mode_enabled;
-        let pool_total_staked_tokens = ctx.accounts.pool.current_tokens_staked;
-        let pool_total_rewards_tokens = ctx.accounts.pool.current_rewards;
-
-        let deposit_is_withdrawn = ctx.accounts.deposit.is_withdrawn;
-        let deposit_is_cooldown_active = ctx.accounts.deposit.is_cooldown_active;
-        let deposit_unlock_timestamp = ctx.accounts.deposit.unlock_timestamp;
-        let user_total_staked_tokens = ctx.accounts.deposit.tokens_deposited;
-
-        let signer_seeds: [&[&[u8]]; 1] = [&[
-            b"pool",
-            pool_creator.as_ref(),
-            &pool_id.to_le_bytes()[..],
-            &[pool_bump],
-        ]];
-
-        let now = Clock::get()?.unix_timestamp;
+    /// Turn emergency mode back off after a false alarm.
+    /// Refused in the same slot as an emergency withdrawal so in-flight exits are not cut off.
+    pub fn disable_emergency_mode(ctx: Context<UpdatePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let slot = Clock::get()?.slot;
 
-        // If the pool has emergency mode turned on, we can ignore the time.
+        // Verify the signer is the pool authority
         require!(
-            emergency_mode_enabled == false,
-            StakingError::EmergencyModeEnabled
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
         );
 
-        // Require the deposit to not be withdrawn
         require!(
-            deposit_is_withdrawn == false,
-            StakingError::DepositAlreadyWithdrawn
+            pool.emergency_mode_enabled,
+            StakingError::EmergencyModeNotEnabled
         );
 
         require!(
-            deposit_is_cooldown_active == true,
-            StakingError::ClaimCooldownNotActive
+            slot > pool.last_emergency_withdraw_slot,
+            StakingError::EmergencyWithdrawalInFlight
         );
+        pool.emergency_mode_enabled = false;
+        // Cooldowns pay out normally again
+        pool.emergency_reserve.reserved = 0;
+
+        emit!(EmergencyModeDisabled {
+            pool: pool.key(),
+            creator: pool.creator,
+            slot,
+        });
+
+        Ok(())
+    }
+
+    /// Change pool cooldown period, between 0 and `MAX_CLAIM_COOLDOWN`. Only affects new
+    /// cooldowns: activated ones keep the unlock time they were started with.
+    /// Only the pool creator can change the cooldown.
+    pub fn change_pool_cooldown(ctx: Context<UpdatePool>, new_cooldown: i64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
 
-        // Require the user to have waited long enough to unstake
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
         require!(
-            now >= deposit_unlock_timestamp,
-            StakingError::ClaimCooldownNotElapsed
+            (0..=MAX_CLAIM_COOLDOWN).contains(&new_cooldown),
+            StakingError::InvalidCooldown
         );
 
-        // Calculate the user's rewards based on their share of tokens in the total staked tokens
-        let user_rewards = economy_estimate_rewards(
-            pool_total_staked_tokens,
-            user_total_staked_tokens,
-            pool_total_rewards_tokens,
+        // Share tokens are redeemed on demand, so their pools cannot have a cooldown
+        require!(
+            new_cooldown == 0 || pool.share_mint.is_none(),
+            StakingError::ShareTokenUnsupported
         );
 
-        // Now get mutable borrows for updates
-        let deposit = &mut ctx.accounts.deposit;
-        let staker_stats = &mut ctx.accounts.staker_stats;
-        let pool = &mut ctx.accounts.pool;
+        pool.claim_cooldown = new_cooldown;
 
-        // Mark the deposit as withdrawn
-        deposit.is_withdrawn = true;
+        Ok(())
+    }
 
-        // Set the claimed amount in the deposit
-        deposit.tokens_claimed = user_rewards;
+    /// Hand the pool's administration to `new_authority`, e.g. a multisig vault PDA. Pool
+    /// instructions then take the authority as their `creator` signer, which a program
+    /// can provide by signing for its PDA through CPI.
+    pub fn set_pool_authority(ctx: Context<UpdatePool>, new_authority: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
 
-        // Update stats
-        staker_stats.total_staked -= user_total_staked_tokens;
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
 
-        // Remove the reward tokens from the pool
-        pool.current_rewards -= user_rewards;
+        emit!(PoolAuthorityChanged {
+            pool: pool.key(),
+            old_authority: pool.authority,
+            new_authority,
+        });
+        pool.authority = new_authority;
 
-        // Subtract the user's tokens from the pool
-        pool.current_tokens_staked -= user_total_staked_tokens;
+        Ok(())
+    }
 
-        // Get mint decimals before using ctx.accounts
-        let mint_decimals = ctx.accounts.mint.decimals;
+    /// Set the smallest amount a single deposit can stake, 0 to allow any amount.
+    pub fn change_min_stake_amount(ctx: Context<UpdatePool>, min_stake_amount: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
 
-        // Send their initial deposit back
-        token::transfer_checked(
-            ctx.accounts
-                .into_withdraw_context()
-                .with_signer(&signer_seeds),
-            user_total_staked_tokens,
-            mint_decimals,
-        )?;
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
 
-        // Send the rewards from the pool to the staker
-        token::transfer_checked(
-            ctx.accounts
-                .into_withdraw_context()
-                .with_signer(&signer_seeds),
-            user_rewards,
-            mint_decimals,
-        )?;
+        pool.min_stake_amount = min_stake_amount;
 
         Ok(())
     }
 
-    /// Emergency unstake tokens (no rewards). Only works when pool is in emergency mode.
-    pub fn unstake_emergency(
-        ctx: Context<UnstakeDepositEmergency>,
-        _deposit_id: u64,
+    /// Set how many open deposits a staker may hold when staking here, 0 for no limit.
+    /// Open deposits are counted per wallet and pool, on the wallet's deposit counter.
+    pub fn change_max_open_deposits(
+        ctx: Context<UpdatePool>,
+        max_open_deposits: u16,
     ) -> Result<()> {
-        // Extract values before any borrows
-        let pool_creator = ctx.accounts.pool.creator;
-        let pool_id = ctx.accounts.pool.pool_id;
-        let pool_bump = ctx.accounts.pool.bump;
-        let tokens_deposited = ctx.accounts.deposit.tokens_deposited;
-        let mint_decimals = ctx.accounts.mint.decimals;
-
-        let signer_seeds: [&[&[u8]]; 1] = [&[
-            b"pool",
-            pool_creator.as_ref(),
-            &pool_id.to_le_bytes()[..],
-            &[pool_bump],
-        ]];
+        let pool = &mut ctx.accounts.pool;
 
-        // Send their initial deposit back
-        token::transfer_checked(
-            ctx.accounts
-                .into_withdraw_context()
-                .with_signer(&signer_seeds),
-            tokens_deposited,
-            mint_decimals,
-        )?;
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
 
-        let pool_mut = &mut ctx.accounts.pool;
-        let deposit = &mut ctx.accounts.deposit;
-        let staker_stats = &mut ctx.accounts.staker_stats;
+        pool.max_open_deposits = max_open_deposits;
 
-        let emergency_mode_enabled = pool_mut.emergency_mode_enabled;
+        Ok(())
+    }
 
-        // If the pool has emergency mode turned off, fail
-        require!(
-            emergency_mode_enabled == true,
-            StakingError::EmergencyModeNotEnabled
-        );
+    /// Set how long in seconds a deposit must be staked before it earns rewards, 0 for no
+    /// minimum. Younger deposits leave their share in the pool when they unstake.
+    pub fn change_min_reward_age(ctx: Context<UpdatePool>, min_reward_age: i64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
 
-        // Require the deposit to not be withdrawn
+        // Verify the signer is the pool authority
         require!(
-            deposit.is_withdrawn == false,
-            StakingError::DepositAlreadyWithdrawn
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
         );
+        require!(min_reward_age >= 0, StakingError::InvalidMinRewardAge);
 
-        // Mark the deposit as withdrawn
-        deposit.is_withdrawn = true;
-
-        // Update stats
-        staker_stats.total_staked -= deposit.tokens_deposited;
-
-        // Subtract the user's tokens from the pool
-        pool_mut.current_tokens_staked -= deposit.tokens_deposited;
+        pool.min_reward_age = min_reward_age;
 
         Ok(())
     }
 
-    /// Emergency withdraw rewards. Only pool creator can withdraw rewards in emergency mode.
-    pub fn withdraw_rewards_emergency(ctx: Context<WithdrawRewardsEmergency>) -> Result<()> {
-        // Extract values from pool before mutable borrow
-        let pool_creator = ctx.accounts.pool.creator;
-        let pool_id = ctx.accounts.pool.pool_id;
-        let pool_bump = ctx.accounts.pool.bump;
-        let current_rewards_in_pool = ctx.accounts.pool.current_rewards;
-        let emergency_mode_enabled = ctx.accounts.pool.emergency_mode_enabled;
+    /// Set when the pool ends, 0 for an open-ended pool. From then on staking is rejected,
+    /// the reward drip stops releasing and the pool can no longer be funded.
+    pub fn change_end_timestamp(ctx: Context<UpdatePool>, end_timestamp: i64) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool = &mut ctx.accounts.pool;
 
-        // Verify the signer is the pool creator
+        // Verify the signer is the pool authority
         require!(
-            pool_creator == *ctx.accounts.creator.key,
+            pool.authority == *ctx.accounts.creator.key,
             StakingError::UnauthorizedPoolAccess
         );
-
         require!(
-            emergency_mode_enabled,
-            StakingError::EmergencyModeNotEnabled
+            end_timestamp == 0 || end_timestamp > now,
+            StakingError::InvalidEndTimestamp
         );
 
-        let signer_seeds: [&[&[u8]]; 1] = [&[
-            b"pool",
-            pool_creator.as_ref(),
-            &pool_id.to_le_bytes()[..],
-            &[pool_bump],
-        ]];
+        // Settle the drip up to now so moving the end never releases past rewards twice
+        pool.release_drip(now)?;
+        pool.end_timestamp = end_timestamp;
 
-        // Get mint decimals before using ctx.accounts
-        let mint_decimals = ctx.accounts.mint.decimals;
+        Ok(())
+    }
 
-        // Remove the reward tokens from the pool
+    /// Set how long in seconds after enabling emergency mode the creator has to wait before
+    /// withdrawing the rewards, so stakers get to exit first. The protocol minimum still
+    /// applies, and the delay cannot change while emergency mode is on.
+    pub fn change_emergency_withdraw_delay(ctx: Context<UpdatePool>, delay: i64) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        pool.current_rewards = 0;
 
-        token::transfer_checked(
-            ctx.accounts
-                .into_withdraw_context()
-                .with_signer(&signer_seeds),
-            current_rewards_in_pool,
-            mint_decimals,
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(delay >= 0, StakingError::InvalidEmergencyWithdrawDelay);
+        require!(
+            !pool.emergency_mode_enabled,
+            StakingError::EmergencyModeEnabled
+        );
+
+        pool.emergency_withdraw_delay = delay;
+
+        Ok(())
+    }
+
+    /// Set the pool's display name and metadata URI for explorers and front-ends.
+    /// Empty strings clear them.
+    pub fn update_pool_metadata(
+        ctx: Context<UpdatePool>,
+        name: String,
+        metadata_uri: String,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(name.len() <= MAX_POOL_NAME_LEN, StakingError::PoolNameTooLong);
+        require!(
+            metadata_uri.len() <= MAX_METADATA_URI_LEN,
+            StakingError::MetadataUriTooLong
+        );
+
+        pool.name = name;
+        pool.metadata_uri = metadata_uri;
+
+        emit!(PoolMetadataUpdated {
+            pool: pool.key(),
+            name: pool.name.clone(),
+            metadata_uri: pool.metadata_uri.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Set the share of stakers' rewards paid to their referrers, in basis points.
+    pub fn change_referral_share(ctx: Context<UpdatePool>, referral_bps: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(
+            referral_bps <= MAX_REFERRAL_BPS,
+            StakingError::ReferralShareTooHigh
+        );
+
+        pool.referral_bps = referral_bps;
+
+        Ok(())
+    }
+
+    /// Let stakers skip the cooldown through `unstake_instant` for a fee of `fee_bps` of
+    /// their principal, which goes to the pool's rewards. Zero turns instant unstaking off.
+    /// Only for pools that stake and reward the same token.
+    pub fn change_instant_unstake_fee(ctx: Context<UpdatePool>, fee_bps: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(
+            fee_bps <= 10_000,
+            StakingError::InvalidInstantUnstakeFee
+        );
+        if fee_bps > 0 {
+            require!(
+                pool.mint == pool.reward_mint,
+                StakingError::InstantUnstakeRequiresSameMint
+            );
+        }
+
+        pool.instant_unstake_fee_bps = fee_bps;
+
+        Ok(())
+    }
+
+    /// Move the reward dust left over by rounding back into the pool's rewards.
+    pub fn sweep_dust(ctx: Context<UpdatePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(pool.reward_dust > 0, StakingError::NoDustToSweep);
+
+        let amount = pool.reward_dust;
+        pool.reward_dust = 0;
+        pool.current_rewards += amount;
+
+        emit!(DustSwept {
+            pool: pool.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Set the most a single `slash` may take from a deposit, in basis points. The cap
+    /// can always be lowered but only raised while nothing is staked in the pool.
+    pub fn change_max_slash(ctx: Context<UpdatePool>, max_slash_bps: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(max_slash_bps <= 10_000, StakingError::InvalidSlashBps);
+        require!(
+            max_slash_bps <= pool.max_slash_bps || pool.current_tokens_staked == 0,
+            StakingError::SlashCapLocked
+        );
+
+        pool.max_slash_bps = max_slash_bps;
+
+        Ok(())
+    }
+
+    /// Route reward payouts above `threshold` into a vesting schedule for the staker,
+    /// created through the vesting program with the given cliff and interval.
+    /// A zero threshold turns the lockup off.
+    pub fn configure_reward_lockup(
+        ctx: Context<UpdatePool>,
+        threshold: u64,
+        cliff_duration: i64,
+        interval_duration: i64,
+        unlock_percentage: u8,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        if threshold > 0 {
+            require!(
+                cliff_duration > 0
+                    && interval_duration > 0
+                    && unlock_percentage > 0
+                    && unlock_percentage <= 100,
+                StakingError::InvalidRewardLockup
+            );
+        }
+
+        pool.reward_lockup = RewardLockupConfig {
+            threshold,
+            cliff_duration,
+            interval_duration,
+            unlock_percentage,
+        };
+
+        Ok(())
+    }
+
+    /// Hold back the rewards paid at unstake in a per-claim escrow that streams them to
+    /// the staker linearly over `duration` seconds, see `release_claimed_rewards`. Takes
+    /// precedence over the reward lockup. A zero duration pays rewards out immediately.
+    pub fn configure_claim_vesting(ctx: Context<UpdatePool>, duration: i64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(duration >= 0, StakingError::InvalidClaimVesting);
+
+        pool.claim_vesting_duration = duration;
+
+        Ok(())
+    }
+
+    /// Multiply the rewards of stakers holding an NFT of the verified `collection` by
+    /// `multiplier_bps` / 10_000 when they unstake. The extra is taken from the pool's
+    /// remaining rewards. A zero multiplier turns the boost off.
+    pub fn configure_nft_boost(
+        ctx: Context<UpdatePool>,
+        collection: Pubkey,
+        multiplier_bps: u16,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        if multiplier_bps > 0 {
+            require!(
+                multiplier_bps > 10_000 && multiplier_bps <= MAX_NFT_BOOST_BPS,
+                StakingError::InvalidNftBoost
+            );
+        }
+
+        pool.nft_boost = NftBoostConfig {
+            collection,
+            multiplier_bps,
+        };
+
+        Ok(())
+    }
+
+    /// Reward stakers who extend their deposit's lock through `extend_lock`: an extension of
+    /// `MAX_VOTE_LOCK_DURATION` adds `boost_bps` / 10_000 to their rewards, shorter ones a
+    /// linear part of it. The extra is taken from the pool's remaining rewards. Zero turns
+    /// extensions off; deposits already extended keep their boost.
+    pub fn configure_lock_boost(ctx: Context<UpdatePool>, boost_bps: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(
+            boost_bps <= MAX_LOCK_BOOST_BPS,
+            StakingError::InvalidLockBoost
+        );
+
+        pool.lock_boost_bps = boost_bps;
+
+        Ok(())
+    }
+
+    /// Let stakers stake through a share token: `stake_liquid` mints shares of the pool's
+    /// liquid position and `unstake_liquid` burns them for principal plus accrued rewards.
+    /// Only for fresh pools that stake and reward the same token, without a cooldown, fee
+    /// or extra reward tokens.
+    pub fn enable_share_token(ctx: Context<EnableShareToken>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(pool.share_mint.is_none(), StakingError::ShareTokenAlreadyEnabled);
+        require!(pool.current_tokens_staked == 0, StakingError::PoolHasStakers);
+        require!(
+            pool.mint == pool.reward_mint
+                && pool.claim_cooldown == 0
+                && pool.reward_fee_bps == 0
+                && pool.reward_tokens.is_empty(),
+            StakingError::ShareTokenUnsupported
+        );
+
+        pool.share_mint = Some(ctx.accounts.share_mint.key());
+
+        emit!(ShareTokenEnabled {
+            pool: pool.key(),
+            share_mint: ctx.accounts.share_mint.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Freeze a deposit, e.g. for compliance or abuse cases: it cannot start its cooldown,
+    /// be unstaked or change hands until `unfreeze_deposit`. It keeps earning meanwhile.
+    pub fn freeze_deposit(ctx: Context<FreezeDeposit>, deposit_id: u64) -> Result<()> {
+        set_deposit_frozen(ctx, deposit_id, true)
+    }
+
+    /// Lift a freeze set by `freeze_deposit`.
+    pub fn unfreeze_deposit(ctx: Context<FreezeDeposit>, deposit_id: u64) -> Result<()> {
+        set_deposit_frozen(ctx, deposit_id, false)
+    }
+
+    /// Punish a staker by moving `bps` of their deposit's principal into the pool's rewards,
+    /// up to the pool's slashing cap. Only for pools that stake and reward the same token.
+    pub fn slash(ctx: Context<SlashDeposit>, _deposit_id: u64, bps: u16) -> Result<()> {
+        let pool_creator = ctx.accounts.pool.creator;
+        let pool_id = ctx.accounts.pool.pool_id;
+        let pool_bump = ctx.accounts.pool.bump;
+
+        let pool = &mut ctx.accounts.pool;
+        let deposit = &mut ctx.accounts.deposit;
+        let staker_stats = &mut ctx.accounts.staker_stats;
+
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(
+            pool.mint == pool.reward_mint,
+            StakingError::SlashRequiresSameMint
+        );
+        require!(
+            bps > 0 && bps <= pool.max_slash_bps,
+            StakingError::InvalidSlashBps
+        );
+        require!(
+            !deposit.is_withdrawn,
+            StakingError::DepositAlreadyWithdrawn
+        );
+
+        let amount = ((deposit.tokens_deposited as u128 * bps as u128) / 10_000) as u64;
+        let voting_weight = ((deposit.voting_weight as u128 * bps as u128) / 10_000) as u64;
+        require!(amount > 0, StakingError::NothingToSlash);
+
+        // Extra reward tokens earned on the slashed part stay with the staker
+        pool.release_drip(current_timestamp(ctx.remaining_accounts)?)?;
+        deposit.settle_reward_tokens(&pool.reward_tokens)?;
+        deposit.tokens_deposited -= amount;
+        for (index, reward_token) in pool.reward_tokens.iter().enumerate() {
+            deposit.reward_debts[index] = reward_token.accumulated(deposit.tokens_deposited)?;
+        }
+        deposit.voting_weight -= voting_weight;
+
+        staker_stats.total_staked -= amount;
+        staker_stats.voting_weight -= voting_weight;
+        ctx.accounts.deposit_counter.remove_stake(amount);
+
+        pool.current_tokens_staked -= amount;
+        pool.total_voting_weight -= voting_weight;
+        pool.current_rewards += amount;
+        if deposit.is_cooldown_active {
+            pool.cooldown_stake -= amount;
+        }
+
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.pool_vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.reward_vault.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        emit!(Slashed {
+            pool: ctx.accounts.pool.key(),
+            staker: ctx.accounts.staker.key(),
+            deposit_id: ctx.accounts.deposit.deposit_id,
+            bps,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Register an extra reward token distributed alongside the pool's reward mint.
+    /// Only the pool creator can add reward tokens.
+    pub fn add_reward_token(ctx: Context<AddRewardToken>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let reward_token_mint = ctx.accounts.reward_token_mint.key();
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+
+        require!(pool.share_mint.is_none(), StakingError::ShareTokenUnsupported);
+        require!(
+            pool.reward_tokens.len() < MAX_REWARD_TOKENS,
+            StakingError::TooManyRewardTokens
+        );
+        require!(
+            pool.reward_tokens
+                .iter()
+                .all(|reward_token| reward_token.mint != reward_token_mint),
+            StakingError::RewardTokenAlreadyAdded
+        );
+
+        pool.reward_tokens.push(RewardTokenInfo {
+            mint: reward_token_mint,
+            vault: ctx.accounts.reward_token_vault.key(),
+            acc_reward_per_share: 0,
+            total_funded: 0,
+            undistributed: 0,
+            emission_rate: 0,
+            emission_remaining: 0,
+            last_emission_at: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Fund an extra reward token. The amount is shared pro-rata between the tokens
+    /// staked right now. Only the pool creator can fund reward tokens.
+    pub fn fund_reward_token(ctx: Context<FundRewardToken>, amount: u64) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool = &mut ctx.accounts.pool;
+        let reward_token_mint = ctx.accounts.reward_token_mint.key();
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(!pool.has_ended(now), StakingError::PoolEnded);
+
+        let current_tokens_staked = pool.current_tokens_staked;
+        let reward_token = pool
+            .reward_tokens
+            .iter_mut()
+            .find(|reward_token| reward_token.mint == reward_token_mint)
+            .ok_or(StakingError::RewardTokenNotFound)?;
+
+        reward_token.total_funded = reward_token
+            .total_funded
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        reward_token.distribute(amount, current_tokens_staked)?;
+
+        // Send the reward tokens from the creator to the reward token vault
+        token::transfer_checked(
+            ctx.accounts.into_transfer_to_pda_context(),
+            amount,
+            ctx.accounts.reward_token_mint.decimals,
         )?;
 
-        Ok(())
+        Ok(())
+    }
+
+    /// Fund an extra reward token's emission and set its rate: the funded tokens are
+    /// streamed to stakers at `emission_rate` per second until they run out, independently
+    /// of the pool's other rewards. A rate of 0 pauses the emission.
+    pub fn fund_reward_token_emission(
+        ctx: Context<FundRewardToken>,
+        amount: u64,
+        emission_rate: u64,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool_key = ctx.accounts.pool.key();
+        let pool = &mut ctx.accounts.pool;
+        let reward_token_mint = ctx.accounts.reward_token_mint.key();
+
+        // Verify the signer is the pool authority
+        require!(
+            pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+        require!(!pool.has_ended(now), StakingError::PoolEnded);
+
+        // Stream what the old rate released before changing it
+        pool.release_drip(now)?;
+
+        let reward_token = pool
+            .reward_tokens
+            .iter_mut()
+            .find(|reward_token| reward_token.mint == reward_token_mint)
+            .ok_or(StakingError::RewardTokenNotFound)?;
+
+        reward_token.total_funded = reward_token
+            .total_funded
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        reward_token.emission_remaining = reward_token
+            .emission_remaining
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        reward_token.emission_rate = emission_rate;
+
+        emit!(RewardTokenEmissionSet {
+            pool: pool_key,
+            mint: reward_token_mint,
+            emission_rate,
+            emission_remaining: reward_token.emission_remaining,
+        });
+
+        if amount > 0 {
+            token::transfer_checked(
+                ctx.accounts.into_transfer_to_pda_context(),
+                amount,
+                ctx.accounts.reward_token_mint.decimals,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // ********* END POOL CREATOR FUNCTIONS **************
+
+    // ********* START PROTOCOL ADMIN FUNCTIONS **************
+    /// Create the global protocol config. Can only happen once, by the program's upgrade
+    /// authority, which becomes admin.
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        default_reward_fee_bps: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        require!(
+            default_reward_fee_bps <= MAX_REWARD_FEE_BPS,
+            StakingError::RewardFeeTooHigh
+        );
+        require!(
+            default_reward_fee_bps == 0 || treasury != Pubkey::default(),
+            StakingError::MissingFeeTreasury
+        );
+
+        let config = &mut ctx.accounts.global_config;
+        config.admin = *ctx.accounts.admin.key;
+        config.pending_admin = None;
+        config.default_reward_fee_bps = default_reward_fee_bps;
+        config.paused = false;
+        config.treasury = treasury;
+        config.min_emergency_withdraw_delay = 0;
+        config.pool_creation_fee = 0;
+        config.bump = ctx.bumps.global_config;
+
+        Ok(())
+    }
+
+    /// Update the fee, treasury and pause flag. Existing pools keep the fee they opted in to.
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        default_reward_fee_bps: u16,
+        treasury: Pubkey,
+        paused: bool,
+    ) -> Result<()> {
+        require!(
+            default_reward_fee_bps <= MAX_REWARD_FEE_BPS,
+            StakingError::RewardFeeTooHigh
+        );
+        require!(
+            default_reward_fee_bps == 0 || treasury != Pubkey::default(),
+            StakingError::MissingFeeTreasury
+        );
+
+        let config = &mut ctx.accounts.global_config;
+        config.default_reward_fee_bps = default_reward_fee_bps;
+        config.treasury = treasury;
+        config.paused = paused;
+
+        Ok(())
+    }
+
+    /// Set the shortest delay, in seconds, any pool must wait between enabling emergency mode
+    /// and withdrawing its rewards. Applies to existing pools too.
+    pub fn set_min_emergency_withdraw_delay(ctx: Context<UpdateConfig>, delay: i64) -> Result<()> {
+        require!(delay >= 0, StakingError::InvalidEmergencyWithdrawDelay);
+
+        ctx.accounts.global_config.min_emergency_withdraw_delay = delay;
+
+        Ok(())
+    }
+
+    /// Set the fee, in lamports, paid to the treasury for every new pool. Zero disables it.
+    pub fn set_pool_creation_fee(ctx: Context<UpdateConfig>, fee: u64) -> Result<()> {
+        let config = &mut ctx.accounts.global_config;
+        require!(
+            fee == 0 || config.treasury != Pubkey::default(),
+            StakingError::MissingFeeTreasury
+        );
+
+        config.pool_creation_fee = fee;
+
+        Ok(())
+    }
+
+    /// First step of an admin handover: nominate the new admin.
+    pub fn transfer_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.global_config.pending_admin = Some(new_admin);
+
+        Ok(())
+    }
+
+    /// Second step of an admin handover: the nominated admin accepts.
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.global_config;
+        require!(
+            config.pending_admin == Some(*ctx.accounts.new_admin.key),
+            StakingError::NotPendingAdmin
+        );
+
+        config.admin = *ctx.accounts.new_admin.key;
+        config.pending_admin = None;
+
+        Ok(())
+    }
+
+    /// Dev builds only: shift the clock seen by the program by `offset` seconds so QA can
+    /// fast-forward cooldowns and reward drips. Only the program's upgrade authority.
+    pub fn set_dev_clock(ctx: Context<SetDevClock>, offset: i64) -> Result<()> {
+        require!(cfg!(feature = "dev-clock"), StakingError::DevClockDisabled);
+
+        let dev_clock = &mut ctx.accounts.dev_clock;
+        dev_clock.authority = ctx.accounts.authority.key();
+        dev_clock.offset = offset;
+        dev_clock.bump = ctx.bumps.dev_clock;
+        Ok(())
+    }
+    // ********* END PROTOCOL ADMIN FUNCTIONS **************
+
+    /// Create a staker deposit in a pool.
+    ///
+    /// When `deposit_id` is `None` the next id from the staker's per-pool counter is used.
+    /// Explicit ids must not be lower than the counter, so ids are never reused.
+    /// The assigned id is returned via return data.
+    pub fn stake(
+        ctx: Context<CreateDeposit>,
+        deposit_id: Option<u64>,
+        deposit_amount: u64,
+        referrer: Option<Pubkey>,
+    ) -> Result<u64> {
+        ctx.accounts.deposit.bump = ctx.bumps.deposit;
+        ctx.accounts.deposit_counter.bump = ctx.bumps.deposit_counter;
+        ctx.accounts.staker_stats.bump = ctx.bumps.staker_stats;
+        ctx.accounts.staker_stats.version = StakerStats::VERSION;
+
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let deposit_id = open_deposit(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.deposit,
+            &mut ctx.accounts.deposit_counter,
+            &mut ctx.accounts.staker_stats,
+            ctx.accounts.referral_stats.as_mut(),
+            ctx.accounts.staker.key(),
+            deposit_id,
+            deposit_amount,
+            referrer,
+            now,
+        )?;
+
+        // Send the tokens from the staker to the pool
+        token::transfer_checked(
+            ctx.accounts.into_transfer_to_pda_context(),
+            deposit_amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        Ok(deposit_id)
+    }
+
+    /// Stake on behalf of a staker who approved the pool as delegate on their token account.
+    /// A relayer signs and pays the fees and rent; the deposit still belongs to the staker.
+    /// The staker does not sign, so the relayer cannot name a referrer for them, nor pick
+    /// the deposit id: the deposit always takes the next id of the staker's counter.
+    pub fn stake_with_delegate(
+        ctx: Context<StakeWithDelegate>,
+        deposit_amount: u64,
+    ) -> Result<u64> {
+        let staker_ata = &ctx.accounts.staker_ata;
+        require!(
+            staker_ata.delegate == COption::Some(ctx.accounts.pool.key()),
+            StakingError::PoolNotDelegate
+        );
+        require!(
+            staker_ata.delegated_amount >= deposit_amount,
+            StakingError::InsufficientDelegatedAmount
+        );
+
+        ctx.accounts.deposit.bump = ctx.bumps.deposit;
+        ctx.accounts.deposit_counter.bump = ctx.bumps.deposit_counter;
+        ctx.accounts.staker_stats.bump = ctx.bumps.staker_stats;
+        ctx.accounts.staker_stats.version = StakerStats::VERSION;
+
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let deposit_id = open_deposit(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.deposit,
+            &mut ctx.accounts.deposit_counter,
+            &mut ctx.accounts.staker_stats,
+            None,
+            ctx.accounts.staker.key(),
+            None,
+            deposit_amount,
+            None,
+            now,
+        )?;
+
+        let pool_creator = ctx.accounts.pool.creator;
+        let pool_id = ctx.accounts.pool.pool_id;
+        let pool_bump = ctx.accounts.pool.bump;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        // The pool moves the tokens as the staker's delegate
+        token::transfer_checked(
+            ctx.accounts
+                .into_transfer_to_pda_context()
+                .with_signer(&signer_seeds),
+            deposit_amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        Ok(deposit_id)
+    }
+
+    /// Stake again into one of the signer's withdrawn deposits, reusing its id and account
+    /// instead of opening a new one. The deposit starts over without a referrer.
+    /// Deposits whose rewards were locked up cannot be reused, as the lockup's vesting
+    /// schedule is tied to the deposit. Extra reward tokens owed to the deposit and its
+    /// claim escrow must be claimed first, restaking would otherwise drop them.
+    pub fn restake(ctx: Context<Restake>, _deposit_id: u64, deposit_amount: u64) -> Result<()> {
+        require!(
+            ctx.accounts.deposit.is_withdrawn,
+            StakingError::DepositNotWithdrawn
+        );
+
+        let deposit_key = ctx.accounts.deposit.key();
+        let (lockup_authority, _) =
+            Pubkey::find_program_address(&[b"reward-lockup", deposit_key.as_ref()], &crate::ID);
+        let (lockup_schedule, _) = Pubkey::find_program_address(
+            &[
+                b"vesting-schedule",
+                lockup_authority.as_ref(),
+                ctx.accounts.staker.key().as_ref(),
+            ],
+            &vesting::ID,
+        );
+        require_keys_eq!(
+            ctx.accounts.reward_lockup_schedule.key(),
+            lockup_schedule,
+            StakingError::MissingRewardLockupAccounts
+        );
+        require!(
+            ctx.accounts.reward_lockup_schedule.data_is_empty(),
+            StakingError::DepositRewardsLockedUp
+        );
+        require!(
+            ctx.accounts.deposit.rewards_owed.iter().all(|owed| *owed == 0)
+                && ctx.accounts.claim_escrow.data_is_empty(),
+            StakingError::DepositRewardsUnclaimed
+        );
+
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        fill_deposit(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.deposit,
+            &mut ctx.accounts.deposit_counter,
+            &mut ctx.accounts.staker_stats,
+            None,
+            ctx.accounts.staker.key(),
+            deposit_amount,
+            None,
+            now,
+        )?;
+
+        let deposit_counter = &mut ctx.accounts.deposit_counter;
+        deposit_counter.deposit_count = deposit_counter
+            .deposit_count
+            .checked_add(1)
+            .ok_or(StakingError::MathOverflow)?;
+
+        token::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.staker_ata.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.pool_vault.to_account_info(),
+                    authority: ctx.accounts.staker.to_account_info(),
+                },
+            ),
+            deposit_amount,
+            ctx.accounts.mint.decimals,
+        )
+    }
+
+    /// Stake native SOL in a pool of the wrapped SOL mint. The lamports are moved straight
+    /// into the pool vault and synced, so the staker doesn't need to wrap them first.
+    pub fn stake_sol(
+        ctx: Context<StakeSol>,
+        deposit_id: Option<u64>,
+        deposit_amount: u64,
+        referrer: Option<Pubkey>,
+    ) -> Result<u64> {
+        ctx.accounts.deposit.bump = ctx.bumps.deposit;
+        ctx.accounts.deposit_counter.bump = ctx.bumps.deposit_counter;
+        ctx.accounts.staker_stats.bump = ctx.bumps.staker_stats;
+        ctx.accounts.staker_stats.version = StakerStats::VERSION;
+
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let deposit_id = open_deposit(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.deposit,
+            &mut ctx.accounts.deposit_counter,
+            &mut ctx.accounts.staker_stats,
+            ctx.accounts.referral_stats.as_mut(),
+            ctx.accounts.staker.key(),
+            deposit_id,
+            deposit_amount,
+            referrer,
+            now,
+        )?;
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.staker.to_account_info(),
+                    to: ctx.accounts.pool_vault.to_account_info(),
+                },
+            ),
+            deposit_amount,
+        )?;
+        token::sync_native(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            SyncNative {
+                account: ctx.accounts.pool_vault.to_account_info(),
+            },
+        ))?;
+
+        Ok(deposit_id)
+    }
+
+    /// Stake into the pool's liquid position and receive share tokens, priced at the
+    /// position's value (principal plus accrued rewards) per share. Returns the shares
+    /// minted via return data.
+    pub fn stake_liquid(ctx: Context<StakeLiquid>, amount: u64) -> Result<u64> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.release_drip(now)?;
+
+        require!(
+            !pool.emergency_mode_enabled,
+            StakingError::EmergencyModeEnabled
+        );
+        require!(!pool.has_ended(now), StakingError::PoolEnded);
+        require!(
+            amount > 0 && amount >= pool.min_stake_amount,
+            StakingError::StakeBelowMinimum
+        );
+
+        let liquid_value = pool.liquid_value();
+        let shares = if pool.total_shares == 0 || liquid_value == 0 {
+            amount
+        } else {
+            ((amount as u128 * pool.total_shares as u128) / liquid_value as u128) as u64
+        };
+        require!(shares > 0, StakingError::StakeBelowMinimum);
+
+        pool.liquid_staked = pool
+            .liquid_staked
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        pool.current_tokens_staked = pool
+            .current_tokens_staked
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        pool.total_shares = pool
+            .total_shares
+            .checked_add(shares)
+            .ok_or(StakingError::MathOverflow)?;
+        pool.lifetime_staked += amount;
+        pool.last_liquid_stake_slot = Clock::get()?.slot;
+
+        let pool_creator = pool.creator;
+        let pool_id = pool.pool_id;
+        let pool_bump = pool.bump;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        // Send the tokens from the staker to the pool
+        token::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.staker_ata.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.pool_vault.to_account_info(),
+                    authority: ctx.accounts.staker.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    to: ctx.accounts.staker_share_ata.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            shares,
+        )?;
+
+        emit!(LiquidStaked {
+            pool: ctx.accounts.pool.key(),
+            staker: ctx.accounts.staker.key(),
+            amount,
+            shares,
+        });
+
+        Ok(shares)
+    }
+
+    /// Burn share tokens for their part of the liquid position: principal from the pool
+    /// vault and accrued rewards from the reward vault. In emergency mode only the
+    /// principal is paid.
+    pub fn unstake_liquid(ctx: Context<UnstakeLiquid>, shares: u64) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.release_drip(now)?;
+
+        require!(
+            shares > 0 && shares <= pool.total_shares,
+            StakingError::NotEnoughTokensToUnstake
+        );
+        // Shares change hands freely, so the guard covers the latest stake into the pool
+        require!(
+            Clock::get()?.slot >= pool.last_liquid_stake_slot.saturating_add(MIN_STAKE_SLOTS),
+            StakingError::StakeTooRecent
+        );
+
+        let value = ((pool.liquid_value() as u128 * shares as u128)
+            / pool.total_shares as u128) as u64;
+        let principal = ((pool.liquid_staked as u128 * shares as u128)
+            / pool.total_shares as u128) as u64;
+        let rewards = value.saturating_sub(principal);
+
+        require!(
+            ctx.accounts.pool_vault.amount >= principal,
+            StakingError::InsufficientPrincipalInVault
+        );
+        require!(
+            ctx.accounts.reward_vault.amount >= rewards,
+            StakingError::InsufficientRewardsInVault
+        );
+
+        pool.liquid_staked -= principal;
+        pool.current_tokens_staked -= principal;
+        pool.current_rewards -= rewards;
+        pool.lifetime_rewards_paid += rewards;
+        pool.total_shares -= shares;
+
+        let pool_creator = pool.creator;
+        let pool_id = pool.pool_id;
+        let pool_bump = pool.bump;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    from: ctx.accounts.staker_share_ata.to_account_info(),
+                    authority: ctx.accounts.staker.to_account_info(),
+                },
+            ),
+            shares,
+        )?;
+
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.pool_vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.staker_ata.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            principal,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        if rewards > 0 {
+            token::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.reward_vault.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.staker_ata.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &signer_seeds,
+                ),
+                rewards,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        emit!(LiquidUnstaked {
+            pool: ctx.accounts.pool.key(),
+            staker: ctx.accounts.staker.key(),
+            shares,
+            principal,
+            rewards,
+        });
+
+        Ok(())
+    }
+
+    /// Activate cooldown for a deposit to enable unstaking.
+    /// With a `keeper_tip` in lamports, anyone may execute the unstake through
+    /// `crank_auto_unstake` once the cooldown elapses and earns the tip, which the deposit
+    /// holds until then. The tip goes back to the staker if they exit or cancel themselves.
+    pub fn activate_cooldown(
+        ctx: Context<ActivateDepositCooldown>,
+        _deposit_id: u64,
+        keeper_tip: Option<u64>,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        start_cooldown(&mut ctx.accounts.deposit, &mut ctx.accounts.pool, now)?;
+
+        let Some(keeper_tip) = keeper_tip else {
+            return Ok(());
+        };
+
+        // The crank cannot burn a receipt on the holder's behalf
+        require!(
+            !ctx.accounts.deposit.has_receipt,
+            StakingError::DepositTokenized
+        );
+        ctx.accounts.deposit.auto_unstake_at = ctx.accounts.deposit.unlock_timestamp;
+
+        if keeper_tip > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.staker.to_account_info(),
+                        to: ctx.accounts.deposit.to_account_info(),
+                    },
+                ),
+                keeper_tip,
+            )?;
+            ctx.accounts.deposit.keeper_tip += keeper_tip;
+        }
+
+        Ok(())
+    }
+
+    /// Cancel an activated cooldown so the deposit keeps earning as if it was never started.
+    pub fn cancel_cooldown(
+        ctx: Context<ActivateDepositCooldown>,
+        _deposit_id: u64,
+    ) -> Result<()> {
+        let deposit = &mut ctx.accounts.deposit;
+        let pool = &mut ctx.accounts.pool;
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        require!(
+            !deposit.is_withdrawn,
+            StakingError::DepositAlreadyWithdrawn
+        );
+
+        require!(
+            deposit.is_cooldown_active,
+            StakingError::ClaimCooldownNotActive
+        );
+
+        // The deposit's emergency reserve stays tied to its cooldown
+        require!(
+            !pool.emergency_mode_enabled,
+            StakingError::EmergencyModeEnabled
+        );
+
+        // Same state as a freshly created deposit
+        deposit.is_cooldown_active = false;
+        pool.cooldown_stake -= deposit.tokens_deposited;
+        deposit.auto_unstake_at = 0;
+        deposit.unlock_timestamp = now + deposit.cooldown_duration(pool);
+        refund_keeper_tip(deposit, &ctx.accounts.staker)
+    }
+
+    /// Commit a deposit to a cooldown `extra_duration` seconds longer than the pool's, for
+    /// the pool's lock boost on its rewards and a higher voting weight. A running cooldown
+    /// is pushed back by the same amount. Extensions add up and cannot be undone.
+    pub fn extend_lock(
+        ctx: Context<ExtendLock>,
+        deposit_id: u64,
+        extra_duration: i64,
+    ) -> Result<()> {
+        let deposit = &mut ctx.accounts.deposit;
+        let pool = &mut ctx.accounts.pool;
+
+        require!(pool.lock_boost_bps > 0, StakingError::LockBoostDisabled);
+        require!(extra_duration > 0, StakingError::InvalidLockExtension);
+        require!(
+            !deposit.is_withdrawn,
+            StakingError::DepositAlreadyWithdrawn
+        );
+        require!(!deposit.is_frozen, StakingError::DepositFrozen);
+
+        deposit.lock_extension = deposit
+            .lock_extension
+            .checked_add(extra_duration)
+            .ok_or(StakingError::MathOverflow)?;
+        if deposit.is_cooldown_active {
+            deposit.unlock_timestamp = deposit
+                .unlock_timestamp
+                .checked_add(extra_duration)
+                .ok_or(StakingError::MathOverflow)?;
+            // A scheduled unstake must still come after the new unlock
+            if deposit.auto_unstake_at != 0 {
+                deposit.auto_unstake_at = deposit.auto_unstake_at.max(deposit.unlock_timestamp);
+            }
+        }
+
+        // Re-weigh the deposit for its longer lock, never below the weight it was made with
+        let voting_weight =
+            economy_voting_weight(deposit.tokens_deposited, deposit.cooldown_duration(pool))
+                .max(deposit.voting_weight);
+        let added_weight = voting_weight - deposit.voting_weight;
+        deposit.voting_weight = voting_weight;
+        ctx.accounts.staker_stats.voting_weight += added_weight;
+        pool.total_voting_weight += added_weight;
+
+        emit!(LockExtended {
+            pool: pool.key(),
+            staker: ctx.accounts.staker.key(),
+            deposit_id,
+            lock_extension: deposit.lock_extension,
+            unlock_timestamp: deposit.unlock_timestamp,
+            voting_weight,
+        });
+
+        Ok(())
+    }
+
+    /// Tokenize a deposit by minting a one-off receipt NFT with Metaplex metadata to the
+    /// staker. Bundle it with `stake` to receive the receipt at stake time. Unstaking a
+    /// tokenized deposit burns the receipt, so only its holder can exit the position.
+    pub fn mint_stake_receipt(
+        ctx: Context<MintStakeReceipt>,
+        _deposit_id: u64,
+        name: String,
+        symbol: String,
+        uri: String,
+    ) -> Result<()> {
+        let pool_creator = ctx.accounts.pool.creator;
+        let pool_id = ctx.accounts.pool.pool_id;
+        let pool_bump = ctx.accounts.pool.bump;
+
+        let deposit = &mut ctx.accounts.deposit;
+        require!(
+            !deposit.is_withdrawn,
+            StakingError::DepositAlreadyWithdrawn
+        );
+        require!(!deposit.has_receipt, StakingError::ReceiptAlreadyMinted);
+        deposit.has_receipt = true;
+
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    to: ctx.accounts.staker_receipt_ata.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            1,
+        )?;
+
+        create_metadata_accounts_v3(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_metadata_program.to_account_info(),
+                CreateMetadataAccountsV3 {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    mint_authority: ctx.accounts.pool.to_account_info(),
+                    payer: ctx.accounts.staker.to_account_info(),
+                    update_authority: ctx.accounts.pool.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            DataV2 {
+                name,
+                symbol,
+                uri,
+                seller_fee_basis_points: 0,
+                creators: None,
+                collection: None,
+                uses: None,
+            },
+            false,
+            true,
+            None,
+        )?;
+
+        // Fix the supply at one
+        token::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    current_authority: ctx.accounts.pool.to_account_info(),
+                    account_or_mint: ctx.accounts.receipt_mint.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            AuthorityType::MintTokens,
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    /// Move an active deposit to `new_owner` without unstaking, keeping its lock, cooldown
+    /// and accrued rewards. The deposit is re-created under the new owner's next deposit id,
+    /// which is returned via return data; the old deposit account is closed.
+    pub fn transfer_deposit(ctx: Context<TransferDeposit>, _deposit_id: u64) -> Result<u64> {
+        let deposit = &ctx.accounts.deposit;
+        require!(
+            !deposit.is_withdrawn,
+            StakingError::DepositAlreadyWithdrawn
+        );
+        require!(
+            ctx.accounts.new_owner.key() != ctx.accounts.staker.key(),
+            StakingError::InvalidNewOwner
+        );
+        // Tokenized deposits change hands through their receipt instead
+        require!(!deposit.has_receipt, StakingError::DepositTokenized);
+        require!(!deposit.is_frozen, StakingError::DepositFrozen);
+
+        // The deposit counts against the new owner's limit in the pool
+        ctx.accounts.deposit_counter.close_deposit(deposit.tokens_deposited);
+        let new_deposit_counter = &mut ctx.accounts.new_deposit_counter;
+        new_deposit_counter
+            .open_deposit(ctx.accounts.pool.max_open_deposits, deposit.tokens_deposited)?;
+        let new_deposit_id = new_deposit_counter.next_deposit_id;
+        new_deposit_counter.staker = ctx.accounts.new_owner.key();
+        new_deposit_counter.pool = ctx.accounts.pool.key();
+        new_deposit_counter.next_deposit_id = new_deposit_id
+            .checked_add(1)
+            .ok_or(StakingError::MathOverflow)?;
+        new_deposit_counter.deposit_count = new_deposit_counter
+            .deposit_count
+            .checked_add(1)
+            .ok_or(StakingError::MathOverflow)?;
+        new_deposit_counter.bump = ctx.bumps.new_deposit_counter;
+
+        let new_deposit = &mut ctx.accounts.new_deposit;
+        new_deposit.deposit_id = new_deposit_id;
+        new_deposit.tokens_deposited = deposit.tokens_deposited;
+        new_deposit.tokens_claimed = deposit.tokens_claimed;
+        new_deposit.unlock_timestamp = deposit.unlock_timestamp;
+        new_deposit.is_withdrawn = false;
+        new_deposit.is_cooldown_active = deposit.is_cooldown_active;
+        new_deposit.bump = ctx.bumps.new_deposit;
+        new_deposit.version = StakerDeposit::VERSION;
+        new_deposit.reward_debts = deposit.reward_debts;
+        new_deposit.rewards_owed = deposit.rewards_owed;
+        // The new owner decides about their own exit
+        new_deposit.auto_unstake_at = 0;
+        new_deposit.referrer = deposit.referrer;
+        new_deposit.voting_weight = deposit.voting_weight;
+        new_deposit.stake_slot = deposit.stake_slot;
+        new_deposit.staked_at = deposit.staked_at;
+        new_deposit.lock_extension = deposit.lock_extension;
+
+        // Move the stake between the owners' stats
+        ctx.accounts.staker_stats.total_staked -= deposit.tokens_deposited;
+        ctx.accounts.staker_stats.voting_weight -= deposit.voting_weight;
+        ctx.accounts.staker_stats.close_deposit();
+        let new_owner_stats = &mut ctx.accounts.new_owner_stats;
+        new_owner_stats.staker = ctx.accounts.new_owner.key();
+        new_owner_stats.total_staked += deposit.tokens_deposited;
+        new_owner_stats.voting_weight += deposit.voting_weight;
+        new_owner_stats.open_deposits += 1;
+        new_owner_stats.bump = ctx.bumps.new_owner_stats;
+        new_owner_stats.version = StakerStats::VERSION;
+
+        Ok(new_deposit_id)
+    }
+
+    /// Unstake tokens from a pool after cooldown has elapsed.
+    pub fn unstake(ctx: Context<UnstakeDeposit>, _deposit_id: u64) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        unstake_deposit(ctx.accounts, now)
+    }
+
+    /// Start the cooldown and unstake in one call, for pools without a claim cooldown.
+    pub fn unstake_now(ctx: Context<UnstakeDeposit>, _deposit_id: u64) -> Result<()> {
+        require!(
+            ctx.accounts.pool.claim_cooldown == 0,
+            StakingError::PoolHasCooldown
+        );
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        if !ctx.accounts.deposit.is_cooldown_active {
+            start_cooldown(&mut ctx.accounts.deposit, &mut ctx.accounts.pool, now)?;
+        }
+        unstake_deposit(ctx.accounts, now)
+    }
+
+    /// Unstake without waiting for the cooldown by paying the pool's instant unstake fee
+    /// out of the principal. The fee is added to the pool's rewards once the deposit is
+    /// paid, so it goes to the remaining stakers.
+    pub fn unstake_instant(ctx: Context<UnstakeDeposit>, deposit_id: u64) -> Result<()> {
+        let fee_bps = ctx.accounts.pool.instant_unstake_fee_bps;
+        require!(fee_bps > 0, StakingError::InstantUnstakeDisabled);
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        if !ctx.accounts.deposit.is_cooldown_active {
+            start_cooldown(&mut ctx.accounts.deposit, &mut ctx.accounts.pool, now)?;
+        }
+        // Skipping the lock also forfeits the boost of any lock extension
+        ctx.accounts.deposit.unlock_timestamp = now;
+        ctx.accounts.deposit.lock_extension = 0;
+
+        let fee = mul_div(
+            ctx.accounts.deposit.tokens_deposited,
+            fee_bps as u64,
+            10_000,
+            Rounding::Up,
+        )?;
+        if fee > 0 {
+            let pool = &mut ctx.accounts.pool;
+            let deposit = &mut ctx.accounts.deposit;
+
+            // Extra reward tokens earned on the fee stay with the staker
+            pool.release_drip(now)?;
+            deposit.settle_reward_tokens(&pool.reward_tokens)?;
+            deposit.tokens_deposited -= fee;
+            for (index, reward_token) in pool.reward_tokens.iter().enumerate() {
+                deposit.reward_debts[index] = reward_token.accumulated(deposit.tokens_deposited)?;
+            }
+            ctx.accounts.staker_stats.total_staked -= fee;
+            ctx.accounts.deposit_counter.remove_stake(fee);
+            pool.current_tokens_staked -= fee;
+            pool.cooldown_stake -= fee;
+
+            let pool_creator = pool.creator;
+            let pool_id = pool.pool_id;
+            let pool_bump = pool.bump;
+            let signer_seeds: [&[&[u8]]; 1] = [&[
+                b"pool",
+                pool_creator.as_ref(),
+                &pool_id.to_le_bytes()[..],
+                &[pool_bump],
+            ]];
+            token::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.pool_vault.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.reward_vault.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &signer_seeds,
+                ),
+                fee,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        unstake_deposit(ctx.accounts, now)?;
+        ctx.accounts.pool.current_rewards += fee;
+
+        emit!(InstantUnstaked {
+            pool: ctx.accounts.pool.key(),
+            staker: ctx.accounts.staker.key(),
+            deposit_id,
+            principal: ctx.accounts.deposit.tokens_deposited,
+            fee,
+        });
+
+        Ok(())
+    }
+
+    /// Unstake from a native SOL pool and unwrap the principal: `staker_ata` must be a wSOL
+    /// account of the staker, which is closed once paid so the staker receives lamports.
+    pub fn unstake_sol(ctx: Context<UnstakeDeposit>, _deposit_id: u64) -> Result<()> {
+        require_keys_eq!(
+            ctx.accounts.pool.mint,
+            native_mint::ID,
+            StakingError::NotNativeSolPool
+        );
+        // The principal is unwrapped from `staker_ata`, it cannot go elsewhere
+        require!(
+            ctx.accounts.destination.is_none(),
+            StakingError::InvalidDestination
+        );
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        unstake_deposit(ctx.accounts, now)?;
+
+        token::close_account(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.staker_ata.to_account_info(),
+                destination: ctx.accounts.staker.to_account_info(),
+                authority: ctx.accounts.staker.to_account_info(),
+            },
+        ))
+    }
+
+    /// Unstake several deposits of the signer in one go, with a single principal and
+    /// reward payout. `remaining_accounts` holds the writable deposit PDAs, all in `pool`.
+    /// Tokenized and referred deposits must be unstaked one by one, as must batches whose
+    /// rewards are above the pool's lockup threshold or go to a claim escrow.
+    pub fn unstake_many<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UnstakeMany<'info>>,
+    ) -> Result<()> {
+        // Remaining accounts hold the deposits, so the dev clock is a named account
+        let dev_clock = ctx.accounts.dev_clock.as_ref().map(|info| info.to_account_info());
+        let now = current_timestamp(dev_clock.as_slice())?;
+        let remaining_accounts = ctx.remaining_accounts;
+        let accounts = ctx.accounts;
+        let staker_key = accounts.staker.key();
+        let pool_key = accounts.pool.key();
+
+        require!(
+            !remaining_accounts.is_empty(),
+            StakingError::InvalidDepositBatch
+        );
+
+        let mut total_principal: u64 = 0;
+        let mut total_rewards: u64 = 0;
+        let mut last_deposit = None;
+        for deposit_info in remaining_accounts.iter() {
+            require!(
+                deposit_info.is_writable,
+                StakingError::InvalidDepositBatch
+            );
+
+            let mut deposit: Account<'info, StakerDeposit> = Account::try_from(deposit_info)?;
+            let deposit_key = Pubkey::create_program_address(
+                &[
+                    b"deposit",
+                    staker_key.as_ref(),
+                    pool_key.as_ref(),
+                    &deposit.deposit_id.to_le_bytes(),
+                    &[deposit.bump],
+                ],
+                &crate::ID,
+            )
+            .map_err(|_| StakingError::InvalidDepositBatch)?;
+            require_keys_eq!(
+                deposit_info.key(),
+                deposit_key,
+                StakingError::InvalidDepositBatch
+            );
+            require!(!deposit.has_receipt, StakingError::DepositTokenized);
+            require!(
+                deposit.referrer.is_none(),
+                StakingError::ReferredDepositInBatch
+            );
+
+            total_principal = total_principal
+                .checked_add(deposit.tokens_deposited)
+                .ok_or(StakingError::MathOverflow)?;
+            refund_keeper_tip(&mut deposit, &accounts.staker)?;
+            let rewards = close_deposit(
+                &mut deposit,
+                &mut accounts.staker_stats,
+                &mut accounts.deposit_counter,
+                &mut accounts.pool,
+                now,
+            )?;
+            total_rewards = total_rewards
+                .checked_add(rewards)
+                .ok_or(StakingError::MathOverflow)?;
+
+            // Persist the deposit now so a duplicate entry fails as already withdrawn
+            deposit.exit(&crate::ID)?;
+            last_deposit = Some(deposit);
+        }
+        let last_deposit = last_deposit.ok_or(StakingError::InvalidDepositBatch)?;
+
+        // Principal and rewards are paid from their own vaults
+        require!(
+            accounts.pool_vault.amount >= total_principal,
+            StakingError::InsufficientPrincipalInVault
+        );
+        require!(
+            accounts.reward_vault.amount >= total_rewards,
+            StakingError::InsufficientRewardsInVault
+        );
+
+        let pool_creator = accounts.pool.creator;
+        let pool_id = accounts.pool.pool_id.to_le_bytes();
+        let pool_bump = accounts.pool.bump;
+        let signer_seeds: [&[&[u8]]; 1] =
+            [&[b"pool", pool_creator.as_ref(), &pool_id[..], &[pool_bump]]];
+
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.pool_vault.to_account_info(),
+                    mint: accounts.mint.to_account_info(),
+                    to: accounts.staker_ata.to_account_info(),
+                    authority: accounts.pool.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            total_principal,
+            accounts.mint.decimals,
+        )?;
+
+        let fee = pay_reward_fee(
+            &accounts.pool,
+            total_rewards,
+            &accounts.reward_vault,
+            &accounts.reward_mint,
+            accounts.treasury_reward_ata.as_ref(),
+            &accounts.token_program,
+            &signer_seeds,
+        )?;
+        pay_staker_rewards(
+            &accounts.pool,
+            &last_deposit,
+            total_rewards - fee,
+            &accounts.reward_vault,
+            &accounts.reward_mint,
+            &accounts.staker,
+            &accounts.staker_reward_ata,
+            &accounts.staker,
+            None,
+            None,
+            &accounts.token_program,
+            &accounts.associated_token_program,
+            &accounts.system_program,
+            now,
+        )
+    }
+
+    /// Schedule an automatic unstake at `auto_unstake_at`, after which anyone can crank it
+    /// through `crank_auto_unstake`. Scheduling starts the deposit's cooldown if it is not
+    /// running yet, and the time must not be before the deposit unlocks. Pass 0 to clear.
+    pub fn set_auto_unstake(
+        ctx: Context<ActivateDepositCooldown>,
+        _deposit_id: u64,
+        auto_unstake_at: i64,
+    ) -> Result<()> {
+        let deposit = &mut ctx.accounts.deposit;
+        let pool = &mut ctx.accounts.pool;
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        require!(
+            !deposit.is_withdrawn,
+            StakingError::DepositAlreadyWithdrawn
+        );
+
+        if auto_unstake_at == 0 {
+            deposit.auto_unstake_at = 0;
+            return refund_keeper_tip(deposit, &ctx.accounts.staker);
+        }
+
+        // The crank cannot burn a receipt on the holder's behalf
+        require!(!deposit.has_receipt, StakingError::DepositTokenized);
+
+        if !deposit.is_cooldown_active {
+            require!(
+                !pool.emergency_mode_enabled,
+                StakingError::EmergencyModeEnabled
+            );
+            require!(
+                !deposit.is_too_recent(Clock::get()?.slot),
+                StakingError::StakeTooRecent
+            );
+            deposit.is_cooldown_active = true;
+            deposit.unlock_timestamp = now + deposit.cooldown_duration(pool);
+            pool.cooldown_stake += deposit.tokens_deposited;
+        }
+
+        require!(
+            auto_unstake_at >= deposit.unlock_timestamp,
+            StakingError::AutoUnstakeBeforeUnlock
+        );
+
+        deposit.auto_unstake_at = auto_unstake_at;
+        Ok(())
+    }
+
+    /// Permissionless crank executing a scheduled unstake once its time is reached.
+    /// Principal and rewards go to the staker's associated token accounts; the caller
+    /// only pays for creating them if they are missing.
+    pub fn crank_auto_unstake(ctx: Context<CrankAutoUnstake>, _deposit_id: u64) -> Result<()> {
+        let auto_unstake_at = ctx.accounts.deposit.auto_unstake_at;
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        require!(auto_unstake_at != 0, StakingError::AutoUnstakeNotScheduled);
+        require!(
+            !ctx.accounts.deposit.has_receipt,
+            StakingError::DepositTokenized
+        );
+        require!(
+            now >= auto_unstake_at,
+            StakingError::AutoUnstakeNotReached
+        );
+
+        let accounts = ctx.accounts;
+        accounts.deposit.auto_unstake_at = 0;
+
+        // The executor earns the tip the staker left for it
+        let tip = accounts.deposit.keeper_tip;
+        if tip > 0 {
+            accounts.deposit.keeper_tip = 0;
+            accounts.deposit.sub_lamports(tip)?;
+            accounts.cranker.add_lamports(tip)?;
+        }
+        let staker_rewards = withdraw_deposit(
+            &accounts.mint,
+            &accounts.reward_mint,
+            &mut accounts.deposit,
+            &mut accounts.staker_stats,
+            &mut accounts.deposit_counter,
+            &mut accounts.pool,
+            &accounts.pool_vault,
+            &accounts.reward_vault,
+            &accounts.staker_ata,
+            accounts.treasury_reward_ata.as_ref(),
+            accounts.referrer_reward_ata.as_ref(),
+            accounts.referral_stats.as_mut(),
+            &accounts.token_program,
+            now,
+        )?;
+        pay_staker_rewards(
+            &accounts.pool,
+            &accounts.deposit,
+            staker_rewards,
+            &accounts.reward_vault,
+            &accounts.reward_mint,
+            &accounts.staker,
+            &accounts.staker_reward_ata,
+            &accounts.cranker,
+            reward_lockup_accounts(
+                accounts.reward_lockup_authority.as_ref(),
+                accounts.reward_lockup_ata.as_ref(),
+                accounts.vesting_schedule.as_ref(),
+                accounts.vesting_vault.as_ref(),
+                accounts.vesting_program.as_ref(),
+            ),
+            accounts.claim_escrow.as_mut(),
+            &accounts.token_program,
+            &accounts.associated_token_program,
+            &accounts.system_program,
+            now,
+        )?;
+
+        emit!(AutoUnstakeExecuted {
+            pool: accounts.pool.key(),
+            staker: accounts.staker.key(),
+            deposit_id: accounts.deposit.deposit_id,
+            cranker: accounts.cranker.key(),
+            tip,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless crank rolling the pool's reward drip forward to now, so its
+    /// accounting stays fresh while nobody stakes or unstakes.
+    pub fn update_pool(ctx: Context<RefreshPool>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.release_drip(now)?;
+
+        emit!(PoolUpdated {
+            pool: pool.key(),
+            current_rewards: pool.current_rewards,
+            dripping_rewards: pool.reward_drip.remaining(),
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Emergency unstake tokens (no rewards). Only works when pool is in emergency mode.
+    pub fn unstake_emergency(
+        ctx: Context<UnstakeDepositEmergency>,
+        _deposit_id: u64,
+    ) -> Result<()> {
+        // Extract values before any borrows
+        let pool_creator = ctx.accounts.pool.creator;
+        let pool_id = ctx.accounts.pool.pool_id;
+        let pool_bump = ctx.accounts.pool.bump;
+        let tokens_deposited = ctx.accounts.deposit.tokens_deposited;
+        let mint_decimals = ctx.accounts.mint.decimals;
+
+        require!(!ctx.accounts.deposit.is_frozen, StakingError::DepositFrozen);
+
+        refund_keeper_tip(&mut ctx.accounts.deposit, &ctx.accounts.staker)?;
+        burn_receipt(
+            &ctx.accounts.deposit,
+            ctx.accounts.receipt_mint.as_ref(),
+            ctx.accounts.staker_receipt_ata.as_ref(),
+            &ctx.accounts.staker,
+            &ctx.accounts.token_program,
+        )?;
+
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        // Send their initial deposit back
+        token::transfer_checked(
+            ctx.accounts
+                .into_withdraw_context()
+                .with_signer(&signer_seeds),
+            tokens_deposited,
+            mint_decimals,
+        )?;
+
+        let pool_mut = &mut ctx.accounts.pool;
+        let deposit = &mut ctx.accounts.deposit;
+        let staker_stats = &mut ctx.accounts.staker_stats;
+
+        let emergency_mode_enabled = pool_mut.emergency_mode_enabled;
+
+        // If the pool has emergency mode turned off, fail
+        require!(
+            emergency_mode_enabled,
+            StakingError::EmergencyModeNotEnabled
+        );
+
+        // Require the deposit to not be withdrawn
+        require!(
+            !deposit.is_withdrawn,
+            StakingError::DepositAlreadyWithdrawn
+        );
+
+        // Lock in extra reward tokens earned so far, they stay claimable after withdrawal
+        deposit.settle_reward_tokens(&pool_mut.reward_tokens)?;
+
+        // Settle the rewards reserved for a deposit in cooldown: paid out if the cooldown
+        // completed before emergency mode was enabled, released otherwise
+        let mut reserved_payout = 0;
+        if deposit.is_cooldown_active {
+            let reserve = &mut pool_mut.emergency_reserve;
+            let share = reserve.share(deposit.tokens_deposited);
+            reserve.reserved -= share;
+            if deposit.unlock_timestamp <= reserve.enabled_at {
+                reserved_payout = share;
+                pool_mut.current_rewards -= share;
+                pool_mut.lifetime_rewards_paid += share;
+                staker_stats.lifetime_rewards_earned += share;
+                deposit.tokens_claimed = share;
+            }
+            pool_mut.cooldown_stake -= deposit.tokens_deposited;
+
+            // Rounding leftovers of the reserve are set aside once nothing is left to cover
+            if pool_mut.cooldown_stake == 0 && pool_mut.emergency_reserve.reserved > 0 {
+                let dust = pool_mut.emergency_reserve.reserved;
+                pool_mut.emergency_reserve.reserved = 0;
+                pool_mut.current_rewards -= dust;
+                pool_mut.reward_dust += dust;
+            }
+        }
+
+        // Mark the deposit as withdrawn
+        deposit.is_withdrawn = true;
+
+        // Update stats
+        staker_stats.total_staked -= deposit.tokens_deposited;
+        staker_stats.voting_weight -= deposit.voting_weight;
+        staker_stats.close_deposit();
+        ctx.accounts.deposit_counter.close_deposit(deposit.tokens_deposited);
+
+        // Subtract the user's tokens from the pool
+        pool_mut.current_tokens_staked -= deposit.tokens_deposited;
+        pool_mut.total_voting_weight -= deposit.voting_weight;
+        pool_mut.last_emergency_withdraw_slot = Clock::get()?.slot;
+
+        if reserved_payout > 0 {
+            let (Some(reward_mint), Some(reward_vault), Some(staker_reward_ata)) = (
+                ctx.accounts.reward_mint.as_ref(),
+                ctx.accounts.reward_vault.as_ref(),
+                ctx.accounts.staker_reward_ata.as_ref(),
+            ) else {
+                return err!(StakingError::MissingReservedRewardAccounts);
+            };
+            require_keys_eq!(
+                staker_reward_ata.mint,
+                reward_mint.key(),
+                StakingError::MissingReservedRewardAccounts
+            );
+
+            token::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: reward_vault.to_account_info(),
+                        mint: reward_mint.to_account_info(),
+                        to: staker_reward_ata.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &signer_seeds,
+                ),
+                reserved_payout,
+                reward_mint.decimals,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Emergency withdraw rewards. Only pool creator can withdraw rewards in emergency mode.
+    pub fn withdraw_rewards_emergency(ctx: Context<WithdrawRewardsEmergency>) -> Result<()> {
+        // Extract values from pool before mutable borrow
+        let pool_creator = ctx.accounts.pool.creator;
+        let pool_id = ctx.accounts.pool.pool_id;
+        let pool_bump = ctx.accounts.pool.bump;
+        // Rewards still dripping are withdrawn too, rewards reserved for stakers are not
+        let reserved_rewards = ctx.accounts.pool.emergency_reserve.reserved;
+        let current_rewards_in_pool = ctx.accounts.pool.current_rewards - reserved_rewards
+            + ctx.accounts.pool.reward_drip.remaining();
+        let emergency_mode_enabled = ctx.accounts.pool.emergency_mode_enabled;
+
+        // Verify the signer is the pool authority
+        require!(
+            ctx.accounts.pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+
+        require!(
+            emergency_mode_enabled,
+            StakingError::EmergencyModeNotEnabled
+        );
+
+        // Stakers get a head start before the creator can pull the rewards
+        let withdraw_delay = ctx
+            .accounts
+            .pool
+            .emergency_withdraw_delay
+            .max(ctx.accounts.global_config.min_emergency_withdraw_delay);
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        require!(
+            now >= ctx
+                .accounts
+                .pool
+                .emergency_reserve
+                .enabled_at
+                .saturating_add(withdraw_delay),
+            StakingError::EmergencyWithdrawTimelocked
+        );
+
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        // Get mint decimals before using ctx.accounts
+        let reward_mint_decimals = ctx.accounts.reward_mint.decimals;
+
+        // Remove the reward tokens from the pool
+        let pool = &mut ctx.accounts.pool;
+        pool.current_rewards = reserved_rewards;
+        pool.reward_drip = RewardDrip::default();
+        pool.last_emergency_withdraw_slot = Clock::get()?.slot;
+
+        token::transfer_checked(
+            ctx.accounts
+                .into_withdraw_context()
+                .with_signer(&signer_seeds),
+            current_rewards_in_pool,
+            reward_mint_decimals,
+        )?;
+
+        Ok(())
+    }
+
+    /// Creator-only: recover the rewards of a pool nobody stakes in anymore, including the
+    /// part of the drip not released yet, without going through emergency mode.
+    pub fn withdraw_unused_rewards(ctx: Context<WithdrawUnusedRewards>) -> Result<()> {
+        let pool_creator = ctx.accounts.pool.creator;
+        let pool_id = ctx.accounts.pool.pool_id;
+        let pool_bump = ctx.accounts.pool.bump;
+
+        // Verify the signer is the pool authority
+        require!(
+            ctx.accounts.pool.authority == *ctx.accounts.creator.key,
+            StakingError::UnauthorizedPoolAccess
+        );
+
+        // Rewards are only unused once every deposit has left the pool
+        require!(
+            ctx.accounts.pool.current_tokens_staked == 0,
+            StakingError::PoolHasStakers
+        );
+
+        let pool = &mut ctx.accounts.pool;
+        let amount = pool
+            .current_rewards
+            .checked_add(pool.reward_drip.remaining())
+            .ok_or(StakingError::MathOverflow)?;
+        require!(amount > 0, StakingError::NothingToClaim);
+
+        pool.current_rewards = 0;
+        pool.reward_drip = RewardDrip::default();
+
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reward_vault.to_account_info(),
+                    mint: ctx.accounts.reward_mint.to_account_info(),
+                    to: ctx.accounts.creator_reward_ata.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            amount,
+            ctx.accounts.reward_mint.decimals,
+        )?;
+
+        emit!(UnusedRewardsWithdrawn {
+            pool: ctx.accounts.pool.key(),
+            creator: pool_creator,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Claim the extra reward token earned by a deposit. Works before and after unstaking.
+    pub fn claim_reward_token(ctx: Context<ClaimRewardToken>, _deposit_id: u64) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        ctx.accounts.pool.release_drip(now)?;
+
+        let pool_creator = ctx.accounts.pool.creator;
+        let pool_id = ctx.accounts.pool.pool_id;
+        let pool_bump = ctx.accounts.pool.bump;
+        let reward_token_mint = ctx.accounts.reward_token_mint.key();
+
+        let reward_index = ctx
+            .accounts
+            .pool
+            .reward_tokens
+            .iter()
+            .position(|reward_token| reward_token.mint == reward_token_mint)
+            .ok_or(StakingError::RewardTokenNotFound)?;
+
+        let deposit = &mut ctx.accounts.deposit;
+        if !deposit.is_withdrawn {
+            deposit.settle_reward_tokens(&ctx.accounts.pool.reward_tokens)?;
+        }
+
+        let amount = deposit.rewards_owed[reward_index];
+        require!(amount > 0, StakingError::NothingToClaim);
+        deposit.rewards_owed[reward_index] = 0;
+
+        let fee = ctx.accounts.pool.reward_fee(amount)?;
+
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        if fee > 0 {
+            let treasury_reward_token_ata = ctx
+                .accounts
+                .treasury_reward_token_ata
+                .as_ref()
+                .ok_or(StakingError::MissingFeeTreasury)?;
+            token::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.reward_token_vault.to_account_info(),
+                        mint: ctx.accounts.reward_token_mint.to_account_info(),
+                        to: treasury_reward_token_ata.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &signer_seeds,
+                ),
+                fee,
+                ctx.accounts.reward_token_mint.decimals,
+            )?;
+        }
+
+        let referral = pay_referral(
+            &ctx.accounts.pool,
+            &ctx.accounts.deposit,
+            amount - fee,
+            &ctx.accounts.reward_token_vault,
+            &ctx.accounts.reward_token_mint,
+            ctx.accounts.referrer_reward_token_ata.as_ref(),
+            ctx.accounts.referral_stats.as_mut(),
+            &ctx.accounts.token_program,
+            &signer_seeds,
+        )?;
+
+        token::transfer_checked(
+            ctx.accounts
+                .into_withdraw_context()
+                .with_signer(&signer_seeds),
+            amount - fee - referral,
+            ctx.accounts.reward_token_mint.decimals,
+        )?;
+
+        Ok(())
+    }
+
+    /// Claim every extra reward token earned by a deposit in one go. `remaining_accounts`
+    /// holds, for each of the pool's reward tokens in order, its vault, its mint and the
+    /// staker's token account. Deposits of fee-charging pools or with a referrer claim
+    /// token by token through `claim_reward_token`.
+    pub fn claim_rewards<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimRewards<'info>>,
+        _deposit_id: u64,
+    ) -> Result<()> {
+        // Remaining accounts hold the reward accounts, so the dev clock is a named account
+        let dev_clock = ctx.accounts.dev_clock.as_ref().map(|info| info.to_account_info());
+        let now = current_timestamp(dev_clock.as_slice())?;
+        let remaining_accounts = ctx.remaining_accounts;
+        let pool = &mut ctx.accounts.pool;
+        let deposit = &mut ctx.accounts.deposit;
+
+        require!(
+            pool.reward_fee_bps == 0 && deposit.referrer.is_none(),
+            StakingError::ClaimRewardTokensSeparately
+        );
+        require!(
+            remaining_accounts.len() == pool.reward_tokens.len() * 3,
+            StakingError::InvalidRewardTokenAccounts
+        );
+
+        pool.release_drip(now)?;
+        if !deposit.is_withdrawn {
+            deposit.settle_reward_tokens(&pool.reward_tokens)?;
+        }
+
+        let pool_creator = pool.creator;
+        let pool_id = pool.pool_id;
+        let pool_bump = pool.bump;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"pool",
+            pool_creator.as_ref(),
+            &pool_id.to_le_bytes()[..],
+            &[pool_bump],
+        ]];
+
+        let mut claimed_any = false;
+        for (index, accounts) in remaining_accounts.chunks(3).enumerate() {
+            let reward_token = &pool.reward_tokens[index];
+            let (vault_info, mint_info, staker_ata_info) = (&accounts[0], &accounts[1], &accounts[2]);
+            require_keys_eq!(
+                vault_info.key(),
+                reward_token.vault,
+                StakingError::InvalidRewardTokenAccounts
+            );
+            let reward_token_mint: Account<'info, Mint> = Account::try_from(mint_info)?;
+            require_keys_eq!(
+                reward_token_mint.key(),
+                reward_token.mint,
+                StakingError::InvalidRewardTokenAccounts
+            );
+            let staker_ata: Account<'info, TokenAccount> = Account::try_from(staker_ata_info)?;
+            require!(
+                staker_ata.mint == reward_token.mint
+                    && staker_ata.owner == ctx.accounts.staker.key(),
+                StakingError::InvalidRewardTokenAccounts
+            );
+
+            let amount = deposit.rewards_owed[index];
+            if amount == 0 {
+                continue;
+            }
+            deposit.rewards_owed[index] = 0;
+            claimed_any = true;
+
+            token::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: vault_info.clone(),
+                        mint: mint_info.clone(),
+                        to: staker_ata_info.clone(),
+                        authority: pool.to_account_info(),
+                    },
+                    &signer_seeds,
+                ),
+                amount,
+                reward_token_mint.decimals,
+            )?;
+        }
+        require!(claimed_any, StakingError::NothingToClaim);
+
+        Ok(())
+    }
+
+    /// Pay out the part of an escrowed reward claim streamed so far. The escrow is closed
+    /// once it has been fully released, its rent going back to whoever paid it.
+    pub fn release_claimed_rewards(ctx: Context<ReleaseClaimedRewards>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let pool = &ctx.accounts.pool;
+        let claim_escrow = &mut ctx.accounts.claim_escrow;
+
+        let amount = claim_escrow.stream.releasable(now);
+        // Empty escrows can always be closed
+        require!(
+            amount > 0 || claim_escrow.stream.remaining() == 0,
+            StakingError::NothingToClaim
+        );
+        claim_escrow.stream.released += amount;
+
+        if amount > 0 {
+            let pool_id = pool.pool_id.to_le_bytes();
+            let signer_seeds: [&[&[u8]]; 1] =
+                [&[b"pool", pool.creator.as_ref(), &pool_id[..], &[pool.bump]]];
+            token::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.reward_vault.to_account_info(),
+                        mint: ctx.accounts.reward_mint.to_account_info(),
+                        to: ctx.accounts.staker_reward_ata.to_account_info(),
+                        authority: pool.to_account_info(),
+                    },
+                    &signer_seeds,
+                ),
+                amount,
+                ctx.accounts.reward_mint.decimals,
+            )?;
+
+            emit!(ClaimedRewardsReleased {
+                pool: pool.key(),
+                staker: ctx.accounts.staker.key(),
+                deposit: claim_escrow.deposit,
+                amount,
+            });
+        }
+
+        if claim_escrow.stream.remaining() == 0 {
+            claim_escrow.close(ctx.accounts.rent_payer.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    // ********* START VIEW FUNCTIONS **************
+    // Read-only, meant to be called through `simulateTransaction`. Results are
+    // returned via return data (`set_return_data`).
+
+    /// Rewards a deposit would receive if it was unstaked now, plus its extra reward tokens.
+    pub fn get_pending_rewards(
+        ctx: Context<GetPendingRewards>,
+        _deposit_id: u64,
+    ) -> Result<PendingRewards> {
+        let mut pool = (*ctx.accounts.pool).clone();
+        let mut deposit = (*ctx.accounts.deposit).clone();
+        pool.release_drip(current_timestamp(ctx.remaining_accounts)?)?;
+
+        let base_rewards = if deposit.is_withdrawn || pool.current_tokens_staked == 0 {
+            0
+        } else {
+            economy_estimate_rewards(
+                pool.current_tokens_staked,
+                deposit.tokens_deposited,
+                pool.current_rewards,
+            )
+        };
+
+        if !deposit.is_withdrawn {
+            deposit.settle_reward_tokens(&pool.reward_tokens)?;
+        }
+
+        let reward_tokens = pool
+            .reward_tokens
+            .iter()
+            .enumerate()
+            .map(|(index, reward_token)| RewardTokenAmount {
+                mint: reward_token.mint,
+                amount: deposit.rewards_owed[index],
+            })
+            .collect();
+
+        Ok(PendingRewards {
+            deposit_id: deposit.deposit_id,
+            tokens_deposited: deposit.tokens_deposited,
+            unlock_timestamp: deposit.unlock_timestamp,
+            is_cooldown_active: deposit.is_cooldown_active,
+            is_withdrawn: deposit.is_withdrawn,
+            base_rewards,
+            reward_tokens,
+        })
+    }
+
+    /// Pool totals and vault balances.
+    pub fn get_pool_stats(ctx: Context<GetPoolStats>) -> Result<PoolStats> {
+        let mut pool = (*ctx.accounts.pool).clone();
+        pool.release_drip(current_timestamp(ctx.remaining_accounts)?)?;
+
+        Ok(PoolStats {
+            current_tokens_staked: pool.current_tokens_staked,
+            current_rewards: pool.current_rewards,
+            dripping_rewards: pool.reward_drip.remaining(),
+            claim_cooldown: pool.claim_cooldown,
+            emergency_mode_enabled: pool.emergency_mode_enabled,
+            pool_vault_balance: ctx.accounts.pool_vault.amount,
+            reward_vault_balance: ctx.accounts.reward_vault.amount,
+            reward_token_count: pool.reward_tokens.len() as u8,
+            lifetime_staked: pool.lifetime_staked,
+            lifetime_rewards_paid: pool.lifetime_rewards_paid,
+            unique_staker_count: pool.unique_staker_count,
+        })
+    }
+
+    /// Vote-escrow voting power of a staker across all pools and the total of a pool.
+    pub fn get_voting_power(ctx: Context<GetVotingPower>) -> Result<VotingPower> {
+        Ok(VotingPower {
+            staker_voting_weight: ctx.accounts.staker_stats.voting_weight,
+            staker_total_staked: ctx.accounts.staker_stats.total_staked,
+            pool_voting_weight: ctx.accounts.pool.total_voting_weight,
+        })
+    }
+
+    // ********* END VIEW FUNCTIONS **************
+
+    // ********* START MIGRATION FUNCTIONS **************
+    // Permissionless, `payer` covers the rent of accounts that grow.
+
+    /// Upgrade a pool written by an older program version to the current layout.
+    pub fn migrate_pool(ctx: Context<MigrateAccount>) -> Result<()> {
+        migrate_account::<StakingPool>(ctx.accounts)
+    }
+
+    /// Upgrade a deposit written by an older program version to the current layout.
+    pub fn migrate_deposit(ctx: Context<MigrateAccount>) -> Result<()> {
+        migrate_account::<StakerDeposit>(ctx.accounts)
+    }
+
+    /// Upgrade staker stats written by an older program version to the current layout.
+    pub fn migrate_staker_stats(ctx: Context<MigrateAccount>) -> Result<()> {
+        migrate_account::<StakerStats>(ctx.accounts)
+    }
+
+    // ********* END MIGRATION FUNCTIONS **************
+}
+
+/// Grow `accounts.account` to `T::SPACE` if needed and upgrade it to `T::VERSION`.
+fn migrate_account<T>(accounts: &MigrateAccount) -> Result<()>
+where
+    T: AccountSerialize + AccountDeserialize + Versioned,
+{
+    let account = accounts.account.to_account_info();
+    require_keys_eq!(
+        *account.owner,
+        crate::ID,
+        StakingError::InvalidMigrationAccount
+    );
+
+    if account.data_len() < T::SPACE {
+        let rent = Rent::get()?
+            .minimum_balance(T::SPACE)
+            .saturating_sub(account.lamports());
+        if rent > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: accounts.payer.to_account_info(),
+                        to: account.clone(),
+                    },
+                ),
+                rent,
+            )?;
+        }
+        account.resize(T::SPACE)?;
+    }
+
+    // Deserializing checks the discriminator
+    let mut data = account.try_borrow_mut_data()?;
+    let mut state = T::try_deserialize(&mut &data[..])?;
+    let from_version = state.version();
+    require!(from_version < T::VERSION, StakingError::AccountUpToDate);
+    state.upgrade()?;
+    state.try_serialize(&mut &mut data[..])?;
+
+    emit!(AccountMigrated {
+        account: account.key(),
+        from_version,
+        to_version: T::VERSION,
+    });
+
+    Ok(())
+}
+
+/// `vesting::current_timestamp`, reading this program's dev clock.
+pub fn current_timestamp(accounts: &[AccountInfo]) -> Result<i64> {
+    vesting::program_timestamp(&crate::ID, accounts)
+}
+
+/// Return data of `get_pending_rewards`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PendingRewards {
+    pub deposit_id: u64,
+    pub tokens_deposited: u64,
+    pub unlock_timestamp: i64,
+    pub is_cooldown_active: bool,
+    pub is_withdrawn: bool,
+    /// Share of the pool rewards, in `reward_mint`.
+    pub base_rewards: u64,
+    /// Claimable amount of each extra reward token.
+    pub reward_tokens: Vec<RewardTokenAmount>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RewardTokenAmount {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Return data of `get_pool_stats`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PoolStats {
+    pub current_tokens_staked: u64,
+    pub current_rewards: u64,
+    pub dripping_rewards: u64,
+    pub claim_cooldown: i64,
+    pub emergency_mode_enabled: bool,
+    pub pool_vault_balance: u64,
+    pub reward_vault_balance: u64,
+    pub reward_token_count: u8,
+    pub lifetime_staked: u64,
+    pub lifetime_rewards_paid: u64,
+    pub unique_staker_count: u64,
+}
+
+/// Return data of `get_voting_power`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct VotingPower {
+    pub staker_voting_weight: u64,
+    pub staker_total_staked: u64,
+    pub pool_voting_weight: u64,
+}
+
+/// Record a new deposit of `deposit_amount` for `staker` and update the counter, stats and
+/// pool totals. Token transfer and bumps are left to the caller. Returns the assigned id.
+#[allow(clippy::too_many_arguments)]
+fn open_deposit<'info>(
+    pool: &mut Account<'info, StakingPool>,
+    deposit: &mut Account<'info, StakerDeposit>,
+    deposit_counter: &mut Account<'info, DepositCounter>,
+    staker_stats: &mut Account<'info, StakerStats>,
+    referral_stats: Option<&mut Account<'info, ReferralStats>>,
+    staker: Pubkey,
+    deposit_id: Option<u64>,
+    deposit_amount: u64,
+    referrer: Option<Pubkey>,
+    now: i64,
+) -> Result<u64> {
+    let deposit_id = deposit_id.unwrap_or(deposit_counter.next_deposit_id);
+    require!(
+        deposit_id >= deposit_counter.next_deposit_id,
+        StakingError::DepositIdAlreadyUsed
+    );
+    // Deposits created before the counter existed are caught here
+    require!(
+        deposit.unlock_timestamp == 0,
+        StakingError::DepositIdAlreadyUsed
+    );
+
+    // A fresh counter means the staker's first deposit in this pool
+    if deposit_counter.staker == Pubkey::default() {
+        pool.unique_staker_count += 1;
+    }
+
+    deposit_counter.staker = staker;
+    deposit_counter.pool = pool.key();
+    deposit_counter.next_deposit_id = deposit_id
+        .checked_add(1)
+        .ok_or(StakingError::MathOverflow)?;
+    deposit_counter.deposit_count = deposit_counter
+        .deposit_count
+        .checked_add(1)
+        .ok_or(StakingError::MathOverflow)?;
+
+    deposit.deposit_id = deposit_id;
+    fill_deposit(
+        pool,
+        deposit,
+        deposit_counter,
+        staker_stats,
+        referral_stats,
+        staker,
+        deposit_amount,
+        referrer,
+        now,
+    )?;
+
+    Ok(deposit_id)
+}
+
+/// Fill `deposit` with a fresh stake of `deposit_amount` and add it to the stats and pool
+/// totals. Shared by new deposits and `restake` into a withdrawn one.
+#[allow(clippy::too_many_arguments)]
+fn fill_deposit<'info>(
+    pool: &mut Account<'info, StakingPool>,
+    deposit: &mut Account<'info, StakerDeposit>,
+    deposit_counter: &mut Account<'info, DepositCounter>,
+    staker_stats: &mut Account<'info, StakerStats>,
+    referral_stats: Option<&mut Account<'info, ReferralStats>>,
+    staker: Pubkey,
+    deposit_amount: u64,
+    referrer: Option<Pubkey>,
+    now: i64,
+) -> Result<()> {
+    // Depositing tokens is only allowed if the pool is not in emergency mode
+    require!(
+        !pool.emergency_mode_enabled,
+        StakingError::EmergencyModeEnabled
+    );
+
+    require!(!pool.has_ended(now), StakingError::PoolEnded);
+
+    // Stream extra reward token emissions to the stake they accrued on
+    pool.release_drip(now)?;
+
+    // Keep dust deposits out of the pool
+    require!(
+        deposit_amount >= pool.min_stake_amount,
+        StakingError::StakeBelowMinimum
+    );
+    deposit_counter.open_deposit(pool.max_open_deposits, deposit_amount)?;
+
+    deposit.tokens_deposited = deposit_amount;
+    deposit.tokens_claimed = 0;
+    deposit.unlock_timestamp = now + pool.claim_cooldown;
+    deposit.stake_slot = Clock::get()?.slot;
+    deposit.staked_at = now;
+    deposit.is_frozen = false;
+    deposit.version = StakerDeposit::VERSION;
+    deposit.is_withdrawn = false;
+    deposit.is_cooldown_active = false;
+    deposit.auto_unstake_at = 0;
+    deposit.has_receipt = false;
+    deposit.referrer = referrer;
+    deposit.lock_extension = 0;
+    deposit.keeper_tip = 0;
+
+    if let Some(referrer) = referrer {
+        require!(referrer != staker, StakingError::InvalidReferrer);
+        let referral_stats = referral_stats.ok_or(StakingError::MissingReferralAccounts)?;
+        referral_stats.referrer = referrer;
+        referral_stats.referred_deposits += 1;
+        referral_stats.referred_stake += deposit_amount;
+    }
+
+    // Extra reward tokens only accrue from now on
+    deposit.reward_debts = [0; MAX_REWARD_TOKENS];
+    deposit.rewards_owed = [0; MAX_REWARD_TOKENS];
+    for (index, reward_token) in pool.reward_tokens.iter().enumerate() {
+        deposit.reward_debts[index] = reward_token.accumulated(deposit_amount)?;
+    }
+
+    // Voting weight is fixed by the lock the deposit was made under
+    let voting_weight = economy_voting_weight(deposit_amount, pool.claim_cooldown);
+    deposit.voting_weight = voting_weight;
+
+    // Update stats
+    staker_stats.staker = staker;
+    staker_stats.total_staked += deposit_amount;
+    staker_stats.voting_weight += voting_weight;
+    staker_stats.open_deposits += 1;
+
+    // Update the pool
+    pool.current_tokens_staked += deposit_amount;
+    pool.total_voting_weight += voting_weight;
+    pool.lifetime_staked += deposit_amount;
+
+    // Hand out extra rewards funded while nobody was staking
+    let current_tokens_staked = pool.current_tokens_staked;
+    for reward_token in pool.reward_tokens.iter_mut() {
+        let undistributed = reward_token.undistributed;
+        reward_token.undistributed = 0;
+        reward_token.distribute(undistributed, current_tokens_staked)?;
+    }
+
+    Ok(())
+}
+
+/// Burn a tokenized deposit's receipt from the staker; a no-op for plain deposits.
+fn burn_receipt<'info>(
+    deposit: &Account<'info, StakerDeposit>,
+    receipt_mint: Option<&Account<'info, Mint>>,
+    staker_receipt_ata: Option<&Account<'info, TokenAccount>>,
+    staker: &Signer<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    if !deposit.has_receipt {
+        return Ok(());
+    }
+
+    let receipt_mint = receipt_mint.ok_or(StakingError::MissingStakeReceipt)?;
+    let staker_receipt_ata = staker_receipt_ata.ok_or(StakingError::MissingStakeReceipt)?;
+    require!(
+        staker_receipt_ata.mint == receipt_mint.key() && staker_receipt_ata.amount == 1,
+        StakingError::MissingStakeReceipt
+    );
+
+    token::burn(
+        CpiContext::new(
+            token_program.to_account_info(),
+            Burn {
+                mint: receipt_mint.to_account_info(),
+                from: staker_receipt_ata.to_account_info(),
+                authority: staker.to_account_info(),
+            },
+        ),
+        1,
+    )
+}
+
+/// Give a keeper tip left on `deposit` by `activate_cooldown` back to the staker, for exits
+/// and cancellations the staker makes themselves. A no-op without a tip.
+fn refund_keeper_tip<'info>(
+    deposit: &mut Account<'info, StakerDeposit>,
+    staker: &Signer<'info>,
+) -> Result<()> {
+    let tip = deposit.keeper_tip;
+    if tip == 0 {
+        return Ok(());
+    }
+
+    deposit.keeper_tip = 0;
+    deposit.sub_lamports(tip)?;
+    staker.add_lamports(tip)?;
+    Ok(())
+}
+
+/// Pay the deposit's referrer their share of `rewards` from `vault` and return it.
+/// Deposits without a referrer, or pools without a referral share, pay nothing.
+#[allow(clippy::too_many_arguments)]
+fn pay_referral<'info>(
+    pool: &Account<'info, StakingPool>,
+    deposit: &StakerDeposit,
+    rewards: u64,
+    vault: &Account<'info, TokenAccount>,
+    mint: &Account<'info, Mint>,
+    referrer_ata: Option<&Account<'info, TokenAccount>>,
+    referral_stats: Option<&mut Account<'info, ReferralStats>>,
+    token_program: &Program<'info, Token>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<u64> {
+    let Some(referrer) = deposit.referrer else {
+        return Ok(0);
+    };
+    let share = pool.referral_share(rewards)?;
+    if share == 0 {
+        return Ok(0);
+    }
+
+    let referrer_ata = referrer_ata.ok_or(StakingError::MissingReferralAccounts)?;
+    let referral_stats = referral_stats.ok_or(StakingError::MissingReferralAccounts)?;
+    require!(
+        referrer_ata.owner == referrer && referrer_ata.mint == mint.key(),
+        StakingError::InvalidReferrer
+    );
+    require!(
+        referral_stats.referrer == referrer,
+        StakingError::InvalidReferrer
+    );
+
+    token::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: referrer_ata.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        share,
+        mint.decimals,
+    )?;
+    referral_stats.payouts += 1;
+
+    Ok(share)
+}
+
+/// Authority-only: set or clear a deposit's freeze flag.
+fn set_deposit_frozen(ctx: Context<FreezeDeposit>, deposit_id: u64, frozen: bool) -> Result<()> {
+    let deposit = &mut ctx.accounts.deposit;
+
+    // Verify the signer is the pool authority
+    require!(
+        ctx.accounts.pool.authority == *ctx.accounts.creator.key,
+        StakingError::UnauthorizedPoolAccess
+    );
+    require!(
+        !deposit.is_withdrawn,
+        StakingError::DepositAlreadyWithdrawn
+    );
+    if frozen {
+        require!(!deposit.is_frozen, StakingError::DepositFrozen);
+    } else {
+        require!(deposit.is_frozen, StakingError::DepositNotFrozen);
+    }
+
+    deposit.is_frozen = frozen;
+
+    emit!(DepositFreezeChanged {
+        pool: ctx.accounts.pool.key(),
+        staker: ctx.accounts.staker.key(),
+        deposit_id,
+        frozen,
+    });
+
+    Ok(())
+}
+
+/// Start the cooldown of `deposit`, shared by `activate_cooldown` and `unstake_now`.
+fn start_cooldown(deposit: &mut StakerDeposit, pool: &mut StakingPool, now: i64) -> Result<()> {
+    require!(
+        !deposit.is_withdrawn,
+        StakingError::DepositAlreadyWithdrawn
+    );
+
+    require!(
+        !deposit.is_cooldown_active,
+        StakingError::CooldownAlreadyActivated
+    );
+
+    require!(!deposit.is_frozen, StakingError::DepositFrozen);
+
+    require!(
+        !deposit.is_too_recent(Clock::get()?.slot),
+        StakingError::StakeTooRecent
+    );
+
+    // Emergency reserves are sized on the cooldowns active when the mode was enabled
+    require!(
+        !pool.emergency_mode_enabled,
+        StakingError::EmergencyModeEnabled
+    );
+
+    deposit.is_cooldown_active = true;
+    deposit.unlock_timestamp = now + deposit.cooldown_duration(pool);
+    pool.cooldown_stake += deposit.tokens_deposited;
+    Ok(())
+}
+
+/// Unstake logic shared by `unstake`, `unstake_now` and `unstake_sol`: burn the receipt, pay
+/// the principal to the destination, or `staker_ata` without one, and the fee and referral
+/// shares, then the staker's rewards.
+fn unstake_deposit(accounts: &mut UnstakeDeposit, now: i64) -> Result<()> {
+    refund_keeper_tip(&mut accounts.deposit, &accounts.staker)?;
+    burn_receipt(
+        &accounts.deposit,
+        accounts.receipt_mint.as_ref(),
+        accounts.staker_receipt_ata.as_ref(),
+        &accounts.staker,
+        &accounts.token_program,
+    )?;
+    let staker_rewards = withdraw_deposit(
+        &accounts.mint,
+        &accounts.reward_mint,
+        &mut accounts.deposit,
+        &mut accounts.staker_stats,
+        &mut accounts.deposit_counter,
+        &mut accounts.pool,
+        &accounts.pool_vault,
+        &accounts.reward_vault,
+        accounts.destination.as_ref().unwrap_or(&accounts.staker_ata),
+        accounts.treasury_reward_ata.as_ref(),
+        accounts.referrer_reward_ata.as_ref(),
+        accounts.referral_stats.as_mut(),
+        &accounts.token_program,
+        now,
+    )?;
+    let boost = nft_boost_bonus(
+        &accounts.pool,
+        &accounts.staker,
+        staker_rewards,
+        accounts.boost_nft_ata.as_ref(),
+        accounts.boost_nft_metadata.as_ref(),
+    )?;
+    accounts.pool.current_rewards -= boost;
+    accounts.pool.lifetime_rewards_paid += boost;
+    accounts.staker_stats.lifetime_rewards_earned += boost;
+    accounts.deposit.tokens_claimed += boost;
+    let staker_rewards = staker_rewards + boost;
+    pay_staker_rewards(
+        &accounts.pool,
+        &accounts.deposit,
+        staker_rewards,
+        &accounts.reward_vault,
+        &accounts.reward_mint,
+        &accounts.staker,
+        &accounts.staker_reward_ata,
+        &accounts.staker,
+        reward_lockup_accounts(
+            accounts.reward_lockup_authority.as_ref(),
+            accounts.reward_lockup_ata.as_ref(),
+            accounts.vesting_schedule.as_ref(),
+            accounts.vesting_vault.as_ref(),
+            accounts.vesting_program.as_ref(),
+        ),
+        accounts.claim_escrow.as_mut(),
+        &accounts.token_program,
+        &accounts.associated_token_program,
+        &accounts.system_program,
+        now,
+    )
+}
+
+/// Extra rewards for a staker proving they hold an NFT of the pool's boost collection,
+/// capped by the pool's remaining rewards. Zero when the pool has no boost or no NFT is
+/// passed.
+fn nft_boost_bonus<'info>(
+    pool: &StakingPool,
+    staker: &Signer<'info>,
+    rewards: u64,
+    nft_ata: Option<&Account<'info, TokenAccount>>,
+    nft_metadata: Option<&Account<'info, MetadataAccount>>,
+) -> Result<u64> {
+    let boost = pool.nft_boost;
+    if boost.multiplier_bps == 0 {
+        return Ok(0);
+    }
+    let (Some(nft_ata), Some(nft_metadata)) = (nft_ata, nft_metadata) else {
+        return Ok(0);
+    };
+
+    require!(
+        nft_ata.owner == staker.key() && nft_ata.amount == 1,
+        StakingError::InvalidBoostNft
+    );
+    require_keys_eq!(nft_metadata.mint, nft_ata.mint, StakingError::InvalidBoostNft);
+    let in_collection = nft_metadata
+        .collection
+        .as_ref()
+        .is_some_and(|collection| collection.verified && collection.key == boost.collection);
+    require!(in_collection, StakingError::InvalidBoostNft);
+
+    let bonus = (rewards as u128)
+        .checked_mul((boost.multiplier_bps - 10_000) as u128)
+        .ok_or(StakingError::MathOverflow)?
+        / 10_000;
+
+    Ok((bonus as u64).min(pool.current_rewards))
+}
+
+/// Pay out a deposit whose cooldown has elapsed: principal back to `staker_ata`, the pool's
+/// fee and the referrer's share of the rewards. Returns the rewards left for the staker,
+/// which the caller pays through `pay_staker_rewards`.
+#[allow(clippy::too_many_arguments)]
+fn withdraw_deposit<'info>(
+    mint: &Account<'info, Mint>,
+    reward_mint: &Account<'info, Mint>,
+    deposit: &mut Account<'info, StakerDeposit>,
+    staker_stats: &mut Account<'info, StakerStats>,
+    deposit_counter: &mut Account<'info, DepositCounter>,
+    pool: &mut Account<'info, StakingPool>,
+    pool_vault: &Account<'info, TokenAccount>,
+    reward_vault: &Account<'info, TokenAccount>,
+    staker_ata: &Account<'info, TokenAccount>,
+    treasury_reward_ata: Option<&Account<'info, TokenAccount>>,
+    referrer_reward_ata: Option<&Account<'info, TokenAccount>>,
+    referral_stats: Option<&mut Account<'info, ReferralStats>>,
+    token_program: &Program<'info, Token>,
+    now: i64,
+) -> Result<u64> {
+    let pool_creator = pool.creator;
+    let pool_id = pool.pool_id;
+    let pool_bump = pool.bump;
+    let user_total_staked_tokens = deposit.tokens_deposited;
+
+    let signer_seeds: [&[&[u8]]; 1] = [&[
+        b"pool",
+        pool_creator.as_ref(),
+        &pool_id.to_le_bytes()[..],
+        &[pool_bump],
+    ]];
+
+    let user_rewards = close_deposit(deposit, staker_stats, deposit_counter, pool, now)?;
+
+    // Principal and rewards are paid from their own vaults
+    require!(
+        pool_vault.amount >= user_total_staked_tokens,
+        StakingError::InsufficientPrincipalInVault
+    );
+    require!(
+        reward_vault.amount >= user_rewards,
+        StakingError::InsufficientRewardsInVault
+    );
+
+    // Send their initial deposit back
+    token::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: pool_vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: staker_ata.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            &signer_seeds,
+        ),
+        user_total_staked_tokens,
+        mint.decimals,
+    )?;
+
+    // Take the pool's fee out of the rewards
+    let fee = pay_reward_fee(
+        pool,
+        user_rewards,
+        reward_vault,
+        reward_mint,
+        treasury_reward_ata,
+        token_program,
+        &signer_seeds,
+    )?;
+
+    // The referrer gets their share of what is left for the staker
+    let staker_rewards = user_rewards - fee;
+    let referral = pay_referral(
+        pool,
+        deposit,
+        staker_rewards,
+        reward_vault,
+        reward_mint,
+        referrer_reward_ata,
+        referral_stats,
+        token_program,
+        &signer_seeds,
+    )?;
+
+    Ok(staker_rewards - referral)
+}
+
+/// Mark a deposit whose cooldown has elapsed as withdrawn and take it out of the staker's
+/// and the pool's totals. Returns the deposit's rewards, which the caller pays out along
+/// with its principal.
+fn close_deposit(
+    deposit: &mut StakerDeposit,
+    staker_stats: &mut StakerStats,
+    deposit_counter: &mut DepositCounter,
+    pool: &mut StakingPool,
+    now: i64,
+) -> Result<u64> {
+    let user_total_staked_tokens = deposit.tokens_deposited;
+
+    // If the pool has emergency mode turned on, we can ignore the time.
+    require!(
+        !pool.emergency_mode_enabled,
+        StakingError::EmergencyModeEnabled
+    );
+
+    // Require the deposit to not be withdrawn
+    require!(
+        !deposit.is_withdrawn,
+        StakingError::DepositAlreadyWithdrawn
+    );
+
+    require!(
+        deposit.is_cooldown_active,
+        StakingError::ClaimCooldownNotActive
+    );
+
+    require!(!deposit.is_frozen, StakingError::DepositFrozen);
+
+    // Require the user to have waited long enough to unstake
+    require!(
+        now >= deposit.unlock_timestamp,
+        StakingError::ClaimCooldownNotElapsed
+    );
+
+    // Count the rewards dripped in so far
+    pool.release_drip(now)?;
+
+    // Calculate the user's rewards based on their share of tokens in the total staked tokens,
+    // deposits younger than the pool's minimum reward age earn nothing
+    let user_rewards = if now.saturating_sub(deposit.staked_at) < pool.min_reward_age {
+        0
+    } else {
+        economy_estimate_rewards(
+            pool.current_tokens_staked,
+            user_total_staked_tokens,
+            pool.current_rewards,
+        )
+    };
+
+    // Extended locks earn their boost on top, within what the pool has left
+    let user_rewards = user_rewards + pool.lock_bonus(user_rewards, deposit.lock_extension)?;
+
+    // Lock in extra reward tokens earned so far, they stay claimable after withdrawal
+    deposit.settle_reward_tokens(&pool.reward_tokens)?;
+
+    // Mark the deposit as withdrawn
+    deposit.is_withdrawn = true;
+
+    // Set the claimed amount in the deposit
+    deposit.tokens_claimed = user_rewards;
+
+    // Update stats
+    staker_stats.total_staked -= user_total_staked_tokens;
+    staker_stats.voting_weight -= deposit.voting_weight;
+    staker_stats.close_deposit();
+    deposit_counter.close_deposit(user_total_staked_tokens);
+
+    // Remove the reward tokens from the pool
+    pool.current_rewards -= user_rewards;
+    pool.lifetime_rewards_paid += user_rewards;
+    staker_stats.lifetime_rewards_earned += user_rewards;
+
+    // Subtract the user's tokens from the pool
+    pool.current_tokens_staked -= user_total_staked_tokens;
+    pool.cooldown_stake -= user_total_staked_tokens;
+    pool.total_voting_weight -= deposit.voting_weight;
+
+    Ok(user_rewards)
+}
+
+/// Send the pool's fee on a reward payout of `rewards` to the fee treasury. Returns the fee.
+fn pay_reward_fee<'info>(
+    pool: &Account<'info, StakingPool>,
+    rewards: u64,
+    reward_vault: &Account<'info, TokenAccount>,
+    reward_mint: &Account<'info, Mint>,
+    treasury_reward_ata: Option<&Account<'info, TokenAccount>>,
+    token_program: &Program<'info, Token>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<u64> {
+    let fee = pool.reward_fee(rewards)?;
+    if fee > 0 {
+        let treasury_reward_ata = treasury_reward_ata.ok_or(StakingError::MissingFeeTreasury)?;
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: reward_vault.to_account_info(),
+                    mint: reward_mint.to_account_info(),
+                    to: treasury_reward_ata.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            fee,
+            reward_mint.decimals,
+        )?;
+    }
+
+    Ok(fee)
+}
+
+/// Accounts needed to lock a reward payout up in a vesting schedule.
+struct RewardLockupAccounts<'a, 'info> {
+    /// System-owned PDA acting as the schedule's creator, one per deposit
+    authority: &'a UncheckedAccount<'info>,
+    /// Authority's reward token account, only alive during the payout
+    ata: &'a UncheckedAccount<'info>,
+    vesting_schedule: &'a UncheckedAccount<'info>,
+    vesting_vault: &'a UncheckedAccount<'info>,
+    vesting_program: &'a Program<'info, Vesting>,
+}
+
+/// Bundle the optional lockup accounts of an instruction, `None` unless all were passed.
+fn reward_lockup_accounts<'a, 'info>(
+    authority: Option<&'a UncheckedAccount<'info>>,
+    ata: Option<&'a UncheckedAccount<'info>>,
+    vesting_schedule: Option<&'a UncheckedAccount<'info>>,
+    vesting_vault: Option<&'a UncheckedAccount<'info>>,
+    vesting_program: Option<&'a Program<'info, Vesting>>,
+) -> Option<RewardLockupAccounts<'a, 'info>> {
+    Some(RewardLockupAccounts {
+        authority: authority?,
+        ata: ata?,
+        vesting_schedule: vesting_schedule?,
+        vesting_vault: vesting_vault?,
+        vesting_program: vesting_program?,
+    })
+}
+
+/// Send the staker's rewards to `staker_reward_ata`, into the deposit's claim escrow when
+/// the pool streams claimed rewards, or into a new vesting schedule for the staker when the
+/// payout is above the pool's lockup threshold. `payer` covers the rent of the escrow,
+/// refunded by `release_claimed_rewards`, or of the schedule and its vault.
+#[allow(clippy::too_many_arguments)]
+fn pay_staker_rewards<'info>(
+    pool: &Account<'info, StakingPool>,
+    deposit: &Account<'info, StakerDeposit>,
+    amount: u64,
+    reward_vault: &Account<'info, TokenAccount>,
+    reward_mint: &Account<'info, Mint>,
+    staker: &AccountInfo<'info>,
+    staker_reward_ata: &Account<'info, TokenAccount>,
+    payer: &AccountInfo<'info>,
+    lockup: Option<RewardLockupAccounts<'_, 'info>>,
+    claim_escrow: Option<&mut Account<'info, ClaimEscrow>>,
+    token_program: &Program<'info, Token>,
+    associated_token_program: &Program<'info, AssociatedToken>,
+    system_program: &Program<'info, System>,
+    now: i64,
+) -> Result<()> {
+    // Streamed claims stay in the reward vault until `release_claimed_rewards`
+    if pool.claim_vesting_duration > 0 || claim_escrow.is_some() {
+        let claim_escrow = claim_escrow.ok_or(StakingError::MissingClaimEscrow)?;
+        let deposit_key = deposit.key();
+        let (_, bump) =
+            Pubkey::find_program_address(&[b"claim-escrow", deposit_key.as_ref()], &crate::ID);
+        claim_escrow.staker = staker.key();
+        claim_escrow.pool = pool.key();
+        claim_escrow.deposit = deposit_key;
+        claim_escrow.stream = RewardDrip {
+            amount,
+            released: 0,
+            start: now,
+            end: now + pool.claim_vesting_duration,
+        };
+        claim_escrow.bump = bump;
+        claim_escrow.rent_payer = payer.key();
+
+        emit!(ClaimedRewardsEscrowed {
+            pool: pool.key(),
+            staker: staker.key(),
+            deposit: deposit_key,
+            amount,
+            end: claim_escrow.stream.end,
+        });
+
+        return Ok(());
+    }
+
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let pool_id = pool.pool_id.to_le_bytes();
+    let pool_signer_seeds: [&[&[u8]]; 1] =
+        [&[b"pool", pool.creator.as_ref(), &pool_id[..], &[pool.bump]]];
+
+    let config = pool.reward_lockup;
+    if config.threshold == 0 || amount <= config.threshold {
+        return token::transfer_checked(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: reward_vault.to_account_info(),
+                    mint: reward_mint.to_account_info(),
+                    to: staker_reward_ata.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                &pool_signer_seeds,
+            ),
+            amount,
+            reward_mint.decimals,
+        );
+    }
+
+    let lockup = lockup.ok_or(StakingError::MissingRewardLockupAccounts)?;
+    let deposit_key = deposit.key();
+    let (authority_key, authority_bump) =
+        Pubkey::find_program_address(&[b"reward-lockup", deposit_key.as_ref()], &crate::ID);
+    require_keys_eq!(
+        lockup.authority.key(),
+        authority_key,
+        StakingError::MissingRewardLockupAccounts
+    );
+    require_keys_eq!(
+        lockup.ata.key(),
+        get_associated_token_address(&authority_key, &reward_mint.key()),
+        StakingError::MissingRewardLockupAccounts
+    );
+    let authority_signer_seeds: [&[&[u8]]; 1] =
+        [&[b"reward-lockup", deposit_key.as_ref(), &[authority_bump]]];
+
+    // The authority pays for the schedule and its vault inside the vesting program
+    let rent = Rent::get()?;
+    let rent_lamports = rent
+        .minimum_balance(8 + vesting::VestingSchedule::INIT_SPACE)
+        .checked_add(rent.minimum_balance(TokenAccount::LEN))
+        .ok_or(StakingError::MathOverflow)?;
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: payer.clone(),
+                to: lockup.authority.to_account_info(),
+            },
+        ),
+        rent_lamports,
+    )?;
+
+    // Stage the rewards in an account owned by the authority
+    associated_token::create_idempotent(CpiContext::new(
+        associated_token_program.to_account_info(),
+        associated_token::Create {
+            payer: payer.clone(),
+            associated_token: lockup.ata.to_account_info(),
+            authority: lockup.authority.to_account_info(),
+            mint: reward_mint.to_account_info(),
+            system_program: system_program.to_account_info(),
+            token_program: token_program.to_account_info(),
+        },
+    ))?;
+    token::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: reward_vault.to_account_info(),
+                mint: reward_mint.to_account_info(),
+                to: lockup.ata.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            &pool_signer_seeds,
+        ),
+        amount,
+        reward_mint.decimals,
+    )?;
+
+    vesting::cpi::create_vesting(
+        CpiContext::new_with_signer(
+            lockup.vesting_program.to_account_info(),
+            vesting::cpi::accounts::CreateVesting {
+                vesting_schedule: lockup.vesting_schedule.to_account_info(),
+                creator: lockup.authority.to_account_info(),
+                beneficiary: staker.clone(),
+                token_mint: reward_mint.to_account_info(),
+                creator_token_account: lockup.ata.to_account_info(),
+                vault: lockup.vesting_vault.to_account_info(),
+                token_program: token_program.to_account_info(),
+                system_program: system_program.to_account_info(),
+            },
+            &authority_signer_seeds,
+        ),
+        config.cliff_duration,
+        config.interval_duration,
+        config.unlock_percentage,
+        amount,
+        false,
+        vesting::RoundingMode::Floor,
+    )?;
+
+    // Hand the staging account's rent back
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: lockup.ata.to_account_info(),
+            destination: payer.clone(),
+            authority: lockup.authority.to_account_info(),
+        },
+        &authority_signer_seeds,
+    ))?;
+
+    emit!(RewardsLockedUp {
+        pool: pool.key(),
+        staker: staker.key(),
+        deposit_id: deposit.deposit_id,
+        vesting_schedule: lockup.vesting_schedule.key(),
+        amount,
+    });
+
+    Ok(())
+}
+
+/// Program account with a layout version, brought up to date by the `migrate_*`
+/// instructions when its layout changes.
+pub trait Versioned {
+    /// Layout version written by this program.
+    const VERSION: u8;
+    /// Allocated size at `VERSION`, discriminator included.
+    const SPACE: usize;
+
+    fn version(&self) -> u8;
+
+    /// Fill in the fields added since `version()` and set the current version. New bytes
+    /// of a grown account read as zero before this runs.
+    fn upgrade(&mut self) -> Result<()>;
+}
+
+#[account]
+pub struct StakingPool {
+    pub pool_id: u64,                 // 8
+    pub creator: Pubkey,              // 32
+    pub mint: Pubkey,                 // 32
+    pub reward_mint: Pubkey,          // 32
+    pub current_tokens_staked: u64,   // 8
+    pub current_rewards: u64,         // 8
+    pub claim_cooldown: i64,          // 8
+    pub emergency_mode_enabled: bool, // 1
+    pub bump: u8,                     // 1
+    pub reward_vault_bump: u8,        // 1
+    pub last_emergency_withdraw_slot: u64, // 8
+    pub reward_fee_bps: u16,          // 2
+    pub referral_bps: u16,            // 2
+    pub total_voting_weight: u64,     // 8
+    pub max_slash_bps: u16,           // 2
+    pub reward_lockup: RewardLockupConfig, // 25
+    pub reward_drip: RewardDrip,      // 32
+    pub min_stake_amount: u64,        // 8
+    pub cooldown_stake: u64,          // 8
+    pub emergency_reserve: EmergencyReserve, // 32
+    pub min_reward_age: i64,          // 8, seconds a deposit must be staked to earn rewards
+    pub end_timestamp: i64,           // 8, 0 for a pool without an end
+    pub name: String,                 // 4 + MAX_POOL_NAME_LEN, empty when unset
+    pub metadata_uri: String,         // 4 + MAX_METADATA_URI_LEN, empty when unset
+    pub nft_boost: NftBoostConfig,    // 34
+    pub emergency_withdraw_delay: i64, // 8, seconds between emergency mode and reward withdrawal
+    pub share_mint: Option<Pubkey>,   // 1 + 32, set once liquid staking is enabled
+    pub total_shares: u64,            // 8, share tokens in circulation
+    pub liquid_staked: u64,           // 8, principal staked through share tokens
+    pub claim_vesting_duration: i64,  // 8, 0 pays rewards out at unstake
+    pub authority: Pubkey,            // 32, signs pool administration, the creator by default
+    pub lifetime_staked: u64,         // 8, all tokens ever staked
+    pub lifetime_rewards_paid: u64,   // 8, all rewards ever paid to stakers, before fees
+    pub unique_staker_count: u64,     // 8, stakers who opened a deposit in the pool
+    pub last_liquid_stake_slot: u64,  // 8, slot of the latest stake_liquid
+    pub reward_tokens: Vec<RewardTokenInfo>, // 4 + MAX_REWARD_TOKENS * 120
+    // Fields added after version 1 go below `version`, so older accounts read it as 0
+    pub version: u8,                  // 1
+    pub lock_boost_bps: u16,          // 2, extra rewards of a fully extended lock, since version 2
+    pub max_open_deposits: u16,       // 2, 0 for no limit, since version 3
+    pub instant_unstake_fee_bps: u16, // 2, 0 disables unstake_instant, since version 4
+    pub reward_dust: u64,             // 8, rewards left over by rounding, since version 5
+}
+
+impl Versioned for StakingPool {
+    const VERSION: u8 = 5;
+    const SPACE: usize = 8 + // anchor overhead
+        8 + // pool_id
+        32 + // creator
+        32 + // mint
+        32 + // reward_mint
+        8 + // current_tokens_staked
+        8 + // current_rewards
+        8 + // claim_cooldown
+        1 + // emergency_mode_enabled
+        1 + // bump
+        1 + // reward_vault_bump
+        8 + // last_emergency_withdraw_slot
+        2 + // reward_fee_bps
+        2 + // referral_bps
+        8 + // total_voting_weight
+        2 + // max_slash_bps
+        25 + // reward_lockup
+        32 + // reward_drip
+        8 + // min_stake_amount
+        8 + // cooldown_stake
+        32 + // emergency_reserve
+        8 + // min_reward_age
+        8 + // end_timestamp
+        4 + MAX_POOL_NAME_LEN + // name
+        4 + MAX_METADATA_URI_LEN + // metadata_uri
+        34 + // nft_boost
+        8 + // emergency_withdraw_delay
+        1 + 32 + // share_mint
+        8 + // total_shares
+        8 + // liquid_staked
+        8 + // claim_vesting_duration
+        32 + // authority
+        8 + // lifetime_staked
+        8 + // lifetime_rewards_paid
+        8 + // unique_staker_count
+        8 + // last_liquid_stake_slot
+        4 + MAX_REWARD_TOKENS * 120 + // reward_tokens
+        1 + // version
+        2 + // lock_boost_bps
+        2 + // max_open_deposits
+        2 + // instant_unstake_fee_bps
+        8; // reward_dust
+
+    fn version(&self) -> u8 {
+        self.version
+    }
+
+    fn upgrade(&mut self) -> Result<()> {
+        // Version 1 only appended `version`, version 2 added lock boosts, version 3 the
+        // open deposit limit, version 4 the instant unstake fee and version 5 reward dust
+        if self.version < 2 {
+            self.lock_boost_bps = 0;
+        }
+        if self.version < 3 {
+            self.max_open_deposits = 0;
+        }
+        if self.version < 4 {
+            self.instant_unstake_fee_bps = 0;
+        }
+        if self.version < 5 {
+            self.reward_dust = 0;
+        }
+        self.version = Self::VERSION;
+        Ok(())
+    }
+}
+
+impl StakingPool {
+    /// Value of the liquid position backing the share tokens: its principal plus its
+    /// pro-rata share of the pool rewards, or just the principal in emergency mode.
+    pub fn liquid_value(&self) -> u64 {
+        if self.emergency_mode_enabled || self.current_tokens_staked == 0 {
+            return self.liquid_staked;
+        }
+
+        self.liquid_staked
+            + economy_estimate_rewards(
+                self.current_tokens_staked,
+                self.liquid_staked,
+                self.current_rewards,
+            )
+    }
+
+    /// Extra rewards on top of `rewards` for a deposit whose lock was extended by
+    /// `lock_extension` seconds, capped by what is left in the pool after `rewards`.
+    pub fn lock_bonus(&self, rewards: u64, lock_extension: i64) -> Result<u64> {
+        let extension = lock_extension.clamp(0, MAX_VOTE_LOCK_DURATION) as u128;
+        let bonus = (rewards as u128)
+            .checked_mul(self.lock_boost_bps as u128)
+            .and_then(|value| value.checked_mul(extension))
+            .ok_or(StakingError::MathOverflow)?
+            / (10_000 * MAX_VOTE_LOCK_DURATION as u128);
+
+        Ok((bonus as u64).min(self.current_rewards.saturating_sub(rewards)))
+    }
+
+    /// Whether the pool's end timestamp has passed.
+    pub fn has_ended(&self, now: i64) -> bool {
+        self.end_timestamp != 0 && now >= self.end_timestamp
+    }
+
+    /// Move the part of the reward drip unlocked by `now` into `current_rewards`, and
+    /// stream the extra reward tokens' emissions up to `now`.
+    /// Nothing unlocks after the pool ends.
+    pub fn release_drip(&mut self, now: i64) -> Result<()> {
+        let now = if self.end_timestamp != 0 {
+            now.min(self.end_timestamp)
+        } else {
+            now
+        };
+        let releasable = self.reward_drip.releasable(now);
+        self.reward_drip.released += releasable;
+        self.current_rewards = self
+            .current_rewards
+            .checked_add(releasable)
+            .ok_or(StakingError::MathOverflow)?;
+
+        let current_tokens_staked = self.current_tokens_staked;
+        for reward_token in self.reward_tokens.iter_mut() {
+            reward_token.emit(now, current_tokens_staked)?;
+        }
+
+        Ok(())
+    }
+
+    /// Part of a reward payout of `amount` that goes to the fee treasury.
+    pub fn reward_fee(&self, amount: u64) -> Result<u64> {
+        mul_div(amount, self.reward_fee_bps as u64, 10_000, Rounding::Down)
+    }
+
+    /// Part of a staker's reward payout of `amount` that goes to their referrer.
+    pub fn referral_share(&self, amount: u64) -> Result<u64> {
+        mul_div(amount, self.referral_bps as u64, 10_000, Rounding::Down)
+    }
+}
+
+/// Rewards set aside when emergency mode is enabled for deposits in cooldown at that time.
+/// Deposits whose cooldown had completed are paid their share by `unstake_emergency`; the
+/// share of the others is released when they exit. The creator can only withdraw the rest.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct EmergencyReserve {
+    pub enabled_at: i64,     // 8
+    pub total_staked: u64,   // 8, pool stake when emergency mode was enabled
+    pub total_rewards: u64,  // 8, pool rewards when emergency mode was enabled
+    pub reserved: u64,       // 8, part of `current_rewards` still set aside
+}
+
+impl EmergencyReserve {
+    /// Reserved share of a deposit of `amount` tokens.
+    pub fn share(&self, amount: u64) -> u64 {
+        if self.total_staked == 0 {
+            return 0;
+        }
+
+        economy_estimate_rewards(self.total_staked, amount, self.total_rewards).min(self.reserved)
+    }
+}
+
+/// Reward tranche that becomes part of `current_rewards` linearly between `start` and `end`,
+/// see `fund_pool_drip`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RewardDrip {
+    pub amount: u64,   // 8
+    pub released: u64, // 8
+    pub start: i64,    // 8
+    pub end: i64,      // 8
+}
+
+impl RewardDrip {
+    /// Part of the tranche unlocked by `now` that has not been released yet.
+    pub fn releasable(&self, now: i64) -> u64 {
+        let unlocked = if now >= self.end {
+            self.amount
+        } else if now <= self.start {
+            0
+        } else {
+            let elapsed = (now - self.start) as u128;
+            let duration = (self.end - self.start) as u128;
+            (self.amount as u128 * elapsed / duration) as u64
+        };
+
+        unlocked - self.released
+    }
+
+    /// Part of the tranche that is still locked.
+    pub fn remaining(&self) -> u64 {
+        self.amount - self.released
+    }
+}
+
+/// Rewards of one unstake held back in the reward vault and streamed to the staker, see
+/// `configure_claim_vesting`.
+#[account]
+pub struct ClaimEscrow {
+    pub staker: Pubkey,     // 32
+    pub pool: Pubkey,       // 32
+    pub deposit: Pubkey,    // 32
+    pub stream: RewardDrip, // 32
+    pub bump: u8,           // 1
+    pub rent_payer: Pubkey, // 32, refunded the rent once the escrow is closed
+}
+
+/// Reward multiplier for holders of a collection's NFTs, see `configure_nft_boost`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct NftBoostConfig {
+    pub collection: Pubkey,  // 32, verified collection the NFT must belong to
+    pub multiplier_bps: u16, // 2, 0 disables the boost
+}
+
+/// Vesting applied to large reward payouts, see `configure_reward_lockup`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RewardLockupConfig {
+    pub threshold: u64,         // 8, 0 disables the lockup
+    pub cliff_duration: i64,    // 8
+    pub interval_duration: i64, // 8
+    pub unlock_percentage: u8,  // 1
+}
+
+/// Extra reward token distributed by a pool through a per-share accumulator.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RewardTokenInfo {
+    pub mint: Pubkey,               // 32
+    pub vault: Pubkey,              // 32
+    pub acc_reward_per_share: u128, // 16
+    pub total_funded: u64,          // 8
+    pub undistributed: u64,         // 8
+    pub emission_rate: u64,         // 8, tokens streamed per second
+    pub emission_remaining: u64,    // 8, funded emission not streamed yet
+    pub last_emission_at: i64,      // 8
+}
+
+impl RewardTokenInfo {
+    /// Rewards accumulated by `amount` staked tokens since the pool started.
+    pub fn accumulated(&self, amount: u64) -> Result<u128> {
+        let accumulated = (amount as u128)
+            .checked_mul(self.acc_reward_per_share)
+            .ok_or(StakingError::MathOverflow)?
+            / REWARD_PRECISION;
+        Ok(accumulated)
+    }
+
+    /// Stream the emission accrued since the last update, capped at what is left funded.
+    pub fn emit(&mut self, now: i64, total_staked: u64) -> Result<()> {
+        if now <= self.last_emission_at {
+            return Ok(());
+        }
+
+        let elapsed = (now - self.last_emission_at) as u128;
+        let amount = (elapsed * self.emission_rate as u128).min(self.emission_remaining as u128)
+            as u64;
+        self.last_emission_at = now;
+        if amount == 0 {
+            return Ok(());
+        }
+
+        self.emission_remaining -= amount;
+        self.distribute(amount, total_staked)
+    }
+
+    /// Spread `amount` over the currently staked tokens, or hold it until someone stakes.
+    pub fn distribute(&mut self, amount: u64, total_staked: u64) -> Result<()> {
+        if total_staked == 0 {
+            self.undistributed = self
+                .undistributed
+                .checked_add(amount)
+                .ok_or(StakingError::MathOverflow)?;
+            return Ok(());
+        }
+
+        let increment = (amount as u128)
+            .checked_mul(REWARD_PRECISION)
+            .ok_or(StakingError::MathOverflow)?
+            / total_staked as u128;
+        self.acc_reward_per_share = self
+            .acc_reward_per_share
+            .checked_add(increment)
+            .ok_or(StakingError::MathOverflow)?;
+
+        Ok(())
+    }
+}
+
+/// Protocol-wide settings, a single PDA.
+#[account]
+pub struct GlobalConfig {
+    pub admin: Pubkey,                  // 32
+    pub pending_admin: Option<Pubkey>,  // 1 + 32
+    pub default_reward_fee_bps: u16,    // 2
+    pub paused: bool,                   // 1
+    pub treasury: Pubkey,               // 32
+    pub bump: u8,                       // 1
+    pub min_emergency_withdraw_delay: i64, // 8, floor of every pool's emergency withdraw delay
+    pub pool_creation_fee: u64,         // 8, lamports paid to the treasury per new pool
+}
+
+/// Pools created by one creator, so front-ends can list them without `getProgramAccounts`.
+#[account]
+pub struct PoolRegistry {
+    pub creator: Pubkey,              // 32
+    pub bump: u8,                     // 1
+    pub pools: Vec<RegisteredPool>,   // 4 + MAX_REGISTERED_POOLS * 96
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RegisteredPool {
+    pub pool: Pubkey,        // 32
+    pub mint: Pubkey,        // 32
+    pub reward_mint: Pubkey, // 32
+}
+
+#[account]
+pub struct StakerDeposit {
+    pub deposit_id: u64,          // 8
+    pub tokens_deposited: u64,    // 8
+    pub tokens_claimed: u64,      // 8
+    pub unlock_timestamp: i64,    // 8
+    pub is_withdrawn: bool,       // 1
+    pub is_cooldown_active: bool, // 1
+    pub bump: u8,                 // 1
+    pub reward_debts: [u128; MAX_REWARD_TOKENS], // 16 * MAX_REWARD_TOKENS
+    pub rewards_owed: [u64; MAX_REWARD_TOKENS],  // 8 * MAX_REWARD_TOKENS
+    pub auto_unstake_at: i64,     // 8, 0 when no automatic unstake is scheduled
+    pub has_receipt: bool,        // 1
+    pub referrer: Option<Pubkey>, // 1 + 32
+    pub voting_weight: u64,       // 8
+    pub stake_slot: u64,          // 8, slot the current stake was made in
+    pub staked_at: i64,           // 8
+    pub is_frozen: bool,          // 1, set by the pool creator to block exits
+    pub version: u8,              // 1
+    pub lock_extension: i64,      // 8, seconds added to the pool's cooldown, since version 2
+    pub keeper_tip: u64,          // 8, lamports held for the auto-unstake executor, since version 3
+}
+
+impl Versioned for StakerDeposit {
+    const VERSION: u8 = 3;
+    const SPACE: usize = 8 + // Anchor allocation
+        8 + // deposit_id
+        8 + // tokens_deposited
+        8 + // tokens_claimed
+        8 + // unlock_timestamp
+        1 + // is_withdrawn
+        1 + // is_cooldown_active
+        1 + // bump u8
+        16 * MAX_REWARD_TOKENS + // reward_debts
+        8 * MAX_REWARD_TOKENS + // rewards_owed
+        8 + // auto_unstake_at
+        1 + // has_receipt
+        1 + 32 + // referrer
+        8 + // voting_weight
+        8 + // stake_slot
+        8 + // staked_at
+        1 + // is_frozen
+        1 + // version
+        8 + // lock_extension
+        8; // keeper_tip
+
+    fn version(&self) -> u8 {
+        self.version
+    }
+
+    fn upgrade(&mut self) -> Result<()> {
+        // Version 1 only appended `version`, version 2 added lock extensions and version 3
+        // keeper tips
+        if self.version < 2 {
+            self.lock_extension = 0;
+        }
+        if self.version < 3 {
+            self.keeper_tip = 0;
+        }
+        self.version = Self::VERSION;
+        Ok(())
+    }
+}
+
+impl StakerDeposit {
+    /// Cooldown this deposit is committed to: the pool's plus its lock extension.
+    pub fn cooldown_duration(&self, pool: &StakingPool) -> i64 {
+        pool.claim_cooldown.saturating_add(self.lock_extension)
+    }
+
+    /// Whether the stake is too recent for its cooldown to start, see `MIN_STAKE_SLOTS`.
+    pub fn is_too_recent(&self, slot: u64) -> bool {
+        slot < self.stake_slot.saturating_add(MIN_STAKE_SLOTS)
+    }
+
+    /// Move extra reward tokens accrued since the last settlement into `rewards_owed`.
+    pub fn settle_reward_tokens(&mut self, reward_tokens: &[RewardTokenInfo]) -> Result<()> {
+        for (index, reward_token) in reward_tokens.iter().enumerate() {
+            let accumulated = reward_token.accumulated(self.tokens_deposited)?;
+            let pending = accumulated
+                .checked_sub(self.reward_debts[index])
+                .ok_or(StakingError::MathOverflow)?;
+
+            self.rewards_owed[index] = self.rewards_owed[index]
+                .checked_add(u64::try_from(pending).map_err(|_| StakingError::MathOverflow)?)
+                .ok_or(StakingError::MathOverflow)?;
+            self.reward_debts[index] = accumulated;
+        }
+
+        Ok(())
+    }
+}
+
+/// Clock offset used by `dev-clock` builds, see `current_timestamp`.
+#[account]
+pub struct DevClock {
+    pub authority: Pubkey, // 32, upgrade authority that last set the offset
+    pub offset: i64,       // 8, seconds added to the sysvar clock
+    pub bump: u8,          // 1
+}
+
+/// Per staker and pool: the next free deposit id, how many deposits were opened and the
+/// stake they still hold. Ids only ever increase, so a closed deposit's id is never handed
+/// out again.
+#[account]
+pub struct DepositCounter {
+    pub staker: Pubkey,         // 32
+    pub pool: Pubkey,           // 32
+    pub next_deposit_id: u64,   // 8
+    pub deposit_count: u64,     // 8
+    pub bump: u8,               // 1
+    pub open_deposits: u64,     // 8, deposits in this pool not withdrawn yet
+    pub staked: u64,            // 8, tokens held by those deposits
+}
+
+impl DepositCounter {
+    pub const SPACE: usize = 8 + // Anchor allocation
+        32 + // staker
+        32 + // pool
+        8 + // next_deposit_id
+        8 + // deposit_count
+        1 + // bump u8
+        8 + // open_deposits
+        8; // staked
+
+    /// Count a new deposit of `amount` in the pool, within the pool's limit on open deposits.
+    pub fn open_deposit(&mut self, max_open_deposits: u16, amount: u64) -> Result<()> {
+        require!(
+            max_open_deposits == 0 || self.open_deposits < max_open_deposits as u64,
+            StakingError::TooManyOpenDeposits
+        );
+        self.open_deposits += 1;
+        self.staked += amount;
+        Ok(())
+    }
+
+    /// Count a deposit still holding `amount` as withdrawn or moved away.
+    pub fn close_deposit(&mut self, amount: u64) {
+        self.open_deposits = self.open_deposits.saturating_sub(1);
+        self.remove_stake(amount);
+    }
+
+    /// Take `amount` out of an open deposit's stake, e.g. a slash or an unstake fee.
+    pub fn remove_stake(&mut self, amount: u64) {
+        self.staked = self.staked.saturating_sub(amount);
+    }
+}
+
+#[account]
+pub struct ReferralStats {
+    pub referrer: Pubkey,         // 32
+    pub referred_deposits: u64,   // 8
+    pub referred_stake: u64,      // 8
+    pub payouts: u64,             // 8
+}
+
+#[account]
+pub struct StakerStats {
+    pub staker: Pubkey,     // 32
+    pub total_staked: u64,  // 8
+    pub bump: u8,           // 1
+    pub voting_weight: u64, // 8, summed over all pools
+    pub lifetime_rewards_earned: u64, // 8, summed over all pools
+    pub version: u8,        // 1
+    pub open_deposits: u64, // 8, deposits not withdrawn yet over all pools, since version 2
+}
+
+impl Versioned for StakerStats {
+    const VERSION: u8 = 2;
+    const SPACE: usize = 8 + // Anchor allocation
+        32 + // staker
+        8 + // total_staked
+        1 + // bump u8
+        8 + // voting_weight
+        8 + // lifetime_rewards_earned
+        1 + // version
+        8; // open_deposits
+
+    fn version(&self) -> u8 {
+        self.version
+    }
+
+    fn upgrade(&mut self) -> Result<()> {
+        // Version 1 only appended `version`. Version 2 starts counting open deposits from
+        // zero, deposits opened before the upgrade are not counted
+        if self.version < 2 {
+            self.open_deposits = 0;
+        }
+        self.version = Self::VERSION;
+        Ok(())
+    }
+}
+
+impl StakerStats {
+    /// Count a deposit as withdrawn. Saturates for deposits opened before version 2,
+    /// which were never counted.
+    pub fn close_deposit(&mut self) {
+        self.open_deposits = self.open_deposits.saturating_sub(1);
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Staking>,
+    /// Only the upgrade authority may claim the admin role
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ StakingError::UnauthorizedAdmin
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + // anchor overhead
+        32 + // admin
+        1 + 32 + // pending_admin
+        2 + // default_reward_fee_bps
+        1 + // paused
+        32 + // treasury
+        1 + // bump
+        8 + // min_emergency_withdraw_delay
+        8, // pool_creation_fee
+        seeds = [b"global-config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"global-config"],
+        bump = global_config.bump,
+        has_one = admin @ StakingError::UnauthorizedAdmin
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub new_admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"global-config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool_id: u64, initial_funding_amount: u64)]
+pub struct CreatePool<'info> {
+    pub mint: Account<'info, Mint>,
+    pub reward_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(seeds = [b"global-config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+    #[account(
+        init,
+        payer = creator,
+        space = StakingPool::SPACE,
+        seeds = [b"pool", creator.key().as_ref(), &pool_id.to_le_bytes()],
+        bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        init_if_needed,
+        payer = creator,
+        space = 8 + // anchor overhead
+        32 + // creator
+        1 + // bump
+        4 + MAX_REGISTERED_POOLS * 96, // pools
+        seeds = [b"pool-registry", creator.key().as_ref()],
+        bump
+    )]
+    pub pool_registry: Account<'info, PoolRegistry>,
+    #[account(
+        init,
+        payer = creator,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = creator,
+        token::mint = reward_mint,
+        token::authority = pool,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = creator
+    )]
+    pub creator_reward_ata: Account<'info, TokenAccount>,
+    /// CHECK: Fee treasury wallet, required when the protocol charges a pool creation fee
+    #[account(mut, address = global_config.treasury @ StakingError::MissingFeeTreasury)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CreatePool<'info> {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_transfer_to_pda_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: self.creator_reward_ata.to_account_info(),
+            mint: self.reward_mint.to_account_info(),
+            to: self.reward_vault.to_account_info(),
+            authority: self.creator.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: Option<u64>, deposit_amount: u64, referrer: Option<Pubkey>)]
+pub struct CreateDeposit<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = DepositCounter::SPACE,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = StakerDeposit::SPACE,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.unwrap_or(deposit_counter.next_deposit_id).to_le_bytes(),
+        ],
+        bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        init_if_needed, 
+        payer = staker,
+        space = StakerStats::SPACE,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    /// Referrer's stats, required when staking with a referrer
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = 8 + // Anchor allocation
+        32 + // referrer
+        8 + // referred_deposits
+        8 + // referred_stake
+        8, // payouts
+        seeds = [b"referral-stats", referrer.unwrap_or_default().as_ref()],
+        bump
+    )]
+    pub referral_stats: Option<Account<'info, ReferralStats>>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub staker_ata: Account<'info, TokenAccount>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CreateDeposit<'info> {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_transfer_to_pda_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: self.staker_ata.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.pool_vault.to_account_info(),
+            authority: self.staker.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct Restake<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    pub staker: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        mut,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        mut,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub staker_ata: Account<'info, TokenAccount>,
+    /// CHECK: vesting schedule of the deposit's reward lockup, must not exist
+    pub reward_lockup_schedule: UncheckedAccount<'info>,
+    /// CHECK: claim escrow of the deposit, must not exist
+    #[account(seeds = [b"claim-escrow", deposit.key().as_ref()], bump)]
+    pub claim_escrow: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: Option<u64>, deposit_amount: u64, referrer: Option<Pubkey>)]
+pub struct StakeSol<'info> {
+    #[account(
+        address = pool.mint,
+        constraint = mint.key() == native_mint::ID @ StakingError::NotNativeSolPool
+    )]
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = DepositCounter::SPACE,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = StakerDeposit::SPACE,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.unwrap_or(deposit_counter.next_deposit_id).to_le_bytes(),
+        ],
+        bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = StakerStats::SPACE,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    /// Referrer's stats, required when staking with a referrer
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = 8 + // Anchor allocation
+        32 + // referrer
+        8 + // referred_deposits
+        8 + // referred_stake
+        8, // payouts
+        seeds = [b"referral-stats", referrer.unwrap_or_default().as_ref()],
+        bump
+    )]
+    pub referral_stats: Option<Account<'info, ReferralStats>>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeWithDelegate<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    /// Pays fees and rent in place of the staker
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+    /// CHECK: owner of the deposit, checked against `staker_ata`
+    pub staker: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = DepositCounter::SPACE,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = StakerDeposit::SPACE,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_counter.next_deposit_id.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        init_if_needed, 
+        payer = relayer,
+        space = StakerStats::SPACE,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = staker_ata.owner == staker.key() @ StakingError::InvalidStakerTokenAccount,
+        constraint = staker_ata.mint == mint.key() @ StakingError::InvalidStakerTokenAccount
+    )]
+    pub staker_ata: Account<'info, TokenAccount>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> StakeWithDelegate<'info> {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_transfer_to_pda_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: self.staker_ata.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.pool_vault.to_account_info(),
+            authority: self.pool.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct UnstakeDeposit<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(address = pool.reward_mint)]
+    pub reward_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(
+        mut, 
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        mut, 
+        seeds = [b"staker-stats", staker.key().as_ref()], 
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(
+        mut,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub staker_ata: Account<'info, TokenAccount>,
+    /// Token account receiving the principal instead of `staker_ata`, such as a cold wallet
+    /// or an exchange deposit address
+    #[account(
+        mut,
+        constraint = destination.mint == mint.key() @ StakingError::InvalidDestination
+    )]
+    pub destination: Option<Account<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = reward_mint,
+        associated_token::authority = staker
+    )]
+    pub staker_reward_ata: Account<'info, TokenAccount>,
+    #[account(seeds = [b"global-config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+    /// Fee treasury's reward token account, required when the pool charges a fee
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = global_config.treasury
+    )]
+    pub treasury_reward_ata: Option<Account<'info, TokenAccount>>,
+    /// Referrer's reward token account, required when the deposit has a referrer
+    #[account(mut)]
+    pub referrer_reward_ata: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub referral_stats: Option<Account<'info, ReferralStats>>,
+    /// Receipt of a tokenized deposit, burned on exit
+    #[account(
+        mut,
+        seeds = [b"receipt-mint", deposit.key().as_ref()],
+        bump
+    )]
+    pub receipt_mint: Option<Account<'info, Mint>>,
+    #[account(mut, token::authority = staker)]
+    pub staker_receipt_ata: Option<Account<'info, TokenAccount>>,
+    /// NFT of the pool's boost collection held by the staker, with its metadata
+    pub boost_nft_ata: Option<Account<'info, TokenAccount>>,
+    pub boost_nft_metadata: Option<Account<'info, MetadataAccount>>,
+    /// Reward lockup accounts, required when the payout is above the pool's lockup threshold
+    /// CHECK: PDA derived from the deposit, checked in `pay_staker_rewards`
+    #[account(mut)]
+    pub reward_lockup_authority: Option<UncheckedAccount<'info>>,
+    /// CHECK: the authority's reward token ATA, checked in `pay_staker_rewards`
+    #[account(mut)]
+    pub reward_lockup_ata: Option<UncheckedAccount<'info>>,
+    /// CHECK: created and checked by the vesting program
+    #[account(mut)]
+    pub vesting_schedule: Option<UncheckedAccount<'info>>,
+    /// CHECK: created and checked by the vesting program
+    #[account(mut)]
+    pub vesting_vault: Option<UncheckedAccount<'info>>,
+    pub vesting_program: Option<Program<'info, Vesting>>,
+    /// Escrow of the reward payout, required when the pool streams claimed rewards
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + // anchor overhead
+        32 + // staker
+        32 + // pool
+        32 + // deposit
+        32 + // stream
+        1 + // bump
+        32, // rent_payer
+        seeds = [b"claim-escrow", deposit.key().as_ref()],
+        bump
+    )]
+    pub claim_escrow: Option<Account<'info, ClaimEscrow>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeMany<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(address = pool.reward_mint)]
+    pub reward_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(
+        mut,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub staker_ata: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = reward_mint,
+        associated_token::authority = staker
+    )]
+    pub staker_reward_ata: Account<'info, TokenAccount>,
+    #[account(seeds = [b"global-config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+    /// Fee treasury's reward token account, required when the pool charges a fee
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = global_config.treasury
+    )]
+    pub treasury_reward_ata: Option<Account<'info, TokenAccount>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: dev clock PDA, only read by `current_timestamp` in `dev-clock` builds
+    pub dev_clock: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshPool<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct CrankAutoUnstake<'info> {
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    /// CHECK: only used as the deposit seed and the payout ATAs' authority
+    pub staker: AccountInfo<'info>,
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(address = pool.reward_mint)]
+    pub reward_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        mut,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(
+        mut,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        associated_token::mint = mint,
+        associated_token::authority = staker
+    )]
+    pub staker_ata: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        associated_token::mint = reward_mint,
+        associated_token::authority = staker
+    )]
+    pub staker_reward_ata: Account<'info, TokenAccount>,
+    #[account(seeds = [b"global-config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+    /// Fee treasury's reward token account, required when the pool charges a fee
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = global_config.treasury
+    )]
+    pub treasury_reward_ata: Option<Account<'info, TokenAccount>>,
+    /// Referrer's reward token account, required when the deposit has a referrer
+    #[account(mut)]
+    pub referrer_reward_ata: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub referral_stats: Option<Account<'info, ReferralStats>>,
+    /// Reward lockup accounts, required when the payout is above the pool's lockup threshold
+    /// CHECK: PDA derived from the deposit, checked in `pay_staker_rewards`
+    #[account(mut)]
+    pub reward_lockup_authority: Option<UncheckedAccount<'info>>,
+    /// CHECK: the authority's reward token ATA, checked in `pay_staker_rewards`
+    #[account(mut)]
+    pub reward_lockup_ata: Option<UncheckedAccount<'info>>,
+    /// CHECK: created and checked by the vesting program
+    #[account(mut)]
+    pub vesting_schedule: Option<UncheckedAccount<'info>>,
+    /// CHECK: created and checked by the vesting program
+    #[account(mut)]
+    pub vesting_vault: Option<UncheckedAccount<'info>>,
+    pub vesting_program: Option<Program<'info, Vesting>>,
+    /// Escrow of the reward payout, required when the pool streams claimed rewards
+    #[account(
+        init,
+        payer = cranker,
+        space = 8 + // anchor overhead
+        32 + // staker
+        32 + // pool
+        32 + // deposit
+        32 + // stream
+        1 + // bump
+        32, // rent_payer
+        seeds = [b"claim-escrow", deposit.key().as_ref()],
+        bump
+    )]
+    pub claim_escrow: Option<Account<'info, ClaimEscrow>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct ActivateDepositCooldown<'info> {
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(
+        mut, 
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(mut)]
+    pub pool: Account<'info, StakingPool>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct ExtendLock<'info> {
+    pub staker: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        mut,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(mut)]
+    pub pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct MintStakeReceipt<'info> {
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        init,
+        payer = staker,
+        seeds = [b"receipt-mint", deposit.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = pool,
+        mint::freeze_authority = pool
+    )]
+    pub receipt_mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = staker
+    )]
+    pub staker_receipt_ata: Account<'info, TokenAccount>,
+    /// CHECK: created and validated by the token metadata program
+    #[account(
+        mut,
+        seeds = [
+            b"metadata",
+            token_metadata_program.key().as_ref(),
+            receipt_mint.key().as_ref(),
+        ],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct TransferDeposit<'info> {
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    /// CHECK: any wallet or multisig that will own the deposit
+    pub new_owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = staker,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = DepositCounter::SPACE,
+        seeds = [b"deposit-counter", new_owner.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub new_deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        init,
+        payer = staker,
+        space = StakerDeposit::SPACE,
+        seeds = [
+            b"deposit",
+            new_owner.key().as_ref(),
+            pool.key().as_ref(),
+            &new_deposit_counter.next_deposit_id.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub new_deposit: Account<'info, StakerDeposit>,
+    #[account(
+        mut,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(
+        mut,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = StakerStats::SPACE,
+        seeds = [b"staker-stats", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_owner_stats: Account<'info, StakerStats>,
+    pub pool: Account<'info, StakingPool>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct UnstakeDepositEmergency<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(
+        mut, 
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        mut, 
+        seeds = [b"staker-stats", staker.key().as_ref()], 
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(
+        mut,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub staker_ata: Account<'info, TokenAccount>,
+    /// Receipt of a tokenized deposit, burned on exit
+    #[account(
+        mut,
+        seeds = [b"receipt-mint", deposit.key().as_ref()],
+        bump
+    )]
+    pub receipt_mint: Option<Account<'info, Mint>>,
+    #[account(mut, token::authority = staker)]
+    pub staker_receipt_ata: Option<Account<'info, TokenAccount>>,
+    /// Reward accounts, required when the deposit has rewards reserved for it
+    #[account(address = pool.reward_mint)]
+    pub reward_mint: Option<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::authority = staker)]
+    pub staker_reward_ata: Option<Account<'info, TokenAccount>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> UnstakeDepositEmergency<'info> {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_withdraw_context(&self) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: self.pool_vault.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.staker_ata.to_account_info(),
+            authority: self.pool.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
     }
 }
 
-#[account]
-pub struct StakingPool {
-    pub pool_id: u64,                 // 8
-    pub creator: Pubkey,              // 32
-    pub current_tokens_staked: u64,   // 8
-    pub current_rewards: u64,         // 8
-    pub claim_cooldown: i64,          // 8
-    pub emergency_mode_enabled: bool, // 1
-    pub bump: u8,                     // 1
+#[derive(Accounts)]
+pub struct WithdrawRewardsEmergency<'info> {
+    #[account(address = pool.reward_mint)]
+    pub reward_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(mut)]
+    pub pool: Account<'info, StakingPool>,
+    #[account(seeds = [b"global-config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub creator_reward_ata: Account<'info, TokenAccount>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
-#[account]
-pub struct StakerDeposit {
-    pub deposit_id: u64,          // 8
-    pub tokens_deposited: u64,    // 8
-    pub tokens_claimed: u64,      // 8
-    pub unlock_timestamp: i64,    // 8
-    pub is_withdrawn: bool,       // 1
-    pub is_cooldown_active: bool, // 1
-    pub bump: u8,                 // 1
+impl<'info> WithdrawRewardsEmergency<'info> {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_withdraw_context(&self) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: self.reward_vault.to_account_info(),
+            mint: self.reward_mint.to_account_info(),
+            to: self.creator_reward_ata.to_account_info(),
+            authority: self.pool.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
 }
 
-#[account]
-pub struct StakerStats {
-    pub staker: Pubkey,     // 32
-    pub total_staked: u64,  // 8
-    pub bump: u8,           // 1
+#[derive(Accounts)]
+pub struct WithdrawUnusedRewards<'info> {
+    #[account(address = pool.reward_mint)]
+    pub reward_mint: Account<'info, Mint>,
+    pub creator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = reward_mint)]
+    pub creator_reward_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-#[instruction(pool_id: u64, initial_funding_amount: u64)]
-pub struct CreatePool<'info> {
+#[instruction(deposit_id: u64)]
+pub struct SlashDeposit<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    pub creator: Signer<'info>,
+    /// CHECK: owner of the slashed deposit, only used as a seed
+    pub staker: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        mut,
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    #[account(
+        mut,
+        seeds = [b"deposit-counter", staker.key().as_ref(), pool.key().as_ref()],
+        bump = deposit_counter.bump
+    )]
+    pub deposit_counter: Account<'info, DepositCounter>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct FreezeDeposit<'info> {
+    pub creator: Signer<'info>,
+    /// CHECK: owner of the frozen deposit, only used as a seed
+    pub staker: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"deposit",
+            staker.key().as_ref(),
+            pool.key().as_ref(),
+            &deposit_id.to_le_bytes(),
+        ],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, StakerDeposit>,
+    #[account(
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct EnableShareToken<'info> {
+    #[account(address = pool.mint)]
     pub mint: Account<'info, Mint>,
     #[account(mut)]
     pub creator: Signer<'info>,
     #[account(
-        init,
-        payer = creator,
-        space = 8 + // anchor overhead
-        8 + // pool_id
-        32 + // creator
-        8 + // current_tokens_staked
-        8 + // current_rewards
-        8 + // claim_cooldown
-        1 + // emergency_mode_enabled
-        1, // bump
-        seeds = [b"pool", creator.key().as_ref(), &pool_id.to_le_bytes()],
-        bump
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
     )]
     pub pool: Account<'info, StakingPool>,
     #[account(
         init,
         payer = creator,
+        seeds = [b"share-mint", pool.key().as_ref()],
+        bump,
+        mint::decimals = mint.decimals,
+        mint::authority = pool
+    )]
+    pub share_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeLiquid<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub staker_ata: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = pool.share_mint == Some(share_mint.key()) @ StakingError::ShareTokenNotEnabled
+    )]
+    pub share_mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = share_mint,
+        associated_token::authority = staker
+    )]
+    pub staker_share_ata: Account<'info, TokenAccount>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeLiquid<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    pub staker: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub staker_ata: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = pool.share_mint == Some(share_mint.key()) @ StakingError::ShareTokenNotEnabled
+    )]
+    pub share_mint: Account<'info, Mint>,
+    #[account(mut, token::mint = share_mint, token::authority = staker)]
+    pub staker_share_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: owner, discriminator and layout are checked in `migrate_account`
+    #[account(mut)]
+    pub account: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    #[account(address = pool.mint)]
+    pub mint: Account<'info, Mint>,
+    /// Pool authority, a wallet or a program PDA signing through CPI
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
         associated_token::mint = mint,
         associated_token::authority = pool
     )]
     pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub creator_ata: Account<'info, TokenAccount>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundPool<'info> {
+    #[account(address = pool.reward_mint)]
+    pub reward_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(mut)]
+    pub pool: Account<'info, StakingPool>,
     #[account(
         mut,
-        associated_token::mint = mint,
-        associated_token::authority = creator
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
     )]
-    pub creator_ata: Account<'info, TokenAccount>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub creator_reward_ata: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
 }
 
-impl<'info> CreatePool<'info> {
+impl<'info> FundPool<'info> {
+    #[allow(clippy::wrong_self_convention)]
     fn into_transfer_to_pda_context(
         &self,
     ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
         let cpi_accounts = TransferChecked {
-            from: self.creator_ata.to_account_info(),
-            mint: self.mint.to_account_info(),
-            to: self.pool_vault.to_account_info(),
+            from: self.creator_reward_ata.to_account_info(),
+            mint: self.reward_mint.to_account_info(),
+            to: self.reward_vault.to_account_info(),
             authority: self.creator.to_account_info(),
         };
         CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
@@ -476,62 +5617,61 @@ impl<'info> CreatePool<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(deposit_id: u64)]
-pub struct CreateDeposit<'info> {
-    pub mint: Account<'info, Mint>,
+pub struct AddRewardToken<'info> {
+    pub reward_token_mint: Account<'info, Mint>,
     #[account(mut)]
-    pub staker: Signer<'info>,
+    pub creator: Signer<'info>,
+    #[account(mut)]
+    pub pool: Account<'info, StakingPool>,
     #[account(
         init,
-        payer = staker,
-        space = 8 + // Anchor allocation
-        8 + // deposit_id
-        8 + // tokens_deposited
-        8 + // tokens_claimed
-        8 + // unlock_timestamp
-        1 + // is_withdrawn
-        1 + // is_cooldown_active
-        1, // bump u8
+        payer = creator,
+        token::mint = reward_token_mint,
+        token::authority = pool,
         seeds = [
-            b"deposit",
-            staker.key().as_ref(),
+            b"reward-token-vault",
             pool.key().as_ref(),
-            &deposit_id.to_le_bytes(),
+            reward_token_mint.key().as_ref(),
         ],
         bump
     )]
-    pub deposit: Account<'info, StakerDeposit>,
+    pub reward_token_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundRewardToken<'info> {
+    pub reward_token_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(mut)]
+    pub pool: Account<'info, StakingPool>,
     #[account(
-        init_if_needed, 
-        payer = staker,
-        space = 8 + // Anchor allocation
-        32 + // staker
-        8 + // total_staked
-        1, // bump u8
-        seeds = [b"staker-stats", staker.key().as_ref()],
+        mut,
+        seeds = [
+            b"reward-token-vault",
+            pool.key().as_ref(),
+            reward_token_mint.key().as_ref(),
+        ],
         bump
     )]
-    pub staker_stats: Account<'info, StakerStats>,
-    #[account(mut)]
-    pub pool: Account<'info, StakingPool>,
-    #[account(mut)]
-    pub pool_vault: Account<'info, TokenAccount>,
+    pub reward_token_vault: Account<'info, TokenAccount>,
     #[account(mut)]
-    pub staker_ata: Account<'info, TokenAccount>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub creator_reward_token_ata: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
 }
 
-impl<'info> CreateDeposit<'info> {
+impl<'info> FundRewardToken<'info> {
+    #[allow(clippy::wrong_self_convention)]
     fn into_transfer_to_pda_context(
         &self,
     ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
         let cpi_accounts = TransferChecked {
-            from: self.staker_ata.to_account_info(),
-            mint: self.mint.to_account_info(),
-            to: self.pool_vault.to_account_info(),
-            authority: self.staker.to_account_info(),
+            from: self.creator_reward_token_ata.to_account_info(),
+            mint: self.reward_token_mint.to_account_info(),
+            to: self.reward_token_vault.to_account_info(),
+            authority: self.creator.to_account_info(),
         };
         CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
     }
@@ -539,12 +5679,12 @@ impl<'info> CreateDeposit<'info> {
 
 #[derive(Accounts)]
 #[instruction(deposit_id: u64)]
-pub struct UnstakeDeposit<'info> {
-    pub mint: Account<'info, Mint>,
+pub struct ClaimRewardToken<'info> {
+    pub reward_token_mint: Account<'info, Mint>,
     #[account(mut)]
     pub staker: Signer<'info>,
     #[account(
-        mut, 
+        mut,
         seeds = [
             b"deposit",
             staker.key().as_ref(),
@@ -554,42 +5694,50 @@ pub struct UnstakeDeposit<'info> {
         bump = deposit.bump
     )]
     pub deposit: Account<'info, StakerDeposit>,
-    #[account(
-        mut, 
-        seeds = [b"staker-stats", staker.key().as_ref()], 
-        bump = staker_stats.bump
-    )]
-    pub staker_stats: Account<'info, StakerStats>,
     #[account(mut)]
     pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        seeds = [
+            b"reward-token-vault",
+            pool.key().as_ref(),
+            reward_token_mint.key().as_ref(),
+        ],
+        bump
+    )]
+    pub reward_token_vault: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = reward_token_mint,
+        associated_token::authority = staker
+    )]
+    pub staker_reward_token_ata: Account<'info, TokenAccount>,
+    #[account(seeds = [b"global-config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+    /// Fee treasury's account for this reward token, required when the pool charges a fee
+    #[account(
+        mut,
+        associated_token::mint = reward_token_mint,
+        associated_token::authority = global_config.treasury
+    )]
+    pub treasury_reward_token_ata: Option<Account<'info, TokenAccount>>,
+    /// Referrer's account for this reward token, required when the deposit has a referrer
     #[account(mut)]
-    pub pool_vault: Account<'info, TokenAccount>,
+    pub referrer_reward_token_ata: Option<Account<'info, TokenAccount>>,
     #[account(mut)]
-    pub staker_ata: Account<'info, TokenAccount>,
+    pub referral_stats: Option<Account<'info, ReferralStats>>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
-impl<'info> UnstakeDeposit<'info> {
-    fn into_withdraw_context(&self) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
-        let cpi_accounts = TransferChecked {
-            from: self.pool_vault.to_account_info(),
-            mint: self.mint.to_account_info(),
-            to: self.staker_ata.to_account_info(),
-            authority: self.pool.to_account_info(),
-        };
-        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
-    }
-}
-
 #[derive(Accounts)]
 #[instruction(deposit_id: u64)]
-pub struct ActivateDepositCooldown<'info> {
-    #[account(mut)]
+pub struct ClaimRewards<'info> {
     pub staker: Signer<'info>,
     #[account(
-        mut, 
+        mut,
         seeds = [
             b"deposit",
             staker.key().as_ref(),
@@ -599,18 +5747,56 @@ pub struct ActivateDepositCooldown<'info> {
         bump = deposit.bump
     )]
     pub deposit: Account<'info, StakerDeposit>,
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
     pub pool: Account<'info, StakingPool>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: dev clock PDA, only read by `current_timestamp` in `dev-clock` builds
+    pub dev_clock: Option<UncheckedAccount<'info>>,
 }
 
 #[derive(Accounts)]
-#[instruction(deposit_id: u64)]
-pub struct UnstakeDepositEmergency<'info> {
-    pub mint: Account<'info, Mint>,
+pub struct ReleaseClaimedRewards<'info> {
     #[account(mut)]
     pub staker: Signer<'info>,
+    #[account(address = pool.reward_mint)]
+    pub reward_mint: Account<'info, Mint>,
+    #[account(
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"claim-escrow", claim_escrow.deposit.as_ref()],
+        bump = claim_escrow.bump,
+        has_one = staker,
+        has_one = pool
+    )]
+    pub claim_escrow: Account<'info, ClaimEscrow>,
+    /// CHECK: paid the escrow's rent, e.g. a cranker, and gets it back on close
+    #[account(mut, address = claim_escrow.rent_payer)]
+    pub rent_payer: UncheckedAccount<'info>,
+    #[account(mut, token::mint = reward_mint, token::authority = staker)]
+    pub staker_reward_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_id: u64)]
+pub struct GetPendingRewards<'info> {
+    /// CHECK: only used to derive the deposit address
+    pub staker: AccountInfo<'info>,
     #[account(
-        mut, 
         seeds = [
             b"deposit",
             staker.key().as_ref(),
@@ -620,88 +5806,74 @@ pub struct UnstakeDepositEmergency<'info> {
         bump = deposit.bump
     )]
     pub deposit: Account<'info, StakerDeposit>,
-    #[account(
-        mut, 
-        seeds = [b"staker-stats", staker.key().as_ref()], 
-        bump = staker_stats.bump
-    )]
-    pub staker_stats: Account<'info, StakerStats>,
-    #[account(mut)]
     pub pool: Account<'info, StakingPool>,
-    #[account(mut)]
-    pub pool_vault: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub staker_ata: Account<'info, TokenAccount>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-}
-
-impl<'info> UnstakeDepositEmergency<'info> {
-    fn into_withdraw_context(&self) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
-        let cpi_accounts = TransferChecked {
-            from: self.pool_vault.to_account_info(),
-            mint: self.mint.to_account_info(),
-            to: self.staker_ata.to_account_info(),
-            authority: self.pool.to_account_info(),
-        };
-        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
-    }
 }
 
 #[derive(Accounts)]
-pub struct WithdrawRewardsEmergency<'info> {
-    pub mint: Account<'info, Mint>,
-    #[account(mut)]
-    pub creator: Signer<'info>,
-    #[account(mut)]
+pub struct GetPoolStats<'info> {
+    #[account(
+        seeds = [b"pool", pool.creator.as_ref(), &pool.pool_id.to_le_bytes()],
+        bump = pool.bump
+    )]
     pub pool: Account<'info, StakingPool>,
-    #[account(mut)]
+    #[account(
+        associated_token::mint = pool.mint,
+        associated_token::authority = pool
+    )]
     pub pool_vault: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub creator_ata: Account<'info, TokenAccount>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"reward-vault", pool.key().as_ref()],
+        bump = pool.reward_vault_bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
 }
 
-impl<'info> WithdrawRewardsEmergency<'info> {
-    fn into_withdraw_context(&self) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
-        let cpi_accounts = TransferChecked {
-            from: self.pool_vault.to_account_info(),
-            mint: self.mint.to_account_info(),
-            to: self.creator_ata.to_account_info(),
-            authority: self.pool.to_account_info(),
-        };
-        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
-    }
+#[derive(Accounts)]
+pub struct GetVotingPower<'info> {
+    /// CHECK: only used to derive the staker stats address
+    pub staker: AccountInfo<'info>,
+    #[account(
+        seeds = [b"staker-stats", staker.key().as_ref()],
+        bump = staker_stats.bump
+    )]
+    pub staker_stats: Account<'info, StakerStats>,
+    pub pool: Account<'info, StakingPool>,
 }
 
 #[derive(Accounts)]
-pub struct UpdatePool<'info> {
-    pub mint: Account<'info, Mint>,
-    #[account(mut)]
-    pub creator: Signer<'info>,
-    #[account(mut)]
-    pub pool: Account<'info, StakingPool>,
-    #[account(mut)]
-    pub pool_vault: Account<'info, TokenAccount>,
+pub struct SetDevClock<'info> {
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + // Anchor allocation
+        32 + // authority
+        8 + // offset
+        1, // bump u8
+        seeds = [DEV_CLOCK_SEED],
+        bump
+    )]
+    pub dev_clock: Account<'info, DevClock>,
     #[account(mut)]
-    pub creator_ata: Account<'info, TokenAccount>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Program<'info, Token>,
+    pub authority: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Staking>,
+    /// Only the upgrade authority may shift the clock
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ StakingError::InvalidDevClockAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
     pub system_program: Program<'info, System>,
 }
 
-impl<'info> UpdatePool<'info> {
-    fn into_transfer_to_pda_context(
-        &self,
-    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+impl<'info> ClaimRewardToken<'info> {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_withdraw_context(&self) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
         let cpi_accounts = TransferChecked {
-            from: self.creator_ata.to_account_info(),
-            mint: self.mint.to_account_info(),
-            to: self.pool_vault.to_account_info(),
-            authority: self.creator.to_account_info(),
+            from: self.reward_token_vault.to_account_info(),
+            mint: self.reward_token_mint.to_account_info(),
+            to: self.staker_reward_token_ata.to_account_info(),
+            authority: self.pool.to_account_info(),
         };
         CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
     }
@@ -723,11 +5895,336 @@ pub enum StakingError {
     ClaimCooldownNotActive,
     #[msg("Cooldown already activated")]
     CooldownAlreadyActivated,
+    #[msg("Cooldown must be between zero and the maximum cooldown")]
+    InvalidCooldown,
     #[msg("Emergency mode is not enabled")]
     EmergencyModeNotEnabled,
     #[msg("Deposit already withdrawn")]
     DepositAlreadyWithdrawn,
     #[msg("Unauthorized pool access")]
     UnauthorizedPoolAccess,
+    #[msg("Too many reward tokens")]
+    TooManyRewardTokens,
+    #[msg("Reward token already added")]
+    RewardTokenAlreadyAdded,
+    #[msg("Reward token not found")]
+    RewardTokenNotFound,
+    #[msg("Nothing to claim")]
+    NothingToClaim,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Not enough principal in the pool vault")]
+    InsufficientPrincipalInVault,
+    #[msg("Not enough rewards in the reward vault")]
+    InsufficientRewardsInVault,
+    #[msg("Deposit id has already been used")]
+    DepositIdAlreadyUsed,
+    #[msg("An emergency withdrawal happened in this slot")]
+    EmergencyWithdrawalInFlight,
+    #[msg("Pool is not the delegate of the staker token account")]
+    PoolNotDelegate,
+    #[msg("Delegated amount is lower than the deposit")]
+    InsufficientDelegatedAmount,
+    #[msg("Invalid staker token account")]
+    InvalidStakerTokenAccount,
+    #[msg("Reward fee exceeds the maximum")]
+    RewardFeeTooHigh,
+    #[msg("Fee treasury is required when a reward fee is set")]
+    MissingFeeTreasury,
+    #[msg("Protocol is paused")]
+    ProtocolPaused,
+    #[msg("Only the protocol admin can do this")]
+    UnauthorizedAdmin,
+    #[msg("Signer is not the pending admin")]
+    NotPendingAdmin,
+    #[msg("New owner must differ from the current owner")]
+    InvalidNewOwner,
+    #[msg("Automatic unstake must not be scheduled before the deposit unlocks")]
+    AutoUnstakeBeforeUnlock,
+    #[msg("No automatic unstake is scheduled for this deposit")]
+    AutoUnstakeNotScheduled,
+    #[msg("Scheduled automatic unstake time has not been reached")]
+    AutoUnstakeNotReached,
+    #[msg("A receipt was already minted for this deposit")]
+    ReceiptAlreadyMinted,
+    #[msg("Deposit is tokenized, use its receipt instead")]
+    DepositTokenized,
+    #[msg("The deposit's receipt must be burned to exit")]
+    MissingStakeReceipt,
+    #[msg("Referral share exceeds the maximum allowed")]
+    ReferralShareTooHigh,
+    #[msg("Invalid referrer or referrer account")]
+    InvalidReferrer,
+    #[msg("Referral accounts are required for this deposit")]
+    MissingReferralAccounts,
+    #[msg("Slash must be above zero and within the pool's slashing cap")]
+    InvalidSlashBps,
+    #[msg("Slashing cap can only be raised while nothing is staked")]
+    SlashCapLocked,
+    #[msg("Slashing needs a pool that stakes and rewards the same token")]
+    SlashRequiresSameMint,
+    #[msg("Deposit is too small to slash")]
+    NothingToSlash,
+    #[msg("Invalid reward lockup configuration")]
+    InvalidRewardLockup,
+    #[msg("Reward drip needs an amount and an end after its start")]
+    InvalidRewardDrip,
+    #[msg("The previous reward drip has not finished")]
+    RewardDripInProgress,
+    #[msg("Stake amount is below the pool minimum")]
+    StakeBelowMinimum,
+    #[msg("Reward accounts are required to pay the deposit's reserved rewards")]
+    MissingReservedRewardAccounts,
+    #[msg("Remaining accounts must be writable deposits of the staker in this pool")]
+    InvalidDepositBatch,
+    #[msg("Referred deposits cannot be unstaked in a batch")]
+    ReferredDepositInBatch,
+    #[msg("Reward lockup accounts are required for this payout")]
+    MissingRewardLockupAccounts,
+    #[msg("Dev clock is only available in dev-clock builds")]
+    DevClockDisabled,
+    #[msg("Signer is not the upgrade authority")]
+    InvalidDevClockAuthority,
+    #[msg("Pool does not stake native SOL")]
+    NotNativeSolPool,
+    #[msg("Only withdrawn deposits can be restaked")]
+    DepositNotWithdrawn,
+    #[msg("Deposit rewards were locked up, stake into a new deposit instead")]
+    DepositRewardsLockedUp,
+    #[msg("Deposit is too recent to start its cooldown")]
+    StakeTooRecent,
+    #[msg("Minimum reward age cannot be negative")]
+    InvalidMinRewardAge,
+    #[msg("Pool still has staked tokens")]
+    PoolHasStakers,
+    #[msg("Pool has ended")]
+    PoolEnded,
+    #[msg("Pool has a claim cooldown, activate it first")]
+    PoolHasCooldown,
+    #[msg("Pool name is too long")]
+    PoolNameTooLong,
+    #[msg("Metadata URI is too long")]
+    MetadataUriTooLong,
+    #[msg("NFT boost multiplier must be above 1x and at most the maximum")]
+    InvalidNftBoost,
+    #[msg("NFT is not held by the staker or not in the boost collection")]
+    InvalidBoostNft,
+    #[msg("Deposit is frozen by the pool creator")]
+    DepositFrozen,
+    #[msg("Deposit is not frozen")]
+    DepositNotFrozen,
+    #[msg("Emergency withdraw delay cannot be negative")]
+    InvalidEmergencyWithdrawDelay,
+    #[msg("Rewards cannot be withdrawn until the emergency withdraw delay has passed")]
+    EmergencyWithdrawTimelocked,
+    #[msg("End timestamp must be in the future, or 0 for no end")]
+    InvalidEndTimestamp,
+    #[msg("Share token is already enabled for this pool")]
+    ShareTokenAlreadyEnabled,
+    #[msg("Share token is not enabled for this pool")]
+    ShareTokenNotEnabled,
+    #[msg("Share tokens need a single-token pool without cooldown, fee or extra rewards")]
+    ShareTokenUnsupported,
+    #[msg("Deposits paying a fee or referral claim each reward token separately")]
+    ClaimRewardTokensSeparately,
+    #[msg("Reward token accounts do not match the pool's reward tokens")]
+    InvalidRewardTokenAccounts,
+    #[msg("Claim vesting duration cannot be negative")]
+    InvalidClaimVesting,
+    #[msg("Pool streams claimed rewards, pass the claim escrow")]
+    MissingClaimEscrow,
+    #[msg("Account is not owned by the staking program")]
+    InvalidMigrationAccount,
+    #[msg("Account already has the current layout")]
+    AccountUpToDate,
+    #[msg("Lock boost must be at most the maximum")]
+    InvalidLockBoost,
+    #[msg("Pool does not reward lock extensions")]
+    LockBoostDisabled,
+    #[msg("Lock extension must be positive")]
+    InvalidLockExtension,
+    #[msg("Staker has reached the pool's limit of open deposits")]
+    TooManyOpenDeposits,
+    #[msg("Instant unstake fee must be at most 100%")]
+    InvalidInstantUnstakeFee,
+    #[msg("Instant unstaking needs a pool that stakes and rewards the same token")]
+    InstantUnstakeRequiresSameMint,
+    #[msg("Pool does not allow instant unstaking")]
+    InstantUnstakeDisabled,
+    #[msg("Pool has no reward dust to sweep")]
+    NoDustToSweep,
+    #[msg("Destination is not a valid token account for this unstake")]
+    InvalidDestination,
+    #[msg("Deposit still has rewards to claim, claim them before restaking")]
+    DepositRewardsUnclaimed,
+}
+
+#[event]
+pub struct EmergencyModeDisabled {
+    pub pool: Pubkey,
+    pub creator: Pubkey,
+    pub slot: u64,
+}
+
+#[event]
+pub struct AutoUnstakeExecuted {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub deposit_id: u64,
+    pub cranker: Pubkey,
+    pub tip: u64,
+}
+
+#[event]
+pub struct Slashed {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub deposit_id: u64,
+    pub bps: u16,
+    pub amount: u64,
+}
+
+#[event]
+pub struct RewardsLockedUp {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub deposit_id: u64,
+    pub vesting_schedule: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct RewardDripFunded {
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[event]
+pub struct DepositFreezeChanged {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub deposit_id: u64,
+    pub frozen: bool,
+}
+
+#[event]
+pub struct PoolMetadataUpdated {
+    pub pool: Pubkey,
+    pub name: String,
+    pub metadata_uri: String,
+}
+
+#[event]
+pub struct RewardsExtended {
+    pub pool: Pubkey,
+    pub additional_funding: u64,
+    /// Rewards dripping after the extension, including the ones not released before it
+    pub drip_amount: u64,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[event]
+pub struct UnusedRewardsWithdrawn {
+    pub pool: Pubkey,
+    pub creator: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PoolUpdated {
+    pub pool: Pubkey,
+    pub current_rewards: u64,
+    /// Drip rewards not released yet
+    pub dripping_rewards: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ShareTokenEnabled {
+    pub pool: Pubkey,
+    pub share_mint: Pubkey,
+}
+
+#[event]
+pub struct LiquidStaked {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct LiquidUnstaked {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub shares: u64,
+    pub principal: u64,
+    pub rewards: u64,
+}
+
+#[event]
+pub struct RewardTokenEmissionSet {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub emission_rate: u64,
+    pub emission_remaining: u64,
+}
+
+#[event]
+pub struct ClaimedRewardsEscrowed {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub deposit: Pubkey,
+    pub amount: u64,
+    pub end: i64,
+}
+
+#[event]
+pub struct ClaimedRewardsReleased {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub deposit: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PoolAuthorityChanged {
+    pub pool: Pubkey,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+}
+
+#[event]
+pub struct LockExtended {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub deposit_id: u64,
+    pub lock_extension: i64,
+    pub unlock_timestamp: i64,
+    pub voting_weight: u64,
+}
+
+#[event]
+pub struct InstantUnstaked {
+    pub pool: Pubkey,
+    pub staker: Pubkey,
+    pub deposit_id: u64,
+    pub principal: u64,
+    pub fee: u64,
 }
 
+#[event]
+pub struct DustSwept {
+    pub pool: Pubkey,
+    pub amount: u64,
+}
@@ -1,9 +1,19 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
 use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::{self, Mint, Token, TokenAccount, TransferChecked};
 
 declare_id!("A3ThhSfoxnsQHEMToLZBKoxsPZ2CcBQSw8sGFFE45CXE");
 
+/// Most payouts listed by `preview_unlocks`, keeping the return data under 1024 bytes.
+pub const MAX_PREVIEW_INTERVALS: usize = 127;
+
+/// Seed of the dev clock PDA read by `current_timestamp` in `dev-clock` builds
+pub const DEV_CLOCK_SEED: &[u8] = b"dev-clock";
+
+/// Most schedules listed in a creator's snapshot.
+pub const MAX_SNAPSHOT_ENTRIES: usize = 64;
+
 #[program]
 pub mod vesting {
     use super::*;
@@ -15,393 +25,1653 @@ pub mod vesting {
         interval_duration: i64,
         unlock_percentage: u8,
         total_amount: u64,
+        revocable: bool,
+        rounding: RoundingMode,
     ) -> Result<()> {
-        let clock = Clock::get()?;
-        let now = clock.unix_timestamp;
+        let now = current_timestamp(ctx.remaining_accounts)?;
 
-        // Input validation
         require!(cliff_duration > 0, VestingError::InvalidCliffDuration);
-        require!(interval_duration > 0, VestingError::InvalidIntervalDuration);
-        require!(
-            unlock_percentage > 0 && unlock_percentage <= 100,
-            VestingError::InvalidUnlockPercentage
-        );
-        require!(total_amount > 0, VestingError::InvalidAmount);
 
         // Calculate cliff end timestamp
         let cliff_end_timestamp = now
             .checked_add(cliff_duration)
             .ok_or(VestingError::MathOverflow)?;
 
-        let vesting = &mut ctx.accounts.vesting_schedule;
-        vesting.creator = ctx.accounts.creator.key();
-        vesting.beneficiary = ctx.accounts.beneficiary.key();
-        vesting.token_mint = ctx.accounts.token_mint.key();
-        vesting.vault = ctx.accounts.vault.key();
-        vesting.total_amount = total_amount;
-        vesting.unlocked_amount = 0;
-        vesting.cliff_end_timestamp = cliff_end_timestamp;
-        vesting.interval_duration = interval_duration;
-        vesting.unlock_percentage = unlock_percentage;
-        vesting.last_unlock_timestamp = cliff_end_timestamp;
-        vesting.created_at = now;
-        vesting.bump = ctx.bumps.vesting_schedule;
-
-        // Transfer tokens from creator to vault
-        let cpi_accounts = TransferChecked {
-            from: ctx.accounts.creator_token_account.to_account_info(),
-            mint: ctx.accounts.token_mint.to_account_info(),
-            to: ctx.accounts.vault.to_account_info(),
-            authority: ctx.accounts.creator.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts,
-        );
-        token::transfer_checked(
-            cpi_ctx,
+        init_schedule(
+            ctx,
+            now,
+            cliff_end_timestamp,
+            interval_duration,
+            unlock_percentage,
             total_amount,
-            ctx.accounts.token_mint.decimals,
-        )?;
+            revocable,
+            rounding,
+        )
+    }
 
-        emit!(VestingCreated {
-            vesting_schedule: vesting.key(),
-            creator: vesting.creator,
-            beneficiary: vesting.beneficiary,
-            token_mint: vesting.token_mint,
-            total_amount,
+    /// Create a vesting schedule whose cliff ends at `cliff_end_timestamp`, which may be in
+    /// the past: intervals elapsed since then are unlockable right away. Lets a creator
+    /// start vesting from an earlier event, such as the end of a sale.
+    pub fn create_vesting_at(
+        ctx: Context<CreateVesting>,
+        cliff_end_timestamp: i64,
+        interval_duration: i64,
+        unlock_percentage: u8,
+        total_amount: u64,
+        revocable: bool,
+        rounding: RoundingMode,
+    ) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        require!(cliff_end_timestamp > 0, VestingError::InvalidCliffDuration);
+
+        init_schedule(
+            ctx,
+            now,
             cliff_end_timestamp,
             interval_duration,
             unlock_percentage,
-        });
+            total_amount,
+            revocable,
+            rounding,
+        )
+    }
+
+    /// Grow a schedule created before revocation, rounding and keeper bounties to the current
+    /// layout. The schedule keeps vesting as before: it is not revocable, rounds down and has
+    /// no keeper bounty. The payer covers the extra rent.
+    pub fn migrate_schedule(ctx: Context<MigrateSchedule>) -> Result<()> {
+        let schedule_info = ctx.accounts.vesting_schedule.to_account_info();
+        let space = 8 + VestingSchedule::INIT_SPACE;
+
+        let legacy = {
+            let data = schedule_info.try_borrow_data()?;
+            require!(data.len() < space, VestingError::ScheduleAlreadyMigrated);
+            require!(
+                data.len() >= 8 && data[..8] == *VestingSchedule::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+            LegacyVestingSchedule::deserialize(&mut &data[8..])?
+        };
+        require_keys_eq!(
+            ctx.accounts.token_mint.key(),
+            legacy.token_mint,
+            VestingError::TokenMintMismatch
+        );
+
+        let rent = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(schedule_info.lamports());
+        if rent > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: schedule_info.clone(),
+                    },
+                ),
+                rent,
+            )?;
+        }
+        schedule_info.resize(space)?;
+
+        let vesting = VestingSchedule {
+            creator: legacy.creator,
+            beneficiary: legacy.beneficiary,
+            token_mint: legacy.token_mint,
+            vault: legacy.vault,
+            total_amount: legacy.total_amount,
+            unlocked_amount: legacy.unlocked_amount,
+            cliff_end_timestamp: legacy.cliff_end_timestamp,
+            interval_duration: legacy.interval_duration,
+            unlock_percentage: legacy.unlock_percentage,
+            last_unlock_timestamp: legacy.last_unlock_timestamp,
+            created_at: legacy.created_at,
+            bump: legacy.bump,
+            revocable: false,
+            revoked: false,
+            revoked_at: 0,
+            claimable_at_revoke: 0,
+            returned_at_revoke: 0,
+            rounding: RoundingMode::Floor,
+            token_decimals: ctx.accounts.token_mint.decimals,
+            keeper_bounty: 0,
+            keeper_bounty_balance: 0,
+            version: VestingSchedule::VERSION,
+        };
+        vesting.try_serialize(&mut &mut schedule_info.try_borrow_mut_data()?[..])?;
 
         Ok(())
     }
 
     /// Unlock vested tokens to the beneficiary.
     pub fn unlock(ctx: Context<Unlock>) -> Result<()> {
-        let clock = Clock::get()?;
-        let now = clock.unix_timestamp;
-
-        // Read vesting schedule first (immutable borrow)
-        let cliff_end_timestamp = ctx.accounts.vesting_schedule.cliff_end_timestamp;
-        let interval_duration = ctx.accounts.vesting_schedule.interval_duration;
-        let unlock_percentage = ctx.accounts.vesting_schedule.unlock_percentage;
-        let total_amount = ctx.accounts.vesting_schedule.total_amount;
-        let unlocked_amount = ctx.accounts.vesting_schedule.unlocked_amount;
-        let last_unlock_timestamp = ctx.accounts.vesting_schedule.last_unlock_timestamp;
-        let creator_key = ctx.accounts.vesting_schedule.creator;
-        let beneficiary_key = ctx.accounts.vesting_schedule.beneficiary;
-        let bump = ctx.accounts.vesting_schedule.bump;
-        let decimals = ctx.accounts.token_mint.decimals;
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        release_interval(
+            &mut ctx.accounts.vesting_schedule,
+            &ctx.accounts.token_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint_freeze,
+            &ctx.accounts.beneficiary_ata,
+            &ctx.accounts.token_program,
+            now,
+        )?;
+
+        Ok(())
+    }
+
+    /// Permissionless crank unlocking the next interval to the beneficiary's ATA.
+    /// The caller pays for creating the ATA if missing and earns the schedule's keeper
+    /// bounty while the creator's bounty balance lasts.
+    pub fn unlock_for(ctx: Context<UnlockFor>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        release_interval(
+            &mut ctx.accounts.vesting_schedule,
+            &ctx.accounts.token_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint_freeze,
+            &ctx.accounts.beneficiary_ata,
+            &ctx.accounts.token_program,
+            now,
+        )?;
+
+        let vesting = &mut ctx.accounts.vesting_schedule;
+        let bounty = vesting.keeper_bounty.min(vesting.keeper_bounty_balance);
+        if bounty > 0 {
+            vesting.keeper_bounty_balance -= bounty;
+            vesting.sub_lamports(bounty)?;
+            ctx.accounts.keeper.add_lamports(bounty)?;
+
+            emit!(KeeperBountyPaid {
+                vesting_schedule: vesting.key(),
+                keeper: ctx.accounts.keeper.key(),
+                amount: bounty,
+                remaining: vesting.keeper_bounty_balance,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Creator-only: set the lamports paid to the caller of each `unlock_for` and add
+    /// `amount` lamports to the bounty balance held by the schedule.
+    pub fn fund_keeper_bounty(
+        ctx: Context<KeeperBounty>,
+        bounty_per_unlock: u64,
+        amount: u64,
+    ) -> Result<()> {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.creator.to_account_info(),
+                    to: ctx.accounts.vesting_schedule.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let vesting = &mut ctx.accounts.vesting_schedule;
+        vesting.keeper_bounty = bounty_per_unlock;
+        vesting.keeper_bounty_balance = vesting
+            .keeper_bounty_balance
+            .checked_add(amount)
+            .ok_or(VestingError::MathOverflow)?;
+
+        emit!(KeeperBountyFunded {
+            vesting_schedule: vesting.key(),
+            bounty_per_unlock,
+            amount,
+            balance: vesting.keeper_bounty_balance,
+        });
+
+        Ok(())
+    }
+
+    /// Creator-only: take back the unused keeper bounty balance.
+    pub fn reclaim_keeper_bounty(ctx: Context<KeeperBounty>) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting_schedule;
+        let amount = vesting.keeper_bounty_balance;
+        require!(amount > 0, VestingError::NoKeeperBounty);
+
+        vesting.keeper_bounty_balance = 0;
+        vesting.sub_lamports(amount)?;
+        ctx.accounts.creator.add_lamports(amount)?;
+
+        Ok(())
+    }
+
+    /// Calculate the amount of tokens available for unlock without actually unlocking.
+    /// Result is logged as a message that can be parsed by clients.
+    pub fn get_unlockable_amount(ctx: Context<GetUnlockableAmount>) -> Result<()> {
+        let vesting = &ctx.accounts.vesting_schedule;
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        // Calculate how much can be unlocked now (subtract already unlocked)
+        let unlockable_amount = vesting
+            .vested_amount(now)?.saturating_sub(vesting.unlocked_amount);
+
+        // Log the result as JSON for clients to parse
+        msg!("{{\"unlockable_amount\":{}}}", unlockable_amount);
+        Ok(())
+    }
+
+    /// List the amount paid out by each unlock, in order, for a beneficiary unlocking
+    /// once per interval. Reflects the schedule's rounding mode; returned via return data.
+    pub fn preview_unlocks(ctx: Context<GetUnlockableAmount>) -> Result<Vec<u64>> {
+        let vesting = &ctx.accounts.vesting_schedule;
+        let amount_per_interval = vesting.amount_per_interval()?;
+
+        let mut payouts = Vec::new();
+        let mut unlocked = 0u64;
+        let mut intervals = 0u64;
+        while unlocked < vesting.total_amount && payouts.len() < MAX_PREVIEW_INTERVALS {
+            intervals += 1;
+            let vested = vesting.vested_after_intervals(intervals)?;
+            let payout = amount_per_interval
+                .min(vesting.total_amount - unlocked)
+                .min(vested.saturating_sub(unlocked));
+            if payout == 0 {
+                break;
+            }
+            unlocked += payout;
+            payouts.push(payout);
+        }
+
+        Ok(payouts)
+    }
+
+    /// Revoke a revocable schedule. Tokens vested so far stay claimable by the
+    /// beneficiary, the unvested remainder goes back to the creator.
+    pub fn revoke(ctx: Context<Revoke>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+
+        revoke_schedule(
+            &mut ctx.accounts.vesting_schedule,
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.token_mint,
+            &ctx.accounts.creator_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            now,
+        )?;
+
+        Ok(())
+    }
+
+    /// Revoke several schedules of the signer in one transaction.
+    /// `remaining_accounts` holds `(vesting_schedule, vault)` pairs, all writable and
+    /// all vesting `token_mint`. Fails atomically if any schedule cannot be revoked.
+    pub fn revoke_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RevokeBatch<'info>>,
+    ) -> Result<()> {
+        // Remaining accounts hold the schedules, so the dev clock is a named account
+        let dev_clock = ctx.accounts.dev_clock.as_ref().map(|info| info.to_account_info());
+        let now = current_timestamp(dev_clock.as_slice())?;
+        let remaining_accounts = ctx.remaining_accounts;
 
-        // Check that cliff has passed
         require!(
-            now >= cliff_end_timestamp,
-            VestingError::CliffNotPassed
+            !remaining_accounts.is_empty() && remaining_accounts.len() % 2 == 0,
+            VestingError::InvalidRemainingAccounts
         );
 
-        // Calculate how many intervals have passed since cliff ended
-        let time_since_cliff = now
-            .checked_sub(cliff_end_timestamp)
-            .ok_or(VestingError::MathOverflow)?;
-        let total_intervals_passed_i64 = time_since_cliff
-            .checked_div(interval_duration)
-            .ok_or(VestingError::MathOverflow)?;
-        
-        // Convert to u64 (intervals can't be negative)
-        let total_intervals_passed = total_intervals_passed_i64.max(0) as u64;
+        for pair in remaining_accounts.chunks(2) {
+            let schedule_info = &pair[0];
+            let vault_info = &pair[1];
 
-        // For first unlock, require at least one interval to have passed
-        if unlocked_amount == 0 {
             require!(
-                total_intervals_passed >= 1,
-                VestingError::IntervalNotPassed
+                schedule_info.is_writable && vault_info.is_writable,
+                VestingError::InvalidRemainingAccounts
+            );
+
+            let mut vesting_schedule: Account<'info, VestingSchedule> =
+                Account::try_from(schedule_info)?;
+
+            require_keys_eq!(
+                vesting_schedule.creator,
+                ctx.accounts.creator.key(),
+                VestingError::Unauthorized
+            );
+            require_keys_eq!(
+                vesting_schedule.token_mint,
+                ctx.accounts.token_mint.key(),
+                VestingError::TokenMintMismatch
+            );
+            require_keys_eq!(
+                vesting_schedule.vault,
+                vault_info.key(),
+                VestingError::InvalidVault
+            );
+
+            revoke_schedule(
+                &mut vesting_schedule,
+                vault_info,
+                &ctx.accounts.token_mint,
+                &ctx.accounts.creator_token_account.to_account_info(),
+                &ctx.accounts.token_program,
+                now,
+            )?;
+
+            // Persist the schedule now so a duplicate entry fails as already revoked
+            vesting_schedule.exit(&crate::ID)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the signer's snapshot: the schedules passed in `remaining_accounts` with the
+    /// amount each still holds, so reporting tools can fetch one small account instead of
+    /// every schedule. Each call replaces the previous list.
+    pub fn publish_snapshot<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PublishSnapshot<'info>>,
+    ) -> Result<()> {
+        // Remaining accounts hold the schedules, so the dev clock is a named account
+        let dev_clock = ctx.accounts.dev_clock.as_ref().map(|info| info.to_account_info());
+        let now = current_timestamp(dev_clock.as_slice())?;
+        let remaining_accounts = ctx.remaining_accounts;
+
+        require!(
+            remaining_accounts.len() <= MAX_SNAPSHOT_ENTRIES,
+            VestingError::SnapshotTooLarge
+        );
+
+        let mut entries = Vec::with_capacity(remaining_accounts.len());
+        for schedule_info in remaining_accounts {
+            let vesting_schedule: Account<'info, VestingSchedule> =
+                Account::try_from(schedule_info)?;
+
+            require_keys_eq!(
+                vesting_schedule.creator,
+                ctx.accounts.creator.key(),
+                VestingError::Unauthorized
             );
-        } else {
-            // For subsequent unlocks, check time since last unlock
-            let time_since_last_unlock = now
-                .checked_sub(last_unlock_timestamp)
-                .ok_or(VestingError::MathOverflow)?;
             require!(
-                time_since_last_unlock >= interval_duration,
-                VestingError::IntervalNotPassed
+                entries
+                    .iter()
+                    .all(|entry: &SnapshotEntry| entry.schedule != schedule_info.key()),
+                VestingError::InvalidRemainingAccounts
             );
+
+            entries.push(SnapshotEntry {
+                schedule: schedule_info.key(),
+                remaining_amount: vesting_schedule.remaining_amount(),
+            });
         }
 
-        // Calculate how many intervals have been unlocked so far
-        let percentage_per_interval = unlock_percentage as u64;
-        let intervals_unlocked_so_far = if unlocked_amount == 0 {
-            0u64
-        } else {
-            // Calculate: unlocked_amount / (total_amount * unlock_percentage / 100)
-            let amount_per_interval = total_amount
-                .checked_mul(percentage_per_interval)
-                .ok_or(VestingError::MathOverflow)?
-                .checked_div(100)
+        let snapshot = &mut ctx.accounts.snapshot;
+        snapshot.creator = ctx.accounts.creator.key();
+        snapshot.updated_at = now;
+        snapshot.entries = entries;
+        snapshot.bump = ctx.bumps.snapshot;
+
+        Ok(())
+    }
+
+    /// Set up the program config. Only the program's upgrade authority can, and becomes admin
+    /// appointing the guardian.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, guardian: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.guardian = guardian;
+        config.bump = ctx.bumps.config;
+
+        Ok(())
+    }
+
+    /// Admin-only: replace the guardian.
+    pub fn set_guardian(ctx: Context<SetGuardian>, guardian: Pubkey) -> Result<()> {
+        ctx.accounts.config.guardian = guardian;
+
+        Ok(())
+    }
+
+    /// Guardian-only: freeze unlocks of every schedule vesting `token_mint`,
+    /// e.g. after an exploit of the token ahead of a migration.
+    pub fn freeze_mint(ctx: Context<SetMintFreeze>) -> Result<()> {
+        let now = current_timestamp(ctx.remaining_accounts)?;
+        let mint_freeze = &mut ctx.accounts.mint_freeze;
+        mint_freeze.mint = ctx.accounts.token_mint.key();
+        mint_freeze.frozen = true;
+        mint_freeze.frozen_at = now;
+        mint_freeze.bump = ctx.bumps.mint_freeze;
+
+        emit!(MintFreezeChanged {
+            mint: mint_freeze.mint,
+            frozen: true,
+        });
+
+        Ok(())
+    }
+
+    /// Guardian-only: lift a mint freeze.
+    pub fn unfreeze_mint(ctx: Context<SetMintFreeze>) -> Result<()> {
+        let mint_freeze = &mut ctx.accounts.mint_freeze;
+        mint_freeze.mint = ctx.accounts.token_mint.key();
+        mint_freeze.frozen = false;
+        mint_freeze.bump = ctx.bumps.mint_freeze;
+
+        emit!(MintFreezeChanged {
+            mint: mint_freeze.mint,
+            frozen: false,
+        });
+
+        Ok(())
+    }
+
+    /// Guardian-only: approve moving schedules of a frozen mint to `replacement_mint` at
+    /// `rate_numerator / rate_denominator` new tokens per old token, see `migrate_vault`.
+    pub fn approve_migration(
+        ctx: Context<ApproveMigration>,
+        replacement_mint: Pubkey,
+        rate_numerator: u64,
+        rate_denominator: u64,
+    ) -> Result<()> {
+        require!(
+            rate_numerator > 0 && rate_denominator > 0,
+            VestingError::InvalidConversionRate
+        );
+        let mint_freeze = &mut ctx.accounts.mint_freeze;
+        require!(mint_freeze.frozen, VestingError::MintNotFrozen);
+        require_keys_neq!(
+            replacement_mint,
+            mint_freeze.mint,
+            VestingError::TokenMintMismatch
+        );
+
+        mint_freeze.replacement_mint = Some(replacement_mint);
+        mint_freeze.rate_numerator = rate_numerator;
+        mint_freeze.rate_denominator = rate_denominator;
+
+        emit!(MigrationApproved {
+            mint: mint_freeze.mint,
+            replacement_mint,
+            rate_numerator,
+            rate_denominator,
+        });
+
+        Ok(())
+    }
+
+    /// Creator-only: move a schedule of a frozen mint to the replacement mint approved by the
+    /// guardian. The old vault is emptied to the creator, who funds a new vault with the
+    /// converted amount. Amounts are converted at the approved rate.
+    pub fn migrate_vault(ctx: Context<MigrateVault>) -> Result<()> {
+        let mint_freeze = &ctx.accounts.mint_freeze;
+        require!(mint_freeze.frozen, VestingError::MintNotFrozen);
+        // The creator funds the new vault, so only the guardian may set what it must hold
+        require!(
+            mint_freeze.replacement_mint == Some(ctx.accounts.new_mint.key()),
+            VestingError::MigrationNotApproved
+        );
+        let rate_numerator = mint_freeze.rate_numerator;
+        let rate_denominator = mint_freeze.rate_denominator;
+
+        let old_vault_amount = ctx.accounts.vault.amount;
+        let convert = |amount: u64, round_up: bool| -> Result<u64> {
+            let scaled = (amount as u128)
+                .checked_mul(rate_numerator as u128)
                 .ok_or(VestingError::MathOverflow)?;
-            unlocked_amount
-                .checked_div(amount_per_interval)
-                .unwrap_or(0)
+            let mut converted = scaled / rate_denominator as u128;
+            if round_up && scaled % rate_denominator as u128 != 0 {
+                converted += 1;
+            }
+            u64::try_from(converted).map_err(|_| error!(VestingError::MathOverflow))
+        };
+
+        // Rounding the deposit up keeps the new vault able to cover the converted remainder
+        let new_total_amount = convert(ctx.accounts.vesting_schedule.total_amount, false)?;
+        let new_unlocked_amount =
+            convert(ctx.accounts.vesting_schedule.unlocked_amount, false)?;
+        let new_claimable_at_revoke =
+            convert(ctx.accounts.vesting_schedule.claimable_at_revoke, false)?;
+        let new_returned_at_revoke =
+            convert(ctx.accounts.vesting_schedule.returned_at_revoke, false)?;
+        let deposit_amount = convert(old_vault_amount, true)?;
+
+        let creator_key = ctx.accounts.vesting_schedule.creator;
+        let beneficiary_key = ctx.accounts.vesting_schedule.beneficiary;
+        let bump = ctx.accounts.vesting_schedule.bump;
+        let signer_seeds: &[&[u8]] = &[
+            b"vesting-schedule",
+            creator_key.as_ref(),
+            beneficiary_key.as_ref(),
+            &[bump],
+        ];
+        let signers = &[signer_seeds];
+
+        // Return the old tokens to the creator
+        if old_vault_amount > 0 {
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.creator_token_account.to_account_info(),
+                authority: ctx.accounts.vesting_schedule.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                signers,
+            );
+            token::transfer_checked(cpi_ctx, old_vault_amount, ctx.accounts.token_mint.decimals)?;
+        }
+
+        // Fund the new vault with the replacement tokens
+        check_vault_authorities(&ctx.accounts.new_vault, ctx.accounts.vesting_schedule.key())?;
+        if deposit_amount > 0 {
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.creator_new_token_account.to_account_info(),
+                mint: ctx.accounts.new_mint.to_account_info(),
+                to: ctx.accounts.new_vault.to_account_info(),
+                authority: ctx.accounts.creator.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+            );
+            token::transfer_checked(cpi_ctx, deposit_amount, ctx.accounts.new_mint.decimals)?;
+        }
+
+        let vesting = &mut ctx.accounts.vesting_schedule;
+        let old_mint = vesting.token_mint;
+        vesting.token_mint = ctx.accounts.new_mint.key();
+        vesting.vault = ctx.accounts.new_vault.key();
+        vesting.total_amount = new_total_amount;
+        vesting.unlocked_amount = new_unlocked_amount;
+        vesting.claimable_at_revoke = new_claimable_at_revoke;
+        vesting.returned_at_revoke = new_returned_at_revoke;
+        vesting.token_decimals = ctx.accounts.new_mint.decimals;
+
+        emit!(VaultMigrated {
+            vesting_schedule: vesting.key(),
+            old_mint,
+            new_mint: vesting.token_mint,
+            rate_numerator,
+            rate_denominator,
+            returned_amount: old_vault_amount,
+            deposited_amount: deposit_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Dev builds only: shift the clock seen by the program by `offset` seconds.
+    /// Only the program's upgrade authority.
+    pub fn set_dev_clock(ctx: Context<SetDevClock>, offset: i64) -> Result<()> {
+        require!(cfg!(feature = "dev-clock"), VestingError::DevClockDisabled);
+
+        let dev_clock = &mut ctx.accounts.dev_clock;
+        dev_clock.authority = ctx.accounts.authority.key();
+        dev_clock.offset = offset;
+        dev_clock.bump = ctx.bumps.dev_clock;
+        Ok(())
+    }
+}
+
+/// Current unix timestamp. Builds with the `dev-clock` feature add the dev clock offset
+/// when its PDA is among `accounts`: the remaining accounts, or the named `dev_clock`
+/// account of instructions taking a batch in remaining accounts. Other builds only read
+/// the sysvar.
+pub fn current_timestamp(accounts: &[AccountInfo]) -> Result<i64> {
+    program_timestamp(&crate::ID, accounts)
+}
+
+/// `current_timestamp` of `program_id`, reading its own dev clock. The workspace programs
+/// share it through their `dev-clock` feature, their `DevClock` accounts keep this layout.
+pub fn program_timestamp(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<i64> {
+    let now = Clock::get()?.unix_timestamp;
+
+    #[cfg(feature = "dev-clock")]
+    {
+        let (dev_clock_key, _) = Pubkey::find_program_address(&[DEV_CLOCK_SEED], program_id);
+        if let Some(info) = accounts
+            .iter()
+            .find(|info| *info.key == dev_clock_key && info.owner == program_id)
+        {
+            let dev_clock = DevClock::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            return Ok(now.saturating_add(dev_clock.offset));
+        }
+    }
+    #[cfg(not(feature = "dev-clock"))]
+    let _ = (program_id, accounts);
+
+    Ok(now)
+}
+
+/// Unlock the next interval of `vesting_schedule` into `beneficiary_ata`, shared by
+/// `unlock` and `unlock_for`.
+/// Fill a new schedule with a cliff ending at `cliff_end_timestamp` and lock its tokens,
+/// shared by `create_vesting` and `create_vesting_at`.
+#[allow(clippy::too_many_arguments)]
+fn init_schedule(
+    ctx: Context<CreateVesting>,
+    now: i64,
+    cliff_end_timestamp: i64,
+    interval_duration: i64,
+    unlock_percentage: u8,
+    total_amount: u64,
+    revocable: bool,
+    rounding: RoundingMode,
+) -> Result<()> {
+    // Input validation
+    require!(interval_duration > 0, VestingError::InvalidIntervalDuration);
+    require!(
+        unlock_percentage > 0 && unlock_percentage <= 100,
+        VestingError::InvalidUnlockPercentage
+    );
+    require!(total_amount > 0, VestingError::InvalidAmount);
+
+    let vesting = &mut ctx.accounts.vesting_schedule;
+    vesting.creator = ctx.accounts.creator.key();
+    vesting.beneficiary = ctx.accounts.beneficiary.key();
+    vesting.token_mint = ctx.accounts.token_mint.key();
+    vesting.vault = ctx.accounts.vault.key();
+    vesting.total_amount = total_amount;
+    vesting.unlocked_amount = 0;
+    vesting.cliff_end_timestamp = cliff_end_timestamp;
+    vesting.interval_duration = interval_duration;
+    vesting.unlock_percentage = unlock_percentage;
+    vesting.last_unlock_timestamp = cliff_end_timestamp;
+    vesting.created_at = now;
+    vesting.revocable = revocable;
+    vesting.revoked = false;
+    vesting.revoked_at = 0;
+    vesting.claimable_at_revoke = 0;
+    vesting.returned_at_revoke = 0;
+    vesting.rounding = rounding;
+    vesting.token_decimals = ctx.accounts.token_mint.decimals;
+    vesting.bump = ctx.bumps.vesting_schedule;
+    vesting.keeper_bounty = 0;
+    vesting.keeper_bounty_balance = 0;
+    vesting.version = VestingSchedule::VERSION;
+
+    // Every interval has to pay out something
+    require!(
+        vesting.amount_per_interval()? > 0,
+        VestingError::IntervalAmountRoundsToZero
+    );
+
+    // The vault must be fully controlled by the schedule PDA before funding it
+    check_vault_authorities(&ctx.accounts.vault, vesting.key())?;
+
+    // Transfer tokens from creator to vault
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.creator_token_account.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to: ctx.accounts.vault.to_account_info(),
+        authority: ctx.accounts.creator.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        cpi_accounts,
+    );
+    token::transfer_checked(
+        cpi_ctx,
+        total_amount,
+        ctx.accounts.token_mint.decimals,
+    )?;
+
+    emit!(VestingCreated {
+        vesting_schedule: vesting.key(),
+        creator: vesting.creator,
+        beneficiary: vesting.beneficiary,
+        token_mint: vesting.token_mint,
+        total_amount,
+        cliff_end_timestamp,
+        interval_duration,
+        unlock_percentage,
+        revocable,
+        rounding,
+    });
+
+    Ok(())
+}
+
+fn release_interval<'info>(
+    vesting_schedule: &mut Account<'info, VestingSchedule>,
+    token_mint: &Account<'info, Mint>,
+    vault: &Account<'info, TokenAccount>,
+    mint_freeze: &AccountInfo<'info>,
+    beneficiary_ata: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    now: i64,
+) -> Result<()> {
+    // Unlocks stop while the guardian has frozen the mint
+    require!(
+        !is_mint_frozen(mint_freeze)?,
+        VestingError::MintFrozen
+    );
+
+    // Read vesting schedule first (immutable borrow)
+    let cliff_end_timestamp = vesting_schedule.cliff_end_timestamp;
+    let interval_duration = vesting_schedule.interval_duration;
+    let total_amount = vesting_schedule.total_amount;
+    let unlocked_amount = vesting_schedule.unlocked_amount;
+    let last_unlock_timestamp = vesting_schedule.last_unlock_timestamp;
+    let creator_key = vesting_schedule.creator;
+    let beneficiary_key = vesting_schedule.beneficiary;
+    let bump = vesting_schedule.bump;
+    let decimals = token_mint.decimals;
+
+    // Vesting stops accruing at the revocation time
+    let vesting_now = vesting_schedule.effective_timestamp(now);
+    let vested_amount = vesting_schedule.vested_amount(now)?;
+
+    // Check that cliff has passed
+    require!(
+        now >= cliff_end_timestamp,
+        VestingError::CliffNotPassed
+    );
+
+    // Calculate how many intervals have passed since cliff ended
+    let time_since_cliff = vesting_now
+        .checked_sub(cliff_end_timestamp)
+        .ok_or(VestingError::MathOverflow)?;
+    let total_intervals_passed_i64 = time_since_cliff
+        .checked_div(interval_duration)
+        .ok_or(VestingError::MathOverflow)?;
+    
+    // Convert to u64 (intervals can't be negative)
+    let total_intervals_passed = total_intervals_passed_i64.max(0) as u64;
+
+    // For first unlock, require at least one interval to have passed
+    if unlocked_amount == 0 {
+        require!(
+            total_intervals_passed >= 1,
+            VestingError::IntervalNotPassed
+        );
+    } else {
+        // For subsequent unlocks, check time since last unlock
+        let time_since_last_unlock = now
+            .checked_sub(last_unlock_timestamp)
+            .ok_or(VestingError::MathOverflow)?;
+        require!(
+            time_since_last_unlock >= interval_duration,
+            VestingError::IntervalNotPassed
+        );
+    }
+
+    // Calculate how many intervals have been unlocked so far
+    let intervals_unlocked_so_far = if unlocked_amount == 0 {
+        0u64
+    } else {
+        // Calculate: unlocked_amount / (total_amount * unlock_percentage / 100)
+        let amount_per_interval = vesting_schedule.amount_per_interval()?;
+        unlocked_amount
+            .checked_div(amount_per_interval)
+            .unwrap_or(0)
+    };
+
+    // Calculate how many new intervals can be unlocked
+    let new_intervals_to_unlock = total_intervals_passed
+        .checked_sub(intervals_unlocked_so_far)
+        .ok_or(VestingError::MathOverflow)?;
+
+    require!(new_intervals_to_unlock > 0, VestingError::NothingToUnlock);
+
+    // Calculate amount to unlock: only one interval at a time
+    let amount_per_interval = vesting_schedule.amount_per_interval()?;
+
+    // Unlock only one interval worth of tokens, never more than has vested
+    let amount_to_unlock = amount_per_interval
+        .min(
+            total_amount
+                .checked_sub(unlocked_amount)
+                .ok_or(VestingError::MathOverflow)?,
+        )
+        .min(vested_amount.saturating_sub(unlocked_amount));
+
+    require!(amount_to_unlock > 0, VestingError::NothingToUnlock);
+
+    // Re-check on every unlock that nobody else gained control over the vault
+    check_vault_authorities(vault, vesting_schedule.key())?;
+
+    // Ensure vault has enough tokens
+    require!(
+        vault.amount >= amount_to_unlock,
+        VestingError::InsufficientVaultBalance
+    );
+
+    // Transfer tokens from vault to beneficiary
+    let signer_seeds: &[&[u8]] = &[
+        b"vesting-schedule",
+        creator_key.as_ref(),
+        beneficiary_key.as_ref(),
+        &[bump],
+    ];
+    let signers = &[signer_seeds];
+
+    let cpi_accounts = TransferChecked {
+        from: vault.to_account_info(),
+        mint: token_mint.to_account_info(),
+        to: beneficiary_ata.to_account_info(),
+        authority: vesting_schedule.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        cpi_accounts,
+        signers,
+    );
+    token::transfer_checked(cpi_ctx, amount_to_unlock, decimals)?;
+
+    // Update vesting schedule
+    let vesting = vesting_schedule;
+    vesting.unlocked_amount = unlocked_amount
+        .checked_add(amount_to_unlock)
+        .ok_or(VestingError::MathOverflow)?;
+    vesting.last_unlock_timestamp = now;
+
+    emit!(TokensUnlocked {
+        vesting_schedule: vesting.key(),
+        beneficiary: vesting.beneficiary,
+        amount: amount_to_unlock,
+        remaining: vesting
+            .total_amount.saturating_sub(vesting.unlocked_amount),
+    });
+
+    Ok(())
+}
+
+/// Whether the guardian froze the mint. `mint_freeze` is the mint's freeze PDA, which
+/// only exists once the mint has been frozen at least once.
+fn is_mint_frozen(mint_freeze: &AccountInfo) -> Result<bool> {
+    if mint_freeze.owner != &crate::ID || mint_freeze.data_is_empty() {
+        return Ok(false);
+    }
+    let data = mint_freeze.try_borrow_data()?;
+    let mint_freeze = MintFreeze::try_deserialize(&mut &data[..])?;
+
+    Ok(mint_freeze.frozen)
+}
+
+/// Ensure the vault is owned by the schedule PDA and has no delegate or close
+/// authority that could move or close the locked tokens behind the schedule's back.
+fn check_vault_authorities(vault: &TokenAccount, vesting_schedule: Pubkey) -> Result<()> {
+    require_keys_eq!(vault.owner, vesting_schedule, VestingError::VaultOwnerMismatch);
+    require!(vault.delegate.is_none(), VestingError::VaultHasDelegate);
+    require!(
+        vault.close_authority.is_none(),
+        VestingError::VaultHasCloseAuthority
+    );
+
+    Ok(())
+}
+
+/// Stop vesting at `now` and send the unvested remainder from the vault back to
+/// the creator. Returns the amount sent back.
+fn revoke_schedule<'info>(
+    vesting_schedule: &mut Account<'info, VestingSchedule>,
+    vault: &AccountInfo<'info>,
+    token_mint: &Account<'info, Mint>,
+    creator_token_account: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    now: i64,
+) -> Result<u64> {
+    require!(vesting_schedule.revocable, VestingError::NotRevocable);
+    require!(!vesting_schedule.revoked, VestingError::AlreadyRevoked);
+
+    let vested_amount = vesting_schedule.vested_amount(now)?;
+    let returned_amount = vesting_schedule
+        .total_amount
+        .checked_sub(vested_amount)
+        .ok_or(VestingError::MathOverflow)?;
+    // Vested but not yet unlocked, stays in the vault for the beneficiary
+    let claimable_amount = vested_amount
+        .checked_sub(vesting_schedule.unlocked_amount)
+        .ok_or(VestingError::MathOverflow)?;
+
+    vesting_schedule.revoked = true;
+    vesting_schedule.revoked_at = now;
+    vesting_schedule.claimable_at_revoke = claimable_amount;
+    vesting_schedule.returned_at_revoke = returned_amount;
+
+    if returned_amount > 0 {
+        let signer_seeds: &[&[u8]] = &[
+            b"vesting-schedule",
+            vesting_schedule.creator.as_ref(),
+            vesting_schedule.beneficiary.as_ref(),
+            &[vesting_schedule.bump],
+        ];
+        let signers = &[signer_seeds];
+
+        let cpi_accounts = TransferChecked {
+            from: vault.clone(),
+            mint: token_mint.to_account_info(),
+            to: creator_token_account.clone(),
+            authority: vesting_schedule.to_account_info(),
         };
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            signers,
+        );
+        token::transfer_checked(cpi_ctx, returned_amount, token_mint.decimals)?;
+    }
+
+    emit!(VestingRevoked {
+        vesting_schedule: vesting_schedule.key(),
+        creator: vesting_schedule.creator,
+        beneficiary: vesting_schedule.beneficiary,
+        vested_amount,
+        claimable_amount,
+        returned_amount,
+        revoked_at: now,
+    });
+
+    Ok(returned_amount)
+}
+
+/// Program-wide settings, a single PDA.
+#[account]
+#[derive(InitSpace)]
+pub struct VestingConfig {
+    /// Can replace the guardian
+    pub admin: Pubkey,
+    /// Can freeze unlocks per mint
+    pub guardian: Pubkey,
+    /// PDA bump
+    pub bump: u8,
+}
+
+/// Freeze marker of one mint, created on the first freeze.
+#[account]
+#[derive(InitSpace)]
+pub struct MintFreeze {
+    /// Mint the marker belongs to
+    pub mint: Pubkey,
+    /// Whether unlocks of this mint are frozen
+    pub frozen: bool,
+    /// Timestamp of the last freeze
+    pub frozen_at: i64,
+    /// PDA bump
+    pub bump: u8,
+    /// Replacement mint approved by the guardian, see `approve_migration`
+    pub replacement_mint: Option<Pubkey>,
+    /// Approved new tokens per old token, as a fraction
+    pub rate_numerator: u64,
+    pub rate_denominator: u64,
+}
+
+/// Compact list of a creator's schedules, written by `publish_snapshot`.
+#[account]
+#[derive(InitSpace)]
+pub struct VestingSnapshot {
+    /// Creator whose schedules are listed
+    pub creator: Pubkey,
+    /// Timestamp of the last publish
+    pub updated_at: i64,
+    /// Schedules with the amount they still hold
+    #[max_len(MAX_SNAPSHOT_ENTRIES)]
+    pub entries: Vec<SnapshotEntry>,
+    /// PDA bump
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct SnapshotEntry {
+    /// Vesting schedule account
+    pub schedule: Pubkey,
+    /// Tokens still held for the beneficiary, unlocked or not
+    pub remaining_amount: u64,
+}
+
+/// Clock offset used by `dev-clock` builds, see `current_timestamp`.
+#[account]
+#[derive(InitSpace)]
+pub struct DevClock {
+    /// Upgrade authority that last set the offset
+    pub authority: Pubkey,
+    /// Seconds added to the sysvar clock
+    pub offset: i64,
+    /// PDA bump
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct VestingSchedule {
+    /// Creator of the vesting schedule
+    pub creator: Pubkey,
+    /// Beneficiary who receives the tokens
+    pub beneficiary: Pubkey,
+    /// Token mint being vested
+    pub token_mint: Pubkey,
+    /// Vault PDA that holds the locked tokens
+    pub vault: Pubkey,
+    /// Total amount of tokens locked
+    pub total_amount: u64,
+    /// Amount already unlocked
+    pub unlocked_amount: u64,
+    /// Timestamp when cliff period ends
+    pub cliff_end_timestamp: i64,
+    /// Duration of each unlock interval in seconds
+    pub interval_duration: i64,
+    /// Percentage unlocked per interval (0-100)
+    pub unlock_percentage: u8,
+    /// Timestamp of last unlock
+    pub last_unlock_timestamp: i64,
+    /// Timestamp when vesting was created
+    pub created_at: i64,
+    /// PDA bump
+    pub bump: u8,
+    /// Whether the creator may revoke the unvested part
+    pub revocable: bool,
+    /// Whether the schedule has been revoked
+    pub revoked: bool,
+    /// Timestamp of revocation, vesting stops accruing at this point
+    pub revoked_at: i64,
+    /// Vested amount the beneficiary could still unlock when the schedule was revoked
+    pub claimable_at_revoke: u64,
+    /// Unvested amount returned to the creator on revocation
+    pub returned_at_revoke: u64,
+    /// How interval amounts are rounded
+    pub rounding: RoundingMode,
+    /// Decimals of `token_mint`, the unit of whole-token rounding
+    pub token_decimals: u8,
+    /// Lamports paid to the caller of each `unlock_for`
+    pub keeper_bounty: u64,
+    /// Lamports set aside by the creator for keeper bounties, held by this account
+    pub keeper_bounty_balance: u64,
+    /// Layout version, see `migrate_schedule`
+    pub version: u8,
+}
+
+/// Schedule layout before revocation, rounding and keeper bounties, see `migrate_schedule`
+#[derive(AnchorDeserialize)]
+pub struct LegacyVestingSchedule {
+    pub creator: Pubkey,
+    pub beneficiary: Pubkey,
+    pub token_mint: Pubkey,
+    pub vault: Pubkey,
+    pub total_amount: u64,
+    pub unlocked_amount: u64,
+    pub cliff_end_timestamp: i64,
+    pub interval_duration: i64,
+    pub unlock_percentage: u8,
+    pub last_unlock_timestamp: i64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+/// Rounding of vested amounts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum RoundingMode {
+    /// Round down to the token's smallest unit
+    Floor,
+    /// Round to the nearest whole token, halves up
+    Nearest,
+}
+
+impl VestingSchedule {
+    /// Current layout version
+    pub const VERSION: u8 = 1;
+
+    /// Tokens still in the vault: neither unlocked nor returned on revocation.
+    pub fn remaining_amount(&self) -> u64 {
+        self.total_amount
+            .saturating_sub(self.unlocked_amount)
+            .saturating_sub(self.returned_at_revoke)
+    }
+
+    /// Timestamp used for vesting math: frozen at `revoked_at` once revoked.
+    pub fn effective_timestamp(&self, now: i64) -> i64 {
+        if self.revoked {
+            now.min(self.revoked_at)
+        } else {
+            now
+        }
+    }
+
+    /// Total amount vested at `now`, including what was already unlocked.
+    pub fn vested_amount(&self, now: i64) -> Result<u64> {
+        let now = self.effective_timestamp(now);
+
+        // If cliff hasn't passed, nothing is vested
+        if now < self.cliff_end_timestamp {
+            return Ok(0);
+        }
+
+        // Calculate how many intervals have passed since cliff ended
+        let time_since_cliff = now
+            .checked_sub(self.cliff_end_timestamp)
+            .ok_or(VestingError::MathOverflow)?;
+        let intervals_passed_i64 = time_since_cliff
+            .checked_div(self.interval_duration)
+            .ok_or(VestingError::MathOverflow)?;
+
+        // Convert to u64 (intervals can't be negative)
+        let intervals_passed = intervals_passed_i64.max(0) as u64;
+
+        self.vested_after_intervals(intervals_passed)
+    }
+
+    /// Total amount vested once `intervals` intervals have passed after the cliff.
+    pub fn vested_after_intervals(&self, intervals: u64) -> Result<u64> {
+        // Calculate total vested amount based on intervals
+        let percentage_per_interval = self.unlock_percentage as u64;
+        let total_percentage_vested = intervals
+            .checked_mul(percentage_per_interval)
+            .ok_or(VestingError::MathOverflow)?
+            .min(100);
+
+        // Calculate vested amount: (total_amount * total_percentage_vested) / 100
+        let vested_amount = self.round(
+            (self.total_amount as u128)
+                .checked_mul(total_percentage_vested as u128)
+                .ok_or(VestingError::MathOverflow)?,
+            100,
+        )?;
+
+        // Ensure we don't vest more than total amount
+        Ok(vested_amount.min(self.total_amount))
+    }
+
+    /// Amount released by one unlock: `total_amount * unlock_percentage / 100`, rounded.
+    pub fn amount_per_interval(&self) -> Result<u64> {
+        let amount = self.round(
+            (self.total_amount as u128)
+                .checked_mul(self.unlock_percentage as u128)
+                .ok_or(VestingError::MathOverflow)?,
+            100,
+        )?;
+
+        Ok(amount.min(self.total_amount))
+    }
+
+    /// `numerator / denominator` rounded per the schedule's rounding mode.
+    fn round(&self, numerator: u128, denominator: u128) -> Result<u64> {
+        let rounded = match self.rounding {
+            RoundingMode::Floor => numerator / denominator,
+            RoundingMode::Nearest => {
+                let unit = 10u128
+                    .checked_pow(self.token_decimals as u32)
+                    .ok_or(VestingError::MathOverflow)?;
+                let step = denominator
+                    .checked_mul(unit)
+                    .ok_or(VestingError::MathOverflow)?;
+                let whole_tokens = numerator
+                    .checked_add(step / 2)
+                    .ok_or(VestingError::MathOverflow)?
+                    / step;
+                whole_tokens
+                    .checked_mul(unit)
+                    .ok_or(VestingError::MathOverflow)?
+            }
+        };
+
+        u64::try_from(rounded).map_err(|_| error!(VestingError::MathOverflow))
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateVesting<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + VestingSchedule::INIT_SPACE,
+        seeds = [
+            b"vesting-schedule",
+            creator.key().as_ref(),
+            beneficiary.key().as_ref()
+        ],
+        bump
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+
+    /// Creator who locks the tokens
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    /// Beneficiary who will receive the tokens
+    /// CHECK: stored as Pubkey in VestingSchedule
+    pub beneficiary: AccountInfo<'info>,
+
+    /// Token mint being vested
+    pub token_mint: Account<'info, Mint>,
+
+    /// Creator's token account from which tokens are transferred
+    #[account(
+        mut,
+        constraint = creator_token_account.mint == token_mint.key(),
+        constraint = creator_token_account.owner == creator.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    /// Vault PDA that will hold the locked tokens
+    #[account(
+        init,
+        payer = creator,
+        token::mint = token_mint,
+        token::authority = vesting_schedule,
+        seeds = [
+            b"vault",
+            vesting_schedule.key().as_ref()
+        ],
+        bump
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Unlock<'info> {
+    #[account(
+        mut,
+        has_one = beneficiary,
+        has_one = token_mint,
+        has_one = vault,
+        seeds = [
+            b"vesting-schedule",
+            vesting_schedule.creator.as_ref(),
+            beneficiary.key().as_ref()
+        ],
+        bump = vesting_schedule.bump
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+
+    /// Beneficiary who receives the unlocked tokens
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+
+    #[account(address = vesting_schedule.token_mint)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = vault.mint == token_mint.key(),
+        constraint = vault.owner == vesting_schedule.key()
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    /// CHECK: freeze marker of the mint, may not exist; read by `is_mint_frozen`
+    #[account(
+        seeds = [b"mint-freeze", token_mint.key().as_ref()],
+        bump
+    )]
+    pub mint_freeze: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = beneficiary,
+        associated_token::mint = token_mint,
+        associated_token::authority = beneficiary
+    )]
+    pub beneficiary_ata: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockFor<'info> {
+    #[account(
+        mut,
+        has_one = beneficiary,
+        has_one = token_mint,
+        has_one = vault,
+        seeds = [
+            b"vesting-schedule",
+            vesting_schedule.creator.as_ref(),
+            beneficiary.key().as_ref()
+        ],
+        bump = vesting_schedule.bump
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+
+    /// CHECK: beneficiary of the schedule, checked by `has_one`
+    pub beneficiary: UncheckedAccount<'info>,
+
+    /// Caller of the crank, receives the keeper bounty
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(address = vesting_schedule.token_mint)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = vault.mint == token_mint.key(),
+        constraint = vault.owner == vesting_schedule.key()
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    /// CHECK: freeze marker of the mint, may not exist; read by `is_mint_frozen`
+    #[account(
+        seeds = [b"mint-freeze", token_mint.key().as_ref()],
+        bump
+    )]
+    pub mint_freeze: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        associated_token::mint = token_mint,
+        associated_token::authority = beneficiary
+    )]
+    pub beneficiary_ata: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct KeeperBounty<'info> {
+    #[account(
+        mut,
+        has_one = creator @ VestingError::Unauthorized,
+        seeds = [
+            b"vesting-schedule",
+            creator.key().as_ref(),
+            vesting_schedule.beneficiary.as_ref()
+        ],
+        bump = vesting_schedule.bump
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetUnlockableAmount<'info> {
+    #[account(
+        seeds = [
+            b"vesting-schedule",
+            vesting_schedule.creator.as_ref(),
+            vesting_schedule.beneficiary.as_ref()
+        ],
+        bump = vesting_schedule.bump
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+}
+
+#[derive(Accounts)]
+pub struct Revoke<'info> {
+    #[account(
+        mut,
+        has_one = creator,
+        has_one = token_mint,
+        has_one = vault,
+        seeds = [
+            b"vesting-schedule",
+            creator.key().as_ref(),
+            vesting_schedule.beneficiary.as_ref()
+        ],
+        bump = vesting_schedule.bump
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+
+    /// Creator who revokes the schedule
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(address = vesting_schedule.token_mint)]
+    pub token_mint: Account<'info, Mint>,
 
-        // Calculate how many new intervals can be unlocked
-        let new_intervals_to_unlock = total_intervals_passed
-            .checked_sub(intervals_unlocked_so_far)
-            .ok_or(VestingError::MathOverflow)?;
+    #[account(
+        mut,
+        constraint = vault.mint == token_mint.key(),
+        constraint = vault.owner == vesting_schedule.key()
+    )]
+    pub vault: Account<'info, TokenAccount>,
 
-        require!(new_intervals_to_unlock > 0, VestingError::NothingToUnlock);
+    /// Creator's token account receiving the unvested tokens
+    #[account(
+        mut,
+        constraint = creator_token_account.mint == token_mint.key(),
+        constraint = creator_token_account.owner == creator.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
 
-        // Calculate amount to unlock: only one interval at a time
-        let amount_per_interval = total_amount
-            .checked_mul(percentage_per_interval)
-            .ok_or(VestingError::MathOverflow)?
-            .checked_div(100)
-            .ok_or(VestingError::MathOverflow)?;
+    pub token_program: Program<'info, Token>,
+}
 
-        // Unlock only one interval worth of tokens
-        let amount_to_unlock = amount_per_interval.min(
-            total_amount
-                .checked_sub(unlocked_amount)
-                .ok_or(VestingError::MathOverflow)?
-        );
+#[derive(Accounts)]
+pub struct RevokeBatch<'info> {
+    /// Creator of every schedule in the batch
+    #[account(mut)]
+    pub creator: Signer<'info>,
 
-        require!(amount_to_unlock > 0, VestingError::NothingToUnlock);
+    /// Token mint shared by every schedule in the batch
+    pub token_mint: Account<'info, Mint>,
 
-        // Ensure vault has enough tokens
-        require!(
-            ctx.accounts.vault.amount >= amount_to_unlock,
-            VestingError::InsufficientVaultBalance
-        );
+    /// Creator's token account receiving the unvested tokens
+    #[account(
+        mut,
+        constraint = creator_token_account.mint == token_mint.key(),
+        constraint = creator_token_account.owner == creator.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
 
-        // Transfer tokens from vault to beneficiary
-        let signer_seeds: &[&[u8]] = &[
-            b"vesting-schedule",
-            creator_key.as_ref(),
-            beneficiary_key.as_ref(),
-            &[bump],
-        ];
-        let signers = &[&signer_seeds[..]];
+    pub token_program: Program<'info, Token>,
 
-        let cpi_accounts = TransferChecked {
-            from: ctx.accounts.vault.to_account_info(),
-            mint: ctx.accounts.token_mint.to_account_info(),
-            to: ctx.accounts.beneficiary_ata.to_account_info(),
-            authority: ctx.accounts.vesting_schedule.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts,
-            signers,
-        );
-        token::transfer_checked(cpi_ctx, amount_to_unlock, decimals)?;
+    /// CHECK: dev clock PDA, only read by `current_timestamp` in `dev-clock` builds
+    pub dev_clock: Option<UncheckedAccount<'info>>,
+}
 
-        // Update vesting schedule (now we can mutably borrow)
-        let vesting = &mut ctx.accounts.vesting_schedule;
-        vesting.unlocked_amount = unlocked_amount
-            .checked_add(amount_to_unlock)
-            .ok_or(VestingError::MathOverflow)?;
-        vesting.last_unlock_timestamp = now;
+#[derive(Accounts)]
+pub struct PublishSnapshot<'info> {
+    /// Creator of every listed schedule
+    #[account(mut)]
+    pub creator: Signer<'info>,
 
-        emit!(TokensUnlocked {
-            vesting_schedule: vesting.key(),
-            beneficiary: vesting.beneficiary,
-            amount: amount_to_unlock,
-            remaining: vesting
-                .total_amount
-                .checked_sub(vesting.unlocked_amount)
-                .unwrap_or(0),
-        });
+    #[account(
+        init_if_needed,
+        payer = creator,
+        space = 8 + VestingSnapshot::INIT_SPACE,
+        seeds = [b"vesting-snapshot", creator.key().as_ref()],
+        bump
+    )]
+    pub snapshot: Account<'info, VestingSnapshot>,
 
-        Ok(())
-    }
+    pub system_program: Program<'info, System>,
 
-    /// Calculate the amount of tokens available for unlock without actually unlocking.
-    /// Result is logged as a message that can be parsed by clients.
-    pub fn get_unlockable_amount(ctx: Context<GetUnlockableAmount>) -> Result<()> {
-        let vesting = &ctx.accounts.vesting_schedule;
-        let clock = Clock::get()?;
-        let now = clock.unix_timestamp;
+    /// CHECK: dev clock PDA, only read by `current_timestamp` in `dev-clock` builds
+    pub dev_clock: Option<UncheckedAccount<'info>>,
+}
 
-        let unlockable_amount = if now < vesting.cliff_end_timestamp {
-            // If cliff hasn't passed, nothing is unlockable
-            0u64
-        } else {
-            // Calculate how many intervals have passed since cliff ended
-            let time_since_cliff = now
-                .checked_sub(vesting.cliff_end_timestamp)
-                .ok_or(VestingError::MathOverflow)?;
-            let intervals_passed_i64 = time_since_cliff
-                .checked_div(vesting.interval_duration)
-                .ok_or(VestingError::MathOverflow)?;
-            
-            // Convert to u64 (intervals can't be negative)
-            let intervals_passed = intervals_passed_i64.max(0) as u64;
-
-            // Calculate total unlockable amount based on intervals
-            let percentage_per_interval = vesting.unlock_percentage as u64;
-            let total_percentage_unlockable = intervals_passed
-                .checked_mul(percentage_per_interval)
-                .ok_or(VestingError::MathOverflow)?;
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + VestingConfig::INIT_SPACE,
+        seeds = [b"vesting-config"],
+        bump
+    )]
+    pub config: Account<'info, VestingConfig>,
 
-            // Calculate unlockable amount: (total_amount * total_percentage_unlockable) / 100
-            let unlockable_amount = vesting
-                .total_amount
-                .checked_mul(total_percentage_unlockable)
-                .ok_or(VestingError::MathOverflow)?
-                .checked_div(100)
-                .ok_or(VestingError::MathOverflow)?;
+    #[account(mut)]
+    pub admin: Signer<'info>,
 
-            // Ensure we don't unlock more than total amount
-            let max_unlockable = unlockable_amount.min(vesting.total_amount);
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Vesting>,
 
-            // Calculate how much can be unlocked now (subtract already unlocked)
-            max_unlockable
-                .checked_sub(vesting.unlocked_amount)
-                .unwrap_or(0)
-        };
+    /// Only the upgrade authority may claim the admin role
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ VestingError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
 
-        // Log the result as JSON for clients to parse
-        msg!("{{\"unlockable_amount\":{}}}", unlockable_amount);
-        Ok(())
-    }
+    pub system_program: Program<'info, System>,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct VestingSchedule {
-    /// Creator of the vesting schedule
-    pub creator: Pubkey,
-    /// Beneficiary who receives the tokens
-    pub beneficiary: Pubkey,
-    /// Token mint being vested
-    pub token_mint: Pubkey,
-    /// Vault PDA that holds the locked tokens
-    pub vault: Pubkey,
-    /// Total amount of tokens locked
-    pub total_amount: u64,
-    /// Amount already unlocked
-    pub unlocked_amount: u64,
-    /// Timestamp when cliff period ends
-    pub cliff_end_timestamp: i64,
-    /// Duration of each unlock interval in seconds
-    pub interval_duration: i64,
-    /// Percentage unlocked per interval (0-100)
-    pub unlock_percentage: u8,
-    /// Timestamp of last unlock
-    pub last_unlock_timestamp: i64,
-    /// Timestamp when vesting was created
-    pub created_at: i64,
-    /// PDA bump
-    pub bump: u8,
+#[derive(Accounts)]
+pub struct SetGuardian<'info> {
+    #[account(
+        mut,
+        has_one = admin @ VestingError::Unauthorized,
+        seeds = [b"vesting-config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, VestingConfig>,
+
+    pub admin: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct CreateVesting<'info> {
+pub struct SetMintFreeze<'info> {
     #[account(
-        init,
-        payer = creator,
-        space = 8 + VestingSchedule::INIT_SPACE,
-        seeds = [
-            b"vesting-schedule",
-            creator.key().as_ref(),
-            beneficiary.key().as_ref()
-        ],
-        bump
+        has_one = guardian @ VestingError::Unauthorized,
+        seeds = [b"vesting-config"],
+        bump = config.bump
     )]
-    pub vesting_schedule: Account<'info, VestingSchedule>,
+    pub config: Account<'info, VestingConfig>,
 
-    /// Creator who locks the tokens
     #[account(mut)]
-    pub creator: Signer<'info>,
-
-    /// Beneficiary who will receive the tokens
-    /// CHECK: stored as Pubkey in VestingSchedule
-    pub beneficiary: AccountInfo<'info>,
+    pub guardian: Signer<'info>,
 
-    /// Token mint being vested
     pub token_mint: Account<'info, Mint>,
 
-    /// Creator's token account from which tokens are transferred
+    #[account(
+        init_if_needed,
+        payer = guardian,
+        space = 8 + MintFreeze::INIT_SPACE,
+        seeds = [b"mint-freeze", token_mint.key().as_ref()],
+        bump
+    )]
+    pub mint_freeze: Account<'info, MintFreeze>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveMigration<'info> {
+    #[account(
+        has_one = guardian @ VestingError::Unauthorized,
+        seeds = [b"vesting-config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, VestingConfig>,
+
+    pub guardian: Signer<'info>,
+
     #[account(
         mut,
-        constraint = creator_token_account.mint == token_mint.key(),
-        constraint = creator_token_account.owner == creator.key()
+        seeds = [b"mint-freeze", mint_freeze.mint.as_ref()],
+        bump = mint_freeze.bump
     )]
-    pub creator_token_account: Account<'info, TokenAccount>,
+    pub mint_freeze: Account<'info, MintFreeze>,
+}
 
-    /// Vault PDA that will hold the locked tokens
+#[derive(Accounts)]
+pub struct SetDevClock<'info> {
     #[account(
-        init,
-        payer = creator,
-        token::mint = token_mint,
-        token::authority = vesting_schedule,
-        seeds = [
-            b"vault",
-            vesting_schedule.key().as_ref()
-        ],
+        init_if_needed,
+        payer = authority,
+        space = 8 + DevClock::INIT_SPACE,
+        seeds = [DEV_CLOCK_SEED],
         bump
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub dev_clock: Account<'info, DevClock>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Vesting>,
+
+    /// Only the upgrade authority may shift the clock
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ VestingError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
 
-    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Unlock<'info> {
+pub struct MigrateSchedule<'info> {
+    /// CHECK: legacy schedule that no longer deserializes as `VestingSchedule`; ownership is
+    /// checked here, the discriminator and layout in `migrate_schedule`
+    #[account(mut, owner = crate::ID)]
+    pub vesting_schedule: UncheckedAccount<'info>,
+
+    /// Mint the schedule vests, its decimals are recorded for rounding
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateVault<'info> {
     #[account(
         mut,
-        has_one = beneficiary,
+        has_one = creator,
         has_one = token_mint,
         has_one = vault,
         seeds = [
             b"vesting-schedule",
-            vesting_schedule.creator.as_ref(),
-            beneficiary.key().as_ref()
+            creator.key().as_ref(),
+            vesting_schedule.beneficiary.as_ref()
         ],
         bump = vesting_schedule.bump
     )]
     pub vesting_schedule: Account<'info, VestingSchedule>,
 
-    /// Beneficiary who receives the unlocked tokens
     #[account(mut)]
-    pub beneficiary: Signer<'info>,
+    pub creator: Signer<'info>,
 
-    #[account(address = vesting_schedule.token_mint)]
+    /// Compromised mint the schedule currently vests
     pub token_mint: Account<'info, Mint>,
 
     #[account(
-        mut,
-        constraint = vault.mint == token_mint.key(),
-        constraint = vault.owner == vesting_schedule.key()
+        seeds = [b"mint-freeze", token_mint.key().as_ref()],
+        bump = mint_freeze.bump
     )]
+    pub mint_freeze: Account<'info, MintFreeze>,
+
+    #[account(mut)]
     pub vault: Account<'info, TokenAccount>,
 
+    /// Receives the old tokens left in the vault
     #[account(
-        init_if_needed,
-        payer = beneficiary,
-        associated_token::mint = token_mint,
-        associated_token::authority = beneficiary
+        mut,
+        constraint = creator_token_account.mint == token_mint.key(),
+        constraint = creator_token_account.owner == creator.key()
     )]
-    pub beneficiary_ata: Account<'info, TokenAccount>,
+    pub creator_token_account: Account<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-}
+    /// Replacement mint
+    #[account(constraint = new_mint.key() != token_mint.key() @ VestingError::TokenMintMismatch)]
+    pub new_mint: Account<'info, Mint>,
 
-#[derive(Accounts)]
-pub struct GetUnlockableAmount<'info> {
     #[account(
+        init,
+        payer = creator,
+        token::mint = new_mint,
+        token::authority = vesting_schedule,
         seeds = [
-            b"vesting-schedule",
-            vesting_schedule.creator.as_ref(),
-            vesting_schedule.beneficiary.as_ref()
+            b"vault",
+            vesting_schedule.key().as_ref(),
+            new_mint.key().as_ref()
         ],
-        bump = vesting_schedule.bump
+        bump
     )]
-    pub vesting_schedule: Account<'info, VestingSchedule>,
+    pub new_vault: Account<'info, TokenAccount>,
+
+    /// Funds the new vault
+    #[account(
+        mut,
+        constraint = creator_new_token_account.mint == new_mint.key(),
+        constraint = creator_new_token_account.owner == creator.key()
+    )]
+    pub creator_new_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[error_code]
@@ -424,6 +1694,42 @@ pub enum VestingError {
     InsufficientVaultBalance,
     #[msg("Math overflow")]
     MathOverflow,
+    #[msg("Vesting schedule is not revocable")]
+    NotRevocable,
+    #[msg("Vesting schedule already revoked")]
+    AlreadyRevoked,
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Token mint mismatch")]
+    TokenMintMismatch,
+    #[msg("Invalid vault account")]
+    InvalidVault,
+    #[msg("Invalid remaining accounts")]
+    InvalidRemainingAccounts,
+    #[msg("Vault is not owned by the vesting schedule")]
+    VaultOwnerMismatch,
+    #[msg("Vault has a delegate")]
+    VaultHasDelegate,
+    #[msg("Vault has a close authority")]
+    VaultHasCloseAuthority,
+    #[msg("Unlocks of this mint are frozen")]
+    MintFrozen,
+    #[msg("Mint is not frozen")]
+    MintNotFrozen,
+    #[msg("Invalid conversion rate")]
+    InvalidConversionRate,
+    #[msg("Amount per interval rounds to zero")]
+    IntervalAmountRoundsToZero,
+    #[msg("Dev clock is only available in dev-clock builds")]
+    DevClockDisabled,
+    #[msg("No keeper bounty left to reclaim")]
+    NoKeeperBounty,
+    #[msg("Too many schedules for one snapshot")]
+    SnapshotTooLarge,
+    #[msg("The guardian has not approved this replacement mint")]
+    MigrationNotApproved,
+    #[msg("Vesting schedule already has the current layout")]
+    ScheduleAlreadyMigrated,
 }
 
 #[event]
@@ -436,6 +1742,8 @@ pub struct VestingCreated {
     pub cliff_end_timestamp: i64,
     pub interval_duration: i64,
     pub unlock_percentage: u8,
+    pub revocable: bool,
+    pub rounding: RoundingMode,
 }
 
 #[event]
@@ -446,3 +1754,59 @@ pub struct TokensUnlocked {
     pub remaining: u64,
 }
 
+#[event]
+pub struct VestingRevoked {
+    pub vesting_schedule: Pubkey,
+    pub creator: Pubkey,
+    pub beneficiary: Pubkey,
+    /// Total vested at revocation, unlocked or not
+    pub vested_amount: u64,
+    /// Part of `vested_amount` still left for the beneficiary to unlock
+    pub claimable_amount: u64,
+    /// Unvested amount sent back to the creator
+    pub returned_amount: u64,
+    pub revoked_at: i64,
+}
+
+#[event]
+pub struct MintFreezeChanged {
+    pub mint: Pubkey,
+    pub frozen: bool,
+}
+
+#[event]
+pub struct MigrationApproved {
+    pub mint: Pubkey,
+    pub replacement_mint: Pubkey,
+    pub rate_numerator: u64,
+    pub rate_denominator: u64,
+}
+
+#[event]
+pub struct VaultMigrated {
+    pub vesting_schedule: Pubkey,
+    pub old_mint: Pubkey,
+    pub new_mint: Pubkey,
+    pub rate_numerator: u64,
+    pub rate_denominator: u64,
+    pub returned_amount: u64,
+    pub deposited_amount: u64,
+}
+
+#[event]
+pub struct KeeperBountyFunded {
+    pub vesting_schedule: Pubkey,
+    pub bounty_per_unlock: u64,
+    pub amount: u64,
+    /// Bounty balance after funding
+    pub balance: u64,
+}
+
+#[event]
+pub struct KeeperBountyPaid {
+    pub vesting_schedule: Pubkey,
+    pub keeper: Pubkey,
+    pub amount: u64,
+    /// Bounty balance left for later unlocks
+    pub remaining: u64,
+}
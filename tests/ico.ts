@@ -2,16 +2,27 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import {
+    approve,
     ASSOCIATED_TOKEN_PROGRAM_ID,
+    createInitializeMintInstruction,
+    createInitializeTransferFeeConfigInstruction,
     createMint,
+    ExtensionType,
     getAccount,
     getAssociatedTokenAddress,
+    getAssociatedTokenAddressSync,
+    getMintLen,
     getOrCreateAssociatedTokenAccount,
     mintTo,
+    TOKEN_2022_PROGRAM_ID,
     TOKEN_PROGRAM_ID,
+    transfer,
 } from "@solana/spl-token";
 import { strict as assert } from "assert";
+import { createHash } from "crypto";
 import { Ico } from "../target/types/ico";
+import { Staking } from "../target/types/staking";
+import { Vesting } from "../target/types/vesting";
 
 const { SystemProgram, LAMPORTS_PER_SOL, PublicKey, Keypair } = anchor.web3;
 
@@ -21,6 +32,58 @@ describe("ico", () => {
 
   const provider = anchor.getProvider() as anchor.AnchorProvider;
   const program = anchor.workspace.ico as Program<Ico>;
+  const vestingProgram = anchor.workspace.vesting as Program<Vesting>;
+  const stakingProgram = anchor.workspace.staking as Program<Staking>;
+
+  const [launchpadConfigPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("launchpad-config")],
+    program.programId
+  );
+
+  // Only the upgrade authority, the provider wallet on localnet, can create the config
+  const [programData] = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  );
+
+  before(async () => {
+    // Anchor creates the config before checking the other accounts, so the gate is only
+    // observable while the config does not exist yet
+    const stranger = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      stranger.publicKey,
+      LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+    try {
+      await program.methods
+        .initializeLaunchpadConfig(0, stranger.publicKey)
+        .accountsStrict({
+          admin: stranger.publicKey,
+          program: program.programId,
+          programData,
+          launchpadConfig: launchpadConfigPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([stranger])
+        .rpc();
+      assert.fail("Expected a non upgrade authority to be rejected");
+    } catch (err) {
+      assert.ok(err.toString().includes("UnauthorizedAdmin"));
+    }
+
+    // Every presale reads its protocol fee from the launchpad config
+    await program.methods
+      .initializeLaunchpadConfig(0, provider.wallet.publicKey)
+      .accountsStrict({
+        admin: provider.wallet.publicKey,
+        program: program.programId,
+        programData,
+        launchpadConfig: launchpadConfigPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+  });
 
   it("contribute updates presale and profile state", async () => {
     const mint = await createMint(
@@ -85,6 +148,7 @@ describe("ico", () => {
         fundsReceiver: provider.wallet.publicKey,
         tokenMint: mint,
         authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
       })
       .rpc();
 
@@ -107,11 +171,16 @@ describe("ico", () => {
     const contribution = new anchor.BN(1 * LAMPORTS_PER_SOL);
 
     await program.methods
-      .contribute(contribution)
+      .contribute(contribution, null)
       .accountsStrict({
         contributor: contributor.publicKey,
         presale: presalePda,
         profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
         systemProgram: SystemProgram.programId,
       })
       .signers([contributor])
@@ -155,7 +224,8 @@ describe("ico", () => {
     const poolId = new anchor.BN(2);
     const tokenPriceLamports = new anchor.BN(LAMPORTS_PER_SOL); // 1 token = 1 SOL
     const softCap = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
-    const hardCap = new anchor.BN(2 * LAMPORTS_PER_SOL);
+    // Filled by the contribution, so the sale can be finalized right away.
+    const hardCap = new anchor.BN(1 * LAMPORTS_PER_SOL);
     const minContribution = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
     const maxContribution = new anchor.BN(2 * LAMPORTS_PER_SOL);
 
@@ -189,6 +259,7 @@ describe("ico", () => {
         fundsReceiver: provider.wallet.publicKey,
         tokenMint: mint,
         authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
       })
       .rpc();
 
@@ -211,11 +282,16 @@ describe("ico", () => {
     const contribution = new anchor.BN(1 * LAMPORTS_PER_SOL);
 
     await program.methods
-      .contribute(contribution)
+      .contribute(contribution, null)
       .accountsStrict({
         contributor: contributor.publicKey,
         presale: presalePda,
         profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
         systemProgram: SystemProgram.programId,
       })
       .signers([contributor])
@@ -226,6 +302,11 @@ describe("ico", () => {
       contributor.publicKey
     );
 
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
     await program.methods
       .claim()
       .accountsStrict({
@@ -234,6 +315,7 @@ describe("ico", () => {
         profile: profilePda,
         tokenMint: mint,
         presaleVault: presaleVaultPda,
+        destination: contributor.publicKey,
         contributorAta: contributorAta,
         tokenProgram: TOKEN_PROGRAM_ID,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -279,7 +361,8 @@ describe("ico", () => {
     const poolId = new anchor.BN(3);
     const tokenPriceLamports = new anchor.BN(LAMPORTS_PER_SOL);
     const softCap = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
-    const hardCap = new anchor.BN(2 * LAMPORTS_PER_SOL);
+    // Filled by the contribution, so the sale can be finalized right away.
+    const hardCap = new anchor.BN(1 * LAMPORTS_PER_SOL);
     const minContribution = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
     const maxContribution = new anchor.BN(2 * LAMPORTS_PER_SOL);
 
@@ -313,6 +396,7 @@ describe("ico", () => {
         fundsReceiver: provider.wallet.publicKey,
         tokenMint: mint,
         authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
       })
       .rpc();
 
@@ -335,11 +419,16 @@ describe("ico", () => {
     const contribution = new anchor.BN(1 * LAMPORTS_PER_SOL);
 
     await program.methods
-      .contribute(contribution)
+      .contribute(contribution, null)
       .accountsStrict({
         contributor: contributor.publicKey,
         presale: presalePda,
         profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
         systemProgram: SystemProgram.programId,
       })
       .signers([contributor])
@@ -349,12 +438,19 @@ describe("ico", () => {
       provider.wallet.publicKey
     );
 
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
     await program.methods
       .adminWithdraw(new anchor.BN(LAMPORTS_PER_SOL))
       .accountsStrict({
         presale: presalePda,
         authority: provider.wallet.publicKey,
         fundsReceiver: provider.wallet.publicKey,
+        launchpadConfig: launchpadConfigPda,
+        treasury: provider.wallet.publicKey,
         systemProgram: SystemProgram.programId,
       })
       .rpc();
@@ -401,7 +497,7 @@ describe("ico", () => {
 
     const now = Math.floor(Date.now() / 1000);
     const startTimestamp = new anchor.BN(now - 60);
-    const endTimestamp = new anchor.BN(now + 3600);
+    const endTimestamp = new anchor.BN(now + 8);
 
     const [presalePda] = PublicKey.findProgramAddressSync(
       [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
@@ -429,6 +525,7 @@ describe("ico", () => {
         fundsReceiver: provider.wallet.publicKey,
         tokenMint: mint,
         authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
       })
       .rpc();
 
@@ -451,11 +548,16 @@ describe("ico", () => {
     const contribution = new anchor.BN(1 * LAMPORTS_PER_SOL); // < soft cap
 
     await program.methods
-      .contribute(contribution)
+      .contribute(contribution, null)
       .accountsStrict({
         contributor: contributor.publicKey,
         presale: presalePda,
         profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
         systemProgram: SystemProgram.programId,
       })
       .signers([contributor])
@@ -470,6 +572,14 @@ describe("ico", () => {
       contributor.publicKey
     );
 
+    // Let the sale end before finalizing it.
+    await new Promise((resolve) => setTimeout(resolve, 10000));
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
     await program.methods
       .claim()
       .accountsStrict({
@@ -478,6 +588,7 @@ describe("ico", () => {
         profile: profilePda,
         tokenMint: mint,
         presaleVault: presaleVaultPda,
+        destination: contributor.publicKey,
         contributorAta: contributorAta,
         tokenProgram: TOKEN_PROGRAM_ID,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -492,4 +603,5727 @@ describe("ico", () => {
 
     assert.ok(balanceAfter > balanceBefore);
   });
-});
\ No newline at end of file
+
+  it("reports sale state for widgets", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(5);
+    const tokenPriceLamports = new anchor.BN(LAMPORTS_PER_SOL);
+    const softCap = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+    const hardCap = new anchor.BN(4 * LAMPORTS_PER_SOL);
+    const minContribution = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const maxContribution = new anchor.BN(2 * LAMPORTS_PER_SOL);
+
+    const now = Math.floor(Date.now() / 1000);
+    const startTimestamp = new anchor.BN(now - 60);
+    const endTimestamp = new anchor.BN(now + 3600);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        tokenPriceLamports,
+        softCap,
+        hardCap,
+        minContribution,
+        maxContribution,
+        startTimestamp,
+        endTimestamp,
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .contribute(new anchor.BN(1 * LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    const state = await program.methods
+      .getSaleState()
+      .accountsStrict({
+        presale: presalePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        profile: profilePda,
+      })
+      .view();
+
+    assert.ok(state.status.active !== undefined);
+    assert.equal(state.hardCapFilledBps, 2500);
+    assert.ok(state.remainingAllowance.eq(new anchor.BN(1 * LAMPORTS_PER_SOL)));
+    // 1 SOL at 1 SOL per token leaves 9 of the 10 deposited tokens unsold.
+    assert.ok(state.tokensRemaining.eq(new anchor.BN(9 * 10 ** 9)));
+  });
+
+  it("tops up sale tokens and raises the hard cap before start", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      10 * 10 ** 9
+    );
+
+    const poolId = new anchor.BN(6);
+    const tokenPriceLamports = new anchor.BN(LAMPORTS_PER_SOL);
+    const softCap = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+    const hardCap = new anchor.BN(2 * LAMPORTS_PER_SOL);
+    const minContribution = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const maxContribution = new anchor.BN(2 * LAMPORTS_PER_SOL);
+
+    const now = Math.floor(Date.now() / 1000);
+    const startTimestamp = new anchor.BN(now + 3600);
+    const endTimestamp = new anchor.BN(now + 7200);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        tokenPriceLamports,
+        softCap,
+        hardCap,
+        minContribution,
+        maxContribution,
+        startTimestamp,
+        endTimestamp,
+        new anchor.BN(2 * 10 ** 9)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const topUpAccounts = {
+      presale: presalePda,
+      authority: provider.wallet.publicKey,
+      tokenMint: mint,
+      presaleVault: presaleVaultPda,
+      authorityTokenAccount: authorityAta.address,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // The vault already covers the 2 SOL hard cap, so a plain top-up is rejected.
+    try {
+      await program.methods
+        .depositAdditionalTokens(new anchor.BN(1 * 10 ** 9), null)
+        .accountsStrict(topUpAccounts)
+        .rpc();
+      assert.fail("Expected top-up beyond the hard cap to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("TokensExceedHardcap"));
+    }
+
+    await program.methods
+      .depositAdditionalTokens(
+        new anchor.BN(3 * 10 ** 9),
+        new anchor.BN(5 * LAMPORTS_PER_SOL)
+      )
+      .accountsStrict(topUpAccounts)
+      .rpc();
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.hardCap.eq(new anchor.BN(5 * LAMPORTS_PER_SOL)));
+
+    const vaultAccount = await getAccount(provider.connection, presaleVaultPda);
+    assert.equal(vaultAccount.amount, BigInt(5 * 10 ** 9));
+  });
+
+  it("clips a contribution that crosses the hard cap", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(7);
+    const tokenPriceLamports = new anchor.BN(LAMPORTS_PER_SOL);
+    const softCap = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+    const hardCap = new anchor.BN(2 * LAMPORTS_PER_SOL);
+    const minContribution = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const maxContribution = new anchor.BN(2 * LAMPORTS_PER_SOL);
+
+    const now = Math.floor(Date.now() / 1000);
+    const startTimestamp = new anchor.BN(now - 60);
+    const endTimestamp = new anchor.BN(now + 3600);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        tokenPriceLamports,
+        softCap,
+        hardCap,
+        minContribution,
+        maxContribution,
+        startTimestamp,
+        endTimestamp,
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const contributors = [Keypair.generate(), Keypair.generate()];
+    const profiles = [];
+    for (const contributor of contributors) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        contributor.publicKey,
+        3 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          contributor.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      profiles.push(profilePda);
+    }
+
+    await program.methods
+      .contribute(new anchor.BN(1.5 * LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: contributors[0].publicKey,
+        presale: presalePda,
+        profile: profiles[0],
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributors[0]])
+      .rpc();
+
+    // Only 0.5 SOL of room is left; the rest stays with the contributor.
+    await program.methods
+      .contributeUpToCap(new anchor.BN(1 * LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: contributors[1].publicKey,
+        presale: presalePda,
+        profile: profiles[1],
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributors[1]])
+      .rpc();
+
+    const profileAccount = await program.account.contributorProfile.fetch(
+      profiles[1]
+    );
+    assert.ok(
+      profileAccount.contributed.eq(new anchor.BN(0.5 * LAMPORTS_PER_SOL))
+    );
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.totalContributions.eq(hardCap));
+  });
+
+  it("claim delivers tokens to the chosen destination wallet", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9; // 10 tokens, 9 decimals
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(8);
+    const tokenPriceLamports = new anchor.BN(LAMPORTS_PER_SOL); // 1 token = 1 SOL
+    const softCap = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+    // Filled by the contribution, so the sale can be finalized right away.
+    const hardCap = new anchor.BN(1 * LAMPORTS_PER_SOL);
+    const minContribution = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const maxContribution = new anchor.BN(2 * LAMPORTS_PER_SOL);
+
+    const now = Math.floor(Date.now() / 1000);
+    const startTimestamp = new anchor.BN(now - 60);
+    const endTimestamp = new anchor.BN(now + 3600);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        tokenPriceLamports,
+        softCap,
+        hardCap,
+        minContribution,
+        maxContribution,
+        startTimestamp,
+        endTimestamp,
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    const contribution = new anchor.BN(1 * LAMPORTS_PER_SOL);
+
+    await program.methods
+      .contribute(contribution, null)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    // Deliver to a cold wallet instead of the contributor's own ATA
+    const coldWallet = Keypair.generate();
+    await program.methods
+      .setClaimDestination(coldWallet.publicKey)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+      })
+      .signers([contributor])
+      .rpc();
+
+    const coldWalletAta = await getAssociatedTokenAddress(
+      mint,
+      coldWallet.publicKey
+    );
+
+    const claimAccounts = {
+      contributor: contributor.publicKey,
+      presale: presalePda,
+      profile: profilePda,
+      tokenMint: mint,
+      presaleVault: presaleVaultPda,
+      destination: contributor.publicKey,
+      contributorAta: await getAssociatedTokenAddress(
+        mint,
+        contributor.publicKey
+      ),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    try {
+      await program.methods
+        .claim()
+        .accountsStrict(claimAccounts)
+        .signers([contributor])
+        .rpc();
+      assert.fail("claim to the contributor's own ATA should fail");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidClaimDestination");
+    }
+
+    await program.methods
+      .claim()
+      .accountsStrict({
+        ...claimAccounts,
+        destination: coldWallet.publicKey,
+        contributorAta: coldWalletAta,
+      })
+      .signers([contributor])
+      .rpc();
+
+    const coldWalletAtaAccount = await getAccount(
+      provider.connection,
+      coldWalletAta
+    );
+    assert.equal(coldWalletAtaAccount.amount, BigInt(10 ** 9));
+  });
+
+  it("rejects claims into a delegated ATA and claims to a new account instead", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9; // 10 tokens, 9 decimals
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(9);
+    const tokenPriceLamports = new anchor.BN(LAMPORTS_PER_SOL); // 1 token = 1 SOL
+    const softCap = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+    // Filled by the contribution, so the sale can be finalized right away.
+    const hardCap = new anchor.BN(1 * LAMPORTS_PER_SOL);
+    const minContribution = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const maxContribution = new anchor.BN(2 * LAMPORTS_PER_SOL);
+
+    const now = Math.floor(Date.now() / 1000);
+    const startTimestamp = new anchor.BN(now - 60);
+    const endTimestamp = new anchor.BN(now + 3600);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        tokenPriceLamports,
+        softCap,
+        hardCap,
+        minContribution,
+        maxContribution,
+        startTimestamp,
+        endTimestamp,
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    const contribution = new anchor.BN(1 * LAMPORTS_PER_SOL);
+
+    await program.methods
+      .contribute(contribution, null)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    // The contributor's ATA exists and has a delegate
+    const contributorAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      contributor,
+      mint,
+      contributor.publicKey
+    );
+    await approve(
+      provider.connection,
+      contributor,
+      contributorAta.address,
+      Keypair.generate().publicKey,
+      contributor,
+      1
+    );
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    try {
+      await program.methods
+        .claim()
+        .accountsStrict({
+          contributor: contributor.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          tokenMint: mint,
+          presaleVault: presaleVaultPda,
+          destination: contributor.publicKey,
+          contributorAta: contributorAta.address,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([contributor])
+        .rpc();
+      assert.fail("Expected claiming into a delegated ATA to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("RecipientAccountDelegated"));
+    }
+
+    const recipient = Keypair.generate();
+    await program.methods
+      .claimToNewAta()
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: contributor.publicKey,
+        recipient: recipient.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor, recipient])
+      .rpc();
+
+    const recipientAccount = await getAccount(
+      provider.connection,
+      recipient.publicKey
+    );
+    assert.ok(recipientAccount.owner.equals(contributor.publicKey));
+    assert.equal(recipientAccount.amount, BigInt(10 ** 9));
+  });
+
+  it("Rejects setting the dev clock outside dev-clock builds", async () => {
+    const [devClockPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("dev-clock")],
+      program.programId
+    );
+
+    const setDevClock = (authority: Keypair) =>
+      program.methods
+        .setDevClock(new anchor.BN(86_400))
+        .accountsStrict({
+          devClock: devClockPda,
+          authority: authority.publicKey,
+          program: program.programId,
+          programData,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+    // Only the upgrade authority may shift the clock, in any build.
+    const stranger = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      stranger.publicKey,
+      LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+    try {
+      await setDevClock(stranger);
+      assert.fail("Expected a stranger setting the dev clock to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidDevClockAuthority"));
+    }
+
+    try {
+      await setDevClock(provider.wallet.payer);
+      assert.fail("Expected setting the dev clock to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("DevClockDisabled"));
+    }
+  });
+
+  it("checks the declared tokenomics against on-chain balances", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      100 * 10 ** 9
+    );
+
+    // 40% sale, 20% each for liquidity, team and treasury.
+    const shareAccounts = [];
+    for (let i = 0; i < 3; i++) {
+      const holder = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        Keypair.generate().publicKey
+      );
+      await transfer(
+        provider.connection,
+        provider.wallet.payer,
+        authorityAta.address,
+        holder.address,
+        provider.wallet.publicKey,
+        20 * 10 ** 9
+      );
+      shareAccounts.push(holder.address);
+    }
+    const [liquidityAccount, teamAccount, treasuryAccount] = shareAccounts;
+
+    const poolId = new anchor.BN(10);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+    const [tokenomicsPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tokenomics"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL / 10),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(4 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(4 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 10),
+        new anchor.BN(now + 12),
+        new anchor.BN(40 * 10 ** 9)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const declare = (teamBps: number, treasuryBps: number) =>
+      program.methods
+        .declareTokenomics(
+          {
+            saleBps: 4_000,
+            liquidityBps: 2_000,
+            teamBps,
+            treasuryBps,
+          },
+          liquidityAccount,
+          teamAccount,
+          treasuryAccount
+        )
+        .accountsStrict({
+          presale: presalePda,
+          tokenomics: tokenomicsPda,
+          authority: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    const checkBalances = () =>
+      program.methods
+        .checkTokenomicsBalances()
+        .accountsStrict({
+          presale: presalePda,
+          tokenomics: tokenomicsPda,
+          tokenMint: mint,
+          presaleVault: presaleVaultPda,
+          liquidityAccount,
+          teamAccount,
+          treasuryAccount,
+        })
+        .rpc();
+
+    // The team holds 20%, not the declared 30%.
+    await declare(3_000, 1_000);
+    try {
+      await checkBalances();
+      assert.fail("Expected a mismatched declaration to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("TokenomicsMismatch"));
+    }
+
+    await declare(2_000, 2_000);
+    await checkBalances();
+
+    let presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.equal(presaleAccount.tokenomicsBalancesChecked, true);
+
+    // Finalization records that the sale ran with the checked balances
+    await new Promise((resolve) =>
+      setTimeout(resolve, Math.max(0, (now + 13) * 1000 - Date.now()))
+    );
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+    presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.equal(presaleAccount.tokenomicsVerified, true);
+  });
+
+  it("only lets whitelisted wallets contribute up to their allocation", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(11);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 4),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const [capped, uncapped, outsider] = [
+      Keypair.generate(),
+      Keypair.generate(),
+      Keypair.generate(),
+    ];
+    for (const wallet of [capped, uncapped, outsider]) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        2 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+    }
+
+    // Two-leaf tree: sha256(wallet || allocation LE), pairs hashed in sorted order.
+    const leaf = (wallet: anchor.web3.PublicKey, allocation: anchor.BN) =>
+      createHash("sha256")
+        .update(wallet.toBuffer())
+        .update(allocation.toArrayLike(Buffer, "le", 8))
+        .digest();
+    const cappedAllocation = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+    const cappedLeaf = leaf(capped.publicKey, cappedAllocation);
+    const uncappedLeaf = leaf(uncapped.publicKey, new anchor.BN(0));
+    const root = createHash("sha256")
+      .update(Buffer.concat([cappedLeaf, uncappedLeaf].sort(Buffer.compare)))
+      .digest();
+
+    const setRoot = () =>
+      program.methods
+        .setWhitelistRoot(Array.from(root))
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+    await setRoot();
+
+    const contribute = (
+      wallet: anchor.web3.Keypair,
+      amount: number,
+      whitelist: {
+        allocation: anchor.BN;
+        tier: number | null;
+        proof: number[][];
+      } | null
+    ) => {
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      return program.methods
+        .contribute(new anchor.BN(amount), whitelist)
+        .accountsStrict({
+          contributor: wallet.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+    };
+
+    await new Promise((resolve) => setTimeout(resolve, 5000));
+
+    try {
+      await setRoot();
+      assert.fail("Expected changing the whitelist after the start to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("WhitelistOnlyBeforeStart"));
+    }
+
+    try {
+      await contribute(outsider, LAMPORTS_PER_SOL, null);
+      assert.fail("Expected a contribution without a proof to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("NotWhitelisted"));
+    }
+
+    // Claiming a larger allocation changes the leaf, so the proof no longer matches.
+    try {
+      await contribute(capped, LAMPORTS_PER_SOL, {
+        allocation: new anchor.BN(LAMPORTS_PER_SOL),
+        tier: null,
+        proof: [Array.from(uncappedLeaf)],
+      });
+      assert.fail("Expected a forged allocation to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("NotWhitelisted"));
+    }
+
+    const cappedProof = {
+      allocation: cappedAllocation,
+      tier: null,
+      proof: [Array.from(uncappedLeaf)],
+    };
+    try {
+      await contribute(capped, LAMPORTS_PER_SOL, cappedProof);
+      assert.fail("Expected a contribution above the allocation to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ContributionAboveMaximum"));
+    }
+    await contribute(capped, 0.5 * LAMPORTS_PER_SOL, cappedProof);
+
+    // No allocation in the leaf: the pool maximum applies.
+    await contribute(uncapped, LAMPORTS_PER_SOL, {
+      allocation: new anchor.BN(0),
+      tier: null,
+      proof: [Array.from(cappedLeaf)],
+    });
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.totalContributions.eq(
+        new anchor.BN(1.5 * LAMPORTS_PER_SOL)
+      )
+    );
+  });
+
+  it("prices contributions by the active sale round", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 4 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(12);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 2),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // Seed round at half price capped at 1 SOL, then the public round at full price.
+    const round = (price: number, start: number, end: number) => ({
+      tokenPriceLamports: new anchor.BN(price),
+      cap: new anchor.BN(LAMPORTS_PER_SOL),
+      maxContribution: new anchor.BN(LAMPORTS_PER_SOL),
+      startTimestamp: new anchor.BN(start),
+      endTimestamp: new anchor.BN(end),
+      raised: new anchor.BN(0),
+    });
+    await program.methods
+      .setSaleRounds([
+        round(0.5 * LAMPORTS_PER_SOL, now + 2, now + 7),
+        round(LAMPORTS_PER_SOL, now + 9, now + 3600),
+      ])
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const [buyer, latecomer] = [Keypair.generate(), Keypair.generate()];
+    const profiles = [];
+    for (const wallet of [buyer, latecomer]) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        3 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      profiles.push(profilePda);
+    }
+    const contribute = (index: number, amount: number) =>
+      program.methods
+        .contribute(new anchor.BN(amount), null)
+        .accountsStrict({
+          contributor: [buyer, latecomer][index].publicKey,
+          presale: presalePda,
+          profile: profiles[index],
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([[buyer, latecomer][index]])
+        .rpc();
+    const waitUntil = (timestamp: number) =>
+      new Promise((resolve) =>
+        setTimeout(resolve, Math.max(0, timestamp * 1000 - Date.now()))
+      );
+
+    await waitUntil(now + 4);
+    await contribute(0, LAMPORTS_PER_SOL);
+    try {
+      await contribute(1, 0.5 * LAMPORTS_PER_SOL);
+      assert.fail("Expected a contribution above the round cap to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SaleRoundCapExceeded"));
+    }
+
+    await waitUntil(now + 11);
+    await contribute(0, LAMPORTS_PER_SOL);
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.rounds[0].raised.eq(new anchor.BN(LAMPORTS_PER_SOL)));
+    assert.ok(presaleAccount.rounds[1].raised.eq(new anchor.BN(LAMPORTS_PER_SOL)));
+
+    // 1 SOL at 0.5 SOL plus 1 SOL at 1 SOL buys 3 tokens.
+    const buyerAta = await getAssociatedTokenAddress(mint, buyer.publicKey);
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    await program.methods
+      .claim()
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profiles[0],
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: buyer.publicKey,
+        contributorAta: buyerAta,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const buyerAtaAccount = await getAccount(provider.connection, buyerAta);
+    assert.equal(buyerAtaAccount.amount, BigInt(3 * 10 ** 9));
+  });
+
+  it("collects and refunds contributions in an SPL payment token", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+    const usdcMint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      6
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(13);
+    const now = Math.floor(Date.now() / 1000);
+    const usdc = (amount: number) => new anchor.BN(amount * 10 ** 6);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [paymentVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payment-vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // 1 USDC per token, soft cap 5 USDC.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        usdc(1),
+        usdc(5),
+        usdc(10),
+        usdc(1),
+        usdc(10),
+        new anchor.BN(now + 3),
+        new anchor.BN(now + 10),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    await program.methods
+      .setPaymentMint()
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        paymentMint: usdcMint,
+        paymentVault: paymentVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+    const contributorUsdc = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      usdcMint,
+      contributor.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      usdcMint,
+      contributorUsdc.address,
+      provider.wallet.publicKey,
+      usdc(3).toNumber()
+    );
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    try {
+      await program.methods
+        .contribute(usdc(2), null)
+        .accountsStrict({
+          contributor: contributor.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([contributor])
+        .rpc();
+      assert.fail("Expected a SOL contribution to an SPL sale to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SplPaymentRequired"));
+    }
+
+    await program.methods
+      .contributeSpl(usdc(2), null, false)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        paymentMint: usdcMint,
+        paymentVault: paymentVaultPda,
+        contributorPaymentAccount: contributorUsdc.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    let vaultAccount = await getAccount(provider.connection, paymentVaultPda);
+    assert.equal(vaultAccount.amount, BigInt(usdc(2).toString()));
+
+    // Soft cap missed: the authority cannot withdraw and the contributor gets the USDC back.
+    const authorityUsdc = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      usdcMint,
+      provider.wallet.publicKey
+    );
+    // Let the sale end before finalizing it.
+    await new Promise((resolve) => setTimeout(resolve, 8000));
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    try {
+      await program.methods
+        .adminWithdrawSpl(usdc(2))
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+          fundsReceiver: provider.wallet.publicKey,
+          paymentMint: usdcMint,
+          paymentVault: paymentVaultPda,
+          receiverPaymentAccount: authorityUsdc.address,
+          launchpadConfig: launchpadConfigPda,
+          treasuryPaymentAccount: authorityUsdc.address,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.fail("Expected a withdrawal below the soft cap to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SoftcapNotReached"));
+    }
+
+    await program.methods
+      .refundSpl()
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        paymentMint: usdcMint,
+        paymentVault: paymentVaultPda,
+        contributorPaymentAccount: contributorUsdc.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([contributor])
+      .rpc();
+
+    const contributorUsdcAccount = await getAccount(
+      provider.connection,
+      contributorUsdc.address
+    );
+    assert.equal(contributorUsdcAccount.amount, BigInt(usdc(3).toString()));
+    vaultAccount = await getAccount(provider.connection, paymentVaultPda);
+    assert.equal(vaultAccount.amount, BigInt(0));
+  });
+
+  it("vests claimed tokens through the vesting program", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(14);
+    const now = Math.floor(Date.now() / 1000);
+    const endTimestamp = now + 3600;
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3),
+        new anchor.BN(endTimestamp),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // One day cliff after the sale end, then 25% per hour.
+    await program.methods
+      .setClaimVesting({
+        cliffDuration: new anchor.BN(86_400),
+        intervalDuration: new anchor.BN(3_600),
+        unlockPercentage: 25,
+      })
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        tokenMint: mint,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    const contributorAta = await getAssociatedTokenAddress(
+      mint,
+      contributor.publicKey
+    );
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    try {
+      await program.methods
+        .claim()
+        .accountsStrict({
+          contributor: contributor.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          tokenMint: mint,
+          presaleVault: presaleVaultPda,
+          destination: contributor.publicKey,
+          contributorAta,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([contributor])
+        .rpc();
+      assert.fail("Expected an immediate claim to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("VestedClaimRequired"));
+    }
+
+    // Each contributor has their own schedule creator and escrow
+    const [vestingAuthorityPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting-authority"),
+        presalePda.toBuffer(),
+        contributor.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [vestingEscrowPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting-escrow"),
+        presalePda.toBuffer(),
+        contributor.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [vestingSchedulePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting-schedule"),
+        vestingAuthorityPda.toBuffer(),
+        contributor.publicKey.toBuffer(),
+      ],
+      vestingProgram.programId
+    );
+    const [vestingVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), vestingSchedulePda.toBuffer()],
+      vestingProgram.programId
+    );
+
+    await program.methods
+      .claimVested()
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: contributor.publicKey,
+        vestingAuthority: vestingAuthorityPda,
+        vestingEscrow: vestingEscrowPda,
+        vestingSchedule: vestingSchedulePda,
+        vestingVault: vestingVaultPda,
+        vestingProgram: vestingProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    const schedule = await vestingProgram.account.vestingSchedule.fetch(
+      vestingSchedulePda
+    );
+    assert.ok(schedule.beneficiary.equals(contributor.publicKey));
+    assert.ok(schedule.totalAmount.eq(new anchor.BN(10 ** 9)));
+    assert.equal(schedule.cliffEndTimestamp.toNumber(), endTimestamp + 86_400);
+
+    const vestingVault = await getAccount(provider.connection, vestingVaultPda);
+    assert.equal(vestingVault.amount, BigInt(10 ** 9));
+    assert.equal(await provider.connection.getAccountInfo(vestingEscrowPda), null);
+
+    const profileAccount = await program.account.contributorProfile.fetch(
+      profilePda
+    );
+    assert.equal(profileAccount.claimed, true);
+  });
+
+  it("starts claimed vesting at the sale end for claims after the cliff", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(40);
+    const now = Math.floor(Date.now() / 1000);
+    const endTimestamp = now + 6;
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3),
+        new anchor.BN(endTimestamp),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // Two second cliff after the sale end, then 25% every two seconds.
+    await program.methods
+      .setClaimVesting({
+        cliffDuration: new anchor.BN(2),
+        intervalDuration: new anchor.BN(2),
+        unlockPercentage: 25,
+      })
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        tokenMint: mint,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    // Claim once the cliff and two intervals have passed
+    await new Promise((resolve) =>
+      setTimeout(resolve, Math.max(0, (endTimestamp + 7) * 1000 - Date.now()))
+    );
+
+    const [vestingAuthorityPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting-authority"),
+        presalePda.toBuffer(),
+        contributor.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [vestingEscrowPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting-escrow"),
+        presalePda.toBuffer(),
+        contributor.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [vestingSchedulePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting-schedule"),
+        vestingAuthorityPda.toBuffer(),
+        contributor.publicKey.toBuffer(),
+      ],
+      vestingProgram.programId
+    );
+    const [vestingVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), vestingSchedulePda.toBuffer()],
+      vestingProgram.programId
+    );
+
+    await program.methods
+      .claimVested()
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: contributor.publicKey,
+        vestingAuthority: vestingAuthorityPda,
+        vestingEscrow: vestingEscrowPda,
+        vestingSchedule: vestingSchedulePda,
+        vestingVault: vestingVaultPda,
+        vestingProgram: vestingProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    // The schedule runs from the sale end, not from the claim
+    const schedule = await vestingProgram.account.vestingSchedule.fetch(
+      vestingSchedulePda
+    );
+    assert.equal(schedule.cliffEndTimestamp.toNumber(), endTimestamp + 2);
+
+    const contributorAta = await getAssociatedTokenAddress(
+      mint,
+      contributor.publicKey
+    );
+    await vestingProgram.methods
+      .unlock()
+      .accounts({
+        vestingSchedule: vestingSchedulePda,
+        beneficiary: contributor.publicKey,
+        tokenMint: mint,
+        vault: vestingVaultPda,
+        beneficiaryAta: contributorAta,
+      })
+      .signers([contributor])
+      .rpc();
+
+    // The intervals elapsed before the claim unlock at once
+    const contributorAccount = await getAccount(provider.connection, contributorAta);
+    const quarter = BigInt(10 ** 9 / 4);
+    assert.ok(contributorAccount.amount >= BigInt(2) * quarter);
+    assert.equal(contributorAccount.amount % quarter, BigInt(0));
+  });
+
+  it("finalizes a sale once the hard cap is filled", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 4 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(15);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    // 0.5 SOL per token, hard cap 2 SOL.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      3 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    const contribute = (amount: number) =>
+      program.methods
+        .contribute(new anchor.BN(amount), null)
+        .accountsStrict({
+          contributor: contributor.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([contributor])
+        .rpc();
+    const checkBalances = () =>
+      program.methods
+        .finalize()
+        .accountsStrict({ presale: presalePda, tokenMint: mint })
+        .rpc();
+
+    await contribute(LAMPORTS_PER_SOL);
+
+    // Still open and below the hard cap: nothing can settle yet.
+    try {
+      await checkBalances();
+      assert.fail("Expected finalizing an open sale to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SaleNotEnded"));
+    }
+    try {
+      await program.methods
+        .adminWithdraw(new anchor.BN(LAMPORTS_PER_SOL))
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+          fundsReceiver: provider.wallet.publicKey,
+          launchpadConfig: launchpadConfigPda,
+          treasury: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("Expected a withdrawal before finalization to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SaleNotFinalized"));
+    }
+
+    await contribute(LAMPORTS_PER_SOL);
+    await checkBalances();
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.deepEqual(presaleAccount.outcome, { succeeded: {} });
+    // 2 tokens per SOL: 2 * 10^9 base units per 10^9 lamports, scaled by 10^9.
+    assert.ok(presaleAccount.finalRate.eq(new anchor.BN(2 * 10 ** 9)));
+
+    try {
+      await checkBalances();
+      assert.fail("Expected finalizing twice to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SaleFinalized"));
+    }
+  });
+
+  it("cancels a sale, refunds contributors and returns the sale tokens", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(16);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    const contribute = () =>
+      program.methods
+        .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+        .accountsStrict({
+          contributor: contributor.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([contributor])
+        .rpc();
+
+    await contribute();
+
+    // The soft cap is met, but the sale is called off before it ends.
+    await program.methods
+      .cancelPresale()
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    try {
+      await contribute();
+      assert.fail("Expected contributing to a cancelled sale to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SaleFinalized"));
+    }
+
+    const balanceBefore = await provider.connection.getBalance(
+      contributor.publicKey
+    );
+    await program.methods
+      .claim()
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: contributor.publicKey,
+        contributorAta: await getAssociatedTokenAddress(
+          mint,
+          contributor.publicKey
+        ),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+    const balanceAfter = await provider.connection.getBalance(
+      contributor.publicKey
+    );
+    assert.ok(balanceAfter > balanceBefore + 0.9 * LAMPORTS_PER_SOL);
+
+    // The sale has started, but cancellation unlocks the whole inventory.
+    await program.methods
+      .emergencyWithdrawToken(new anchor.BN(tokensForSaleNumber))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        receiver: provider.wallet.publicKey,
+        receiverAta: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const vaultAccount = await getAccount(provider.connection, presaleVaultPda);
+    assert.equal(vaultAccount.amount, BigInt(0));
+  });
+
+  it("moves the sale window until the sale starts", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(17);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const updateSchedule = (start: number, end: number) =>
+      program.methods
+        .updateSchedule(new anchor.BN(start), new anchor.BN(end))
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+
+    try {
+      await updateSchedule(now + 7200, now + 3600);
+      assert.fail("Expected an end before the start to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("EndTimestampBeforeStart"));
+    }
+
+    await updateSchedule(now + 86_400, now + 90_000);
+    let presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.equal(presaleAccount.startTimestamp.toNumber(), now + 86_400);
+    assert.equal(presaleAccount.endTimestamp.toNumber(), now + 90_000);
+
+    // Opening the sale right away is allowed, moving it again afterwards is not.
+    await updateSchedule(now - 60, now + 3600);
+    try {
+      await updateSchedule(now + 86_400, now + 90_000);
+      assert.fail("Expected changing the window of a running sale to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ScheduleOnlyBeforeStart"));
+    }
+
+    presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.equal(presaleAccount.startTimestamp.toNumber(), now - 60);
+  });
+
+  it("corrects the token price before the sale starts", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(18);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const updatePrice = (price: number) =>
+      program.methods
+        .updatePrice(new anchor.BN(price))
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+
+    try {
+      await updatePrice(0);
+      assert.fail("Expected a zero price to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidPrice"));
+    }
+
+    await updatePrice(0.25 * LAMPORTS_PER_SOL);
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.tokenPriceLamports.eq(new anchor.BN(0.25 * LAMPORTS_PER_SOL))
+    );
+
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 60), new anchor.BN(now + 3600))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+    try {
+      await updatePrice(LAMPORTS_PER_SOL);
+      assert.fail("Expected changing the price of a running sale to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("PriceOnlyBeforeStart"));
+    }
+  });
+
+  it("sells at the dutch auction price of each contribution", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 4 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(19);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 10_800),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // From 1 SOL down to 0.5 SOL over two hours, dropping every hour.
+    await program.methods
+      .setDutchAuction({
+        startPriceLamports: new anchor.BN(LAMPORTS_PER_SOL),
+        floorPriceLamports: new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        stepDuration: new anchor.BN(3600),
+      })
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    // Move the window so the sale is one step in: the price is 0.75 SOL.
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 3600), new anchor.BN(now + 3600))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .contribute(new anchor.BN(0.75 * LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    const profileAccount = await program.account.contributorProfile.fetch(
+      profilePda
+    );
+    assert.ok(
+      profileAccount.entryPriceLamports.eq(new anchor.BN(0.75 * LAMPORTS_PER_SOL))
+    );
+    assert.ok(profileAccount.auctionTokens.eq(new anchor.BN(10 ** 9)));
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.auctionTokensSold.eq(new anchor.BN(10 ** 9)));
+  });
+
+  it("shares an oversubscribed fair launch pro-rata and refunds the excess", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(20);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // 2 tokens at 1 SOL: the sale target is 2 SOL, the hard cap is ignored.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(5 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    await program.methods
+      .setFairLaunch(true)
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 60), new anchor.BN(now + 10))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const [whale, minnow] = [Keypair.generate(), Keypair.generate()];
+    const profiles = [];
+    for (const wallet of [whale, minnow]) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        4 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      profiles.push(profilePda);
+
+      await program.methods
+        .contribute(
+          new anchor.BN((wallet === whale ? 3 : 1) * LAMPORTS_PER_SOL),
+          null
+        )
+        .accountsStrict({
+          contributor: wallet.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+    }
+
+    // Let the sale end before finalizing it.
+    await new Promise((resolve) => setTimeout(resolve, 10000));
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    // 4 SOL raised for a 2 SOL target: the whale gets 3/4 of the tokens and 1.5 SOL back.
+    const whaleAta = await getAssociatedTokenAddress(mint, whale.publicKey);
+    const balanceBefore = await provider.connection.getBalance(whale.publicKey);
+    await program.methods
+      .claim()
+      .accountsStrict({
+        contributor: whale.publicKey,
+        presale: presalePda,
+        profile: profiles[0],
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: whale.publicKey,
+        contributorAta: whaleAta,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([whale])
+      .rpc();
+    const balanceAfter = await provider.connection.getBalance(whale.publicKey);
+
+    const whaleAtaAccount = await getAccount(provider.connection, whaleAta);
+    assert.equal(whaleAtaAccount.amount, BigInt(1.5 * 10 ** 9));
+    // The refund minus the ATA rent and fees.
+    assert.ok(balanceAfter - balanceBefore > 1.49 * LAMPORTS_PER_SOL);
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.overflowRefunded.eq(new anchor.BN(1.5 * LAMPORTS_PER_SOL))
+    );
+  });
+
+  it("applies the contribution limits of the contributor's tier", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(21);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // Tier 0 (silver) and tier 1 (gold).
+    await program.methods
+      .setTiers([
+        {
+          minContribution: new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+          maxContribution: new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        },
+        {
+          minContribution: new anchor.BN(LAMPORTS_PER_SOL),
+          maxContribution: new anchor.BN(2 * LAMPORTS_PER_SOL),
+        },
+      ])
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const [gold, regular] = [Keypair.generate(), Keypair.generate()];
+    for (const wallet of [gold, regular]) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        3 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+    }
+
+    const [goldTierPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-tier"),
+        presalePda.toBuffer(),
+        gold.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const setTier = (tier: number) =>
+      program.methods
+        .setContributorTier(gold.publicKey, tier)
+        .accountsStrict({
+          presale: presalePda,
+          contributorTier: goldTierPda,
+          authority: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    try {
+      await setTier(2);
+      assert.fail("Expected an undefined tier to be rejected");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidTier"));
+    }
+    await setTier(1);
+
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 60), new anchor.BN(now + 3600))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const contribute = (
+      wallet: anchor.web3.Keypair,
+      amount: number,
+      contributorTier: PublicKey | null
+    ) => {
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      return program.methods
+        .contribute(new anchor.BN(amount), null)
+        .accountsStrict({
+          contributor: wallet.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+    };
+
+    try {
+      await contribute(gold, 0.5 * LAMPORTS_PER_SOL, goldTierPda);
+      assert.fail("Expected a contribution below the gold minimum to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ContributionBelowMinimum"));
+    }
+    // Above the pool maximum but within the gold limits.
+    await contribute(gold, 1.5 * LAMPORTS_PER_SOL, goldTierPda);
+
+    // Without a tier the pool limits apply.
+    try {
+      await contribute(regular, 1.5 * LAMPORTS_PER_SOL, null);
+      assert.fail("Expected a contribution above the pool maximum to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ContributionAboveMaximum"));
+    }
+    await contribute(regular, LAMPORTS_PER_SOL, null);
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.totalContributions.eq(new anchor.BN(2.5 * LAMPORTS_PER_SOL))
+    );
+  });
+
+  it("pays referrers a bonus on the volume they referred", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    // 1 token for sale plus 1 token of referral bonuses.
+    const tokensForSaleNumber = 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      2 * tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(22);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // Hard cap of 1 SOL so the sale can be finalized as soon as it fills.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // 5% bonus.
+    await program.methods
+      .setReferralBonus(500, new anchor.BN(tokensForSaleNumber))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const [referrer, buyer] = [Keypair.generate(), Keypair.generate()];
+    for (const wallet of [referrer, buyer]) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        2 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+    }
+
+    const [referralPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("referral"),
+        presalePda.toBuffer(),
+        referrer.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    await program.methods
+      .registerReferrer()
+      .accountsStrict({
+        referrer: referrer.publicKey,
+        presale: presalePda,
+        referral: referralPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([referrer])
+      .rpc();
+
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 60), new anchor.BN(now + 3600))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const contribute = (wallet: anchor.web3.Keypair, amount: number) => {
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      return program.methods
+        .contribute(new anchor.BN(amount), null)
+        .accountsStrict({
+          contributor: wallet.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: referralPda,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+    };
+
+    try {
+      await contribute(referrer, 0.5 * LAMPORTS_PER_SOL);
+      assert.fail("Expected a self-referral to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SelfReferral"));
+    }
+    await contribute(buyer, LAMPORTS_PER_SOL);
+
+    const referralAccount = await program.account.referralProfile.fetch(
+      referralPda
+    );
+    assert.ok(
+      referralAccount.referredVolume.eq(new anchor.BN(LAMPORTS_PER_SOL))
+    );
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    const referrerAta = await getAssociatedTokenAddress(
+      mint,
+      referrer.publicKey
+    );
+    const claimBonus = () =>
+      program.methods
+        .claimReferralBonus()
+        .accountsStrict({
+          referrer: referrer.publicKey,
+          presale: presalePda,
+          referral: referralPda,
+          tokenMint: mint,
+          presaleVault: presaleVaultPda,
+          referrerAta,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([referrer])
+        .rpc();
+    await claimBonus();
+
+    // 5% of the 1 token bought by the referred contributor.
+    const referrerAtaAccount = await getAccount(provider.connection, referrerAta);
+    assert.equal(referrerAtaAccount.amount, BigInt(0.05 * 10 ** 9));
+
+    try {
+      await claimBonus();
+      assert.fail("Expected a second bonus claim to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("AlreadyClaimed"));
+    }
+  });
+
+  it("returns unsold tokens to the authority after the sale", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(23);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 10),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const buyer = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const withdrawUnsold = () =>
+      program.methods
+        .withdrawUnsoldTokens()
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+          tokenMint: mint,
+          presaleVault: presaleVaultPda,
+          authorityAta: authorityAta.address,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    try {
+      await withdrawUnsold();
+      assert.fail("Expected a withdrawal before the end to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SaleNotEnded"));
+    }
+
+    // Let the sale end before finalizing it.
+    await new Promise((resolve) => setTimeout(resolve, 10000));
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    await withdrawUnsold();
+
+    // Half the tokens were sold; the other half went back to the authority.
+    const vaultAccount = await getAccount(provider.connection, presaleVaultPda);
+    assert.equal(vaultAccount.amount, BigInt(10 ** 9));
+    const authorityAccount = await getAccount(
+      provider.connection,
+      authorityAta.address
+    );
+    assert.equal(authorityAccount.amount, BigInt(10 ** 9));
+
+    try {
+      await withdrawUnsold();
+      assert.fail("Expected the sold tokens to stay in the vault");
+    } catch (err) {
+      assert.ok(err.toString().includes("NothingToWithdraw"));
+    }
+  });
+
+  it("pushes refunds of a failed sale to contributors in a batch", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(24);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    // Soft cap of 5 SOL that the two contributors will miss.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(5 * LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 10),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const wallets = [Keypair.generate(), Keypair.generate()];
+    const profiles = [];
+    for (const wallet of wallets) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      profiles.push(profilePda);
+
+      await program.methods
+        .contribute(new anchor.BN(0.5 * LAMPORTS_PER_SOL), null)
+        .accountsStrict({
+          contributor: wallet.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+    }
+
+    // Let the sale end before finalizing it.
+    await new Promise((resolve) => setTimeout(resolve, 10000));
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    const balancesBefore = await Promise.all(
+      wallets.map((wallet) => provider.connection.getBalance(wallet.publicKey))
+    );
+
+    const remainingAccounts = [];
+    wallets.forEach((wallet, index) => {
+      remainingAccounts.push(
+        { pubkey: profiles[index], isWritable: true, isSigner: false },
+        { pubkey: wallet.publicKey, isWritable: true, isSigner: false }
+      );
+    });
+    await program.methods
+      .refundBatch()
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+    for (const [index, wallet] of wallets.entries()) {
+      const balance = await provider.connection.getBalance(wallet.publicKey);
+      assert.equal(balance - balancesBefore[index], 0.5 * LAMPORTS_PER_SOL);
+
+      const profileAccount = await program.account.contributorProfile.fetch(
+        profiles[index]
+      );
+      assert.ok(profileAccount.claimed);
+    }
+
+    // A profile paired with the wrong wallet is rejected.
+    try {
+      await program.methods
+        .refundBatch()
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .remainingAccounts([
+          { pubkey: profiles[0], isWritable: true, isSigner: false },
+          { pubkey: wallets[1].publicKey, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+      assert.fail("Expected a mismatched pair to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidRefundBatch"));
+    }
+  });
+
+  it("closes a settled contributor profile", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(25);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // Hard cap of 1 SOL so the sale can be finalized as soon as it fills.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const buyer = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const closeProfile = () =>
+      program.methods
+        .closeProfile()
+        .accountsStrict({
+          contributor: buyer.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+        })
+        .signers([buyer])
+        .rpc();
+
+    try {
+      await closeProfile();
+      assert.fail("Expected closing an unclaimed profile to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ProfileNotSettled"));
+    }
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+    await program.methods
+      .claim()
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: buyer.publicKey,
+        contributorAta: await getAssociatedTokenAddress(mint, buyer.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const profileRent = await provider.connection.getBalance(profilePda);
+    const balanceBefore = await provider.connection.getBalance(buyer.publicKey);
+    await closeProfile();
+    const balanceAfter = await provider.connection.getBalance(buyer.publicKey);
+
+    assert.equal(
+      await program.account.contributorProfile.fetchNullable(profilePda),
+      null
+    );
+    // The rent minus the transaction fee.
+    assert.ok(balanceAfter - balanceBefore > profileRent - 10_000);
+  });
+
+  it("closes a settled presale and its vault", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(26);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // Hard cap of 1 SOL so the sale can be finalized as soon as it fills.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const buyer = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    const closePresale = () =>
+      program.methods
+        .closePresale()
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+          tokenMint: mint,
+          presaleVault: presaleVaultPda,
+          paymentVault: null,
+          paymentTokenProgram: null,
+          authorityAta: authorityAta.address,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    try {
+      await closePresale();
+      assert.fail("Expected closing with an unclaimed contribution to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ClaimsOutstanding"));
+    }
+
+    await program.methods
+      .claim()
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: buyer.publicKey,
+        contributorAta: await getAssociatedTokenAddress(mint, buyer.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    try {
+      await closePresale();
+      assert.fail("Expected closing with the raise still in the pool to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("FundsNotWithdrawn"));
+    }
+
+    await program.methods
+      .adminWithdraw(new anchor.BN(2 * LAMPORTS_PER_SOL))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        launchpadConfig: launchpadConfigPda,
+        treasury: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+    await closePresale();
+
+    assert.equal(await provider.connection.getAccountInfo(presalePda), null);
+    assert.equal(
+      await provider.connection.getAccountInfo(presaleVaultPda),
+      null
+    );
+    // The unsold token went back to the authority.
+    const authorityAccount = await getAccount(
+      provider.connection,
+      authorityAta.address
+    );
+    assert.equal(authorityAccount.amount, BigInt(10 ** 9));
+  });
+
+  it("sells a Token-2022 mint with a transfer fee", async () => {
+    // Token-2022 mint taking a 1% transfer fee.
+    const mintKeypair = Keypair.generate();
+    const mint = mintKeypair.publicKey;
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    const mintLamports =
+      await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: provider.wallet.publicKey,
+          newAccountPubkey: mint,
+          space: mintLen,
+          lamports: mintLamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          mint,
+          provider.wallet.publicKey,
+          provider.wallet.publicKey,
+          100,
+          BigInt(10 ** 9),
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(
+          mint,
+          9,
+          provider.wallet.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ),
+      [mintKeypair]
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const poolId = new anchor.BN(27);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // Hard cap of 1 SOL so the sale can be finalized as soon as it fills.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .rpc();
+
+    // Only the tokens reaching the vault are for sale.
+    let presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.tokensForSale.eq(new anchor.BN(1_980_000_000))
+    );
+
+    const buyer = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    const buyerAta = getAssociatedTokenAddressSync(
+      mint,
+      buyer.publicKey,
+      false,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await program.methods
+      .claim()
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: buyer.publicKey,
+        contributorAta: buyerAta,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    // 1 token bought, 1% withheld by the mint on the way out.
+    const buyerAtaAccount = await getAccount(
+      provider.connection,
+      buyerAta,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(buyerAtaAccount.amount, BigInt(990_000_000));
+
+    presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.tokensClaimed.eq(new anchor.BN(10 ** 9)));
+  });
+
+  it("emits events for pool creation and emergency withdrawals", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(28);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    const eventParser = new anchor.EventParser(
+      program.programId,
+      new anchor.BorshCoder(program.idl)
+    );
+    const eventsOf = async (signature: string) => {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return Array.from(eventParser.parseLogs(tx.meta.logMessages));
+    };
+
+    const createSig = await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const [created] = await eventsOf(createSig);
+    assert.equal(created.name, "presaleCreated");
+    assert.ok(created.data.presale.equals(presalePda));
+    assert.ok(created.data.tokenMint.equals(mint));
+    assert.ok(
+      created.data.tokensForSale.eq(new anchor.BN(tokensForSaleNumber))
+    );
+
+    const withdrawSig = await program.methods
+      .emergencyWithdrawToken(new anchor.BN(tokensForSaleNumber))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        receiver: provider.wallet.publicKey,
+        receiverAta: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const [withdrawn] = await eventsOf(withdrawSig);
+    assert.equal(withdrawn.name, "emergencyTokensWithdrawn");
+    assert.ok(withdrawn.data.amount.eq(new anchor.BN(tokensForSaleNumber)));
+    assert.ok(withdrawn.data.tokensForSale.eq(new anchor.BN(0)));
+  });
+
+  it("rejects contributions while the sale is paused", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(29);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const buyer = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    const contribute = () =>
+      program.methods
+        .contribute(new anchor.BN(0.5 * LAMPORTS_PER_SOL), null)
+        .accountsStrict({
+          contributor: buyer.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+    const setPaused = (paused: boolean) =>
+      program.methods
+        .setPaused(paused)
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+
+    await setPaused(true);
+    try {
+      await contribute();
+      assert.fail("Expected a contribution to a paused sale to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("SalePaused"));
+    }
+
+    await setPaused(false);
+    await contribute();
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.totalContributions.eq(
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL)
+      )
+    );
+  });
+
+  it("requires a KYC attestation from the KYC authority", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(30);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const kycAuthority = Keypair.generate();
+    await program.methods
+      .setKycAuthority(kycAuthority.publicKey)
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 60), new anchor.BN(now + 3600))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const [buyer, other] = [Keypair.generate(), Keypair.generate()];
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    // The KYC provider signs contributor || presale.
+    const attestation = (wallet: anchor.web3.Keypair) =>
+      anchor.web3.Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: kycAuthority.secretKey,
+        message: Buffer.concat([
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ]),
+      });
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    const contribute = (preInstructions: anchor.web3.TransactionInstruction[]) =>
+      program.methods
+        .contribute(new anchor.BN(0.5 * LAMPORTS_PER_SOL), null)
+        .accountsStrict({
+          contributor: buyer.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions(preInstructions)
+        .signers([buyer])
+        .rpc();
+
+    try {
+      await contribute([]);
+      assert.fail("Expected a contribution without attestation to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("KycRequired"));
+    }
+
+    try {
+      await contribute([attestation(other)]);
+      assert.fail("Expected another wallet's attestation to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidKycAttestation"));
+    }
+
+    await contribute([attestation(buyer)]);
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.totalContributions.eq(
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL)
+      )
+    );
+  });
+
+  it("prices a USD sale only from a valid Pyth price update", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(31);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // $0.50 per token against the Pyth SOL/USD feed.
+    await program.methods
+      .setUsdPricing({
+        tokenPriceUsd: new anchor.BN(500_000),
+        priceFeedId: Array.from(
+          Buffer.from(
+            "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d",
+            "hex"
+          )
+        ),
+        maxPriceAge: new anchor.BN(60),
+        maxConfidenceBps: 100,
+      })
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    try {
+      await program.methods
+        .setDutchAuction({
+          startPriceLamports: new anchor.BN(LAMPORTS_PER_SOL),
+          floorPriceLamports: new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+          stepDuration: new anchor.BN(3600),
+        })
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+      assert.fail("Expected a dutch auction on a USD-priced sale to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("PricingModeConflict"));
+    }
+
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 60), new anchor.BN(now + 3600))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const buyer = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    const contribute = (priceUpdate: anchor.web3.PublicKey | null) =>
+      program.methods
+        .contribute(new anchor.BN(0.5 * LAMPORTS_PER_SOL), null)
+        .accountsStrict({
+          contributor: buyer.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+    try {
+      await contribute(null);
+      assert.fail("Expected a contribution without the oracle to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("OraclePriceRequired"));
+    }
+
+    // The local validator has no Pyth receiver, so any account passed here is rejected.
+    try {
+      await contribute(buyer.publicKey);
+      assert.fail("Expected a non-Pyth price account to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidOraclePrice"));
+    }
+  });
+
+  it("accepts SPL mints next to SOL and refunds each currency", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(32);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // Soft cap of 5 SOL that the contributor will miss.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(5 * LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // A 6-decimal stablecoin, one full token counting for 0.01 SOL.
+    const usdMint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      6
+    );
+    const [mintVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mint-vault"), presalePda.toBuffer(), usdMint.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .addAcceptedMint(new anchor.BN(0.01 * LAMPORTS_PER_SOL))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        paymentMint: usdMint,
+        mintVault: mintVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 60), new anchor.BN(now + 10))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const buyer = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const buyerUsd = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      usdMint,
+      buyer.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      usdMint,
+      buyerUsd.address,
+      provider.wallet.publicKey,
+      100 * 10 ** 6
+    );
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .contribute(new anchor.BN(0.2 * LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    // 50 tokens count for 0.5 SOL towards the caps and the per-wallet limit.
+    await program.methods
+      .contributeWithMint(new anchor.BN(50 * 10 ** 6), null, false)
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        paymentMint: usdMint,
+        mintVault: mintVaultPda,
+        contributorPaymentAccount: buyerUsd.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.totalContributions.eq(
+        new anchor.BN(0.7 * LAMPORTS_PER_SOL)
+      )
+    );
+    assert.ok(
+      presaleAccount.acceptedMints[0].raised.eq(new anchor.BN(50 * 10 ** 6))
+    );
+    const profileAccount = await program.account.contributorProfile.fetch(
+      profilePda
+    );
+    assert.ok(
+      profileAccount.contributed.eq(new anchor.BN(0.7 * LAMPORTS_PER_SOL))
+    );
+    assert.ok(
+      profileAccount.mintContributions[0].eq(new anchor.BN(50 * 10 ** 6))
+    );
+
+    // Let the sale end before finalizing it.
+    await new Promise((resolve) => setTimeout(resolve, 10000));
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    // The claim only refunds the SOL part.
+    const balanceBefore = await provider.connection.getBalance(buyer.publicKey);
+    await program.methods
+      .claim()
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: buyer.publicKey,
+        contributorAta: getAssociatedTokenAddressSync(mint, buyer.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+    const balanceAfter = await provider.connection.getBalance(buyer.publicKey);
+    assert.ok(balanceAfter - balanceBefore > 0.19 * LAMPORTS_PER_SOL);
+    assert.ok(balanceAfter - balanceBefore <= 0.2 * LAMPORTS_PER_SOL);
+
+    await program.methods
+      .refundWithMint()
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        paymentMint: usdMint,
+        mintVault: mintVaultPda,
+        contributorPaymentAccount: buyerUsd.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const buyerUsdAccount = await getAccount(
+      provider.connection,
+      buyerUsd.address
+    );
+    assert.equal(Number(buyerUsdAccount.amount), 100 * 10 ** 6);
+
+    const settled = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      settled.refundedContributions.eq(new anchor.BN(0.7 * LAMPORTS_PER_SOL))
+    );
+  });
+
+  it("sweeps refunds left unclaimed after the claim deadline", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(33);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // Soft cap of 5 SOL that the two contributors will miss.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(5 * LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3),
+        new anchor.BN(now + 8),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const setClaimDeadline = (deadline: number) =>
+      program.methods
+        .setClaimDeadline(new anchor.BN(deadline))
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+
+    try {
+      await setClaimDeadline(now + 8);
+      assert.fail("Expected a deadline before the sale end to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidClaimDeadline"));
+    }
+    await setClaimDeadline(now + 14);
+
+    const wallets = [Keypair.generate(), Keypair.generate()];
+    const profiles = [];
+    for (const wallet of wallets) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      profiles.push(profilePda);
+    }
+
+    // Let the sale start.
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    for (const [index, wallet] of wallets.entries()) {
+      await program.methods
+        .contribute(new anchor.BN(0.5 * LAMPORTS_PER_SOL), null)
+        .accountsStrict({
+          contributor: wallet.publicKey,
+          presale: presalePda,
+          profile: profiles[index],
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+    }
+
+    // Let the sale end before finalizing it.
+    await new Promise((resolve) => setTimeout(resolve, 6000));
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    const claim = (index: number) =>
+      program.methods
+        .claim()
+        .accountsStrict({
+          contributor: wallets[index].publicKey,
+          presale: presalePda,
+          profile: profiles[index],
+          tokenMint: mint,
+          presaleVault: presaleVaultPda,
+          destination: wallets[index].publicKey,
+          contributorAta: getAssociatedTokenAddressSync(
+            mint,
+            wallets[index].publicKey
+          ),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallets[index]])
+        .rpc();
+    const sweep = () =>
+      program.methods
+        .sweepUnclaimed()
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+          tokenMint: mint,
+          presaleVault: presaleVaultPda,
+          authorityAta: authorityAta.address,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    // Only the first contributor asks for the refund before the deadline.
+    await claim(0);
+
+    try {
+      await sweep();
+      assert.fail("Expected a sweep before the claim deadline to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ClaimDeadlineNotReached"));
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, 6000));
+    const presaleBalance = await provider.connection.getBalance(presalePda);
+    await sweep();
+
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(
+      (await provider.connection.getAccountInfo(presalePda)).data.length
+    );
+    assert.equal(presaleBalance - rent, 0.5 * LAMPORTS_PER_SOL);
+    assert.equal(await provider.connection.getBalance(presalePda), rent);
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.unclaimedSwept);
+
+    try {
+      await claim(1);
+      assert.fail("Expected a refund after the sweep to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ClaimWindowClosed"));
+    }
+  });
+
+  it("releases the fair-launch excess of non-claimers on sweep", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(38);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [presaleVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    // 2 tokens at 1 SOL: the sale target is 2 SOL.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(5 * LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3),
+        new anchor.BN(now + 8),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    await program.methods
+      .setFairLaunch(true)
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+    await program.methods
+      .setClaimDeadline(new anchor.BN(now + 14))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const [whale, minnow] = [Keypair.generate(), Keypair.generate()];
+    const profiles = [];
+    for (const wallet of [whale, minnow]) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        4 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      const [profilePda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("contributor-profile"),
+          wallet.publicKey.toBuffer(),
+          presalePda.toBuffer(),
+        ],
+        program.programId
+      );
+      profiles.push(profilePda);
+    }
+
+    // Let the sale start.
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    for (const [index, wallet] of [whale, minnow].entries()) {
+      await program.methods
+        .contribute(
+          new anchor.BN((wallet === whale ? 3 : 1) * LAMPORTS_PER_SOL),
+          null
+        )
+        .accountsStrict({
+          contributor: wallet.publicKey,
+          presale: presalePda,
+          profile: profiles[index],
+          contributorTier: null,
+          referral: null,
+          depositCounter: null,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+    }
+
+    // Let the sale end before finalizing it.
+    await new Promise((resolve) => setTimeout(resolve, 6000));
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    // Only the whale claims, taking 1.5 SOL of the 2 SOL excess back.
+    await program.methods
+      .claim()
+      .accountsStrict({
+        contributor: whale.publicKey,
+        presale: presalePda,
+        profile: profiles[0],
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        destination: whale.publicKey,
+        contributorAta: getAssociatedTokenAddressSync(mint, whale.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([whale])
+      .rpc();
+
+    // The withdrawal keeps the minnow's 0.5 SOL excess back.
+    await program.methods
+      .adminWithdraw(new anchor.BN(10 * LAMPORTS_PER_SOL))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        launchpadConfig: launchpadConfigPda,
+        treasury: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(
+      (await provider.connection.getAccountInfo(presalePda)).data.length
+    );
+    assert.equal(
+      await provider.connection.getBalance(presalePda),
+      rent + 0.5 * LAMPORTS_PER_SOL
+    );
+
+    await new Promise((resolve) => setTimeout(resolve, 6000));
+    await program.methods
+      .sweepUnclaimed()
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        authorityAta: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    assert.equal(await provider.connection.getBalance(presalePda), rent);
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(
+      presaleAccount.overflowRefunded.eq(new anchor.BN(2 * LAMPORTS_PER_SOL))
+    );
+
+    await program.methods
+      .closePresale()
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        tokenMint: mint,
+        presaleVault: presaleVaultPda,
+        paymentVault: null,
+        paymentTokenProgram: null,
+        authorityAta: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    assert.equal(await provider.connection.getAccountInfo(presalePda), null);
+  });
+
+  it("raises the maximum contribution of stakers", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(34);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    // Wallets may contribute up to 1 SOL, stakers of 100 tokens up to 3 SOL.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now + 3600),
+        new anchor.BN(now + 7200),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // Stake in two staking pools of their own creator, only the first one locks the stake.
+    const staker = Keypair.generate();
+    const creator = Keypair.generate();
+    for (const wallet of [staker, creator]) {
+      const airdropSig = await provider.connection.requestAirdrop(
+        wallet.publicKey,
+        5 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+    }
+
+    const [globalConfig] = PublicKey.findProgramAddressSync(
+      [Buffer.from("global-config")],
+      stakingProgram.programId
+    );
+    const [stakingProgramData] = PublicKey.findProgramAddressSync(
+      [stakingProgram.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    if (!(await provider.connection.getAccountInfo(globalConfig))) {
+      await stakingProgram.methods
+        .initializeConfig(0, provider.wallet.publicKey)
+        .accountsStrict({
+          admin: provider.wallet.publicKey,
+          globalConfig,
+          program: stakingProgram.programId,
+          programData: stakingProgramData,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    const stakeMint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+    const creatorAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      stakeMint,
+      creator.publicKey
+    );
+    const stakerAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      stakeMint,
+      staker.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      stakeMint,
+      stakerAta.address,
+      provider.wallet.publicKey,
+      400 * 10 ** 9
+    );
+
+    const [poolRegistry] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool-registry"), creator.publicKey.toBuffer()],
+      stakingProgram.programId
+    );
+    const [stakerStats] = PublicKey.findProgramAddressSync(
+      [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+      stakingProgram.programId
+    );
+    const createStakingPool = async (id: number, cooldown: number) => {
+      const stakingPoolId = new anchor.BN(id);
+      const [stakingPool] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("pool"),
+          creator.publicKey.toBuffer(),
+          stakingPoolId.toArrayLike(Buffer, "le", 8),
+        ],
+        stakingProgram.programId
+      );
+      const [rewardVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("reward-vault"), stakingPool.toBuffer()],
+        stakingProgram.programId
+      );
+      const poolVault = getAssociatedTokenAddressSync(
+        stakeMint,
+        stakingPool,
+        true
+      );
+      await stakingProgram.methods
+        .createPool(
+          stakingPoolId,
+          new anchor.BN(0),
+          new anchor.BN(cooldown),
+          false
+        )
+        .accountsStrict({
+          mint: stakeMint,
+          rewardMint: stakeMint,
+          creator: creator.publicKey,
+          globalConfig,
+          pool: stakingPool,
+          poolRegistry,
+          poolVault,
+          rewardVault,
+          creatorRewardAta: creatorAta.address,
+          treasury: null,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+      return { stakingPool, poolVault };
+    };
+    const lockedPool = await createStakingPool(1, 60);
+    const unlockedPool = await createStakingPool(2, 0);
+
+    const counterFor = (stakingPool: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("deposit-counter"),
+          staker.publicKey.toBuffer(),
+          stakingPool.toBuffer(),
+        ],
+        stakingProgram.programId
+      )[0];
+    const stake = async ({
+      stakingPool,
+      poolVault,
+    }: {
+      stakingPool: PublicKey;
+      poolVault: PublicKey;
+    }) => {
+      const depositId = new anchor.BN(1);
+      const [deposit] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("deposit"),
+          staker.publicKey.toBuffer(),
+          stakingPool.toBuffer(),
+          depositId.toArrayLike(Buffer, "le", 8),
+        ],
+        stakingProgram.programId
+      );
+      await stakingProgram.methods
+        .stake(depositId, new anchor.BN(200 * 10 ** 9), null)
+        .accountsStrict({
+          mint: stakeMint,
+          staker: staker.publicKey,
+          depositCounter: counterFor(stakingPool),
+          deposit,
+          stakerStats,
+          referralStats: null,
+          pool: stakingPool,
+          poolVault,
+          stakerAta: stakerAta.address,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([staker])
+        .rpc();
+    };
+
+    const setStakingAllocations = (
+      allocations: [number, number][],
+      stakingPool: PublicKey
+    ) =>
+      program.methods
+        .setStakingAllocations(
+          allocations.map(([minStaked, maxContribution]) => ({
+            minStaked: new anchor.BN(minStaked),
+            maxContribution: new anchor.BN(maxContribution),
+          }))
+        )
+        .accountsStrict({
+          presale: presalePda,
+          stakingPool,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+
+    try {
+      await setStakingAllocations(
+        [
+          [100 * 10 ** 9, 3 * LAMPORTS_PER_SOL],
+          [50 * 10 ** 9, 5 * LAMPORTS_PER_SOL],
+        ],
+        lockedPool.stakingPool
+      );
+      assert.fail("Expected unordered thresholds to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidStakingAllocations"));
+    }
+    const allocations: [number, number][] = [
+      [100 * 10 ** 9, 3 * LAMPORTS_PER_SOL],
+      [1000 * 10 ** 9, 5 * LAMPORTS_PER_SOL],
+    ];
+    try {
+      await setStakingAllocations(allocations, unlockedPool.stakingPool);
+      assert.fail("Expected a staking pool without cooldown to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("StakingPoolNotLocked"));
+    }
+    await setStakingAllocations(allocations, lockedPool.stakingPool);
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.stakingPool.equals(lockedPool.stakingPool));
+
+    await program.methods
+      .updateSchedule(new anchor.BN(now - 60), new anchor.BN(now + 3600))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        staker.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    const contribute = (amount: number, depositCounter: PublicKey | null) =>
+      program.methods
+        .contribute(new anchor.BN(amount), null)
+        .accountsStrict({
+          contributor: staker.publicKey,
+          presale: presalePda,
+          profile: profilePda,
+          contributorTier: null,
+          referral: null,
+          depositCounter,
+          instructionsSysvar: null,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([staker])
+        .rpc();
+
+    // Stake in the other pool doesn't raise the maximum.
+    await stake(unlockedPool);
+    try {
+      await contribute(2 * LAMPORTS_PER_SOL, null);
+      assert.fail("Expected a contribution above the pool maximum to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ContributionAboveMaximum"));
+    }
+    try {
+      await contribute(
+        2 * LAMPORTS_PER_SOL,
+        counterFor(unlockedPool.stakingPool)
+      );
+      assert.fail("Expected the counter of another staking pool to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ConstraintSeeds"));
+    }
+
+    await stake(lockedPool);
+    const lockedCounter = counterFor(lockedPool.stakingPool);
+    await contribute(2 * LAMPORTS_PER_SOL, lockedCounter);
+    try {
+      await contribute(2 * LAMPORTS_PER_SOL, lockedCounter);
+      assert.fail("Expected a contribution above the staking maximum to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ContributionAboveMaximum"));
+    }
+
+    const profileAccount = await program.account.contributorProfile.fetch(
+      profilePda
+    );
+    assert.ok(
+      profileAccount.contributed.eq(new anchor.BN(2 * LAMPORTS_PER_SOL))
+    );
+  });
+
+  it("pays the launchpad fee on withdrawn raises to the treasury", async () => {
+    const treasury = Keypair.generate();
+    const newAdmin = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      newAdmin.publicKey,
+      LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const updateConfig = (feeBps: number, treasuryKey: anchor.web3.PublicKey) =>
+      program.methods
+        .updateLaunchpadConfig(feeBps, treasuryKey)
+        .accountsStrict({
+          admin: provider.wallet.publicKey,
+          launchpadConfig: launchpadConfigPda,
+        })
+        .rpc();
+
+    try {
+      await updateConfig(1001, treasury.publicKey);
+      assert.fail("Expected a fee above 10% to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ProtocolFeeTooHigh"));
+    }
+    await updateConfig(250, treasury.publicKey);
+
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(35);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    // Hard cap of 1 SOL so the sale can be finalized as soon as it fills.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // Later fee changes do not reach presales already created.
+    await updateConfig(0, provider.wallet.publicKey);
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.equal(presaleAccount.protocolFeeBps, 250);
+
+    const buyer = Keypair.generate();
+    const buyerAirdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(buyerAirdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    // The fee goes to the treasury of the current config.
+    await updateConfig(0, treasury.publicKey);
+    await program.methods
+      .adminWithdraw(new anchor.BN(2 * LAMPORTS_PER_SOL))
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        launchpadConfig: launchpadConfigPda,
+        treasury: treasury.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+    assert.equal(
+      await provider.connection.getBalance(treasury.publicKey),
+      0.025 * LAMPORTS_PER_SOL
+    );
+
+    // Handing the launchpad over takes a nomination and an acceptance.
+    await program.methods
+      .transferLaunchpadAdmin(newAdmin.publicKey)
+      .accountsStrict({
+        admin: provider.wallet.publicKey,
+        launchpadConfig: launchpadConfigPda,
+      })
+      .rpc();
+    try {
+      await program.methods
+        .acceptLaunchpadAdmin()
+        .accountsStrict({
+          newAdmin: buyer.publicKey,
+          launchpadConfig: launchpadConfigPda,
+        })
+        .signers([buyer])
+        .rpc();
+      assert.fail("Expected a wallet that was not nominated to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("NotPendingAdmin"));
+    }
+    await program.methods
+      .acceptLaunchpadAdmin()
+      .accountsStrict({
+        newAdmin: newAdmin.publicKey,
+        launchpadConfig: launchpadConfigPda,
+      })
+      .signers([newAdmin])
+      .rpc();
+
+    const config = await program.account.launchpadConfig.fetch(
+      launchpadConfigPda
+    );
+    assert.ok(config.admin.equals(newAdmin.publicKey));
+    assert.equal(config.pendingAdmin, null);
+
+    // Hand it back and restore the treasury for the remaining tests.
+    await program.methods
+      .transferLaunchpadAdmin(provider.wallet.publicKey)
+      .accountsStrict({
+        admin: newAdmin.publicKey,
+        launchpadConfig: launchpadConfigPda,
+      })
+      .signers([newAdmin])
+      .rpc();
+    await program.methods
+      .acceptLaunchpadAdmin()
+      .accountsStrict({
+        newAdmin: provider.wallet.publicKey,
+        launchpadConfig: launchpadConfigPda,
+      })
+      .rpc();
+    await updateConfig(0, provider.wallet.publicKey);
+  });
+
+  it("withdraws to a funds receiver changed before any withdrawal", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 2 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(36);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    // Hard cap of 1 SOL so the sale can be finalized as soon as it fills.
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const multisig = Keypair.generate();
+    const setFundsReceiver = () =>
+      program.methods
+        .setFundsReceiver(multisig.publicKey)
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+    await setFundsReceiver();
+
+    const buyer = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      buyer.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        buyer.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: buyer.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    await program.methods
+      .finalize()
+      .accountsStrict({ presale: presalePda, tokenMint: mint })
+      .rpc();
+
+    const adminWithdraw = (fundsReceiver: anchor.web3.PublicKey) =>
+      program.methods
+        .adminWithdraw(new anchor.BN(2 * LAMPORTS_PER_SOL))
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+          fundsReceiver,
+          launchpadConfig: launchpadConfigPda,
+          treasury: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    try {
+      await adminWithdraw(provider.wallet.publicKey);
+      assert.fail("Expected a withdrawal to the previous receiver to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("ConstraintHasOne"));
+    }
+
+    await adminWithdraw(multisig.publicKey);
+    assert.equal(
+      await provider.connection.getBalance(multisig.publicKey),
+      LAMPORTS_PER_SOL
+    );
+
+    try {
+      await setFundsReceiver();
+      assert.fail("Expected a receiver change after a withdrawal to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("FundsAlreadyWithdrawn"));
+    }
+  });
+
+  it("hands the presale authority over in two steps", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(37);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(10 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const dao = Keypair.generate();
+    const stranger = Keypair.generate();
+
+    await program.methods
+      .nominateAuthority(dao.publicKey)
+      .accountsStrict({
+        presale: presalePda,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    // The nomination alone changes nothing.
+    let presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.authority.equals(provider.wallet.publicKey));
+    assert.ok(presaleAccount.pendingAuthority.equals(dao.publicKey));
+
+    try {
+      await program.methods
+        .acceptAuthority()
+        .accountsStrict({ presale: presalePda, newAuthority: stranger.publicKey })
+        .signers([stranger])
+        .rpc();
+      assert.fail("Expected a wallet that was not nominated to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("NotPendingAuthority"));
+    }
+
+    await program.methods
+      .acceptAuthority()
+      .accountsStrict({ presale: presalePda, newAuthority: dao.publicKey })
+      .signers([dao])
+      .rpc();
+
+    presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.ok(presaleAccount.authority.equals(dao.publicKey));
+    assert.equal(presaleAccount.pendingAuthority, null);
+
+    // The previous authority lost its admin rights.
+    try {
+      await program.methods
+        .setPaused(true)
+        .accountsStrict({
+          presale: presalePda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+      assert.fail("Expected the previous authority to be rejected");
+    } catch (err) {
+      assert.ok(err.toString().includes("ConstraintHasOne"));
+    }
+  });
+
+  it("writes the current layout version and rejects migrating it again", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const authorityAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey
+    );
+
+    const tokensForSaleNumber = 10 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      authorityAta.address,
+      provider.wallet.publicKey,
+      tokensForSaleNumber
+    );
+
+    const poolId = new anchor.BN(39);
+    const now = Math.floor(Date.now() / 1000);
+
+    const [presalePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("state"), poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createPresalePool(
+        poolId,
+        new anchor.BN(LAMPORTS_PER_SOL),
+        new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        new anchor.BN(2 * LAMPORTS_PER_SOL),
+        new anchor.BN(now - 60),
+        new anchor.BN(now + 3600),
+        new anchor.BN(tokensForSaleNumber)
+      )
+      .accounts({
+        authority: provider.wallet.publicKey,
+        fundsReceiver: provider.wallet.publicKey,
+        tokenMint: mint,
+        authorityTokenAccount: authorityAta.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const contributor = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      contributor.publicKey,
+      2 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contributor-profile"),
+        contributor.publicKey.toBuffer(),
+        presalePda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .contribute(new anchor.BN(LAMPORTS_PER_SOL), null)
+      .accountsStrict({
+        contributor: contributor.publicKey,
+        presale: presalePda,
+        profile: profilePda,
+        contributorTier: null,
+        referral: null,
+        depositCounter: null,
+        instructionsSysvar: null,
+        priceUpdate: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([contributor])
+      .rpc();
+
+    const presaleAccount = await program.account.presalePool.fetch(presalePda);
+    assert.equal(presaleAccount.version, 1);
+    const profileAccount = await program.account.contributorProfile.fetch(
+      profilePda
+    );
+    assert.equal(profileAccount.version, 1);
+
+    // Up-to-date accounts are left alone
+    try {
+      await program.methods
+        .migratePresale()
+        .accountsStrict({
+          presale: presalePda,
+          tokenMint: mint,
+          payer: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("Expected migrating a current presale to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("AccountUpToDate"));
+    }
+    try {
+      await program.methods
+        .migrateContributorProfile()
+        .accountsStrict({
+          profile: profilePda,
+          payer: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("Expected migrating a current profile to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("AccountUpToDate"));
+    }
+  });
+});
@@ -11,6 +11,7 @@ import {
 } from "@solana/spl-token";
 import { strict as assert } from "assert";
 import { Multisig } from "../target/types/multisig";
+import { Staking } from "../target/types/staking";
 
 const { SystemProgram, LAMPORTS_PER_SOL, PublicKey, Keypair } = anchor.web3;
 
@@ -20,6 +21,7 @@ describe("multisig", () => {
 
     const provider = anchor.getProvider() as anchor.AnchorProvider;
     const program = anchor.workspace.multisig as Program<Multisig>;
+    const stakingProgram = anchor.workspace.staking as Program<Staking>;
 
     let signer1: Keypair;
     let signer2: Keypair;
@@ -72,7 +74,7 @@ describe("multisig", () => {
         );
 
         await program.methods
-            .createVault(vaultId, signers, threshold)
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
             .accounts({
                 creator: creator,
                 vault: vault,
@@ -294,7 +296,7 @@ describe("multisig", () => {
 
         // Create vault
         await program.methods
-            .createVault(vaultId, signers, threshold)
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
             .accounts({
                 creator: creator,
                 vault: vault,
@@ -488,7 +490,7 @@ describe("multisig", () => {
 
         // Create vault
         await program.methods
-            .createVault(vaultId, signers, threshold)
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
             .accounts({
                 creator: creator,
                 vault: vault,
@@ -588,7 +590,7 @@ describe("multisig", () => {
 
         try {
             await program.methods
-                .createVault(vaultId, signers, threshold)
+                .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
                 .accounts({
                     creator: creator,
                     vault: vault,
@@ -618,7 +620,7 @@ describe("multisig", () => {
 
         try {
             await program.methods
-                .createVault(vaultId, signers, threshold)
+                .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
                 .accounts({
                     creator: creator,
                     vault: vault,
@@ -633,5 +635,889 @@ describe("multisig", () => {
             );
         }
     });
-});
 
+    it("fails to create a band below the vault threshold", async () => {
+        const creator = provider.wallet.publicKey;
+        const vaultId = new anchor.BN(11);
+        const signers = [signer1.publicKey, signer2.publicKey, signer3.publicKey];
+        const threshold = 2;
+        // A band of 1 would let a single signer move large amounts
+        const thresholdBands = [
+            {
+                tokenMint: null,
+                minAmount: new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+                threshold: 1,
+            },
+        ];
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        try {
+            await program.methods
+                .createVault(vaultId, signers, threshold, thresholdBands, new anchor.BN(0))
+                .accounts({
+                    creator: creator,
+                    vault: vault,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            assert.fail("Should have failed with a band below the vault threshold");
+        } catch (err) {
+            assert.ok(err.toString().includes("BandThresholdBelowVault"));
+        }
+    });
+
+    it("requires a higher threshold for large transfers", async () => {
+        const creator = provider.wallet.publicKey;
+        const vaultId = new anchor.BN(4);
+        const proposalId = new anchor.BN(1);
+        const signers = [signer1.publicKey, signer2.publicKey, signer3.publicKey];
+        const threshold = 1;
+        const thresholdBands = [
+            {
+                tokenMint: null,
+                minAmount: new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+                threshold: 3,
+            },
+        ];
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createVault(vaultId, signers, threshold, thresholdBands, new anchor.BN(0))
+            .accounts({
+                creator: creator,
+                vault: vault,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [vaultSolPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("vault_sol"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        const fundTx = new anchor.web3.Transaction().add(
+            SystemProgram.transfer({
+                fromPubkey: provider.wallet.publicKey,
+                toPubkey: vaultSolPda,
+                lamports: LAMPORTS_PER_SOL,
+            })
+        );
+        await provider.sendAndConfirm(fundTx);
+
+        const [proposal] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("transfer_proposal"),
+                vault.toBuffer(),
+                proposalId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        // Above the band: the proposer's approval alone is not enough
+        await program.methods
+            .proposeTransfer(
+                proposalId,
+                recipient.publicKey,
+                new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+                null
+            )
+            .accounts({
+                vault: vault,
+                proposal: proposal,
+                proposer: signer1.publicKey,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([signer1])
+            .rpc();
+
+        try {
+            await program.methods
+                .executeSolTransfer()
+                .accounts({
+                    vault: vault,
+                    proposal: proposal,
+                    vaultSolAccount: vaultSolPda,
+                    recipient: recipient.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            assert.fail("Should have failed with insufficient approvals");
+        } catch (err) {
+            assert.ok(err.toString().includes("InsufficientApprovals"));
+        }
+    });
+
+    it("rejects execution to a recipient other than the approved one", async () => {
+        const creator = provider.wallet.publicKey;
+        const vaultId = new anchor.BN(5);
+        const proposalId = new anchor.BN(1);
+        const signers = [signer1.publicKey, signer2.publicKey];
+        const threshold = 1;
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
+            .accounts({
+                creator: creator,
+                vault: vault,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [vaultSolPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("vault_sol"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        const [proposal] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("transfer_proposal"),
+                vault.toBuffer(),
+                proposalId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .proposeTransfer(
+                proposalId,
+                recipient.publicKey,
+                new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+                null
+            )
+            .accounts({
+                vault: vault,
+                proposal: proposal,
+                proposer: signer1.publicKey,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([signer1])
+            .rpc();
+
+        // Executor swaps in an account the signers never reviewed
+        const attacker = Keypair.generate();
+        try {
+            await program.methods
+                .executeSolTransfer()
+                .accounts({
+                    vault: vault,
+                    proposal: proposal,
+                    vaultSolAccount: vaultSolPda,
+                    recipient: attacker.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            assert.fail("Should have failed with a mismatched recipient");
+        } catch (err) {
+            assert.ok(err.toString().includes("RecipientMismatch"));
+        }
+    });
+
+    it("describes a pending proposal in UI units", async () => {
+        const creator = provider.wallet.publicKey;
+        const vaultId = new anchor.BN(6);
+        const proposalId = new anchor.BN(1);
+        const signers = [signer1.publicKey, signer2.publicKey];
+        const threshold = 2;
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
+            .accounts({
+                creator: creator,
+                vault: vault,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [proposal] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("transfer_proposal"),
+                vault.toBuffer(),
+                proposalId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .proposeTransfer(
+                proposalId,
+                recipient.publicKey,
+                new anchor.BN(1.5 * LAMPORTS_PER_SOL),
+                null
+            )
+            .accounts({
+                vault: vault,
+                proposal: proposal,
+                proposer: signer1.publicKey,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([signer1])
+            .rpc();
+
+        const description = await program.methods
+            .describeProposal()
+            .accountsStrict({
+                vault: vault,
+                proposal: proposal,
+                mint: null,
+            })
+            .view();
+
+        assert.equal(description.uiAmount, "1.5");
+        assert.equal(description.decimals, 9);
+        assert.ok(description.recipient.equals(recipient.publicKey));
+        assert.equal(description.approvals, 1);
+        assert.equal(description.requiredApprovals, 2);
+        assert.ok(description.approvedBy[0].equals(signer1.publicKey));
+    });
+
+    it("blocks execution until the review period has passed", async () => {
+        const creator = provider.wallet.publicKey;
+        const vaultId = new anchor.BN(7);
+        const proposalId = new anchor.BN(1);
+        const signers = [signer1.publicKey, signer2.publicKey];
+        const threshold = 1;
+        const reviewPeriod = new anchor.BN(3600);
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createVault(vaultId, signers, threshold, [], reviewPeriod)
+            .accounts({
+                creator: creator,
+                vault: vault,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [vaultSolPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("vault_sol"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        const [proposal] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("transfer_proposal"),
+                vault.toBuffer(),
+                proposalId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        // The proposer's own approval already meets the threshold
+        await program.methods
+            .proposeTransfer(
+                proposalId,
+                recipient.publicKey,
+                new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+                null
+            )
+            .accounts({
+                vault: vault,
+                proposal: proposal,
+                proposer: signer1.publicKey,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([signer1])
+            .rpc();
+
+        const proposalAccount = await program.account.transferProposal.fetch(proposal);
+        const description = await program.methods
+            .describeProposal()
+            .accountsStrict({
+                vault: vault,
+                proposal: proposal,
+                mint: null,
+            })
+            .view();
+        assert.ok(
+            description.executableAt.eq(proposalAccount.createdAt.add(reviewPeriod))
+        );
+
+        try {
+            await program.methods
+                .executeSolTransfer()
+                .accounts({
+                    vault: vault,
+                    proposal: proposal,
+                    vaultSolAccount: vaultSolPda,
+                    recipient: recipient.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            assert.fail("Should have failed during the review period");
+        } catch (err) {
+            assert.ok(err.toString().includes("ReviewPeriodNotElapsed"));
+        }
+    });
+
+    it("only lets transfers go to allowlisted recipients once enabled", async () => {
+        const creator = provider.wallet.publicKey;
+        const vaultId = new anchor.BN(8);
+        const signers = [signer1.publicKey, signer2.publicKey];
+        const threshold = 2;
+        const outsider = Keypair.generate();
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
+            .accounts({
+                creator: creator,
+                vault: vault,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // Both signers agree on each change
+        const changes = [
+            { addAllowedRecipient: { recipient: recipient.publicKey } },
+            { setAllowlistEnabled: { enabled: true } },
+        ];
+        for (let i = 0; i < changes.length; i++) {
+            const proposalId = new anchor.BN(i + 1);
+            const [configProposal] = PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("config_proposal"),
+                    vault.toBuffer(),
+                    proposalId.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            );
+
+            await program.methods
+                .proposeConfigChange(proposalId, changes[i])
+                .accountsStrict({
+                    vault: vault,
+                    proposal: configProposal,
+                    proposer: signer1.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([signer1])
+                .rpc();
+            await program.methods
+                .approveConfigChange()
+                .accountsStrict({
+                    vault: vault,
+                    proposal: configProposal,
+                    approver: signer2.publicKey,
+                })
+                .signers([signer2])
+                .rpc();
+            await program.methods
+                .executeConfigChange()
+                .accountsStrict({
+                    vault: vault,
+                    proposal: configProposal,
+                })
+                .rpc();
+        }
+
+        const vaultAccount = await program.account.multisigVault.fetch(vault);
+        assert.equal(vaultAccount.allowlistEnabled, true);
+        assert.ok(vaultAccount.allowedRecipients[0].equals(recipient.publicKey));
+
+        const proposalFor = (proposalId: anchor.BN) =>
+            PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("transfer_proposal"),
+                    vault.toBuffer(),
+                    proposalId.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            )[0];
+
+        try {
+            await program.methods
+                .proposeTransfer(
+                    new anchor.BN(1),
+                    outsider.publicKey,
+                    new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+                    null
+                )
+                .accounts({
+                    vault: vault,
+                    proposal: proposalFor(new anchor.BN(1)),
+                    proposer: signer1.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([signer1])
+                .rpc();
+            assert.fail("Should have rejected a recipient off the allowlist");
+        } catch (err) {
+            assert.ok(err.toString().includes("RecipientNotAllowed"));
+        }
+
+        await program.methods
+            .proposeTransfer(
+                new anchor.BN(2),
+                recipient.publicKey,
+                new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+                null
+            )
+            .accounts({
+                vault: vault,
+                proposal: proposalFor(new anchor.BN(2)),
+                proposer: signer1.publicKey,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([signer1])
+            .rpc();
+    });
+
+    it("grants and revokes a bounded token allowance", async () => {
+        const creator = provider.wallet.publicKey;
+        const vaultId = new anchor.BN(9);
+        const signers = [signer1.publicKey, signer2.publicKey];
+        const threshold = 2;
+        const dcaBot = Keypair.generate();
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
+            .accounts({
+                creator: creator,
+                vault: vault,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            creator,
+            null,
+            6
+        );
+        const vaultTokenAccount = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            vault,
+            true
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            vaultTokenAccount.address,
+            creator,
+            1_000_000_000
+        );
+
+        const allowance = new anchor.BN(50_000_000);
+        const actions = [
+            { approve: { delegate: dcaBot.publicKey, amount: allowance } },
+            { revoke: {} },
+        ];
+        for (let i = 0; i < actions.length; i++) {
+            const proposalId = new anchor.BN(i + 1);
+            const [delegateProposal] = PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("delegate_proposal"),
+                    vault.toBuffer(),
+                    proposalId.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            );
+
+            await program.methods
+                .proposeDelegateChange(proposalId, actions[i])
+                .accountsStrict({
+                    vault: vault,
+                    proposal: delegateProposal,
+                    tokenAccount: vaultTokenAccount.address,
+                    proposer: signer1.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([signer1])
+                .rpc();
+
+            const execute = () =>
+                program.methods
+                    .executeDelegateChange()
+                    .accountsStrict({
+                        vault: vault,
+                        vaultPda: vault,
+                        proposal: delegateProposal,
+                        mint: mint,
+                        tokenAccount: vaultTokenAccount.address,
+                        delegate: i === 0 ? dcaBot.publicKey : null,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                    })
+                    .rpc();
+
+            // One approval is not enough
+            try {
+                await execute();
+                assert.fail("Should have required both signers");
+            } catch (err) {
+                assert.ok(err.toString().includes("InsufficientApprovals"));
+            }
+
+            await program.methods
+                .approveDelegateChange()
+                .accountsStrict({
+                    vault: vault,
+                    proposal: delegateProposal,
+                    approver: signer2.publicKey,
+                })
+                .signers([signer2])
+                .rpc();
+            await execute();
+
+            const tokenAccount = await getAccount(
+                provider.connection,
+                vaultTokenAccount.address
+            );
+            if (i === 0) {
+                assert.ok(tokenAccount.delegate.equals(dcaBot.publicKey));
+                assert.equal(tokenAccount.delegatedAmount, BigInt(allowance.toString()));
+            } else {
+                assert.equal(tokenAccount.delegate, null);
+                assert.equal(tokenAccount.delegatedAmount, BigInt(0));
+            }
+        }
+    });
+
+    it("pins program upgrades to the approved buffer", async () => {
+        const creator = provider.wallet.publicKey;
+        const vaultId = new anchor.BN(10);
+        const signers = [signer1.publicKey, signer2.publicKey];
+        const threshold = 2;
+        const bpfLoaderUpgradeable = new PublicKey(
+            "BPFLoaderUpgradeab1e11111111111111111111111"
+        );
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                creator.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
+            .accounts({
+                creator: creator,
+                vault: vault,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const proposalId = new anchor.BN(1);
+        const [upgradeProposal] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("upgrade_proposal"),
+                vault.toBuffer(),
+                proposalId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [programData] = PublicKey.findProgramAddressSync(
+            [program.programId.toBuffer()],
+            bpfLoaderUpgradeable
+        );
+        const buffer = Keypair.generate().publicKey;
+
+        await program.methods
+            .proposeProgramUpgrade(proposalId, program.programId, buffer)
+            .accountsStrict({
+                vault: vault,
+                proposal: upgradeProposal,
+                proposer: signer1.publicKey,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([signer1])
+            .rpc();
+        await program.methods
+            .approveProgramUpgrade()
+            .accountsStrict({
+                vault: vault,
+                proposal: upgradeProposal,
+                approver: signer2.publicKey,
+            })
+            .signers([signer2])
+            .rpc();
+
+        const proposal = await program.account.upgradeProposal.fetch(upgradeProposal);
+        assert.ok(proposal.program.equals(program.programId));
+        assert.ok(proposal.buffer.equals(buffer));
+        assert.deepEqual(proposal.approvals, [true, true]);
+
+        // Any other buffer is rejected, whatever its contents
+        try {
+            await program.methods
+                .executeProgramUpgrade()
+                .accountsStrict({
+                    vault: vault,
+                    vaultPda: vault,
+                    proposal: upgradeProposal,
+                    program: program.programId,
+                    programData: programData,
+                    buffer: Keypair.generate().publicKey,
+                    spill: creator,
+                    rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                    clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+                    bpfLoaderUpgradeableProgram: bpfLoaderUpgradeable,
+                })
+                .rpc();
+            assert.fail("Expected a different buffer to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("BufferMismatch"));
+        }
+    });
+
+    it("administers a staking pool it is the authority of through CPI", async () => {
+        const vaultId = new anchor.BN(11);
+        const signers = [signer1.publicKey, signer2.publicKey];
+        const threshold = 2;
+        const poolCreator = Keypair.generate();
+        const airdropSig = await provider.connection.requestAirdrop(
+            poolCreator.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const [vault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("multisig_vault"),
+                provider.wallet.publicKey.toBuffer(),
+                vaultId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        await program.methods
+            .createVault(vaultId, signers, threshold, [], new anchor.BN(0))
+            .accounts({
+                creator: provider.wallet.publicKey,
+                vault: vault,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [globalConfig] = PublicKey.findProgramAddressSync(
+            [Buffer.from("global-config")],
+            stakingProgram.programId
+        );
+        const [stakingProgramData] = PublicKey.findProgramAddressSync(
+            [stakingProgram.programId.toBuffer()],
+            new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+        );
+        if (!(await provider.connection.getAccountInfo(globalConfig))) {
+            await stakingProgram.methods
+                .initializeConfig(0, provider.wallet.publicKey)
+                .accountsStrict({
+                    admin: provider.wallet.publicKey,
+                    globalConfig: globalConfig,
+                    program: stakingProgram.programId,
+                    programData: stakingProgramData,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+        }
+
+        // A pool whose creator hands it to the vault
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            poolCreator.publicKey
+        );
+        const poolId = new anchor.BN(1);
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                poolCreator.publicKey.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            stakingProgram.programId
+        );
+        const [poolRegistry] = PublicKey.findProgramAddressSync(
+            [Buffer.from("pool-registry"), poolCreator.publicKey.toBuffer()],
+            stakingProgram.programId
+        );
+        const [rewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            stakingProgram.programId
+        );
+        const poolVault = await getAssociatedTokenAddress(mint, pool, true);
+        await stakingProgram.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: poolCreator.publicKey,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistry,
+                poolVault: poolVault,
+                rewardVault: rewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([poolCreator])
+            .rpc();
+        await stakingProgram.methods
+            .setPoolAuthority(vault)
+            .accountsStrict({
+                mint: mint,
+                creator: poolCreator.publicKey,
+                pool: pool,
+                poolVault: poolVault,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([poolCreator])
+            .rpc();
+
+        const proposalId = new anchor.BN(1);
+        const [poolAdminProposal] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool_admin_proposal"),
+                vault.toBuffer(),
+                proposalId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        await program.methods
+            .proposePoolAdmin(proposalId, pool, {
+                changePoolCooldown: { cooldown: new anchor.BN(3600) },
+            })
+            .accountsStrict({
+                vault: vault,
+                proposal: poolAdminProposal,
+                proposer: signer1.publicKey,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([signer1])
+            .rpc();
+
+        const execute = () =>
+            program.methods
+                .executePoolAdmin()
+                .accountsStrict({
+                    vault: vault,
+                    vaultPda: vault,
+                    proposal: poolAdminProposal,
+                    pool: pool,
+                    mint: mint,
+                    poolVault: poolVault,
+                    creatorAta: creatorAta.address,
+                    stakingProgram: stakingProgram.programId,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+
+        // One approval is not enough
+        try {
+            await execute();
+            assert.fail("Should have required both signers");
+        } catch (err) {
+            assert.ok(err.toString().includes("InsufficientApprovals"));
+        }
+
+        await program.methods
+            .approvePoolAdmin()
+            .accountsStrict({
+                vault: vault,
+                proposal: poolAdminProposal,
+                approver: signer2.publicKey,
+            })
+            .signers([signer2])
+            .rpc();
+        await execute();
+
+        const poolAccount = await stakingProgram.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.authority.equals(vault));
+        assert.equal(poolAccount.claimCooldown.toNumber(), 3600);
+
+        const proposal = await program.account.poolAdminProposal.fetch(
+            poolAdminProposal
+        );
+        assert.ok(proposal.executed);
+    });
+});
@@ -1,16 +1,19 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import {
+    approve,
     ASSOCIATED_TOKEN_PROGRAM_ID,
     createMint,
     getAccount,
     getAssociatedTokenAddress,
     getOrCreateAssociatedTokenAccount,
     mintTo,
+    NATIVE_MINT,
     TOKEN_PROGRAM_ID,
 } from "@solana/spl-token";
 import { strict as assert } from "assert";
 import { Staking } from "../target/types/staking";
+import { Vesting } from "../target/types/vesting";
 
 const { SystemProgram, LAMPORTS_PER_SOL, PublicKey, Keypair } = anchor.web3;
 
@@ -20,6 +23,75 @@ describe("staking", () => {
 
     const provider = anchor.getProvider() as anchor.AnchorProvider;
     const program = anchor.workspace.staking as Program<Staking>;
+    const vestingProgram = anchor.workspace.vesting as Program<Vesting>;
+
+    const poolRegistryFor = (creator: anchor.web3.PublicKey) =>
+        PublicKey.findProgramAddressSync(
+            [Buffer.from("pool-registry"), creator.toBuffer()],
+            program.programId
+        )[0];
+    const [globalConfig] = PublicKey.findProgramAddressSync(
+        [Buffer.from("global-config")],
+        program.programId
+    );
+    // Only the upgrade authority, the provider wallet on localnet, can create the config
+    const [programData] = PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+
+    // Cooldowns and liquid redemptions need MIN_STAKE_SLOTS slots after the stake
+    const MIN_STAKE_SLOTS = 2;
+    const waitForStakeToAge = async () => {
+        const target = (await provider.connection.getSlot()) + MIN_STAKE_SLOTS;
+        while ((await provider.connection.getSlot()) < target) {
+            await new Promise((resolve) => setTimeout(resolve, 100));
+        }
+    };
+
+    before(async () => {
+        // The protocol config is a singleton shared by every pool, and by the ico
+        // tests staking through it
+        if (await provider.connection.getAccountInfo(globalConfig)) {
+            return;
+        }
+
+        // Anchor creates the config before checking the other accounts, so the gate is
+        // only observable while the config does not exist yet
+        const stranger = Keypair.generate();
+        const airdropSig = await provider.connection.requestAirdrop(
+            stranger.publicKey,
+            LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+        try {
+            await program.methods
+                .initializeConfig(0, stranger.publicKey)
+                .accountsStrict({
+                    admin: stranger.publicKey,
+                    globalConfig: globalConfig,
+                    program: program.programId,
+                    programData: programData,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([stranger])
+                .rpc();
+            assert.fail("Expected a non upgrade authority to be rejected");
+        } catch (err) {
+            assert.ok(err.toString().includes("UnauthorizedAdmin"));
+        }
+
+        await program.methods
+            .initializeConfig(0, provider.wallet.publicKey)
+            .accountsStrict({
+                admin: provider.wallet.publicKey,
+                globalConfig: globalConfig,
+                program: program.programId,
+                programData: programData,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+    });
 
     it("creates a staking pool", async () => {
         const mint = await createMint(
@@ -62,14 +134,23 @@ describe("staking", () => {
         );
 
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId, initialFunding, claimCooldown)
+            .createPool(poolId, initialFunding, claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -85,17 +166,20 @@ describe("staking", () => {
         assert.ok(poolAccount.claimCooldown.eq(claimCooldown));
         assert.ok(poolAccount.emergencyModeEnabled === false);
 
-        // Verify tokens were transferred to pool vault
-        const [poolVault] = PublicKey.findProgramAddressSync(
-            [
-                Buffer.from("pool"),
-                creator.toBuffer(),
-                poolId.toArrayLike(Buffer, "le", 8),
-            ],
-            program.programId
+        // Verify reward tokens were transferred to the reward vault
+        const vaultAccount = await getAccount(
+            provider.connection,
+            poolRewardVault
         );
-        const vaultAccount = await getAccount(provider.connection, poolVaultAta);
         assert.ok(vaultAccount.amount === BigInt(initialFunding.toString()));
+
+        // The pool is listed in the creator's registry
+        const registry = await program.account.poolRegistry.fetch(
+            poolRegistryFor(creator)
+        );
+        const entry = registry.pools.find((p) => p.pool.equals(pool));
+        assert.ok(entry !== undefined);
+        assert.ok(entry.mint.equals(mint));
     });
 
     it("allows any user to create a pool", async () => {
@@ -136,14 +220,23 @@ describe("staking", () => {
         );
 
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId, new anchor.BN(0), claimCooldown)
+            .createPool(poolId, new anchor.BN(0), claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: user.publicKey,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(user.publicKey),
                 poolVault: poolVaultAta,
-                creatorAta: userAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: userAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -156,6 +249,82 @@ describe("staking", () => {
         assert.ok(poolAccount.claimCooldown.eq(claimCooldown));
     });
 
+    it("keeps creating pools once the creator's registry is full", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        // A fresh creator, so the registry starts empty
+        const creator = Keypair.generate();
+        const airdropSig = await provider.connection.requestAirdrop(
+            creator.publicKey,
+            10 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            creator,
+            mint,
+            creator.publicKey
+        );
+
+        const createPool = async (poolId: anchor.BN) => {
+            const [pool] = PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("pool"),
+                    creator.publicKey.toBuffer(),
+                    poolId.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            );
+            const [poolRewardVault] = PublicKey.findProgramAddressSync(
+                [Buffer.from("reward-vault"), pool.toBuffer()],
+                program.programId
+            );
+            await program.methods
+                .createPool(poolId, new anchor.BN(0), new anchor.BN(0), false)
+                .accountsStrict({
+                    mint: mint,
+                    rewardMint: mint,
+                    creator: creator.publicKey,
+                    globalConfig: globalConfig,
+                    pool: pool,
+                    poolRegistry: poolRegistryFor(creator.publicKey),
+                    poolVault: await getAssociatedTokenAddress(mint, pool, true),
+                    rewardVault: poolRewardVault,
+                    creatorRewardAta: creatorAta.address,
+                    treasury: null,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([creator])
+                .rpc();
+            return pool;
+        };
+
+        // The registry lists up to 64 pools
+        for (let i = 1; i <= 64; i++) {
+            await createPool(new anchor.BN(i));
+        }
+
+        // The 65th pool is created, just not listed
+        const pool = await createPool(new anchor.BN(65));
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.creator.equals(creator.publicKey));
+
+        const registry = await program.account.poolRegistry.fetch(
+            poolRegistryFor(creator.publicKey)
+        );
+        assert.equal(registry.pools.length, 64);
+        assert.ok(registry.pools.every((p) => !p.pool.equals(pool)));
+    });
+
     it("allows pool creator to fund the pool", async () => {
         const mint = await createMint(
             provider.connection,
@@ -198,15 +367,24 @@ describe("staking", () => {
 
         // Create pool with initial funding
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         const initialFunding = new anchor.BN(500 * 10 ** 9);
         await program.methods
-            .createPool(poolId, initialFunding, claimCooldown)
+            .createPool(poolId, initialFunding, claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -218,20 +396,32 @@ describe("staking", () => {
         await program.methods
             .fundPool(additionalFunding)
             .accountsStrict({
-                mint: mint,
+                rewardMint: mint,
                 creator: creator,
                 pool: pool,
-                poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
-                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
                 tokenProgram: TOKEN_PROGRAM_ID,
-                systemProgram: SystemProgram.programId,
             })
             .rpc();
 
         const poolAccount = await program.account.stakingPool.fetch(pool);
         const expectedRewards = initialFunding.add(additionalFunding);
         assert.ok(poolAccount.currentRewards.eq(expectedRewards));
+
+        // Rewards are held apart from the staked principal
+        const rewardVaultAccount = await getAccount(
+            provider.connection,
+            poolRewardVault
+        );
+        assert.ok(
+            rewardVaultAccount.amount === BigInt(expectedRewards.toString())
+        );
+        const poolVaultAccount = await getAccount(
+            provider.connection,
+            poolVaultAta
+        );
+        assert.ok(poolVaultAccount.amount === BigInt(0));
     });
 
     it("allows users to stake tokens in a pool", async () => {
@@ -300,14 +490,23 @@ describe("staking", () => {
 
         // Create pool
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown)
+            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -333,13 +532,20 @@ describe("staking", () => {
             program.programId
         );
 
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
         await program.methods
-            .stake(depositId, stakeAmount)
+            .stake(depositId, stakeAmount, null)
             .accountsStrict({
                 mint: mint,
                 staker: staker.publicKey,
+                depositCounter: depositCounter,
                 deposit: deposit,
                 stakerStats: stakerStats,
+                referralStats: null,
                 pool: pool,
                 poolVault: poolVaultAta,
                 stakerAta: stakerAta.address,
@@ -426,14 +632,23 @@ describe("staking", () => {
 
         // Create pool with rewards
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown)
+            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -459,13 +674,20 @@ describe("staking", () => {
             program.programId
         );
 
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
         await program.methods
-            .stake(depositId, stakeAmount)
+            .stake(depositId, stakeAmount, null)
             .accountsStrict({
                 mint: mint,
                 staker: staker.publicKey,
+                depositCounter: depositCounter,
                 deposit: deposit,
                 stakerStats: stakerStats,
+                referralStats: null,
                 pool: pool,
                 poolVault: poolVaultAta,
                 stakerAta: stakerAta.address,
@@ -477,12 +699,14 @@ describe("staking", () => {
             .rpc();
 
         // Activate cooldown
+        await waitForStakeToAge();
         await program.methods
-            .activateCooldown(depositId)
+            .activateCooldown(depositId, null)
             .accountsStrict({
                 staker: staker.publicKey,
                 deposit: deposit,
                 pool: pool,
+                systemProgram: SystemProgram.programId,
             })
             .signers([staker])
             .rpc();
@@ -505,12 +729,31 @@ describe("staking", () => {
             .unstake(depositId)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 staker: staker.publicKey,
                 deposit: deposit,
                 stakerStats: stakerStats,
+                depositCounter: depositCounter,
                 pool: pool,
                 poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
                 stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -602,14 +845,23 @@ describe("staking", () => {
 
         // Create pool
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown)
+            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -635,13 +887,20 @@ describe("staking", () => {
             program.programId
         );
 
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
         await program.methods
-            .stake(depositId, stakeAmount)
+            .stake(depositId, stakeAmount, null)
             .accountsStrict({
                 mint: mint,
                 staker: staker.publicKey,
+                depositCounter: depositCounter,
                 deposit: deposit,
                 stakerStats: stakerStats,
+                referralStats: null,
                 pool: pool,
                 poolVault: poolVaultAta,
                 stakerAta: stakerAta.address,
@@ -653,12 +912,14 @@ describe("staking", () => {
             .rpc();
 
         // Activate cooldown
+        await waitForStakeToAge();
         await program.methods
-            .activateCooldown(depositId)
+            .activateCooldown(depositId, null)
             .accountsStrict({
                 staker: staker.publicKey,
                 deposit: deposit,
                 pool: pool,
+                systemProgram: SystemProgram.programId,
             })
             .signers([staker])
             .rpc();
@@ -669,12 +930,31 @@ describe("staking", () => {
                 .unstake(depositId)
                 .accountsStrict({
                     mint: mint,
+                    rewardMint: mint,
                     staker: staker.publicKey,
                     deposit: deposit,
                     stakerStats: stakerStats,
+                    depositCounter: depositCounter,
                     pool: pool,
                 poolVault: poolVaultAta,
+                    rewardVault: poolRewardVault,
                 stakerAta: stakerAta.address,
+                destination: null,
+                    stakerRewardAta: stakerAta.address,
+                    globalConfig: globalConfig,
+                    treasuryRewardAta: null,
+                    rewardLockupAuthority: null,
+                    rewardLockupAta: null,
+                    vestingSchedule: null,
+                    vestingVault: null,
+                    vestingProgram: null,
+                    referrerRewardAta: null,
+                    referralStats: null,
+                    receiptMint: null,
+                    stakerReceiptAta: null,
+                    boostNftAta: null,
+                    boostNftMetadata: null,
+                    claimEscrow: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -742,14 +1022,23 @@ describe("staking", () => {
 
         // Create pool
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown)
+            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -841,14 +1130,23 @@ describe("staking", () => {
 
         // Create pool
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown)
+            .createPool(poolId, new anchor.BN(creatorTokens), claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -874,13 +1172,20 @@ describe("staking", () => {
             program.programId
         );
 
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
         await program.methods
-            .stake(depositId, stakeAmount)
+            .stake(depositId, stakeAmount, null)
             .accountsStrict({
                 mint: mint,
                 staker: staker.publicKey,
+                depositCounter: depositCounter,
                 deposit: deposit,
                 stakerStats: stakerStats,
+                referralStats: null,
                 pool: pool,
                 poolVault: poolVaultAta,
                 stakerAta: stakerAta.address,
@@ -919,9 +1224,15 @@ describe("staking", () => {
                 staker: staker.publicKey,
                 deposit: deposit,
                 stakerStats: stakerStats,
+                depositCounter: depositCounter,
                 pool: pool,
                 poolVault: poolVaultAta,
                 stakerAta: stakerAta.address,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                rewardMint: null,
+                rewardVault: null,
+                stakerRewardAta: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -988,15 +1299,24 @@ describe("staking", () => {
 
         // Create pool with rewards
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         const initialRewards = new anchor.BN(creatorTokens);
         await program.methods
-            .createPool(poolId, initialRewards, claimCooldown)
+            .createPool(poolId, initialRewards, claimCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -1027,11 +1347,12 @@ describe("staking", () => {
         await program.methods
             .withdrawRewardsEmergency()
             .accountsStrict({
-                mint: mint,
+                rewardMint: mint,
                 creator: creator,
                 pool: pool,
-                poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                globalConfig: globalConfig,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -1084,14 +1405,23 @@ describe("staking", () => {
 
         // Create pool
         const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId, new anchor.BN(0), initialCooldown)
+            .createPool(poolId, new anchor.BN(0), initialCooldown, false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator,
+                globalConfig: globalConfig,
                 pool: pool,
+                poolRegistry: poolRegistryFor(creator),
                 poolVault: poolVaultAta,
-                creatorAta: creatorAta.address,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -1115,6 +1445,28 @@ describe("staking", () => {
 
         const poolAccount = await program.account.stakingPool.fetch(pool);
         assert.ok(poolAccount.claimCooldown.eq(newCooldown));
+
+        // Negative cooldowns and cooldowns over a year are rejected
+        for (const cooldown of [-1, 365 * 24 * 60 * 60 + 1]) {
+            try {
+                await program.methods
+                    .changePoolCooldown(new anchor.BN(cooldown))
+                    .accountsStrict({
+                        mint: mint,
+                        creator: creator,
+                        pool: pool,
+                        poolVault: poolVaultAta,
+                        creatorAta: creatorAta.address,
+                        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    })
+                    .rpc();
+                assert.fail("Expected an out of range cooldown to fail");
+            } catch (err) {
+                assert.ok(err.toString().includes("InvalidCooldown"));
+            }
+        }
     });
 
     it("supports multiple independent pools", async () => {
@@ -1164,14 +1516,23 @@ describe("staking", () => {
         );
 
         const pool1VaultAta = await getAssociatedTokenAddress(mint, pool1, true);
+        const [pool1RewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool1.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId1, new anchor.BN(0), new anchor.BN(60))
+            .createPool(poolId1, new anchor.BN(0), new anchor.BN(60), false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator1,
+                globalConfig: globalConfig,
                 pool: pool1,
+                poolRegistry: poolRegistryFor(creator1),
                 poolVault: pool1VaultAta,
-                creatorAta: creator1Ata.address,
+                rewardVault: pool1RewardVault,
+                creatorRewardAta: creator1Ata.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -1189,14 +1550,23 @@ describe("staking", () => {
         );
 
         const pool2VaultAta = await getAssociatedTokenAddress(mint, pool2, true);
+        const [pool2RewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool2.toBuffer()],
+            program.programId
+        );
         await program.methods
-            .createPool(poolId2, new anchor.BN(0), new anchor.BN(120))
+            .createPool(poolId2, new anchor.BN(0), new anchor.BN(120), false)
             .accountsStrict({
                 mint: mint,
+                rewardMint: mint,
                 creator: creator2.publicKey,
+                globalConfig: globalConfig,
                 pool: pool2,
+                poolRegistry: poolRegistryFor(creator2.publicKey),
                 poolVault: pool2VaultAta,
-                creatorAta: creator2Ata.address,
+                rewardVault: pool2RewardVault,
+                creatorRewardAta: creator2Ata.address,
+                treasury: null,
                 associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 systemProgram: SystemProgram.programId,
@@ -1216,5 +1586,7059 @@ describe("staking", () => {
         assert.ok(pool2Account.poolId.eq(poolId2));
         assert.ok(pool2Account.claimCooldown.eq(new anchor.BN(120)));
     });
-});
 
+    it("pays rewards in a separate reward mint", async () => {
+        const stakeMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+        const rewardMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(13);
+        const claimCooldown = new anchor.BN(0);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorRewardAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            rewardMint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            stakeMint,
+            staker.publicKey
+        );
+
+        const rewardTokens = 1000 * 10 ** 6;
+        const stakerTokens = 500 * 10 ** 9;
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            rewardMint,
+            creatorRewardAta.address,
+            provider.wallet.publicKey,
+            rewardTokens
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            stakeMint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakerTokens
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(
+            stakeMint,
+            pool,
+            true
+        );
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(rewardTokens), claimCooldown, false)
+            .accountsStrict({
+                mint: stakeMint,
+                rewardMint: rewardMint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorRewardAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.mint.equals(stakeMint));
+        assert.ok(poolAccount.rewardMint.equals(rewardMint));
+
+        const depositId = new anchor.BN(1);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: stakeMint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const stakerRewardAta = await getAssociatedTokenAddress(
+            rewardMint,
+            staker.publicKey
+        );
+
+        await program.methods
+            .unstake(depositId)
+            .accountsStrict({
+                mint: stakeMint,
+                rewardMint: rewardMint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerRewardAta,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // Sole staker receives the whole reward pot in the reward mint
+        const stakerRewardAccount = await getAccount(
+            provider.connection,
+            stakerRewardAta
+        );
+        assert.ok(stakerRewardAccount.amount === BigInt(rewardTokens));
+
+        const stakerAccount = await getAccount(
+            provider.connection,
+            stakerAta.address
+        );
+        assert.ok(stakerAccount.amount === BigInt(stakerTokens));
+    });
+
+    it("distributes extra reward tokens through the accumulator", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+        const extraMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(14);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const creatorExtraAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            extraMint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+
+        const extraRewards = 100 * 10 ** 6;
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            extraMint,
+            creatorExtraAta.address,
+            provider.wallet.publicKey,
+            extraRewards
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            500 * 10 ** 9
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+        const [rewardTokenVault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("reward-token-vault"),
+                pool.toBuffer(),
+                extraMint.toBuffer(),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(60), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        await program.methods
+            .addRewardToken()
+            .accountsStrict({
+                rewardTokenMint: extraMint,
+                creator: creator,
+                pool: pool,
+                rewardTokenVault: rewardTokenVault,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, new anchor.BN(200 * 10 ** 9), null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await program.methods
+            .fundRewardToken(new anchor.BN(extraRewards))
+            .accountsStrict({
+                rewardTokenMint: extraMint,
+                creator: creator,
+                pool: pool,
+                rewardTokenVault: rewardTokenVault,
+                creatorRewardTokenAta: creatorExtraAta.address,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        // Views report what the claim below will pay out
+        const pending = await program.methods
+            .getPendingRewards(depositId)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+            })
+            .view();
+        assert.ok(pending.baseRewards.eq(new anchor.BN(0)));
+        assert.ok(pending.rewardTokens[0].mint.equals(extraMint));
+        assert.ok(pending.rewardTokens[0].amount.eq(new anchor.BN(extraRewards)));
+
+        const stats = await program.methods
+            .getPoolStats()
+            .accountsStrict({
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+            })
+            .view();
+        assert.ok(stats.currentTokensStaked.eq(new anchor.BN(200 * 10 ** 9)));
+        assert.ok(stats.poolVaultBalance.eq(new anchor.BN(200 * 10 ** 9)));
+        assert.equal(stats.rewardTokenCount, 1);
+
+        const stakerExtraAta = await getAssociatedTokenAddress(
+            extraMint,
+            staker.publicKey
+        );
+
+        await program.methods
+            .claimRewardToken(depositId)
+            .accountsStrict({
+                rewardTokenMint: extraMint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                rewardTokenVault: rewardTokenVault,
+                stakerRewardTokenAta: stakerExtraAta,
+                globalConfig: globalConfig,
+                treasuryRewardTokenAta: null,
+                referrerRewardTokenAta: null,
+                referralStats: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // Sole staker earns the whole extra funding
+        const stakerExtraAccount = await getAccount(
+            provider.connection,
+            stakerExtraAta
+        );
+        assert.ok(stakerExtraAccount.amount === BigInt(extraRewards));
+    });
+
+    it("assigns deposit ids from the per-pool counter", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(15);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            500 * 10 ** 9
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(60), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositAddress = (id: anchor.BN) =>
+            PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("deposit"),
+                    staker.publicKey.toBuffer(),
+                    pool.toBuffer(),
+                    id.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            )[0];
+        const stakeAccounts = (id: anchor.BN) => ({
+            mint: mint,
+            staker: staker.publicKey,
+            depositCounter: depositCounter,
+            deposit: depositAddress(id),
+            stakerStats: stakerStats,
+            referralStats: null,
+            pool: pool,
+            poolVault: poolVaultAta,
+            stakerAta: stakerAta.address,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+        });
+
+        // The first auto-assigned id is 0
+        const stakeAmount = new anchor.BN(100 * 10 ** 9);
+        const firstId = await program.methods
+            .stake(null, stakeAmount, null)
+            .accountsStrict(stakeAccounts(new anchor.BN(0)))
+            .signers([staker])
+            .view();
+        assert.ok(firstId.eq(new anchor.BN(0)));
+
+        await program.methods
+            .stake(null, stakeAmount, null)
+            .accountsStrict(stakeAccounts(new anchor.BN(0)))
+            .signers([staker])
+            .rpc();
+
+        // Explicit ids may skip ahead of the counter
+        await program.methods
+            .stake(new anchor.BN(5), stakeAmount, null)
+            .accountsStrict(stakeAccounts(new anchor.BN(5)))
+            .signers([staker])
+            .rpc();
+
+        let counterAccount = await program.account.depositCounter.fetch(depositCounter);
+        assert.ok(counterAccount.nextDepositId.eq(new anchor.BN(6)));
+
+        // ...but never fall behind it
+        try {
+            await program.methods
+                .stake(new anchor.BN(3), stakeAmount, null)
+                .accountsStrict(stakeAccounts(new anchor.BN(3)))
+                .signers([staker])
+                .rpc();
+            assert.fail("Expected stake with a stale deposit id to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("DepositIdAlreadyUsed"));
+        }
+
+        await program.methods
+            .stake(null, stakeAmount, null)
+            .accountsStrict(stakeAccounts(new anchor.BN(6)))
+            .signers([staker])
+            .rpc();
+
+        const depositAccount = await program.account.stakerDeposit.fetch(
+            depositAddress(new anchor.BN(6))
+        );
+        assert.ok(depositAccount.depositId.eq(new anchor.BN(6)));
+        counterAccount = await program.account.depositCounter.fetch(depositCounter);
+        assert.ok(counterAccount.nextDepositId.eq(new anchor.BN(7)));
+        // Three deposits opened, whatever ids they got
+        assert.ok(counterAccount.depositCount.eqn(3));
+    });
+
+    it("cancels an activated cooldown", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(16);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            200 * 10 ** 9
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(60), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, new anchor.BN(200 * 10 ** 9), null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await program.methods
+            .cancelCooldown(depositId)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.isCooldownActive === false);
+
+        // Cancelling twice is rejected
+        try {
+            await program.methods
+                .cancelCooldown(depositId)
+                .accountsStrict({
+                    staker: staker.publicKey,
+                    deposit: deposit,
+                    pool: pool,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+            assert.fail("Expected cancel without an active cooldown to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("ClaimCooldownNotActive"));
+        }
+    });
+
+    it("allows pool creator to disable emergency mode", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const poolId = new anchor.BN(17);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(60), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const updatePoolAccounts = {
+            mint: mint,
+            creator: creator,
+            pool: pool,
+            poolVault: poolVaultAta,
+            creatorAta: creatorAta.address,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+        };
+
+        await program.methods
+            .enableEmergencyMode()
+            .accountsStrict(updatePoolAccounts)
+            .rpc();
+
+        await program.methods
+            .disableEmergencyMode()
+            .accountsStrict(updatePoolAccounts)
+            .rpc();
+
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.emergencyModeEnabled === false);
+
+        // Disabling again is rejected
+        try {
+            await program.methods
+                .disableEmergencyMode()
+                .accountsStrict(updatePoolAccounts)
+                .rpc();
+            assert.fail("Expected disabling an inactive emergency mode to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("EmergencyModeNotEnabled"));
+        }
+    });
+
+    it("stakes through a relayer using a delegate approval", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const relayer = provider.wallet.publicKey;
+        // The staker holds no SOL at all
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(18);
+        const stakeAmount = new anchor.BN(100 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(60), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // The staker only signs the approval, the relayer pays for it
+        await approve(
+            provider.connection,
+            provider.wallet.payer,
+            stakerAta.address,
+            pool,
+            staker,
+            BigInt(stakeAmount.toString())
+        );
+
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stakeWithDelegate(stakeAmount)
+            .accountsStrict({
+                mint: mint,
+                relayer: relayer,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.tokensDeposited.eq(stakeAmount));
+
+        const poolVaultAccount = await getAccount(provider.connection, poolVaultAta);
+        assert.ok(poolVaultAccount.amount === BigInt(stakeAmount.toString()));
+
+        const stakerLamports = await provider.connection.getBalance(staker.publicKey);
+        assert.equal(stakerLamports, 0);
+    });
+
+    it("sends the reward fee to the treasury on unstake", async () => {
+        const stakeMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+        const rewardMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const treasury = Keypair.generate();
+        const poolId = new anchor.BN(19);
+        const feeBps = 500; // 5%
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorRewardAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            rewardMint,
+            creator
+        );
+        const treasuryRewardAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            rewardMint,
+            treasury.publicKey
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            stakeMint,
+            staker.publicKey
+        );
+
+        const rewardTokens = 1000 * 10 ** 6;
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            rewardMint,
+            creatorRewardAta.address,
+            provider.wallet.publicKey,
+            rewardTokens
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            stakeMint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            200 * 10 ** 9
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(
+            stakeMint,
+            pool,
+            true
+        );
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        // Pools opting in lock the protocol default fee at creation
+        await program.methods
+            .updateConfig(feeBps, treasury.publicKey, false)
+            .accountsStrict({
+                admin: provider.wallet.publicKey,
+                globalConfig: globalConfig,
+            })
+            .rpc();
+
+        await program.methods
+            .createPool(
+                poolId,
+                new anchor.BN(rewardTokens),
+                new anchor.BN(0),
+                true
+            )
+            .accountsStrict({
+                mint: stakeMint,
+                rewardMint: rewardMint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorRewardAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, new anchor.BN(200 * 10 ** 9), null)
+            .accountsStrict({
+                mint: stakeMint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const stakerRewardAta = await getAssociatedTokenAddress(
+            rewardMint,
+            staker.publicKey
+        );
+
+        await program.methods
+            .unstake(depositId)
+            .accountsStrict({
+                mint: stakeMint,
+                rewardMint: rewardMint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerRewardAta,
+                globalConfig: globalConfig,
+                treasuryRewardAta: treasuryRewardAta.address,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // Sole staker earns all rewards, minus the 5% fee
+        const treasuryAccount = await getAccount(
+            provider.connection,
+            treasuryRewardAta.address
+        );
+        assert.ok(treasuryAccount.amount === BigInt(50 * 10 ** 6));
+        const stakerRewardAccount = await getAccount(
+            provider.connection,
+            stakerRewardAta
+        );
+        assert.ok(stakerRewardAccount.amount === BigInt(950 * 10 ** 6));
+    });
+
+    it("hands over the protocol admin in two steps", async () => {
+        const newAdmin = Keypair.generate();
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            newAdmin.publicKey,
+            LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        await program.methods
+            .transferAdmin(newAdmin.publicKey)
+            .accountsStrict({
+                admin: provider.wallet.publicKey,
+                globalConfig: globalConfig,
+            })
+            .rpc();
+
+        // Nominating alone does not hand over control
+        let config = await program.account.globalConfig.fetch(globalConfig);
+        assert.ok(config.admin.equals(provider.wallet.publicKey));
+        assert.ok(config.pendingAdmin.equals(newAdmin.publicKey));
+
+        await program.methods
+            .acceptAdmin()
+            .accountsStrict({
+                newAdmin: newAdmin.publicKey,
+                globalConfig: globalConfig,
+            })
+            .signers([newAdmin])
+            .rpc();
+
+        config = await program.account.globalConfig.fetch(globalConfig);
+        assert.ok(config.admin.equals(newAdmin.publicKey));
+        assert.ok(config.pendingAdmin === null);
+
+        // The previous admin lost access
+        try {
+            await program.methods
+                .updateConfig(0, provider.wallet.publicKey, true)
+                .accountsStrict({
+                    admin: provider.wallet.publicKey,
+                    globalConfig: globalConfig,
+                })
+                .rpc();
+            assert.fail("Expected the previous admin to be rejected");
+        } catch (err) {
+            assert.ok(err.toString().includes("UnauthorizedAdmin"));
+        }
+
+        // Hand control back so the config stays usable
+        await program.methods
+            .transferAdmin(provider.wallet.publicKey)
+            .accountsStrict({
+                admin: newAdmin.publicKey,
+                globalConfig: globalConfig,
+            })
+            .signers([newAdmin])
+            .rpc();
+        await program.methods
+            .acceptAdmin()
+            .accountsStrict({
+                newAdmin: provider.wallet.publicKey,
+                globalConfig: globalConfig,
+            })
+            .rpc();
+    });
+
+    it("transfers a deposit to a new owner", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const newOwner = Keypair.generate();
+        const poolId = new anchor.BN(20);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(60), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositAddress = (owner: anchor.web3.PublicKey, id: anchor.BN) =>
+            PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("deposit"),
+                    owner.toBuffer(),
+                    pool.toBuffer(),
+                    id.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            )[0];
+        const counterAddress = (owner: anchor.web3.PublicKey) =>
+            PublicKey.findProgramAddressSync(
+                [Buffer.from("deposit-counter"), owner.toBuffer(), pool.toBuffer()],
+                program.programId
+            )[0];
+        const statsAddress = (owner: anchor.web3.PublicKey) =>
+            PublicKey.findProgramAddressSync(
+                [Buffer.from("staker-stats"), owner.toBuffer()],
+                program.programId
+            )[0];
+
+        const depositId = new anchor.BN(1);
+        const deposit = depositAddress(staker.publicKey, depositId);
+
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: counterAddress(staker.publicKey),
+                deposit: deposit,
+                stakerStats: statsAddress(staker.publicKey),
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // The new owner has no deposits yet, so the moved deposit gets id 0
+        const newDeposit = depositAddress(newOwner.publicKey, new anchor.BN(0));
+
+        await program.methods
+            .transferDeposit(depositId)
+            .accountsStrict({
+                staker: staker.publicKey,
+                newOwner: newOwner.publicKey,
+                deposit: deposit,
+                newDepositCounter: counterAddress(newOwner.publicKey),
+                newDeposit: newDeposit,
+                stakerStats: statsAddress(staker.publicKey),
+                depositCounter: counterAddress(staker.publicKey),
+                newOwnerStats: statsAddress(newOwner.publicKey),
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const newDepositAccount = await program.account.stakerDeposit.fetch(
+            newDeposit
+        );
+        assert.ok(newDepositAccount.tokensDeposited.eq(stakeAmount));
+        assert.ok(newDepositAccount.isWithdrawn === false);
+
+        const oldDepositInfo = await provider.connection.getAccountInfo(deposit);
+        assert.ok(oldDepositInfo === null);
+
+        const newOwnerStats = await program.account.stakerStats.fetch(
+            statsAddress(newOwner.publicKey)
+        );
+        assert.ok(newOwnerStats.totalStaked.eq(stakeAmount));
+
+        // The open deposit moved to the new owner's count in the pool
+        const oldCounter = await program.account.depositCounter.fetch(
+            counterAddress(staker.publicKey)
+        );
+        assert.ok(oldCounter.openDeposits.eqn(0));
+        const newCounter = await program.account.depositCounter.fetch(
+            counterAddress(newOwner.publicKey)
+        );
+        assert.ok(newCounter.openDeposits.eqn(1));
+    });
+
+    it("lets anyone crank a scheduled auto-unstake", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const cranker = Keypair.generate();
+        const poolId = new anchor.BN(21);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        for (const account of [staker, cranker]) {
+            const airdropSig = await provider.connection.requestAirdrop(
+                account.publicKey,
+                2 * LAMPORTS_PER_SOL
+            );
+            await provider.connection.confirmTransaction(airdropSig);
+        }
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // With a zero cooldown the deposit unlocks right away
+        const { unlockTimestamp } = await program.account.stakerDeposit.fetch(deposit);
+        await program.methods
+            .setAutoUnstake(depositId, unlockTimestamp)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await program.methods
+            .crankAutoUnstake(depositId)
+            .accountsStrict({
+                cranker: cranker.publicKey,
+                staker: staker.publicKey,
+                mint: mint,
+                rewardMint: mint,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                claimEscrow: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([cranker])
+            .rpc();
+
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.isWithdrawn === true);
+        assert.ok(depositAccount.autoUnstakeAt.eqn(0));
+
+        const stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(stakerBalance.amount.toString(), stakeAmount.toString());
+    });
+
+    it("requires burning the stake receipt to unstake a tokenized deposit", async () => {
+        const tokenMetadataProgram = new PublicKey(
+            "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+        );
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(22);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const [receiptMint] = PublicKey.findProgramAddressSync(
+            [Buffer.from("receipt-mint"), deposit.toBuffer()],
+            program.programId
+        );
+        const [metadata] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("metadata"),
+                tokenMetadataProgram.toBuffer(),
+                receiptMint.toBuffer(),
+            ],
+            tokenMetadataProgram
+        );
+        const stakerReceiptAta = await getAssociatedTokenAddress(
+            receiptMint,
+            staker.publicKey
+        );
+
+        // Stake and tokenize the deposit in one transaction
+        const mintReceiptIx = await program.methods
+            .mintStakeReceipt(depositId, "Stake Receipt", "STAKE", "")
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                receiptMint: receiptMint,
+                stakerReceiptAta: stakerReceiptAta,
+                metadata: metadata,
+                tokenMetadataProgram: tokenMetadataProgram,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            })
+            .instruction();
+
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .postInstructions([mintReceiptIx])
+            .signers([staker])
+            .rpc();
+
+        const receiptBalance = await getAccount(provider.connection, stakerReceiptAta);
+        assert.equal(receiptBalance.amount.toString(), "1");
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const unstakeAccounts = {
+            mint: mint,
+            rewardMint: mint,
+            staker: staker.publicKey,
+            deposit: deposit,
+            stakerStats: stakerStats,
+            depositCounter: depositCounter,
+            pool: pool,
+            poolVault: poolVaultAta,
+            rewardVault: poolRewardVault,
+            stakerAta: stakerAta.address,
+            destination: null,
+            stakerRewardAta: stakerAta.address,
+            globalConfig: globalConfig,
+            treasuryRewardAta: null,
+            rewardLockupAuthority: null,
+            rewardLockupAta: null,
+            vestingSchedule: null,
+            vestingVault: null,
+            vestingProgram: null,
+            referrerRewardAta: null,
+            referralStats: null,
+            receiptMint: null,
+            stakerReceiptAta: null,
+            boostNftAta: null,
+            boostNftMetadata: null,
+            claimEscrow: null,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+        };
+
+        try {
+            await program.methods
+                .unstake(depositId)
+                .accountsStrict(unstakeAccounts)
+                .signers([staker])
+                .rpc();
+            assert.fail("Unstake without the receipt should fail");
+        } catch (err) {
+            assert.include(err.toString(), "MissingStakeReceipt");
+        }
+
+        await program.methods
+            .unstake(depositId)
+            .accountsStrict({
+                ...unstakeAccounts,
+                receiptMint: receiptMint,
+                stakerReceiptAta: stakerReceiptAta,
+            })
+            .signers([staker])
+            .rpc();
+
+        const receiptAfter = await getAccount(provider.connection, stakerReceiptAta);
+        assert.equal(receiptAfter.amount.toString(), "0");
+
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.isWithdrawn === true);
+    });
+
+    it("pays the referrer a share of the staker's rewards", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const referrer = Keypair.generate();
+        const poolId = new anchor.BN(23);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        const referrerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            referrer.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // 10% of the staker's rewards go to the referrer
+        await program.methods
+            .changeReferralShare(1_000)
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const [referralStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("referral-stats"), referrer.publicKey.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, stakeAmount, referrer.publicKey)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: referralStats,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await program.methods
+            .unstake(depositId)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: referrerAta.address,
+                referralStats: referralStats,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const referrerBalance = await getAccount(
+            provider.connection,
+            referrerAta.address
+        );
+        assert.equal(referrerBalance.amount.toString(), (10 * 10 ** 9).toString());
+
+        const stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(stakerBalance.amount.toString(), (290 * 10 ** 9).toString());
+
+        const stats = await program.account.referralStats.fetch(referralStats);
+        assert.ok(stats.referrer.equals(referrer.publicKey));
+        assert.ok(stats.referredDeposits.eqn(1));
+        assert.ok(stats.referredStake.eq(stakeAmount));
+        assert.ok(stats.payouts.eqn(1));
+    });
+
+    it("tracks vote-escrow weight per deposit, staker and pool", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(24);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+        // A one year lock is a quarter of the maximum vote lock
+        const claimCooldown = new anchor.BN(365 * 24 * 60 * 60);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), claimCooldown, false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const expectedWeight = stakeAmount.divn(4);
+
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.votingWeight.eq(expectedWeight));
+
+        const votingPower = await program.methods
+            .getVotingPower()
+            .accountsStrict({
+                staker: staker.publicKey,
+                stakerStats: stakerStats,
+                pool: pool,
+            })
+            .view();
+        assert.ok(votingPower.stakerVotingWeight.eq(expectedWeight));
+        assert.ok(votingPower.stakerTotalStaked.eq(stakeAmount));
+        assert.ok(votingPower.poolVotingWeight.eq(expectedWeight));
+    });
+
+    it("lets the pool creator slash a deposit up to the pool cap", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(25);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(60), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // Up to 10% of a deposit per slash
+        await program.methods
+            .changeMaxSlash(1_000)
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const slashAccounts = {
+            mint: mint,
+            creator: creator,
+            staker: staker.publicKey,
+            deposit: deposit,
+            stakerStats: stakerStats,
+            depositCounter: depositCounter,
+            pool: pool,
+            poolVault: poolVaultAta,
+            rewardVault: poolRewardVault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        };
+
+        try {
+            await program.methods
+                .slash(depositId, 1_500)
+                .accountsStrict(slashAccounts)
+                .rpc();
+            assert.fail("Slash above the cap should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidSlashBps");
+        }
+
+        await program.methods
+            .slash(depositId, 500)
+            .accountsStrict(slashAccounts)
+            .rpc();
+
+        const slashed = new anchor.BN(10 * 10 ** 9);
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.tokensDeposited.eq(stakeAmount.sub(slashed)));
+
+        const counterAccount = await program.account.depositCounter.fetch(depositCounter);
+        assert.ok(counterAccount.staked.eq(stakeAmount.sub(slashed)));
+
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.currentTokensStaked.eq(stakeAmount.sub(slashed)));
+        assert.ok(poolAccount.currentRewards.eq(slashed));
+
+        const rewardVault = await getAccount(provider.connection, poolRewardVault);
+        assert.equal(rewardVault.amount.toString(), slashed.toString());
+    });
+
+    it("locks reward payouts above the pool threshold into a vesting schedule", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(26);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // Anything above 50 tokens vests: half after a one minute cliff, the rest a minute later
+        await program.methods
+            .configureRewardLockup(
+                new anchor.BN(50 * 10 ** 9),
+                new anchor.BN(60),
+                new anchor.BN(60),
+                50
+            )
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const [rewardLockupAuthority] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-lockup"), deposit.toBuffer()],
+            program.programId
+        );
+        const [vestingSchedule] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("vesting-schedule"),
+                rewardLockupAuthority.toBuffer(),
+                staker.publicKey.toBuffer(),
+            ],
+            vestingProgram.programId
+        );
+        const [vestingVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("vault"), vestingSchedule.toBuffer()],
+            vestingProgram.programId
+        );
+
+        await program.methods
+            .unstake(depositId)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: rewardLockupAuthority,
+                rewardLockupAta: await getAssociatedTokenAddress(
+                    mint,
+                    rewardLockupAuthority,
+                    true
+                ),
+                vestingSchedule: vestingSchedule,
+                vestingVault: vestingVault,
+                vestingProgram: vestingProgram.programId,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // Only the principal is paid out directly
+        const stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(stakerBalance.amount.toString(), stakeAmount.toString());
+
+        const schedule = await vestingProgram.account.vestingSchedule.fetch(vestingSchedule);
+        assert.ok(schedule.beneficiary.equals(staker.publicKey));
+        assert.ok(schedule.totalAmount.eq(rewardAmount));
+        assert.equal(schedule.revocable, false);
+
+        const vaultBalance = await getAccount(provider.connection, vestingVault);
+        assert.equal(vaultBalance.amount.toString(), rewardAmount.toString());
+    });
+
+    it("drips funded rewards in linearly between start and end", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const poolId = new anchor.BN(27);
+        const dripAmount = new anchor.BN(120 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            2 * dripAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // A two hour drip that is about half way through
+        const now = Math.floor(Date.now() / 1000);
+        const fundDripAccounts = {
+            rewardMint: mint,
+            creator: creator,
+            pool: pool,
+            rewardVault: poolRewardVault,
+            creatorRewardAta: creatorAta.address,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        };
+        await program.methods
+            .fundPoolDrip(dripAmount, new anchor.BN(now - 3600), new anchor.BN(now + 3600))
+            .accountsStrict(fundDripAccounts)
+            .rpc();
+
+        const stats = await program.methods
+            .getPoolStats()
+            .accountsStrict({
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+            })
+            .view();
+        assert.ok(stats.currentRewards.gtn(0));
+        assert.ok(stats.currentRewards.lt(dripAmount));
+        assert.ok(stats.currentRewards.add(stats.drippingRewards).eq(dripAmount));
+        assert.ok(stats.rewardVaultBalance.eq(dripAmount));
+
+        // Anyone can roll the drip forward into the stored pool state
+        await program.methods
+            .updatePool()
+            .accountsStrict({ pool: pool })
+            .rpc();
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.currentRewards.gte(stats.currentRewards));
+        assert.ok(poolAccount.rewardDrip.released.eq(poolAccount.currentRewards));
+
+        // A new tranche has to wait for the current one to finish
+        try {
+            await program.methods
+                .fundPoolDrip(dripAmount, new anchor.BN(now), new anchor.BN(now + 3600))
+                .accountsStrict(fundDripAccounts)
+                .rpc();
+            assert.fail("Expected funding over a running drip to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("RewardDripInProgress"));
+        }
+
+        // Extending cannot shorten the campaign
+        try {
+            await program.methods
+                .extendRewards(new anchor.BN(now + 1800), dripAmount)
+                .accountsStrict(fundDripAccounts)
+                .rpc();
+            assert.fail("Expected shortening the drip to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("InvalidRewardDrip"));
+        }
+
+        // Extending tops up the drip and spreads what is left until the new end
+        await program.methods
+            .extendRewards(new anchor.BN(now + 7200), dripAmount)
+            .accountsStrict(fundDripAccounts)
+            .rpc();
+        const extendedPool = await program.account.stakingPool.fetch(pool);
+        assert.ok(extendedPool.rewardDrip.end.eq(new anchor.BN(now + 7200)));
+        assert.ok(extendedPool.rewardDrip.released.eqn(0));
+        assert.ok(
+            extendedPool.currentRewards
+                .add(extendedPool.rewardDrip.amount)
+                .eq(dripAmount.muln(2))
+        );
+    });
+
+    it("rejects stakes below the pool minimum", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(28);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        // Deposits have to be worth at least 250 tokens
+        await program.methods
+            .changeMinStakeAmount(new anchor.BN(250 * 10 ** 9))
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        try {
+            await program.methods
+                .stake(depositId, stakeAmount, null)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: depositCounter,
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    referralStats: null,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+            assert.fail("Expected a stake below the minimum to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("StakeBelowMinimum"));
+        }
+
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.minStakeAmount.eq(new anchor.BN(250 * 10 ** 9)));
+        assert.ok(poolAccount.currentTokensStaked.eqn(0));
+    });
+
+    it("rejects a pool vault that is not the pool's ATA", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(29);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const depositId = new anchor.BN(1);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+
+        // The staker's own token account passed off as the pool vault
+        try {
+            await program.methods
+                .stake(depositId, stakeAmount, null)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: depositCounter,
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    referralStats: null,
+                    pool: pool,
+                    poolVault: stakerAta.address,
+                    stakerAta: stakerAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+            assert.fail("Expected a foreign pool vault to be rejected");
+        } catch (err) {
+            assert.ok(err.toString().includes("Constraint"));
+        }
+    });
+
+    it("reserves rewards for matured cooldowns when emergency mode is enabled", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(30);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositAddress = (id: anchor.BN) =>
+            PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("deposit"),
+                    staker.publicKey.toBuffer(),
+                    pool.toBuffer(),
+                    id.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            )[0];
+
+        // Two deposits of half the stake each, only the first one in cooldown
+        const half = stakeAmount.divn(2);
+        for (const id of [new anchor.BN(0), new anchor.BN(1)]) {
+            await program.methods
+                .stake(id, half, null)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: depositCounter,
+                    deposit: depositAddress(id),
+                    stakerStats: stakerStats,
+                    referralStats: null,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+        }
+        const maturedDepositId = new anchor.BN(0);
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(maturedDepositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: depositAddress(maturedDepositId),
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await program.methods
+            .enableEmergencyMode()
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // Half the stake was in cooldown, so half the rewards stay with the stakers
+        const reserved = rewardAmount.divn(2);
+        const creatorBalanceBefore = await getAccount(
+            provider.connection,
+            creatorAta.address
+        );
+        await program.methods
+            .withdrawRewardsEmergency()
+            .accountsStrict({
+                rewardMint: mint,
+                creator: creator,
+                pool: pool,
+                globalConfig: globalConfig,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+        const creatorBalanceAfter = await getAccount(
+            provider.connection,
+            creatorAta.address
+        );
+        assert.equal(
+            (creatorBalanceAfter.amount - creatorBalanceBefore.amount).toString(),
+            rewardAmount.sub(reserved).toString()
+        );
+
+        const stakerBalanceBefore = await getAccount(provider.connection, stakerAta.address);
+        await program.methods
+            .unstakeEmergency(maturedDepositId)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                deposit: depositAddress(maturedDepositId),
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                rewardMint: mint,
+                rewardVault: poolRewardVault,
+                stakerRewardAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        const stakerBalanceAfter = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(
+            (stakerBalanceAfter.amount - stakerBalanceBefore.amount).toString(),
+            half.add(reserved).toString()
+        );
+
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.emergencyReserve.reserved.eqn(0));
+        assert.ok(poolAccount.currentRewards.eqn(0));
+    });
+
+    it("unstakes several deposits with a single payout", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(31);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositAddress = (id: anchor.BN) =>
+            PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("deposit"),
+                    staker.publicKey.toBuffer(),
+                    pool.toBuffer(),
+                    id.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            )[0];
+
+        // Two deposits of half the stake each
+        const half = stakeAmount.divn(2);
+        for (const id of [new anchor.BN(0), new anchor.BN(1)]) {
+            await program.methods
+                .stake(id, half, null)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: depositCounter,
+                    deposit: depositAddress(id),
+                    stakerStats: stakerStats,
+                    referralStats: null,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+        }
+        const depositIds = [new anchor.BN(0), new anchor.BN(1)];
+        await waitForStakeToAge();
+        for (const id of depositIds) {
+            await program.methods
+                .activateCooldown(id, null)
+                .accountsStrict({
+                    staker: staker.publicKey,
+                    deposit: depositAddress(id),
+                    pool: pool,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+        }
+
+        const stakerBalanceBefore = await getAccount(provider.connection, stakerAta.address);
+        await program.methods
+            .unstakeMany()
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                staker: staker.publicKey,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+                devClock: null,
+            })
+            .remainingAccounts(
+                depositIds.map((id) => ({
+                    pubkey: depositAddress(id),
+                    isWritable: true,
+                    isSigner: false,
+                }))
+            )
+            .signers([staker])
+            .rpc();
+
+        // Both deposits are out, with all the rewards of the pool
+        const stakerBalanceAfter = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(
+            (stakerBalanceAfter.amount - stakerBalanceBefore.amount).toString(),
+            stakeAmount.add(rewardAmount).toString()
+        );
+        for (const id of depositIds) {
+            const depositAccount = await program.account.stakerDeposit.fetch(depositAddress(id));
+            assert.ok(depositAccount.isWithdrawn === true);
+        }
+
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.currentTokensStaked.eqn(0));
+        assert.ok(poolAccount.currentRewards.eqn(0));
+    });
+
+    it("stakes and unstakes native SOL", async () => {
+        const rewardMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(32);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(LAMPORTS_PER_SOL);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            3 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorRewardAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            rewardMint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            rewardMint,
+            creatorRewardAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(NATIVE_MINT, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: NATIVE_MINT,
+                rewardMint: rewardMint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorRewardAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        // The staker has no wrapped SOL, the lamports go straight to the vault
+        await program.methods
+            .stakeSol(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: NATIVE_MINT,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const poolVault = await getAccount(provider.connection, poolVaultAta);
+        assert.equal(poolVault.amount.toString(), stakeAmount.toString());
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        const stakerWsolAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            NATIVE_MINT,
+            staker.publicKey
+        );
+        const stakerRewardAta = await getAssociatedTokenAddress(rewardMint, staker.publicKey);
+        const lamportsBefore = await provider.connection.getBalance(staker.publicKey);
+        await program.methods
+            .unstakeSol(depositId)
+            .accountsStrict({
+                mint: NATIVE_MINT,
+                rewardMint: rewardMint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerWsolAta.address,
+                destination: null,
+                stakerRewardAta: stakerRewardAta,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // The principal comes back as lamports and the wrapped account is gone
+        const lamportsAfter = await provider.connection.getBalance(staker.publicKey);
+        assert.ok(lamportsAfter - lamportsBefore > stakeAmount.toNumber() - 0.01 * LAMPORTS_PER_SOL);
+        assert.equal(await provider.connection.getAccountInfo(stakerWsolAta.address), null);
+
+        const stakerRewards = await getAccount(provider.connection, stakerRewardAta);
+        assert.equal(stakerRewards.amount.toString(), rewardAmount.toString());
+    });
+
+    it("restakes into a withdrawn deposit", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+        const extraMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(33);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+        const extraRewards = 100 * 10 ** 6;
+        const creatorExtraAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            extraMint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            extraMint,
+            creatorExtraAta.address,
+            provider.wallet.publicKey,
+            extraRewards
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+        const [rewardTokenVault] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("reward-token-vault"),
+                pool.toBuffer(),
+                extraMint.toBuffer(),
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        await program.methods
+            .addRewardToken()
+            .accountsStrict({
+                rewardTokenMint: extraMint,
+                creator: creator,
+                pool: pool,
+                rewardTokenVault: rewardTokenVault,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [rewardLockupAuthority] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-lockup"), deposit.toBuffer()],
+            program.programId
+        );
+        const [rewardLockupSchedule] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("vesting-schedule"),
+                rewardLockupAuthority.toBuffer(),
+                staker.publicKey.toBuffer(),
+            ],
+            vestingProgram.programId
+        );
+        const [claimEscrow] = PublicKey.findProgramAddressSync(
+            [Buffer.from("claim-escrow"), deposit.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await program.methods
+            .fundRewardToken(new anchor.BN(extraRewards))
+            .accountsStrict({
+                rewardTokenMint: extraMint,
+                creator: creator,
+                pool: pool,
+                rewardTokenVault: rewardTokenVault,
+                creatorRewardTokenAta: creatorExtraAta.address,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        const restake = () =>
+            program.methods
+                .restake(depositId, stakeAmount)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: depositCounter,
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    rewardLockupSchedule: rewardLockupSchedule,
+                    claimEscrow: claimEscrow,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .signers([staker])
+                .rpc();
+
+        // A live deposit cannot be restaked
+        try {
+            await restake();
+            assert.fail("Expected restaking a live deposit to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("DepositNotWithdrawn"));
+        }
+
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(depositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        await program.methods
+            .unstake(depositId)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // The extra reward tokens earned by the withdrawn stake have to be claimed first
+        try {
+            await restake();
+            assert.fail("Expected restaking with unclaimed rewards to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("DepositRewardsUnclaimed"));
+        }
+
+        const stakerExtraAta = await getAssociatedTokenAddress(
+            extraMint,
+            staker.publicKey
+        );
+        await program.methods
+            .claimRewardToken(depositId)
+            .accountsStrict({
+                rewardTokenMint: extraMint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                rewardTokenVault: rewardTokenVault,
+                stakerRewardTokenAta: stakerExtraAta,
+                globalConfig: globalConfig,
+                treasuryRewardTokenAta: null,
+                referrerRewardTokenAta: null,
+                referralStats: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        const stakerExtraAccount = await getAccount(
+            provider.connection,
+            stakerExtraAta
+        );
+        assert.ok(stakerExtraAccount.amount === BigInt(extraRewards));
+
+        await restake();
+
+        // Same id and account, live again, and no new id was burned
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.isWithdrawn === false);
+        assert.ok(depositAccount.tokensDeposited.eq(stakeAmount));
+        const counter = await program.account.depositCounter.fetch(depositCounter);
+        assert.ok(counter.nextDepositId.eqn(1));
+        assert.ok(counter.depositCount.eqn(2));
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.currentTokensStaked.eq(stakeAmount));
+    });
+
+    it("blocks same-slot stake and unstake and withholds rewards of young deposits", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(34);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(200 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+        await program.methods
+            .changeMinRewardAge(new anchor.BN(3600))
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+
+        const stakeMethod = () =>
+            program.methods
+                .stake(depositId, stakeAmount, null)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: depositCounter,
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    referralStats: null,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker]);
+        const activateCooldown = () =>
+            program.methods
+                .activateCooldown(depositId, null)
+                .accountsStrict({
+                    staker: staker.publicKey,
+                    deposit: deposit,
+                    pool: pool,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker]);
+
+        // Staking and starting the cooldown in one transaction is rejected
+        try {
+            await stakeMethod()
+                .postInstructions([await activateCooldown().instruction()])
+                .rpc();
+            assert.fail("Expected a same-slot cooldown to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("StakeTooRecent"));
+        }
+
+        await stakeMethod().rpc();
+        await waitForStakeToAge();
+        await activateCooldown().rpc();
+
+        const stakerBalanceBefore = await getAccount(provider.connection, stakerAta.address);
+        await program.methods
+            .unstake(depositId)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // Only the principal comes back, the rewards stay in the pool
+        const stakerBalanceAfter = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(
+            (stakerBalanceAfter.amount - stakerBalanceBefore.amount).toString(),
+            stakeAmount.toString()
+        );
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.currentRewards.eq(rewardAmount));
+    });
+
+    it("lets the creator withdraw the rewards of a pool without stakers", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const intruder = Keypair.generate();
+        const poolId = new anchor.BN(35);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // Only the creator may withdraw
+        try {
+            await program.methods
+                .withdrawUnusedRewards()
+                .accountsStrict({
+                    rewardMint: mint,
+                    creator: intruder.publicKey,
+                    pool: pool,
+                    rewardVault: poolRewardVault,
+                    creatorRewardAta: creatorAta.address,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .signers([intruder])
+                .rpc();
+            assert.fail("Expected a non-creator withdrawal to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("UnauthorizedPoolAccess"));
+        }
+
+        await program.methods
+            .withdrawUnusedRewards()
+            .accountsStrict({
+                rewardMint: mint,
+                creator: creator,
+                pool: pool,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        const creatorBalance = await getAccount(provider.connection, creatorAta.address);
+        assert.equal(creatorBalance.amount.toString(), rewardAmount.toString());
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.equal(poolAccount.currentRewards.toNumber(), 0);
+    });
+
+    it("rejects stakes and funding once the pool has ended", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(36);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const changeEndTimestamp = (endTimestamp: number) =>
+            program.methods
+                .changeEndTimestamp(new anchor.BN(endTimestamp))
+                .accountsStrict({
+                    mint: mint,
+                    creator: creator,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    creatorAta: creatorAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+
+        const chainNow = await provider.connection.getBlockTime(
+            await provider.connection.getSlot()
+        );
+        try {
+            await changeEndTimestamp(chainNow - 60);
+            assert.fail("Expected an end timestamp in the past to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("InvalidEndTimestamp"));
+        }
+
+        await changeEndTimestamp(chainNow + 2);
+        await new Promise((resolve) => setTimeout(resolve, 4000));
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        try {
+            await program.methods
+                .stake(depositId, stakeAmount, null)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: depositCounter,
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    referralStats: null,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+            assert.fail("Expected staking into an ended pool to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("PoolEnded"));
+        }
+
+        try {
+            await program.methods
+                .fundPool(rewardAmount)
+                .accountsStrict({
+                    rewardMint: mint,
+                    creator: creator,
+                    pool: pool,
+                    rewardVault: poolRewardVault,
+                    creatorRewardAta: creatorAta.address,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .rpc();
+            assert.fail("Expected funding an ended pool to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("PoolEnded"));
+        }
+    });
+
+    it("unstakes in a single call from a pool without cooldown", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(37);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        await waitForStakeToAge();
+
+        await program.methods
+            .unstakeNow(depositId)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // The only staker gets the principal and all the rewards back
+        const stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(
+            stakerBalance.amount.toString(),
+            stakeAmount.add(rewardAmount).toString()
+        );
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.equal(depositAccount.isWithdrawn, true);
+
+        // Lifetime counters survive the exit
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.equal(poolAccount.lifetimeStaked.toString(), stakeAmount.toString());
+        assert.equal(poolAccount.lifetimeRewardsPaid.toString(), rewardAmount.toString());
+        assert.equal(poolAccount.uniqueStakerCount.toNumber(), 1);
+        const statsAccount = await program.account.stakerStats.fetch(stakerStats);
+        assert.equal(
+            statsAccount.lifetimeRewardsEarned.toString(),
+            rewardAmount.toString()
+        );
+    });
+
+    it("stores pool metadata set by the creator", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const intruder = Keypair.generate();
+        const poolId = new anchor.BN(38);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const updateMetadata = (name: string, metadataUri: string, signer: Keypair) =>
+            program.methods
+                .updatePoolMetadata(name, metadataUri)
+                .accountsStrict({
+                    mint: mint,
+                    creator: signer.publicKey,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    creatorAta: creatorAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([signer])
+                .rpc();
+
+        const name = "Blue chip farm";
+        const metadataUri = "https://example.com/pools/38.json";
+        await updateMetadata(name, metadataUri, provider.wallet.payer);
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.equal(poolAccount.name, name);
+        assert.equal(poolAccount.metadataUri, metadataUri);
+
+        try {
+            await updateMetadata("x".repeat(33), metadataUri, provider.wallet.payer);
+            assert.fail("Expected an overlong name to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("PoolNameTooLong"));
+        }
+
+        try {
+            await updateMetadata(name, metadataUri, intruder);
+            assert.fail("Expected a non-creator update to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("UnauthorizedPoolAccess"));
+        }
+    });
+
+    it("configures an NFT collection reward boost", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const poolId = new anchor.BN(39);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const collection = Keypair.generate().publicKey;
+        const configureNftBoost = (multiplierBps: number) =>
+            program.methods
+                .configureNftBoost(collection, multiplierBps)
+                .accountsStrict({
+                    mint: mint,
+                    creator: creator,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    creatorAta: creatorAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+
+        // A boost has to multiply rewards by more than 1x and at most 3x
+        for (const multiplierBps of [10_000, 30_001]) {
+            try {
+                await configureNftBoost(multiplierBps);
+                assert.fail("Expected an out of range multiplier to fail");
+            } catch (err) {
+                assert.ok(err.toString().includes("InvalidNftBoost"));
+            }
+        }
+
+        await configureNftBoost(15_000);
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.nftBoost.collection.equals(collection));
+        assert.equal(poolAccount.nftBoost.multiplierBps, 15_000);
+    });
+
+    it("timelocks the emergency reward withdrawal", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const poolId = new anchor.BN(40);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const updatePoolAccounts = {
+            mint: mint,
+            creator: creator,
+            pool: pool,
+            poolVault: poolVaultAta,
+            creatorAta: creatorAta.address,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+        };
+        await program.methods
+            .changeEmergencyWithdrawDelay(new anchor.BN(3600))
+            .accountsStrict(updatePoolAccounts)
+            .rpc();
+        await program.methods
+            .enableEmergencyMode()
+            .accountsStrict(updatePoolAccounts)
+            .rpc();
+
+        // The delay cannot be lowered once emergency mode is on
+        try {
+            await program.methods
+                .changeEmergencyWithdrawDelay(new anchor.BN(0))
+                .accountsStrict(updatePoolAccounts)
+                .rpc();
+            assert.fail("Expected changing the delay in emergency mode to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("EmergencyModeEnabled"));
+        }
+
+        try {
+            await program.methods
+                .withdrawRewardsEmergency()
+                .accountsStrict({
+                    rewardMint: mint,
+                    creator: creator,
+                    pool: pool,
+                    globalConfig: globalConfig,
+                    rewardVault: poolRewardVault,
+                    creatorRewardAta: creatorAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            assert.fail("Expected an immediate reward withdrawal to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("EmergencyWithdrawTimelocked"));
+        }
+
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.currentRewards.eq(rewardAmount));
+    });
+
+    it("freezes a deposit until the creator lifts it", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(41);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        await waitForStakeToAge();
+
+        const setFrozen = (frozen: boolean) =>
+            (frozen ? program.methods.freezeDeposit(depositId) : program.methods.unfreezeDeposit(depositId))
+                .accountsStrict({
+                    creator: creator,
+                    staker: staker.publicKey,
+                    deposit: deposit,
+                    pool: pool,
+                })
+                .rpc();
+        const activateCooldown = () =>
+            program.methods
+                .activateCooldown(depositId, null)
+                .accountsStrict({
+                    staker: staker.publicKey,
+                    deposit: deposit,
+                    pool: pool,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+
+        await setFrozen(true);
+        let depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.equal(depositAccount.isFrozen, true);
+
+        try {
+            await activateCooldown();
+            assert.fail("Expected a frozen deposit to stay locked");
+        } catch (err) {
+            assert.ok(err.toString().includes("DepositFrozen"));
+        }
+
+        await setFrozen(false);
+        await activateCooldown();
+        depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.equal(depositAccount.isFrozen, false);
+        assert.equal(depositAccount.isCooldownActive, true);
+    });
+
+    it("stakes and unstakes through pool share tokens", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(42);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [shareMint] = PublicKey.findProgramAddressSync(
+            [Buffer.from("share-mint"), pool.toBuffer()],
+            program.programId
+        );
+        await program.methods
+            .enableShareToken()
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                shareMint: shareMint,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const stakerShareAta = await getAssociatedTokenAddress(
+            shareMint,
+            staker.publicKey
+        );
+        const stakeLiquid = () =>
+            program.methods
+                .stakeLiquid(stakeAmount)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    shareMint: shareMint,
+                    stakerShareAta: stakerShareAta,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker]);
+        const unstakeLiquid = () =>
+            program.methods
+                .unstakeLiquid(stakeAmount)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    rewardVault: poolRewardVault,
+                    stakerAta: stakerAta.address,
+                    shareMint: shareMint,
+                    stakerShareAta: stakerShareAta,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .signers([staker]);
+
+        // Staking and redeeming in one transaction is rejected
+        try {
+            await stakeLiquid()
+                .postInstructions([await unstakeLiquid().instruction()])
+                .rpc();
+            assert.fail("Expected a same-slot redemption to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("StakeTooRecent"));
+        }
+
+        await stakeLiquid().rpc();
+
+        // The first shares are minted one for one
+        const shareAccount = await getAccount(provider.connection, stakerShareAta);
+        assert.equal(shareAccount.amount.toString(), stakeAmount.toString());
+        let poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.equal(poolAccount.liquidStaked.toString(), stakeAmount.toString());
+        assert.equal(poolAccount.totalShares.toString(), stakeAmount.toString());
+
+        await waitForStakeToAge();
+        await unstakeLiquid().rpc();
+
+        // The only share holder redeems the principal and all the rewards
+        const stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(
+            stakerBalance.amount.toString(),
+            stakeAmount.add(rewardAmount).toString()
+        );
+        poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.equal(poolAccount.totalShares.toNumber(), 0);
+        assert.equal(poolAccount.currentTokensStaked.toNumber(), 0);
+    });
+
+    it("streams two reward tokens at their own rates and claims them together", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+        const projectMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+        const partnerMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(43);
+        const projectRewards = 20 * 10 ** 6;
+        const partnerRewards = 5 * 10 ** 6;
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const rewardMints = [projectMint, partnerMint];
+        const rewardAmounts = [projectRewards, partnerRewards];
+        const creatorRewardAtas = [];
+        const stakerRewardAtas = [];
+        for (let i = 0; i < rewardMints.length; i++) {
+            const creatorRewardAta = await getOrCreateAssociatedTokenAccount(
+                provider.connection,
+                provider.wallet.payer,
+                rewardMints[i],
+                creator
+            );
+            await mintTo(
+                provider.connection,
+                provider.wallet.payer,
+                rewardMints[i],
+                creatorRewardAta.address,
+                provider.wallet.publicKey,
+                rewardAmounts[i]
+            );
+            creatorRewardAtas.push(creatorRewardAta.address);
+            const stakerRewardAta = await getOrCreateAssociatedTokenAccount(
+                provider.connection,
+                staker,
+                rewardMints[i],
+                staker.publicKey
+            );
+            stakerRewardAtas.push(stakerRewardAta.address);
+        }
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            100 * 10 ** 9
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+        const rewardTokenVaults = rewardMints.map(
+            (rewardMint) =>
+                PublicKey.findProgramAddressSync(
+                    [
+                        Buffer.from("reward-token-vault"),
+                        pool.toBuffer(),
+                        rewardMint.toBuffer(),
+                    ],
+                    program.programId
+                )[0]
+        );
+
+        await program.methods
+            .createPool(poolId, new anchor.BN(0), new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        for (let i = 0; i < rewardMints.length; i++) {
+            await program.methods
+                .addRewardToken()
+                .accountsStrict({
+                    rewardTokenMint: rewardMints[i],
+                    creator: creator,
+                    pool: pool,
+                    rewardTokenVault: rewardTokenVaults[i],
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+        }
+
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        await program.methods
+            .stake(depositId, new anchor.BN(100 * 10 ** 9), null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // Each token streams its whole funding within a second
+        for (let i = 0; i < rewardMints.length; i++) {
+            await program.methods
+                .fundRewardTokenEmission(
+                    new anchor.BN(rewardAmounts[i]),
+                    new anchor.BN(rewardAmounts[i])
+                )
+                .accountsStrict({
+                    rewardTokenMint: rewardMints[i],
+                    creator: creator,
+                    pool: pool,
+                    rewardTokenVault: rewardTokenVaults[i],
+                    creatorRewardTokenAta: creatorRewardAtas[i],
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .rpc();
+        }
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.equal(
+            poolAccount.rewardTokens[1].emissionRate.toNumber(),
+            partnerRewards
+        );
+
+        await new Promise((resolve) => setTimeout(resolve, 3000));
+
+        const remainingAccounts = [];
+        for (let i = 0; i < rewardMints.length; i++) {
+            remainingAccounts.push(
+                { pubkey: rewardTokenVaults[i], isWritable: true, isSigner: false },
+                { pubkey: rewardMints[i], isWritable: false, isSigner: false },
+                { pubkey: stakerRewardAtas[i], isWritable: true, isSigner: false }
+            );
+        }
+        await program.methods
+            .claimRewards(depositId)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                devClock: null,
+            })
+            .remainingAccounts(remainingAccounts)
+            .signers([staker])
+            .rpc();
+
+        // The sole staker receives both emissions in the one claim
+        for (let i = 0; i < rewardMints.length; i++) {
+            const stakerRewardAccount = await getAccount(
+                provider.connection,
+                stakerRewardAtas[i]
+            );
+            assert.ok(stakerRewardAccount.amount === BigInt(rewardAmounts[i]));
+        }
+    });
+
+    it("escrows claimed rewards and streams them to the staker", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(44);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        await program.methods
+            .configureClaimVesting(new anchor.BN(1000))
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        await waitForStakeToAge();
+
+        const [claimEscrow] = PublicKey.findProgramAddressSync(
+            [Buffer.from("claim-escrow"), deposit.toBuffer()],
+            program.programId
+        );
+        await program.methods
+            .unstakeNow(depositId)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: null,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: claimEscrow,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // Only the principal is paid out, the rewards wait in the escrow
+        let stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(stakerBalance.amount.toString(), stakeAmount.toString());
+        let escrowAccount = await program.account.claimEscrow.fetch(claimEscrow);
+        assert.ok(escrowAccount.staker.equals(staker.publicKey));
+        assert.equal(escrowAccount.stream.amount.toString(), rewardAmount.toString());
+        assert.equal(
+            escrowAccount.stream.end.sub(escrowAccount.stream.start).toNumber(),
+            1000
+        );
+
+        // The staker paid the escrow's rent, so closing it refunds them
+        assert.ok(escrowAccount.rentPayer.equals(staker.publicKey));
+
+        await new Promise((resolve) => setTimeout(resolve, 2000));
+        const release = (rentPayer: anchor.web3.PublicKey) =>
+            program.methods
+                .releaseClaimedRewards()
+                .accountsStrict({
+                    staker: staker.publicKey,
+                    rewardMint: mint,
+                    pool: pool,
+                    rewardVault: poolRewardVault,
+                    claimEscrow: claimEscrow,
+                    rentPayer: rentPayer,
+                    stakerRewardAta: stakerAta.address,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .signers([staker])
+                .rpc();
+        try {
+            await release(provider.wallet.publicKey);
+            assert.fail("Expected another rent payer to be rejected");
+        } catch (err) {
+            assert.ok(err.toString().includes("ConstraintAddress"));
+        }
+        await release(staker.publicKey);
+
+        // A slice of the rewards is streamed, the rest stays escrowed
+        escrowAccount = await program.account.claimEscrow.fetch(claimEscrow);
+        assert.ok(escrowAccount.stream.released.gtn(0));
+        assert.ok(escrowAccount.stream.released.lt(rewardAmount));
+        stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(
+            stakerBalance.amount.toString(),
+            stakeAmount.add(escrowAccount.stream.released).toString()
+        );
+    });
+
+    it("hands pool administration to a new authority", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const newAuthority = Keypair.generate();
+        const poolId = new anchor.BN(45);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const changeCooldown = (signer: Keypair, cooldown: number) =>
+            program.methods
+                .changePoolCooldown(new anchor.BN(cooldown))
+                .accountsStrict({
+                    mint: mint,
+                    creator: signer.publicKey,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    creatorAta: creatorAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([signer])
+                .rpc();
+
+        let poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.authority.equals(creator));
+
+        await program.methods
+            .setPoolAuthority(newAuthority.publicKey)
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        // The creator no longer administers the pool
+        try {
+            await changeCooldown(provider.wallet.payer, 60);
+            assert.fail("Expected the former authority to be rejected");
+        } catch (err) {
+            assert.ok(err.toString().includes("UnauthorizedPoolAccess"));
+        }
+
+        await changeCooldown(newAuthority, 60);
+        poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.authority.equals(newAuthority.publicKey));
+        assert.ok(poolAccount.creator.equals(creator));
+        assert.equal(poolAccount.claimCooldown.toNumber(), 60);
+    });
+
+    it("writes the current layout version and rejects migrating it again", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const poolId = new anchor.BN(46);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.equal(poolAccount.version, 5);
+
+        // Up-to-date accounts are left alone
+        try {
+            await program.methods
+                .migratePool()
+                .accountsStrict({
+                    payer: creator,
+                    account: pool,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            assert.fail("Expected migrating a current pool to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("AccountUpToDate"));
+        }
+
+        // Accounts of other programs cannot be migrated
+        try {
+            await program.methods
+                .migrateStakerStats()
+                .accountsStrict({
+                    payer: creator,
+                    account: creatorAta.address,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            assert.fail("Expected migrating a foreign account to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("InvalidMigrationAccount"));
+        }
+    });
+
+    it("charges the protocol pool creation fee in SOL", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const treasury = Keypair.generate();
+        const creationFee = new anchor.BN(LAMPORTS_PER_SOL / 10);
+        const poolId = new anchor.BN(47);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .updateConfig(0, treasury.publicKey, false)
+            .accountsStrict({
+                admin: provider.wallet.publicKey,
+                globalConfig: globalConfig,
+            })
+            .rpc();
+        await program.methods
+            .setPoolCreationFee(creationFee)
+            .accountsStrict({
+                admin: provider.wallet.publicKey,
+                globalConfig: globalConfig,
+            })
+            .rpc();
+
+        // The treasury must be passed while a creation fee is set
+        try {
+            await program.methods
+                .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+                .accountsStrict({
+                    mint: mint,
+                    rewardMint: mint,
+                    creator: creator,
+                    globalConfig: globalConfig,
+                    pool: pool,
+                    poolRegistry: poolRegistryFor(creator),
+                    poolVault: poolVaultAta,
+                    rewardVault: poolRewardVault,
+                    creatorRewardAta: creatorAta.address,
+                    treasury: null,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            assert.fail("Expected creating a pool without the treasury to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("MissingFeeTreasury"));
+        }
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: treasury.publicKey,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const treasuryBalance = await provider.connection.getBalance(treasury.publicKey);
+        assert.equal(treasuryBalance, creationFee.toNumber());
+
+        // Restore the config for the other tests
+        await program.methods
+            .setPoolCreationFee(new anchor.BN(0))
+            .accountsStrict({
+                admin: provider.wallet.publicKey,
+                globalConfig: globalConfig,
+            })
+            .rpc();
+        await program.methods
+            .updateConfig(0, provider.wallet.publicKey, false)
+            .accountsStrict({
+                admin: provider.wallet.publicKey,
+                globalConfig: globalConfig,
+            })
+            .rpc();
+    });
+
+    it("extends a deposit's lock for a reward boost and a higher voting weight", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(48);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const lockExtension = new anchor.BN(3600);
+        const extendLock = () =>
+            program.methods
+                .extendLock(depositId, lockExtension)
+                .accountsStrict({
+                    staker: staker.publicKey,
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    pool: pool,
+                })
+                .signers([staker]);
+        const configureLockBoost = (boostBps: number) =>
+            program.methods
+                .configureLockBoost(boostBps)
+                .accountsStrict({
+                    mint: mint,
+                    creator: creator,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    creatorAta: creatorAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+
+        // Extensions are only accepted once the pool rewards them
+        try {
+            await extendLock().rpc();
+            assert.fail("Expected extending a lock without a boost to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("LockBoostDisabled"));
+        }
+        try {
+            await configureLockBoost(20_001);
+            assert.fail("Expected a boost above the maximum to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("InvalidLockBoost"));
+        }
+        await configureLockBoost(10_000);
+
+        await extendLock().rpc();
+
+        // A pool without cooldown gave no voting weight, the longer lock does
+        const depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.lockExtension.eq(lockExtension));
+        assert.ok(depositAccount.votingWeight.gtn(0));
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.equal(poolAccount.lockBoostBps, 10_000);
+        assert.ok(poolAccount.totalVotingWeight.eq(depositAccount.votingWeight));
+        await waitForStakeToAge();
+
+        // The deposit is now committed to the extended cooldown
+        try {
+            await program.methods
+                .unstakeNow(depositId)
+                .accountsStrict({
+                    mint: mint,
+                    rewardMint: mint,
+                    staker: staker.publicKey,
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    depositCounter: depositCounter,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    rewardVault: poolRewardVault,
+                    stakerAta: stakerAta.address,
+                    destination: null,
+                    stakerRewardAta: stakerAta.address,
+                    globalConfig: globalConfig,
+                    treasuryRewardAta: null,
+                    rewardLockupAuthority: null,
+                    rewardLockupAta: null,
+                    vestingSchedule: null,
+                    vestingVault: null,
+                    vestingProgram: null,
+                    referrerRewardAta: null,
+                    referralStats: null,
+                    receiptMint: null,
+                    stakerReceiptAta: null,
+                    boostNftAta: null,
+                    boostNftMetadata: null,
+                    claimEscrow: null,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+            assert.fail("Expected unstaking before the extended lock to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("ClaimCooldownNotElapsed"));
+        }
+    });
+
+    it("caps the number of open deposits per staker and pool", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(49);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            3 * stakeAmount.toNumber()
+        );
+
+        // Two pools of the same mint, each allowing a single open deposit per staker
+        const createPool = async (poolId: anchor.BN) => {
+            const [pool] = PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("pool"),
+                    creator.toBuffer(),
+                    poolId.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            );
+            const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+            const [poolRewardVault] = PublicKey.findProgramAddressSync(
+                [Buffer.from("reward-vault"), pool.toBuffer()],
+                program.programId
+            );
+
+            await program.methods
+                .createPool(poolId, rewardAmount.divn(2), new anchor.BN(0), false)
+                .accountsStrict({
+                    mint: mint,
+                    rewardMint: mint,
+                    creator: creator,
+                    globalConfig: globalConfig,
+                    pool: pool,
+                    poolRegistry: poolRegistryFor(creator),
+                    poolVault: poolVaultAta,
+                    rewardVault: poolRewardVault,
+                    creatorRewardAta: creatorAta.address,
+                    treasury: null,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+
+            await program.methods
+                .changeMaxOpenDeposits(1)
+                .accountsStrict({
+                    mint: mint,
+                    creator: creator,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    creatorAta: creatorAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+            return { pool, poolVaultAta };
+        };
+        const first = await createPool(poolId);
+        const second = await createPool(new anchor.BN(54));
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const counterFor = (pool: anchor.web3.PublicKey) =>
+            PublicKey.findProgramAddressSync(
+                [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+                program.programId
+            )[0];
+        const stake = (
+            { pool, poolVaultAta }: { pool: anchor.web3.PublicKey; poolVaultAta: anchor.web3.PublicKey },
+            depositId: anchor.BN
+        ) => {
+            const [deposit] = PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("deposit"),
+                    staker.publicKey.toBuffer(),
+                    pool.toBuffer(),
+                    depositId.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            );
+            return program.methods
+                .stake(depositId, stakeAmount, null)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: counterFor(pool),
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    referralStats: null,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+        };
+
+        // A deposit in another pool does not count against this pool's limit
+        await stake(second, new anchor.BN(0));
+        await stake(first, new anchor.BN(0));
+        const counter = await program.account.depositCounter.fetch(counterFor(first.pool));
+        assert.ok(counter.openDeposits.eqn(1));
+        const stats = await program.account.stakerStats.fetch(stakerStats);
+        assert.ok(stats.openDeposits.eqn(2));
+
+        // A second deposit would go over the pool's limit
+        try {
+            await stake(first, new anchor.BN(1));
+            assert.fail("Expected a deposit over the limit to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("TooManyOpenDeposits"));
+        }
+    });
+
+    it("skips the cooldown for the instant unstake fee", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(50);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(3600), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        await waitForStakeToAge();
+
+        const unstakeInstant = () =>
+            program.methods
+                .unstakeInstant(depositId)
+                .accountsStrict({
+                    mint: mint,
+                    rewardMint: mint,
+                    staker: staker.publicKey,
+                    deposit: deposit,
+                    stakerStats: stakerStats,
+                    depositCounter: depositCounter,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    rewardVault: poolRewardVault,
+                    stakerAta: stakerAta.address,
+                    destination: null,
+                    stakerRewardAta: stakerAta.address,
+                    globalConfig: globalConfig,
+                    treasuryRewardAta: null,
+                    rewardLockupAuthority: null,
+                    rewardLockupAta: null,
+                    vestingSchedule: null,
+                    vestingVault: null,
+                    vestingProgram: null,
+                    referrerRewardAta: null,
+                    referralStats: null,
+                    receiptMint: null,
+                    stakerReceiptAta: null,
+                    boostNftAta: null,
+                    boostNftMetadata: null,
+                    claimEscrow: null,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+
+
+        // Pools opt in to instant unstaking
+        try {
+            await unstakeInstant();
+            assert.fail("Expected an instant unstake without a fee set to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("InstantUnstakeDisabled"));
+        }
+        await program.methods
+            .changeInstantUnstakeFee(1_000)
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        await unstakeInstant();
+
+        // The staker pays 10% of the principal, which stays in the pool as rewards
+        const fee = stakeAmount.divn(10);
+        const stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(
+            stakerBalance.amount.toString(),
+            stakeAmount.sub(fee).add(rewardAmount).toString()
+        );
+        const poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.currentRewards.eq(fee));
+        assert.ok(poolAccount.currentTokensStaked.eqn(0));
+    });
+
+    it("sets aside the rounding dust of the emergency reserve and sweeps it back", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(51);
+        const rewardAmount = new anchor.BN(100);
+        const stakeAmount = new anchor.BN(3);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositAddress = (id: anchor.BN) =>
+            PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("deposit"),
+                    staker.publicKey.toBuffer(),
+                    pool.toBuffer(),
+                    id.toArrayLike(Buffer, "le", 8),
+                ],
+                program.programId
+            )[0];
+
+        // A third of the stake in cooldown, so its reserve of 100 / 3 does not divide evenly
+        const amounts = [new anchor.BN(1), new anchor.BN(2)];
+        for (const [index, amount] of amounts.entries()) {
+            const id = new anchor.BN(index);
+            await program.methods
+                .stake(id, amount, null)
+                .accountsStrict({
+                    mint: mint,
+                    staker: staker.publicKey,
+                    depositCounter: depositCounter,
+                    deposit: depositAddress(id),
+                    stakerStats: stakerStats,
+                    referralStats: null,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    stakerAta: stakerAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([staker])
+                .rpc();
+        }
+        const maturedDepositId = new anchor.BN(0);
+        await waitForStakeToAge();
+        await program.methods
+            .activateCooldown(maturedDepositId, null)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: depositAddress(maturedDepositId),
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        await program.methods
+            .enableEmergencyMode()
+            .accountsStrict({
+                mint: mint,
+                creator: creator,
+                pool: pool,
+                poolVault: poolVaultAta,
+                creatorAta: creatorAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const stakerBalanceBefore = await getAccount(provider.connection, stakerAta.address);
+        await program.methods
+            .unstakeEmergency(maturedDepositId)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                deposit: depositAddress(maturedDepositId),
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                rewardMint: mint,
+                rewardVault: poolRewardVault,
+                stakerRewardAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        const stakerBalanceAfter = await getAccount(provider.connection, stakerAta.address);
+        // The payout rounds down to 33, the reserve was rounded up to 34
+        assert.equal(
+            (stakerBalanceAfter.amount - stakerBalanceBefore.amount).toString(),
+            "34"
+        );
+
+        let poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.emergencyReserve.reserved.eqn(0));
+        assert.ok(poolAccount.rewardDust.eqn(1));
+        assert.ok(poolAccount.currentRewards.eqn(66));
+
+        const sweepDust = () =>
+            program.methods
+                .sweepDust()
+                .accountsStrict({
+                    mint: mint,
+                    creator: creator,
+                    pool: pool,
+                    poolVault: poolVaultAta,
+                    creatorAta: creatorAta.address,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .rpc();
+        await sweepDust();
+
+        poolAccount = await program.account.stakingPool.fetch(pool);
+        assert.ok(poolAccount.rewardDust.eqn(0));
+        assert.ok(poolAccount.currentRewards.eqn(67));
+
+        try {
+            await sweepDust();
+            assert.fail("Expected sweeping without dust to fail");
+        } catch (err) {
+            assert.ok(err.toString().includes("NoDustToSweep"));
+        }
+    });
+
+    it("unstakes the principal to another token account", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(52);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        // Principal goes straight to a wallet the staker does not sign with
+        const coldWallet = Keypair.generate();
+        const coldWalletAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            coldWallet.publicKey
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        await waitForStakeToAge();
+
+        await program.methods
+            .unstakeNow(depositId)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                staker: staker.publicKey,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                destination: coldWalletAta.address,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                receiptMint: null,
+                stakerReceiptAta: null,
+                boostNftAta: null,
+                boostNftMetadata: null,
+                claimEscrow: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+
+        // The cold wallet gets the principal, the rewards still go to the staker
+        const coldWalletBalance = await getAccount(provider.connection, coldWalletAta.address);
+        assert.equal(coldWalletBalance.amount.toString(), stakeAmount.toString());
+        const stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(stakerBalance.amount.toString(), rewardAmount.toString());
+    });
+
+    it("pays the keeper tip to whoever executes an opted-in unstake", async () => {
+        const mint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+
+        const creator = provider.wallet.publicKey;
+        const staker = Keypair.generate();
+        const poolId = new anchor.BN(53);
+        const rewardAmount = new anchor.BN(100 * 10 ** 9);
+        const stakeAmount = new anchor.BN(10 * 10 ** 9);
+
+        const airdropSig = await provider.connection.requestAirdrop(
+            staker.publicKey,
+            2 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(airdropSig);
+        const keeper = Keypair.generate();
+        const keeperAirdropSig = await provider.connection.requestAirdrop(
+            keeper.publicKey,
+            LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(keeperAirdropSig);
+
+        const creatorAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creator
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            creatorAta.address,
+            provider.wallet.publicKey,
+            rewardAmount.toNumber()
+        );
+        const stakerAta = await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            staker,
+            mint,
+            staker.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            mint,
+            stakerAta.address,
+            provider.wallet.publicKey,
+            stakeAmount.toNumber()
+        );
+
+        const [pool] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("pool"),
+                creator.toBuffer(),
+                poolId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        const poolVaultAta = await getAssociatedTokenAddress(mint, pool, true);
+        const [poolRewardVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reward-vault"), pool.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .createPool(poolId, rewardAmount, new anchor.BN(0), false)
+            .accountsStrict({
+                mint: mint,
+                rewardMint: mint,
+                creator: creator,
+                globalConfig: globalConfig,
+                pool: pool,
+                poolRegistry: poolRegistryFor(creator),
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                creatorRewardAta: creatorAta.address,
+                treasury: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+
+        const [stakerStats] = PublicKey.findProgramAddressSync(
+            [Buffer.from("staker-stats"), staker.publicKey.toBuffer()],
+            program.programId
+        );
+        const [depositCounter] = PublicKey.findProgramAddressSync(
+            [Buffer.from("deposit-counter"), staker.publicKey.toBuffer(), pool.toBuffer()],
+            program.programId
+        );
+        const depositId = new anchor.BN(0);
+        const [deposit] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("deposit"),
+                staker.publicKey.toBuffer(),
+                pool.toBuffer(),
+                depositId.toArrayLike(Buffer, "le", 8),
+            ],
+            program.programId
+        );
+        await program.methods
+            .stake(depositId, stakeAmount, null)
+            .accountsStrict({
+                mint: mint,
+                staker: staker.publicKey,
+                depositCounter: depositCounter,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                referralStats: null,
+                pool: pool,
+                poolVault: poolVaultAta,
+                stakerAta: stakerAta.address,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        await waitForStakeToAge();
+
+        // The staker opts in with a tip held by the deposit until the unstake runs
+        const keeperTip = new anchor.BN(LAMPORTS_PER_SOL / 100);
+        await program.methods
+            .activateCooldown(depositId, keeperTip)
+            .accountsStrict({
+                staker: staker.publicKey,
+                deposit: deposit,
+                pool: pool,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([staker])
+            .rpc();
+        let depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.keeperTip.eq(keeperTip));
+        assert.ok(depositAccount.autoUnstakeAt.eq(depositAccount.unlockTimestamp));
+
+        const keeperLamportsBefore = await provider.connection.getBalance(keeper.publicKey);
+        await program.methods
+            .crankAutoUnstake(depositId)
+            .accountsStrict({
+                cranker: keeper.publicKey,
+                staker: staker.publicKey,
+                mint: mint,
+                rewardMint: mint,
+                deposit: deposit,
+                stakerStats: stakerStats,
+                depositCounter: depositCounter,
+                pool: pool,
+                poolVault: poolVaultAta,
+                rewardVault: poolRewardVault,
+                stakerAta: stakerAta.address,
+                stakerRewardAta: stakerAta.address,
+                globalConfig: globalConfig,
+                treasuryRewardAta: null,
+                rewardLockupAuthority: null,
+                rewardLockupAta: null,
+                vestingSchedule: null,
+                vestingVault: null,
+                vestingProgram: null,
+                claimEscrow: null,
+                referrerRewardAta: null,
+                referralStats: null,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([keeper])
+            .rpc();
+
+        // The staker gets the tokens, the keeper the tip
+        const stakerBalance = await getAccount(provider.connection, stakerAta.address);
+        assert.equal(
+            stakerBalance.amount.toString(),
+            stakeAmount.add(rewardAmount).toString()
+        );
+        const keeperLamportsAfter = await provider.connection.getBalance(keeper.publicKey);
+        assert.equal(keeperLamportsAfter - keeperLamportsBefore, keeperTip.toNumber());
+        depositAccount = await program.account.stakerDeposit.fetch(deposit);
+        assert.ok(depositAccount.isWithdrawn);
+        assert.ok(depositAccount.keeperTip.eqn(0));
+    });
+});
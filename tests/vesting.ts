@@ -1,13 +1,20 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import {
+    AccountLayout,
+    ACCOUNT_SIZE,
     createMint,
     getAccount,
     getAssociatedTokenAddress,
     getOrCreateAssociatedTokenAccount,
+    MINT_SIZE,
+    MintLayout,
     mintTo,
+    TOKEN_PROGRAM_ID,
 } from "@solana/spl-token";
+import { BankrunProvider } from "anchor-bankrun";
 import { strict as assert } from "assert";
+import { Clock, ProgramTestContext, startAnchor } from "solana-bankrun";
 import { Vesting } from "../target/types/vesting";
 
 const { SystemProgram, LAMPORTS_PER_SOL, PublicKey, Keypair } = anchor.web3;
@@ -66,7 +73,9 @@ describe("vesting", () => {
         cliffDuration,
         intervalDuration,
         unlockPercentage,
-        new anchor.BN(totalAmount)
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
       )
       .accounts({
         creator: creator,
@@ -142,7 +151,9 @@ describe("vesting", () => {
         cliffDuration,
         intervalDuration,
         unlockPercentage,
-        new anchor.BN(totalAmount)
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
       )
       .accounts({
         creator: creator,
@@ -271,7 +282,9 @@ describe("vesting", () => {
         cliffDuration,
         intervalDuration,
         unlockPercentage,
-        new anchor.BN(totalAmount)
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
       )
       .accounts({
         creator: creator,
@@ -382,7 +395,9 @@ describe("vesting", () => {
         cliffDuration,
         intervalDuration,
         unlockPercentage,
-        new anchor.BN(totalAmount)
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
       )
       .accounts({
         creator: creator,
@@ -504,7 +519,9 @@ describe("vesting", () => {
         cliffDuration,
         intervalDuration,
         unlockPercentage,
-        new anchor.BN(totalAmount)
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
       )
       .accounts({
         creator: creator,
@@ -623,7 +640,9 @@ describe("vesting", () => {
         cliffDuration,
         intervalDuration,
         unlockPercentage,
-        new anchor.BN(totalAmount)
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
       )
       .accounts({
         creator: creator,
@@ -710,7 +729,9 @@ describe("vesting", () => {
           new anchor.BN(0), // Invalid: 0
           new anchor.BN(60),
           10,
-          new anchor.BN(totalAmount)
+          new anchor.BN(totalAmount),
+          false,
+          { floor: {} }
         )
         .accounts({
           creator: creator,
@@ -731,7 +752,9 @@ describe("vesting", () => {
           new anchor.BN(60),
           new anchor.BN(60),
           101, // Invalid: > 100
-          new anchor.BN(totalAmount)
+          new anchor.BN(totalAmount),
+          false,
+          { floor: {} }
         )
         .accounts({
           creator: creator,
@@ -791,7 +814,9 @@ describe("vesting", () => {
         cliffDuration,
         intervalDuration,
         unlockPercentage,
-        new anchor.BN(totalAmount)
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
       )
       .accounts({
         creator: creator,
@@ -846,5 +871,857 @@ describe("vesting", () => {
       `Expected beneficiary to have ${expectedAmount} tokens, got ${beneficiaryAccount.amount.toString()}`
     );
   });
-});
 
+  it("revokes a batch of schedules for a cohort", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const creator = provider.wallet.publicKey;
+    const beneficiaries = [Keypair.generate(), Keypair.generate()];
+
+    const creatorAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      creator
+    );
+
+    const amountPerSchedule = 100 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      creatorAta.address,
+      provider.wallet.publicKey,
+      amountPerSchedule * beneficiaries.length
+    );
+
+    const remainingAccounts = [];
+    for (const beneficiary of beneficiaries) {
+      await program.methods
+        .createVesting(
+          new anchor.BN(3600),
+          new anchor.BN(60),
+          10,
+          new anchor.BN(amountPerSchedule),
+          true,
+          { floor: {} }
+        )
+        .accounts({
+          creator: creator,
+          beneficiary: beneficiary.publicKey,
+          tokenMint: mint,
+          creatorTokenAccount: creatorAta.address,
+        })
+        .rpc();
+
+      const [vestingSchedule] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("vesting-schedule"),
+          creator.toBuffer(),
+          beneficiary.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), vestingSchedule.toBuffer()],
+        program.programId
+      );
+      remainingAccounts.push(
+        { pubkey: vestingSchedule, isWritable: true, isSigner: false },
+        { pubkey: vault, isWritable: true, isSigner: false }
+      );
+    }
+
+    await program.methods
+      .revokeBatch()
+      .accounts({
+        creator: creator,
+        tokenMint: mint,
+        creatorTokenAccount: creatorAta.address,
+        devClock: null,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+    // Nothing had vested yet, so everything returns to the creator
+    const creatorAccount = await getAccount(
+      provider.connection,
+      creatorAta.address
+    );
+    assert.ok(
+      creatorAccount.amount === BigInt(amountPerSchedule * beneficiaries.length)
+    );
+
+    for (let i = 0; i < remainingAccounts.length; i += 2) {
+      const vestingAccount = await program.account.vestingSchedule.fetch(
+        remainingAccounts[i].pubkey
+      );
+      assert.ok(vestingAccount.revoked === true);
+      assert.ok(vestingAccount.claimableAtRevoke.eqn(0));
+      assert.ok(vestingAccount.returnedAtRevoke.eq(new anchor.BN(amountPerSchedule)));
+    }
+  });
+
+  it("freezes a compromised mint and migrates schedules to its replacement", async () => {
+    const oldMint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+    const newMint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const creator = provider.wallet.publicKey;
+    const beneficiary = Keypair.generate();
+
+    const airdropSig = await provider.connection.requestAirdrop(
+      beneficiary.publicKey,
+      1 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const creatorOldAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      oldMint,
+      creator
+    );
+    const creatorNewAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      newMint,
+      creator
+    );
+
+    const totalAmount = 1000 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      oldMint,
+      creatorOldAta.address,
+      provider.wallet.publicKey,
+      totalAmount
+    );
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      newMint,
+      creatorNewAta.address,
+      provider.wallet.publicKey,
+      2 * totalAmount
+    );
+
+    const [vestingSchedule] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting-schedule"),
+        creator.toBuffer(),
+        beneficiary.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), vestingSchedule.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createVesting(
+        new anchor.BN(1),
+        new anchor.BN(1),
+        10,
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
+      )
+      .accounts({
+        creator: creator,
+        beneficiary: beneficiary.publicKey,
+        tokenMint: oldMint,
+        creatorTokenAccount: creatorOldAta.address,
+      })
+      .rpc();
+
+    // The provider wallet acts as guardian
+    const [config] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vesting-config")],
+      program.programId
+    );
+    // Only the upgrade authority, the provider wallet on localnet, can create the config
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    await program.methods
+      .initializeConfig(provider.wallet.publicKey)
+      .accountsStrict({
+        config: config,
+        admin: provider.wallet.publicKey,
+        program: program.programId,
+        programData: programData,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const [mintFreeze] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mint-freeze"), oldMint.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .freezeMint()
+      .accountsStrict({
+        config: config,
+        guardian: provider.wallet.publicKey,
+        tokenMint: oldMint,
+        mintFreeze: mintFreeze,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    // Wait past the cliff so only the freeze blocks the unlock
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    const beneficiaryOldAta = await getAssociatedTokenAddress(
+      oldMint,
+      beneficiary.publicKey
+    );
+    try {
+      await program.methods
+        .unlock()
+        .accounts({
+          vestingSchedule: vestingSchedule,
+          beneficiary: beneficiary.publicKey,
+          tokenMint: oldMint,
+          vault: vault,
+          beneficiaryAta: beneficiaryOldAta,
+        })
+        .signers([beneficiary])
+        .rpc();
+      assert.fail("Expected unlock of a frozen mint to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("MintFrozen"));
+    }
+
+    const [newVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), vestingSchedule.toBuffer(), newMint.toBuffer()],
+      program.programId
+    );
+    const migrateAccounts = {
+      vestingSchedule: vestingSchedule,
+      creator: creator,
+      tokenMint: oldMint,
+      mintFreeze: mintFreeze,
+      vault: vault,
+      creatorTokenAccount: creatorOldAta.address,
+      newMint: newMint,
+      newVault: newVault,
+      creatorNewTokenAccount: creatorNewAta.address,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+
+    // The creator cannot migrate before the guardian approves the replacement and rate
+    try {
+      await program.methods.migrateVault().accountsStrict(migrateAccounts).rpc();
+      assert.fail("Expected an unapproved migration to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("MigrationNotApproved"));
+    }
+
+    // Migrate at 2 new tokens per old token
+    await program.methods
+      .approveMigration(newMint, new anchor.BN(2), new anchor.BN(1))
+      .accountsStrict({
+        config: config,
+        guardian: provider.wallet.publicKey,
+        mintFreeze: mintFreeze,
+      })
+      .rpc();
+    await program.methods.migrateVault().accountsStrict(migrateAccounts).rpc();
+
+    const vestingAccount = await program.account.vestingSchedule.fetch(
+      vestingSchedule
+    );
+    assert.ok(vestingAccount.tokenMint.equals(newMint));
+    assert.ok(vestingAccount.vault.equals(newVault));
+    assert.ok(vestingAccount.totalAmount.eq(new anchor.BN(2 * totalAmount)));
+
+    const newVaultAccount = await getAccount(provider.connection, newVault);
+    assert.ok(newVaultAccount.amount === BigInt(2 * totalAmount));
+    const oldVaultAccount = await getAccount(provider.connection, vault);
+    assert.ok(oldVaultAccount.amount === BigInt(0));
+  });
+
+  it("previews interval payouts for floor and nearest rounding", async () => {
+    // 2 decimals: 10 tokens are 1000 base units, 15% per interval is 1.5 tokens
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      2
+    );
+
+    const creator = provider.wallet.publicKey;
+    const creatorAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      creator
+    );
+
+    const totalAmount = 1000;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      creatorAta.address,
+      provider.wallet.publicKey,
+      2 * totalAmount
+    );
+
+    const preview = async (rounding: object) => {
+      const beneficiary = Keypair.generate();
+      const [vestingSchedule] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("vesting-schedule"),
+          creator.toBuffer(),
+          beneficiary.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+
+      await program.methods
+        .createVesting(
+          new anchor.BN(60),
+          new anchor.BN(60),
+          15,
+          new anchor.BN(totalAmount),
+          false,
+          rounding
+        )
+        .accounts({
+          creator: creator,
+          beneficiary: beneficiary.publicKey,
+          tokenMint: mint,
+          creatorTokenAccount: creatorAta.address,
+        })
+        .rpc();
+
+      const payouts = await program.methods
+        .previewUnlocks()
+        .accountsStrict({ vestingSchedule })
+        .view();
+      return payouts.map((amount: anchor.BN) => amount.toNumber());
+    };
+
+    assert.deepEqual(
+      await preview({ floor: {} }),
+      [150, 150, 150, 150, 150, 150, 100]
+    );
+    // Whole tokens: 2, 3, 5, 6, 8, 9 then 10 vested
+    assert.deepEqual(
+      await preview({ nearest: {} }),
+      [200, 100, 200, 100, 200, 100, 100]
+    );
+  });
+
+  it("pays the keeper bounty to the caller of unlock_for", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const creator = provider.wallet.publicKey;
+    const beneficiary = Keypair.generate();
+    const keeper = Keypair.generate();
+
+    const airdropSig = await provider.connection.requestAirdrop(
+      keeper.publicKey,
+      1 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const creatorAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      creator
+    );
+
+    const totalAmount = 1000 * 10 ** 9;
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      creatorAta.address,
+      provider.wallet.publicKey,
+      totalAmount
+    );
+
+    await program.methods
+      .createVesting(
+        new anchor.BN(2),
+        new anchor.BN(2),
+        10,
+        new anchor.BN(totalAmount),
+        false,
+        { floor: {} }
+      )
+      .accounts({
+        creator: creator,
+        beneficiary: beneficiary.publicKey,
+        tokenMint: mint,
+        creatorTokenAccount: creatorAta.address,
+      })
+      .rpc();
+
+    const [vestingSchedule] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting-schedule"),
+        creator.toBuffer(),
+        beneficiary.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), vestingSchedule.toBuffer()],
+      program.programId
+    );
+
+    // Enough for a single unlock
+    const bounty = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    await program.methods
+      .fundKeeperBounty(bounty, bounty)
+      .accounts({
+        vestingSchedule: vestingSchedule,
+        creator: creator,
+      })
+      .rpc();
+
+    // Pre-create the beneficiary's ATA so the keeper only receives the bounty
+    const beneficiaryAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      beneficiary.publicKey
+    );
+
+    await new Promise((resolve) => setTimeout(resolve, 5000));
+
+    const keeperBalanceBefore = await provider.connection.getBalance(keeper.publicKey);
+    await program.methods
+      .unlockFor()
+      .accounts({
+        vestingSchedule: vestingSchedule,
+        beneficiary: beneficiary.publicKey,
+        keeper: keeper.publicKey,
+        tokenMint: mint,
+        vault: vault,
+        beneficiaryAta: beneficiaryAta.address,
+      })
+      .signers([keeper])
+      .rpc();
+
+    const keeperBalanceAfter = await provider.connection.getBalance(keeper.publicKey);
+    assert.equal(keeperBalanceAfter - keeperBalanceBefore, bounty.toNumber());
+
+    const beneficiaryAccount = await getAccount(
+      provider.connection,
+      beneficiaryAta.address
+    );
+    assert.equal(beneficiaryAccount.amount, BigInt(totalAmount / 10));
+
+    // The bounty balance is used up, later cranks are unpaid
+    const vestingAccount = await program.account.vestingSchedule.fetch(
+      vestingSchedule
+    );
+    assert.ok(vestingAccount.keeperBountyBalance.eqn(0));
+  });
+
+  it("publishes a snapshot of the creator's schedules", async () => {
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9
+    );
+
+    const creator = provider.wallet.publicKey;
+    const beneficiaries = [Keypair.generate(), Keypair.generate()];
+    const amounts = [100 * 10 ** 9, 50 * 10 ** 9];
+
+    const creatorAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      creator
+    );
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      creatorAta.address,
+      provider.wallet.publicKey,
+      amounts[0] + amounts[1]
+    );
+
+    const schedules = [];
+    for (let i = 0; i < beneficiaries.length; i++) {
+      await program.methods
+        .createVesting(
+          new anchor.BN(3600),
+          new anchor.BN(60),
+          10,
+          new anchor.BN(amounts[i]),
+          true,
+          { floor: {} }
+        )
+        .accounts({
+          creator: creator,
+          beneficiary: beneficiaries[i].publicKey,
+          tokenMint: mint,
+          creatorTokenAccount: creatorAta.address,
+        })
+        .rpc();
+
+      const [vestingSchedule] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("vesting-schedule"),
+          creator.toBuffer(),
+          beneficiaries[i].publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+      schedules.push(vestingSchedule);
+    }
+
+    const [snapshot] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vesting-snapshot"), creator.toBuffer()],
+      program.programId
+    );
+    const remainingAccounts = schedules.map((pubkey) => ({
+      pubkey,
+      isWritable: false,
+      isSigner: false,
+    }));
+
+    // Listing a schedule twice is rejected
+    try {
+      await program.methods
+        .publishSnapshot()
+        .accountsStrict({
+          creator: creator,
+          snapshot: snapshot,
+          systemProgram: SystemProgram.programId,
+          devClock: null,
+        })
+        .remainingAccounts([...remainingAccounts, remainingAccounts[0]])
+        .rpc();
+      assert.fail("Expected a duplicate schedule to fail");
+    } catch (err) {
+      assert.ok(err.toString().includes("InvalidRemainingAccounts"));
+    }
+
+    await program.methods
+      .publishSnapshot()
+      .accountsStrict({
+        creator: creator,
+        snapshot: snapshot,
+        systemProgram: SystemProgram.programId,
+        devClock: null,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+    const snapshotAccount = await program.account.vestingSnapshot.fetch(snapshot);
+    assert.ok(snapshotAccount.creator.equals(creator));
+    assert.equal(snapshotAccount.entries.length, schedules.length);
+    for (let i = 0; i < schedules.length; i++) {
+      assert.ok(snapshotAccount.entries[i].schedule.equals(schedules[i]));
+      assert.ok(
+        snapshotAccount.entries[i].remainingAmount.eq(new anchor.BN(amounts[i]))
+      );
+    }
+  });
+
+  // The vault is a PDA that only the schedule controls, so a delegate or close authority
+  // can only be planted by rewriting the account, which bankrun allows.
+  describe("vault authorities", () => {
+    const totalAmount = 1000n * 10n ** 9n;
+
+    let context: ProgramTestContext;
+    let bankrunProvider: BankrunProvider;
+    let bankrunProgram: Program<Vesting>;
+
+    before(async () => {
+      context = await startAnchor(".", [], []);
+      bankrunProvider = new BankrunProvider(context);
+      bankrunProgram = new Program<Vesting>(program.idl, bankrunProvider);
+    });
+
+    const setTokenAccount = (
+      address: anchor.web3.PublicKey,
+      fields: Partial<ReturnType<typeof AccountLayout.decode>>
+    ) => {
+      const data = Buffer.alloc(ACCOUNT_SIZE);
+      AccountLayout.encode(
+        {
+          mint: PublicKey.default,
+          owner: PublicKey.default,
+          amount: 0n,
+          delegateOption: 0,
+          delegate: PublicKey.default,
+          state: 1,
+          isNativeOption: 0,
+          isNative: 0n,
+          delegatedAmount: 0n,
+          closeAuthorityOption: 0,
+          closeAuthority: PublicKey.default,
+          ...fields,
+        },
+        data
+      );
+      context.setAccount(address, {
+        lamports: LAMPORTS_PER_SOL,
+        data,
+        owner: TOKEN_PROGRAM_ID,
+        executable: false,
+      });
+    };
+
+    // A mint, a funded creator token account and a beneficiary with lamports for fees
+    const setupSchedule = () => {
+      const creator = context.payer.publicKey;
+      const beneficiary = Keypair.generate();
+      const mint = Keypair.generate().publicKey;
+
+      const mintData = Buffer.alloc(MINT_SIZE);
+      MintLayout.encode(
+        {
+          mintAuthorityOption: 1,
+          mintAuthority: creator,
+          supply: totalAmount,
+          decimals: 9,
+          isInitialized: true,
+          freezeAuthorityOption: 0,
+          freezeAuthority: PublicKey.default,
+        },
+        mintData
+      );
+      context.setAccount(mint, {
+        lamports: LAMPORTS_PER_SOL,
+        data: mintData,
+        owner: TOKEN_PROGRAM_ID,
+        executable: false,
+      });
+
+      const creatorTokenAccount = Keypair.generate().publicKey;
+      setTokenAccount(creatorTokenAccount, {
+        mint,
+        owner: creator,
+        amount: totalAmount,
+      });
+
+      context.setAccount(beneficiary.publicKey, {
+        lamports: LAMPORTS_PER_SOL,
+        data: Buffer.alloc(0),
+        owner: SystemProgram.programId,
+        executable: false,
+      });
+
+      const [vestingSchedule] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("vesting-schedule"),
+          creator.toBuffer(),
+          beneficiary.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), vestingSchedule.toBuffer()],
+        program.programId
+      );
+
+      return { creator, beneficiary, mint, creatorTokenAccount, vestingSchedule, vault };
+    };
+
+    // Process `tx` and return its logs, failing the test if it went through
+    const processFailing = async (
+      tx: anchor.web3.Transaction,
+      signers: anchor.web3.Keypair[] = []
+    ) => {
+      tx.recentBlockhash = context.lastBlockhash;
+      tx.feePayer = context.payer.publicKey;
+      tx.sign(context.payer, ...signers);
+      const result = await context.banksClient.tryProcessTransaction(tx);
+      assert.ok(result.result, "Expected the transaction to fail");
+      return result.meta.logMessages.join("\n");
+    };
+
+    const createVestingTx = (schedule: ReturnType<typeof setupSchedule>) =>
+      bankrunProgram.methods
+        .createVesting(
+          new anchor.BN(60),
+          new anchor.BN(60),
+          10,
+          new anchor.BN(totalAmount.toString()),
+          false,
+          { floor: {} }
+        )
+        .accounts({
+          creator: schedule.creator,
+          beneficiary: schedule.beneficiary.publicKey,
+          tokenMint: schedule.mint,
+          creatorTokenAccount: schedule.creatorTokenAccount,
+        })
+        .transaction();
+
+    // Lock tokens, move past the cliff and rewrite the vault with `fields`, then try to
+    // unlock. Returns the logs of the failed unlock.
+    const unlockTamperedVault = async (
+      fields: Partial<ReturnType<typeof AccountLayout.decode>>
+    ) => {
+      const schedule = setupSchedule();
+      await bankrunProvider.sendAndConfirm(await createVestingTx(schedule));
+
+      const clock = await context.banksClient.getClock();
+      context.setClock(
+        new Clock(
+          clock.slot,
+          clock.epochStartTimestamp,
+          clock.epoch,
+          clock.leaderScheduleEpoch,
+          clock.unixTimestamp + 600n
+        )
+      );
+
+      const vaultInfo = await context.banksClient.getAccount(schedule.vault);
+      setTokenAccount(schedule.vault, {
+        ...AccountLayout.decode(vaultInfo.data),
+        ...fields,
+      });
+
+      const tx = await bankrunProgram.methods
+        .unlock()
+        .accounts({
+          vestingSchedule: schedule.vestingSchedule,
+          beneficiary: schedule.beneficiary.publicKey,
+          tokenMint: schedule.mint,
+          vault: schedule.vault,
+          beneficiaryAta: await getAssociatedTokenAddress(
+            schedule.mint,
+            schedule.beneficiary.publicKey
+          ),
+        })
+        .transaction();
+      return processFailing(tx, [schedule.beneficiary]);
+    };
+
+    // `create_vesting` initializes the vault itself, so a planted one never gets funded
+    it("fails to lock tokens into a vault with a delegate", async () => {
+      const schedule = setupSchedule();
+      setTokenAccount(schedule.vault, {
+        mint: schedule.mint,
+        owner: schedule.vestingSchedule,
+        delegateOption: 1,
+        delegate: Keypair.generate().publicKey,
+        delegatedAmount: totalAmount,
+      });
+
+      const logs = await processFailing(await createVestingTx(schedule));
+      assert.ok(logs.includes("already in use"));
+    });
+
+    it("fails to lock tokens into a vault with a close authority", async () => {
+      const schedule = setupSchedule();
+      setTokenAccount(schedule.vault, {
+        mint: schedule.mint,
+        owner: schedule.vestingSchedule,
+        closeAuthorityOption: 1,
+        closeAuthority: Keypair.generate().publicKey,
+      });
+
+      const logs = await processFailing(await createVestingTx(schedule));
+      assert.ok(logs.includes("already in use"));
+    });
+
+    it("fails to unlock from a vault with a delegate", async () => {
+      const logs = await unlockTamperedVault({
+        delegateOption: 1,
+        delegate: Keypair.generate().publicKey,
+        delegatedAmount: totalAmount,
+      });
+      assert.ok(logs.includes("VaultHasDelegate"));
+    });
+
+    it("fails to unlock from a vault with a close authority", async () => {
+      const logs = await unlockTamperedVault({
+        closeAuthorityOption: 1,
+        closeAuthority: Keypair.generate().publicKey,
+      });
+      assert.ok(logs.includes("VaultHasCloseAuthority"));
+    });
+
+    it("migrates a schedule created before revocation and rounding", async () => {
+      const schedule = setupSchedule();
+      await bankrunProvider.sendAndConfirm(await createVestingTx(schedule));
+
+      // The legacy layout ends with the bump, right after `created_at`
+      const legacyLength = 8 + 4 * 32 + 4 * 8 + 1 + 2 * 8 + 1;
+      const scheduleInfo = await context.banksClient.getAccount(schedule.vestingSchedule);
+      context.setAccount(schedule.vestingSchedule, {
+        lamports: scheduleInfo.lamports,
+        data: Buffer.from(scheduleInfo.data).subarray(0, legacyLength),
+        owner: program.programId,
+        executable: false,
+      });
+
+      const migrate = (payer: anchor.web3.PublicKey) =>
+        bankrunProgram.methods
+          .migrateSchedule()
+          .accountsStrict({
+            vestingSchedule: schedule.vestingSchedule,
+            tokenMint: schedule.mint,
+            payer,
+            systemProgram: SystemProgram.programId,
+          })
+          .transaction();
+      await bankrunProvider.sendAndConfirm(await migrate(context.payer.publicKey));
+
+      const migrated = await bankrunProgram.account.vestingSchedule.fetch(
+        schedule.vestingSchedule
+      );
+      assert.equal(migrated.version, 1);
+      assert.equal(migrated.totalAmount.toString(), totalAmount.toString());
+      assert.ok(migrated.beneficiary.equals(schedule.beneficiary.publicKey));
+      assert.equal(migrated.unlockPercentage, 10);
+      assert.equal(migrated.revocable, false);
+      assert.deepEqual(migrated.rounding, { floor: {} });
+      assert.equal(migrated.tokenDecimals, 9);
+      assert.equal(migrated.keeperBounty.toNumber(), 0);
+
+      // Up-to-date schedules are left alone
+      const logs = await processFailing(await migrate(schedule.beneficiary.publicKey), [
+        schedule.beneficiary,
+      ]);
+      assert.ok(logs.includes("ScheduleAlreadyMigrated"));
+    });
+  });
+});